@@ -0,0 +1,152 @@
+#[tauri::command]
+fn greet(name: &str) -> String {
+    format!("Hello, {name}! You've been greeted from Rust!")
+}
+
+use crate::commands::{self, NutState};
+use crate::monitor::outlets::PendingSwitches;
+use crate::monitor::shutdown::ShutdownTracker;
+use crate::monitor::Monitor;
+use crate::nut::simulator::VirtualUps;
+use crate::source::SharedSource;
+use log::info;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+#[cfg_attr(mobile, tauri::mobile_entry_point)]
+use tauri::menu::{Menu, MenuItem};
+use tauri::tray::{MouseButton, MouseButtonState, TrayIconBuilder, TrayIconEvent};
+use tauri::Manager;
+
+pub fn run() {
+    let nut_arc: SharedSource = Arc::new(Mutex::new(None));
+    let shutdown_arc = Arc::new(Mutex::new(ShutdownTracker::default()));
+    let setup_nut_arc = nut_arc.clone();
+    let setup_shutdown_arc = shutdown_arc.clone();
+
+    tauri::Builder::default()
+        .plugin(tauri_plugin_autostart::Builder::new().build())
+        .plugin(tauri_plugin_opener::init())
+        .plugin(tauri_plugin_notification::init())
+        .plugin(tauri_plugin_log::Builder::default().build())
+        .plugin(tauri_plugin_updater::Builder::default().build())
+        .plugin(tauri_plugin_process::init())
+        .manage(NutState(nut_arc))
+        .manage(commands::ShutdownState(shutdown_arc))
+        .manage(commands::PollerState(Arc::new(Mutex::new(None))))
+        .manage(commands::SimulatorState(VirtualUps::shared()))
+        .manage(commands::OutletState(Arc::new(Mutex::new(
+            PendingSwitches::default(),
+        ))))
+        .setup(move |app| {
+            // Initialize Database
+            let app_data_dir = app
+                .path()
+                .app_data_dir()
+                .expect("failed to get app data dir");
+            std::fs::create_dir_all(&app_data_dir)?;
+            let db = crate::db::NutDB::new(&app_data_dir);
+            if let Err(e) = db.init() {
+                eprintln!("Failed to init DB: {}", e);
+            }
+            let db_arc = Arc::new(Mutex::new(Some(db)));
+            app.manage(commands::DbState(db_arc.clone()));
+
+            // The poll loop itself lives in `monitor` and is shared with the daemon;
+            // the app only relays its events to the webview and tray.
+            let monitor = Monitor::new(setup_nut_arc.clone(), db_arc, setup_shutdown_arc.clone());
+            app.manage(commands::MonitorState(monitor));
+            tauri::async_runtime::spawn(commands::forward_monitor_events(app.handle().clone()));
+
+            // Load persisted settings and resume monitoring without waiting for the UI
+            let app_config_dir = app
+                .path()
+                .app_config_dir()
+                .expect("failed to get app config dir");
+            let settings = crate::settings::SettingsStore::open(&app_config_dir);
+            info!("Loaded settings from {}", settings.path().display());
+
+            let credentials = crate::credentials::CredentialStore::new(&app_data_dir);
+            app.manage(commands::CredentialState(Arc::new(credentials)));
+            app.manage(commands::SettingsState(Arc::new(Mutex::new(settings))));
+            tauri::async_runtime::spawn(commands::resume_monitoring(app.handle().clone()));
+
+            let tray_menu = Menu::with_items(
+                app,
+                &[
+                    &MenuItem::with_id(app, "show", "Show Main Window", true, None::<&str>)?,
+                    &MenuItem::with_id(app, "hide", "Hide to Tray", true, None::<&str>)?,
+                    &MenuItem::with_id(app, "quit", "Exit Application", true, None::<&str>)?,
+                ],
+            )?;
+
+            let _tray = TrayIconBuilder::with_id("main")
+                .icon(app.default_window_icon().unwrap().clone())
+                .menu(&tray_menu)
+                .on_menu_event(|app, event| match event.id.as_ref() {
+                    "quit" => {
+                        app.exit(0);
+                    }
+                    "show" => {
+                        if let Some(window) = app.get_webview_window("main") {
+                            let _ = window.show();
+                            let _ = window.set_focus();
+                        }
+                    }
+                    "hide" => {
+                        if let Some(window) = app.get_webview_window("main") {
+                            let _ = window.hide();
+                        }
+                    }
+                    _ => {}
+                })
+                .on_tray_icon_event(|tray, event| {
+                    if let TrayIconEvent::Click {
+                        button: MouseButton::Left,
+                        button_state: MouseButtonState::Up,
+                        ..
+                    } = event
+                    {
+                        let app = tray.app_handle();
+                        if let Some(window) = app.get_webview_window("main") {
+                            let _ = window.show();
+                            let _ = window.set_focus();
+                        }
+                    }
+                })
+                .build(app)?;
+
+            Ok(())
+        })
+        .invoke_handler(tauri::generate_handler![
+            greet,
+            commands::connect_nut,
+            commands::disconnect_nut,
+            commands::get_ups_data,
+            commands::get_source_info,
+            commands::describe_variables,
+            commands::start_background_polling,
+            commands::trigger_system_stop,
+            commands::abort_system_stop,
+            commands::scan_nut_network,
+            commands::list_ups_on_server,
+            commands::list_ups_commands,
+            commands::run_ups_command,
+            commands::prepare_outlet_switch,
+            commands::confirm_outlet_switch,
+            commands::get_journal,
+            commands::get_chart_data,
+            commands::get_history_stats,
+            commands::get_energy,
+            commands::get_cost_report,
+            commands::get_battery_health,
+            commands::get_power_quality,
+            commands::export_data,
+            commands::clean_history_data,
+            commands::get_settings,
+            commands::update_settings,
+            commands::simulate_power_event
+        ])
+        .run(tauri::generate_context!())
+        .expect("error while running tauri application");
+}
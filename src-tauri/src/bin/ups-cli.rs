@@ -0,0 +1,253 @@
+//! Command-line NUT client in the spirit of `upsc`/`upscmd`/`upsrw`, built on the
+//! same `NutClient` the app uses. Handy for checking a server before configuring it.
+//!
+//! Build without the GUI: `cargo build --no-default-features --bin ups-cli`
+
+use clap::{Parser, Subcommand};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use ups_client_lib::nut::capture::CaptureWriter;
+use ups_client_lib::nut::client::NutClient;
+use ups_client_lib::nut::models::NutConfig;
+
+#[derive(Parser)]
+#[command(name = "ups-cli", version, about = "Query and control a NUT server")]
+struct Args {
+    #[arg(long, short = 'H', global = true, default_value = "127.0.0.1")]
+    host: String,
+
+    #[arg(long, short = 'p', global = true, default_value_t = 3493)]
+    port: u16,
+
+    #[arg(long, short = 'u', global = true)]
+    user: Option<String>,
+
+    /// Prefer NUT_PASSWORD so the secret does not end up in shell history
+    #[arg(long, global = true, env = "NUT_PASSWORD", hide_env_values = true)]
+    password: Option<String>,
+
+    /// Record every command and response to a capture file for later replay
+    #[arg(long, global = true, value_name = "FILE")]
+    record: Option<PathBuf>,
+
+    /// Print machine-readable JSON instead of `key: value` lines
+    #[arg(long, global = true)]
+    json: bool,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// List the UPS devices served by upsd
+    List,
+    /// Print a single variable
+    Get { ups: String, var: String },
+    /// Print all variables of a UPS
+    Vars { ups: String },
+    /// List the instant commands a UPS supports
+    Cmds { ups: String },
+    /// Run an instant command, optionally with an argument
+    Run {
+        ups: String,
+        cmd: String,
+        value: Option<String>,
+    },
+    /// Set a writable variable, or list writable variables when none is given
+    Set {
+        ups: String,
+        var: Option<String>,
+        value: Option<String>,
+    },
+    /// Poll a UPS and print variables whenever they change
+    Watch {
+        ups: String,
+        /// Poll interval in milliseconds
+        #[arg(long, short = 'i', default_value_t = 1000)]
+        interval: u64,
+    },
+    /// Send raw protocol lines read from stdin and print the replies
+    Console,
+}
+
+fn print_json<T: Serialize>(value: &T) -> Result<(), Box<dyn std::error::Error>> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+fn print_map(map: &BTreeMap<String, String>, json: bool) -> Result<(), Box<dyn std::error::Error>> {
+    if json {
+        return print_json(map);
+    }
+    for (key, value) in map {
+        println!("{key}: {value}");
+    }
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    match try_main(Args::parse()).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn try_main(args: Args) -> Result<(), Box<dyn std::error::Error>> {
+    let mut client = NutClient::new(NutConfig {
+        host: args.host.clone(),
+        port: args.port,
+        username: args.user.clone(),
+        credential_id: None,
+    })
+    .with_password(args.password.clone());
+    if let Some(path) = &args.record {
+        client = client.with_capture(CaptureWriter::create(path, &args.host, args.port)?);
+    }
+    client
+        .connect()
+        .await
+        .map_err(|e| format!("Failed to connect to {}:{}: {}", args.host, args.port, e))?;
+
+    let result = run(&mut client, args.command, args.json).await;
+    let _ = client.disconnect().await;
+    result
+}
+
+async fn run(
+    client: &mut NutClient,
+    command: Command,
+    json: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match command {
+        Command::List => {
+            let devices = client.list_ups().await?;
+            if json {
+                return print_json(&devices);
+            }
+            for ups in devices {
+                println!("{}: {}", ups.name, ups.description);
+            }
+        }
+        Command::Get { ups, var } => {
+            let value = client.get_var(&ups, &var).await?;
+            if json {
+                return print_json(&BTreeMap::from([(var, value)]));
+            }
+            println!("{value}");
+        }
+        Command::Vars { ups } => {
+            print_map(&client.list_vars(&ups).await?, json)?;
+        }
+        Command::Cmds { ups } => {
+            let cmds = client.list_ups_commands(&ups).await?;
+            if json {
+                return print_json(&cmds);
+            }
+            for cmd in cmds {
+                println!("{cmd}");
+            }
+        }
+        Command::Run { ups, cmd, value } => {
+            match &value {
+                Some(value) => client.run_instant_cmd_with_value(&ups, &cmd, value).await?,
+                None => client.run_instant_cmd(&ups, &cmd).await?,
+            }
+            if json {
+                return print_json(&serde_json::json!({
+                    "ups": ups, "command": cmd, "value": value, "result": "OK",
+                }));
+            }
+            println!("OK");
+        }
+        Command::Set { ups, var, value } => match (var, value) {
+            (Some(var), Some(value)) => {
+                client.set_var(&ups, &var, &value).await?;
+                if json {
+                    return print_json(&serde_json::json!({
+                        "ups": ups, "var": var, "value": value, "result": "OK",
+                    }));
+                }
+                println!("OK");
+            }
+            (Some(var), None) => return Err(format!("missing value for {var}").into()),
+            _ => print_map(&client.list_rw_vars(&ups).await?, json)?,
+        },
+        Command::Watch { ups, interval } => watch(client, &ups, interval, json).await?,
+        Command::Console => console(client).await?,
+    }
+    Ok(())
+}
+
+/// Prints the full variable set once, then only the variables that changed or
+/// disappeared (`--json` gives those as `null`).
+async fn watch(
+    client: &mut NutClient,
+    ups: &str,
+    interval_ms: u64,
+    json: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut interval = tokio::time::interval(Duration::from_millis(interval_ms.max(100)));
+    let mut last: BTreeMap<String, String> = BTreeMap::new();
+    loop {
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => return Ok(()),
+            _ = interval.tick() => {}
+        }
+        let vars = client.list_vars(ups).await?;
+        let mut changed: BTreeMap<&str, Option<&str>> = vars
+            .iter()
+            .filter(|(key, value)| last.get(*key) != Some(*value))
+            .map(|(key, value)| (key.as_str(), Some(value.as_str())))
+            .collect();
+        // e.g. an alarm clearing or `ups.test.result` going away
+        for key in last.keys().filter(|key| !vars.contains_key(*key)) {
+            changed.insert(key, None);
+        }
+        if !changed.is_empty() {
+            if json {
+                // One object per line so the output can be piped into jq
+                println!("{}", serde_json::to_string(&changed)?);
+            } else {
+                println!("--- {}", unix_timestamp());
+                for (key, value) in &changed {
+                    match value {
+                        Some(value) => println!("{key}: {value}"),
+                        None => println!("{key} (removed)"),
+                    }
+                }
+            }
+        }
+        last = vars;
+    }
+}
+
+fn unix_timestamp() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+async fn console(client: &mut NutClient) -> Result<(), Box<dyn std::error::Error>> {
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    while let Some(line) = lines.next_line().await? {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if line.eq_ignore_ascii_case("LOGOUT") {
+            break;
+        }
+        print!("{}", client.send_cmd(line).await?);
+    }
+    Ok(())
+}
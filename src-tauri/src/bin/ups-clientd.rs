@@ -0,0 +1,251 @@
+//! Headless monitoring daemon. Runs the same poll loop, history logging and
+//! shutdown protection as the desktop app, configured from a settings file.
+//!
+//! Build without the GUI: `cargo build --no-default-features --bin ups-clientd`
+
+use clap::{Parser, ValueEnum};
+use log::{error, info, warn, Level, LevelFilter, Log, Metadata, Record};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use ups_client_lib::credentials::CredentialStore;
+use ups_client_lib::db::NutDB;
+use ups_client_lib::monitor::shutdown::ShutdownTracker;
+use ups_client_lib::monitor::{Monitor, MonitorEvent};
+use ups_client_lib::notify::Dispatcher;
+use ups_client_lib::nut::capture::CaptureWriter;
+use ups_client_lib::nut::client::NutClient;
+use ups_client_lib::nut::simulator::VirtualUps;
+use ups_client_lib::settings::{Backend, SettingsStore};
+use ups_client_lib::source::{self, SharedSource, UpsSource};
+use ups_client_lib::telegram;
+
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum)]
+enum LogTarget {
+    /// Plain `LEVEL target: message` lines on stdout
+    Stdout,
+    /// Lines prefixed with syslog priorities (`<3>`...) that journald parses from stdout
+    Journald,
+}
+
+#[derive(Parser)]
+#[command(
+    name = "ups-clientd",
+    version,
+    about = "Headless UPS monitoring daemon"
+)]
+struct Args {
+    /// Directory containing settings.json (same format the desktop app writes)
+    #[arg(long, default_value = "/etc/ups-client")]
+    config_dir: PathBuf,
+
+    /// Directory for the history database and the credential fallback file
+    #[arg(long, default_value = "/var/lib/ups-client")]
+    data_dir: PathBuf,
+
+    /// Record the NUT session to a capture file, e.g. to attach to a bug report
+    #[arg(long, value_name = "FILE")]
+    record: Option<PathBuf>,
+
+    #[arg(long, value_enum, default_value_t = LogTarget::Stdout)]
+    log: LogTarget,
+
+    #[arg(long, default_value_t = LevelFilter::Info)]
+    log_level: LevelFilter,
+}
+
+struct StdoutLogger {
+    target: LogTarget,
+}
+
+impl Log for StdoutLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let mut out = std::io::stdout().lock();
+        let _ = match self.target {
+            LogTarget::Stdout => writeln!(
+                out,
+                "{:<5} {}: {}",
+                record.level(),
+                record.target(),
+                record.args()
+            ),
+            LogTarget::Journald => {
+                // sd-daemon(3) priority prefixes
+                let priority = match record.level() {
+                    Level::Error => 3,
+                    Level::Warn => 4,
+                    Level::Info => 6,
+                    Level::Debug | Level::Trace => 7,
+                };
+                writeln!(out, "<{}>{}", priority, record.args())
+            }
+        };
+    }
+
+    fn flush(&self) {
+        let _ = std::io::stdout().flush();
+    }
+}
+
+#[cfg(unix)]
+async fn wait_for_termination() {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut sigterm = signal(SignalKind::terminate()).expect("failed to install SIGTERM handler");
+    tokio::select! {
+        _ = sigterm.recv() => info!("Received SIGTERM, stopping"),
+        _ = tokio::signal::ctrl_c() => info!("Received SIGINT, stopping"),
+    }
+}
+
+#[cfg(not(unix))]
+async fn wait_for_termination() {
+    let _ = tokio::signal::ctrl_c().await;
+    info!("Received Ctrl+C, stopping");
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse();
+
+    log::set_boxed_logger(Box::new(StdoutLogger { target: args.log }))?;
+    log::set_max_level(args.log_level);
+
+    let settings = SettingsStore::open(&args.config_dir);
+    // Running on defaults would silently drop the configured shutdown protection
+    if let Some(e) = settings.load_error() {
+        return Err(format!("Cannot start with {}: {}", settings.path().display(), e).into());
+    }
+    info!("Loaded settings from {}", settings.path().display());
+    let settings = settings.get().clone();
+    if !settings.connection.is_configured() {
+        return Err(format!(
+            "No connection configured in {}",
+            args.config_dir.join("settings.json").display()
+        )
+        .into());
+    }
+
+    std::fs::create_dir_all(&args.data_dir)?;
+    let db = NutDB::new(&args.data_dir);
+    if let Err(e) = db.init() {
+        error!("Failed to init DB: {}", e);
+    }
+
+    // NUT_PASSWORD, SMTP_PASSWORD and TELEGRAM_BOT_TOKEN let service managers inject
+    // the secrets without a keyring
+    let conn = &settings.connection;
+    let credentials = CredentialStore::new(&args.data_dir);
+    let password = std::env::var("NUT_PASSWORD")
+        .ok()
+        .or_else(|| credentials.resolve(conn.credential_id.as_deref()));
+    if conn.backend == Backend::Simulator {
+        warn!("Simulation mode: polling the virtual UPS, system stops are not executed");
+    }
+    let mut source: Box<dyn UpsSource> = match &args.record {
+        Some(path) if conn.backend != Backend::Simulator => {
+            info!("Recording NUT session to {}", path.display());
+            Box::new(
+                NutClient::new(conn.nut_config())
+                    .with_password(password)
+                    .with_capture(CaptureWriter::create(path, &conn.host, conn.port)?),
+            )
+        }
+        _ => source::open(conn, password, &VirtualUps::shared()),
+    };
+    if let Err(e) = source.connect().await {
+        // The watchdog in the poll loop keeps retrying
+        warn!(
+            "Initial connection to {} failed: {}",
+            source.metadata().endpoint,
+            e
+        );
+    }
+
+    let shared: SharedSource = Arc::new(Mutex::new(Some(source)));
+    let mut monitor = Monitor::new(
+        shared.clone(),
+        Arc::new(Mutex::new(Some(db))),
+        Arc::new(Mutex::new(ShutdownTracker::default())),
+    )
+    .with_load_shedding(settings.load_shedding.clone())
+    .with_self_test(settings.self_test.clone())
+    .with_alerts(settings.alerts.clone());
+    let mut notifier =
+        Dispatcher::from_settings(
+            &settings.notifications,
+            &args.data_dir,
+            |id| match std::env::var("SMTP_PASSWORD") {
+                Ok(password) if id.starts_with("smtp/") => Some(password),
+                _ => credentials.resolve(Some(id)),
+            },
+        );
+    let token = std::env::var("TELEGRAM_BOT_TOKEN")
+        .ok()
+        .or_else(|| credentials.resolve(settings.telegram.credential_id.as_deref()));
+    if let Some((bot, channel)) = telegram::from_settings(&settings.telegram, token) {
+        notifier = notifier.with_channel(Arc::new(channel));
+        monitor = monitor.with_telegram(bot);
+    }
+    monitor = monitor.with_notifier(notifier);
+    if conn.backend == Backend::Simulator {
+        monitor = monitor.without_system_stop();
+    }
+    let mut events = monitor.subscribe();
+    let poller = monitor.spawn(
+        conn.ups_name.clone(),
+        settings.poll_interval_ms,
+        settings.shutdown.clone(),
+    );
+
+    let mut last_status = String::new();
+    let terminate = wait_for_termination();
+    tokio::pin!(terminate);
+    loop {
+        tokio::select! {
+            _ = &mut terminate => break,
+            event = events.recv() => match event {
+                Ok(MonitorEvent::Update(data)) => {
+                    if data.status != last_status {
+                        info!("UPS status: {}", data.status);
+                        last_status = data.status;
+                    }
+                }
+                Ok(MonitorEvent::ShutdownWarning(remaining)) => {
+                    warn!("System stop in {}s", remaining)
+                }
+                Ok(MonitorEvent::ShutdownCancelled) => info!("System stop cancelled"),
+                Ok(MonitorEvent::ShutdownExecuted(action)) => {
+                    warn!("Executing system stop: {}", action)
+                }
+                Ok(MonitorEvent::ConnectionLost(reason)) => warn!("Connection lost: {}", reason),
+                Ok(MonitorEvent::ConnectionRestored) => info!("Connection restored"),
+                Ok(MonitorEvent::LoadShedding { step, outcome }) => {
+                    warn!("Load shedding {}: {}", step, outcome)
+                }
+                Ok(MonitorEvent::SelfTest(message)) => info!("{}", message),
+                // Logged by the monitor, which also journals it
+                Ok(MonitorEvent::Alert(_)) => {}
+                Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => {}
+                Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+            },
+        }
+    }
+
+    poller.abort();
+    // Wait for the loop to unwind so it writes the energy still pending
+    let _ = poller.await;
+    if let Some(mut source) = shared.lock().await.take() {
+        let _ = source.disconnect().await;
+    }
+    info!("Stopped");
+    Ok(())
+}
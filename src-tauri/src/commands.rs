@@ -1,23 +1,46 @@
+use crate::credentials::{
+    nut_credential_id, smtp_credential_id, webhook_credential_id, CredentialStore,
+    TELEGRAM_CREDENTIAL_ID,
+};
+use crate::export::{self, ExportFormat};
+use crate::monitor::outlets::{self, OutletAction, OutletSwitch, PendingSwitches};
+use crate::monitor::shutdown::{self, ShutdownTracker};
+use crate::monitor::{Monitor, MonitorEvent};
+use crate::notify::desktop::DesktopChannel;
+use crate::notify::{Dispatcher, Throttled};
 use crate::nut::client::NutClient;
+use crate::nut::dictionary::{self, VariableInfo};
 use crate::nut::models::{NutConfig, UpsData};
+use crate::nut::simulator::{SharedSimulator, SimEvent};
+use crate::reports::battery::{self, BatteryHealth};
+use crate::reports::cost::{self, CostReport, ReportPeriod};
+use crate::reports::quality::{self, PowerQualityReport};
+use crate::settings::{AppSettings, Backend, NotificationsConfig, SettingsStore, ShutdownConfig};
+use crate::source::{self, CommandOutcome, SharedSource, SourceMetadata};
+use crate::telegram;
+use std::collections::HashMap;
 use std::sync::Arc;
-use tauri::{AppHandle, Emitter, State};
+use tauri::{AppHandle, Emitter, Manager, State};
 use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
 
 use log::{error, info, warn};
 
-// We need a thread-safe wrapper for the client
-pub struct NutState(pub Arc<Mutex<Option<NutClient>>>);
+// The active UPS source (NUT, simulator...), shared with the poll loop
+pub struct NutState(pub SharedSource);
 pub struct DbState(pub Arc<Mutex<Option<crate::db::NutDB>>>);
 
-#[derive(Default)]
-pub struct ShutdownTracker {
-    pub pending: bool,
-    pub countdown_remaining: u64,
-    pub action_type: String,
-}
-
 pub struct ShutdownState(pub Arc<Mutex<ShutdownTracker>>);
+pub struct SettingsState(pub Arc<Mutex<SettingsStore>>);
+pub struct CredentialState(pub Arc<CredentialStore>);
+pub struct MonitorState(pub Monitor);
+
+// Handle of the running poll loop, so restarting monitoring never leaves two loops behind
+pub struct PollerState(pub Arc<Mutex<Option<JoinHandle<()>>>>);
+// Virtual UPS used when the connection is set to simulation mode
+pub struct SimulatorState(pub SharedSimulator);
+// Outlet switches waiting for the user to confirm them
+pub struct OutletState(pub Arc<Mutex<PendingSwitches>>);
 
 /// Creates a simple 32x32 solid color circle icon programmatically
 fn create_status_icon(r: u8, g: u8, b: u8) -> tauri::image::Image<'static> {
@@ -45,12 +68,17 @@ fn create_status_icon(r: u8, g: u8, b: u8) -> tauri::image::Image<'static> {
 }
 
 #[tauri::command]
-pub async fn connect_nut(state: State<'_, NutState>, config: NutConfig) -> Result<String, String> {
-    let mut client = NutClient::new(config);
+pub async fn connect_nut(
+    state: State<'_, NutState>,
+    credentials: State<'_, CredentialState>,
+    config: NutConfig,
+) -> Result<String, String> {
+    let password = credentials.0.resolve(config.credential_id.as_deref());
+    let mut client = NutClient::new(config).with_password(password);
     match client.connect().await {
         Ok(_) => {
             let mut state_val = state.0.lock().await;
-            *state_val = Some(client);
+            *state_val = Some(Box::new(client));
             Ok("Connected".to_string())
         }
         Err(e) => Err(format!("Connection failed: {e}")),
@@ -66,11 +94,31 @@ pub async fn disconnect_nut(state: State<'_, NutState>) -> Result<String, String
     Ok("Disconnected".to_string())
 }
 
+/// Which backend is active and where it points, or `None` when nothing is configured.
+#[tauri::command]
+pub async fn get_source_info(state: State<'_, NutState>) -> Result<Option<SourceMetadata>, String> {
+    Ok(state
+        .0
+        .lock()
+        .await
+        .as_ref()
+        .map(|source| source.metadata()))
+}
+
+/// Units and descriptions for the given variable names; unknown names are left out.
+#[tauri::command]
+pub fn describe_variables(names: Vec<String>) -> HashMap<String, VariableInfo> {
+    names
+        .into_iter()
+        .filter_map(|name| dictionary::lookup(&name).map(|info| (name, *info)))
+        .collect()
+}
+
 #[tauri::command]
 pub async fn get_ups_data(state: State<'_, NutState>, ups_name: String) -> Result<UpsData, String> {
     let mut state_val = state.0.lock().await;
     if let Some(client) = state_val.as_mut() {
-        match client.get_ups_data(&ups_name).await {
+        match client.fetch_data(&ups_name).await {
             Ok(data) => Ok(data),
             Err(e) => Err(format!("Failed to get data: {e}")),
         }
@@ -79,364 +127,330 @@ pub async fn get_ups_data(state: State<'_, NutState>, ups_name: String) -> Resul
     }
 }
 
-#[derive(serde::Deserialize, Clone, Debug)]
-#[serde(rename_all = "camelCase")]
-pub struct ShutdownConfig {
-    pub enabled: bool,
-    pub battery_threshold: u32,
-    pub runtime_threshold: u32,
-    pub stop_type: String,
-    #[serde(rename = "delaySeconds")]
-    pub timer_sec: u64,
-}
-
 #[tauri::command]
 pub async fn start_background_polling(
     app: AppHandle,
-    state: State<'_, NutState>,
-    db_state: State<'_, DbState>,
-    shutdown_state: State<'_, ShutdownState>,
     ups_name: String,
     interval_ms: u64,
     shutdown_config: ShutdownConfig,
 ) -> Result<(), String> {
-    let state_arc = state.0.clone();
-    let db_arc = db_state.0.clone();
-    let shutdown_arc = shutdown_state.0.clone();
-
-    info!(
-        "Starting background polling for {} with interval {}ms",
-        ups_name, interval_ms
-    );
-    info!("Shutdown Config: {:?}", shutdown_config);
-
-    // Spawn a background task
-    tokio::spawn(async move {
-        let mut interval = tokio::time::interval(std::time::Duration::from_millis(interval_ms));
-        let mut last_log_time = std::time::Instant::now();
-        let mut last_logged_data: Option<UpsData> = None;
-        let mut first_run = true;
-
-        loop {
-            interval.tick().await;
-
-            // Scope for lock with Auto-Reconnect Watchdog & Timeout
-            let data_result = {
-                let mut guard = state_arc.lock().await;
-                if let Some(client) = guard.as_mut() {
-                    // Add timeout to prevent locking for too long
-                    match tokio::time::timeout(
-                        std::time::Duration::from_secs(2),
-                        client.get_ups_data(&ups_name),
-                    )
-                    .await
-                    {
-                        Ok(inner_result) => match inner_result {
-                            Ok(data) => Ok(data),
-                            Err(e) => {
-                                warn!("Watchdog: Failed to get data: {}", e);
-                                // Attempt reconnect logic
-                                match client.connect().await {
-                                    Ok(_) => {
-                                        info!("Watchdog: Reconnected.");
-                                        // Retry once
-                                        match tokio::time::timeout(
-                                            std::time::Duration::from_secs(2),
-                                            client.get_ups_data(&ups_name),
-                                        )
-                                        .await
-                                        {
-                                            Ok(Ok(data)) => Ok(data),
-                                            _ => {
-                                                Err(format!("Fetch failed after reconnect: {}", e))
-                                            }
-                                        }
-                                    }
-                                    Err(re_err) => {
-                                        error!("Watchdog: Reconnect failed: {}", re_err);
-                                        Err(format!("Disconnected: {}", e))
-                                    }
-                                }
-                            }
-                        },
-                        Err(_) => {
-                            error!("Watchdog: UPS data fetch timed out (lock held too long?)");
-                            // Force disconnect or just error out?
-                            // If we time out, the socket might be stuck.
-                            // Ideally we should drop the connection.
-                            let _ = client.disconnect().await;
-                            Err("Timeout".to_string())
-                        }
-                    }
-                } else {
-                    Err("Not connected".to_string())
-                }
-            };
-
-            match data_result {
-                Ok(data) => {
-                    if let Err(e) = app.emit("ups-update", &data) {
-                        error!("Failed to emit ups-update: {e}");
-                    }
-
-                    // --- Shutdown Logic ---
-                    if shutdown_config.enabled {
-                        let mut sd_guard = shutdown_arc.lock().await;
-
-                        let bat_charge = data.battery_charge.unwrap_or(100.0);
-                        let bat_critical = bat_charge < shutdown_config.battery_threshold as f64;
-
-                        let runtime_val = data.battery_runtime.unwrap_or(f64::MAX);
-                        let runtime_critical =
-                            runtime_val < shutdown_config.runtime_threshold as f64;
-
-                        if bat_critical || runtime_critical {
-                            if !sd_guard.pending {
-                                info!(
-                                    "Shutdown Triggered! Battery: {}%, Runtime: {}s",
-                                    bat_charge, runtime_val
-                                );
-                                sd_guard.pending = true;
-                                sd_guard.countdown_remaining = shutdown_config.timer_sec;
-                                sd_guard.action_type = shutdown_config.stop_type.clone();
-                            }
-
-                            // Emit warning
-                            let _ = app.emit("shutdown-warning", sd_guard.countdown_remaining);
-
-                            if sd_guard.countdown_remaining == 0 {
-                                info!(
-                                    "Countdown reached 0. Executing system stop: {}",
-                                    sd_guard.action_type
-                                );
-                                // Execute
-                                let action = sd_guard.action_type.clone();
-                                tokio::spawn(async move {
-                                    if let Err(e) = trigger_system_stop(action, 0).await {
-                                        error!("CRITICAL: Failed to execute system stop: {}", e);
-                                    }
-                                });
-                                sd_guard.pending = false;
-                            } else {
-                                sd_guard.countdown_remaining = sd_guard
-                                    .countdown_remaining
-                                    .saturating_sub(interval_ms / 1000);
-                            }
-                        } else {
-                            // Conditions met (Power restored or charged enough)
-                            if sd_guard.pending {
-                                info!("Power conditions restored. Shutdown cancelled.");
-                                sd_guard.pending = false;
-                                let _ = app.emit("shutdown-cancelled", ());
-                            }
-                        }
-                    }
-
-                    // Log to DB Logic
-                    // "The Digital Observer" Strategy:
-                    // 1. Status Change: Log always.
-                    // 2. Volatility Velocity: Log if input voltage changes fast (> 0.5V/s).
-                    // 3. Load/Battery: Log if change > 5% / 2%.
-                    // 4. Heartbeat: Log every 10 minutes (600s) to keep the chart alive during stability.
-
-                    let current_status = data.status.clone();
-                    let mut significant_event = false;
-
-                    if let Some(last) = last_logged_data.as_ref() {
-                        if last.status != current_status {
-                            significant_event = true;
-                        } else {
-                            // Calculate Volatility Velocity
-                            // How much did it change per second?
-                            let time_delta = last_log_time.elapsed().as_secs_f64().max(1.0);
-
-                            let v_now = data.input_voltage.unwrap_or(0.0);
-                            let v_last = last.input_voltage.unwrap_or(0.0);
-                            let v_velocity = (v_now - v_last).abs() / time_delta;
-
-                            // 0.5V per second is a "spike" or "sag" even if small amplitude
-                            if v_velocity > 0.5 {
-                                significant_event = true;
-                            }
-
-                            // Load Change > 5% (Significant load switch)
-                            let l_diff =
-                                (data.ups_load.unwrap_or(0.0) - last.ups_load.unwrap_or(0.0)).abs();
-                            if l_diff > 5.0 {
-                                significant_event = true;
-                            }
-
-                            // Battery Change > 2% (Charging/Discharging)
-                            let b_diff = (data.battery_charge.unwrap_or(0.0)
-                                - last.battery_charge.unwrap_or(0.0))
-                            .abs();
-                            if b_diff > 2.0 {
-                                significant_event = true;
-                            }
-                        }
-                    } else {
-                        significant_event = true; // First run
-                    }
-
-                    let should_log =
-                        first_run || last_log_time.elapsed().as_secs() >= 600 || significant_event;
-
-                    if should_log {
-                        let db_guard = db_arc.lock().await;
-                        if let Some(db) = db_guard.as_ref() {
-                            let entry = crate::db::HistoryEntry {
-                                id: None,
-                                timestamp: std::time::SystemTime::now()
-                                    .duration_since(std::time::UNIX_EPOCH)
-                                    .unwrap()
-                                    .as_secs(),
-                                input_voltage: data.input_voltage,
-                                output_voltage: data.output_voltage,
-                                load_percent: data.ups_load,
-                                battery_charge: data.battery_charge,
-                                status: data.status.clone(),
-                            };
-                            if let Err(e) = db.insert_entry(&entry) {
-                                error!("Failed to log history: {}", e);
-                            } else {
-                                last_log_time = std::time::Instant::now();
-                                last_logged_data = Some(data.clone());
-
-                                // Prune once a day (approx check)
-                                if first_run {
-                                    if let Err(e) = db.prune_old_data(365) {
-                                        error!("Failed to prune old data: {}", e);
-                                    }
-                                }
-                                first_run = false;
-                            }
-                        }
-                    }
-
-                    if let Some(tray) = app.tray_by_id("main") {
-                        let icon = if data.status.contains("LB") {
-                            create_status_icon(239, 68, 68) // Red
-                        } else if data.status.contains("OB") {
-                            create_status_icon(249, 115, 22) // Orange
-                        } else {
-                            create_status_icon(34, 197, 94) // Green
-                        };
-                        let _ = tray.set_icon(Some(icon));
-                    }
-                }
-                Err(_e) => {
-                    // Update frontend state ?
-                }
-            }
-        }
-    });
-
+    spawn_poller(app, ups_name, interval_ms, shutdown_config).await;
     Ok(())
 }
 
-#[cfg(target_os = "windows")]
-fn acquire_shutdown_privilege() -> Result<(), String> {
-    use windows::Win32::Foundation::{FALSE, HANDLE, LUID};
-    use windows::Win32::Security::{
-        AdjustTokenPrivileges, LookupPrivilegeValueW, LUID_AND_ATTRIBUTES, SE_PRIVILEGE_ENABLED,
-        SE_SHUTDOWN_NAME, TOKEN_ADJUST_PRIVILEGES, TOKEN_PRIVILEGES, TOKEN_QUERY,
-    };
-    use windows::Win32::System::Threading::{GetCurrentProcess, OpenProcessToken};
-
-    unsafe {
-        let mut token: HANDLE = HANDLE::default();
-        if OpenProcessToken(
-            GetCurrentProcess(),
-            TOKEN_ADJUST_PRIVILEGES | TOKEN_QUERY,
-            &mut token,
-        )
-        .is_err()
-        {
-            return Err("Failed to open process token".to_string());
-        }
+/// Fails while the settings file on disk could not be loaded, so the UI shows why
+/// instead of treating the defaults in memory as the user's settings.
+#[tauri::command]
+pub async fn get_settings(settings_state: State<'_, SettingsState>) -> Result<AppSettings, String> {
+    let store = settings_state.0.lock().await;
+    if let Some(e) = store.load_error() {
+        return Err(format!(
+            "{} could not be loaded: {e}. Fix the file and restart, or save new settings, \
+             which moves it to settings.json.bak",
+            store.path().display()
+        ));
+    }
+    Ok(store.get().clone())
+}
 
-        let mut luid = LUID::default();
-        if LookupPrivilegeValueW(None, SE_SHUTDOWN_NAME, &mut luid).is_err() {
-            return Err("Failed to lookup privilege".to_string());
+/// `password`, `smtp_password`, `webhook_secrets` (by webhook name) and
+/// `telegram_token` are only sent when the user typed a new one: they go straight
+/// into the credential store and the settings keep just the id. An empty string
+/// forgets it.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn update_settings(
+    app: AppHandle,
+    settings_state: State<'_, SettingsState>,
+    credentials: State<'_, CredentialState>,
+    mut settings: AppSettings,
+    password: Option<String>,
+    smtp_password: Option<String>,
+    webhook_secrets: Option<HashMap<String, String>>,
+    telegram_token: Option<String>,
+) -> Result<AppSettings, String> {
+    if let Some(password) = password {
+        let conn = &mut settings.connection;
+        let id = nut_credential_id(
+            &conn.host,
+            conn.port,
+            conn.username.as_deref().unwrap_or_default(),
+        );
+        if password.is_empty() {
+            credentials.0.delete(&id).map_err(|e| e.to_string())?;
+            conn.credential_id = None;
+        } else {
+            credentials
+                .0
+                .set(&id, &password)
+                .map_err(|e| e.to_string())?;
+            conn.credential_id = Some(id);
         }
-
-        let tp = TOKEN_PRIVILEGES {
-            PrivilegeCount: 1,
-            Privileges: [LUID_AND_ATTRIBUTES {
-                Luid: luid,
-                Attributes: SE_PRIVILEGE_ENABLED,
-            }],
+    }
+    if let Some(password) = smtp_password {
+        let email = &mut settings.notifications.email;
+        let id = smtp_credential_id(
+            &email.host,
+            email.port,
+            email.username.as_deref().unwrap_or_default(),
+        );
+        if password.is_empty() {
+            credentials.0.delete(&id).map_err(|e| e.to_string())?;
+            email.credential_id = None;
+        } else {
+            credentials
+                .0
+                .set(&id, &password)
+                .map_err(|e| e.to_string())?;
+            email.credential_id = Some(id);
+        }
+    }
+    for (name, secret) in webhook_secrets.unwrap_or_default() {
+        let Some(webhook) = settings
+            .notifications
+            .webhooks
+            .iter_mut()
+            .find(|w| w.name == name)
+        else {
+            continue;
         };
+        let id = webhook_credential_id(&name);
+        if secret.is_empty() {
+            credentials.0.delete(&id).map_err(|e| e.to_string())?;
+            webhook.credential_id = None;
+        } else {
+            credentials.0.set(&id, &secret).map_err(|e| e.to_string())?;
+            webhook.credential_id = Some(id);
+        }
+    }
+    if let Some(token) = telegram_token {
+        let telegram = &mut settings.telegram;
+        if token.is_empty() {
+            credentials
+                .0
+                .delete(TELEGRAM_CREDENTIAL_ID)
+                .map_err(|e| e.to_string())?;
+            telegram.credential_id = None;
+        } else {
+            credentials
+                .0
+                .set(TELEGRAM_CREDENTIAL_ID, token.trim())
+                .map_err(|e| e.to_string())?;
+            telegram.credential_id = Some(TELEGRAM_CREDENTIAL_ID.to_string());
+        }
+    }
 
-        if AdjustTokenPrivileges(token, FALSE, Some(&tp), 0, None, None).is_err() {
-            return Err("Failed to adjust token privileges".to_string());
+    let saved = {
+        let mut store = settings_state.0.lock().await;
+        store.update(settings).map_err(|e| e.to_string())?;
+        store.get().clone()
+    };
+    info!("Settings updated, restarting monitoring");
+
+    start_monitoring(&app, &saved).await?;
+    Ok(saved)
+}
+
+/// Called once at launch: picks up the persisted settings and resumes monitoring
+/// and shutdown protection without waiting for the UI.
+pub async fn resume_monitoring(app: AppHandle) {
+    let settings = {
+        let settings_state = app.state::<SettingsState>();
+        let store = settings_state.0.lock().await;
+        if let Some(e) = store.load_error() {
+            warn!(
+                "Not resuming monitoring, settings could not be loaded: {}",
+                e
+            );
+            return;
         }
+        store.get().clone()
+    };
+
+    if !settings.connection.is_configured() {
+        info!("No saved connection, waiting for configuration");
+        return;
     }
-    Ok(())
+
+    if let Err(e) = start_monitoring(&app, &settings).await {
+        warn!("Resume monitoring: {}", e);
+    }
+}
+
+/// Connects with the given settings and (re)starts the poll loop.
+/// The poller is started even if the first connect fails so the watchdog keeps retrying.
+async fn start_monitoring(app: &AppHandle, settings: &AppSettings) -> Result<(), String> {
+    stop_poller(app).await;
+
+    let nut_state = app.state::<NutState>();
+    let previous = nut_state.0.lock().await.take();
+    if let Some(mut old) = previous {
+        let _ = old.disconnect().await;
+    }
+
+    if !settings.connection.is_configured() {
+        return Ok(());
+    }
+
+    let conn = &settings.connection;
+    let password = app
+        .state::<CredentialState>()
+        .0
+        .resolve(conn.credential_id.as_deref());
+    let mut client = source::open(conn, password, &app.state::<SimulatorState>().0);
+    let connect_result = client.connect().await;
+    let endpoint = client.metadata().endpoint;
+    *nut_state.0.lock().await = Some(client);
+
+    spawn_poller(
+        app.clone(),
+        conn.ups_name.clone(),
+        settings.poll_interval_ms,
+        settings.shutdown.clone(),
+    )
+    .await;
+
+    connect_result.map_err(|e| format!("Connection to {endpoint} failed: {e}"))
+}
+
+async fn stop_poller(app: &AppHandle) {
+    let poller_state = app.state::<PollerState>();
+    let previous = poller_state.0.lock().await.take();
+    if let Some(handle) = previous {
+        handle.abort();
+    }
+}
+
+/// (Re)starts the poll loop on the saved settings; both ways of starting monitoring
+/// go through here so they agree on whether an expired countdown stops the machine.
+async fn spawn_poller(
+    app: AppHandle,
+    ups_name: String,
+    interval_ms: u64,
+    shutdown_config: ShutdownConfig,
+) {
+    let poller_arc = app.state::<PollerState>().0.clone();
+    let mut poller_guard = poller_arc.lock().await;
+    if let Some(previous) = poller_guard.take() {
+        previous.abort();
+    }
+
+    let settings = {
+        let store = app.state::<SettingsState>();
+        let store = store.0.lock().await;
+        store.get().clone()
+    };
+    let mut monitor = app
+        .state::<MonitorState>()
+        .0
+        .clone()
+        .with_load_shedding(settings.load_shedding)
+        .with_self_test(settings.self_test)
+        .with_alerts(settings.alerts);
+    let mut dispatcher = notifier(&app, &settings.notifications);
+    let token = app
+        .state::<CredentialState>()
+        .0
+        .resolve(settings.telegram.credential_id.as_deref());
+    if let Some((bot, channel)) = telegram::from_settings(&settings.telegram, token) {
+        dispatcher = dispatcher.with_channel(Arc::new(channel));
+        monitor = monitor.with_telegram(bot);
+    }
+    monitor = monitor.with_notifier(dispatcher);
+    // A simulated outage runs the real countdown but must never power off the machine
+    if settings.connection.backend == Backend::Simulator {
+        monitor = monitor.without_system_stop();
+    }
+    *poller_guard = Some(monitor.spawn(ups_name, interval_ms, shutdown_config));
+}
+
+/// Notification channels for the poller, including native notifications.
+fn notifier(app: &AppHandle, config: &NotificationsConfig) -> Dispatcher {
+    let credentials = app.state::<CredentialState>();
+    let data_dir = app.path().app_data_dir().unwrap_or_default();
+    let dispatcher =
+        Dispatcher::from_settings(config, &data_dir, |id| credentials.0.resolve(Some(id)));
+    let desktop = &config.desktop;
+    if !desktop.enabled {
+        return dispatcher;
+    }
+    dispatcher.with_channel(Arc::new(Throttled::new(
+        Arc::new(DesktopChannel::new(app.clone())),
+        desktop.events.clone(),
+        std::time::Duration::from_secs(desktop.min_interval_sec),
+    )))
 }
 
+/// Drives the virtual UPS, e.g. to cut mains power during a demo.
 #[tauri::command]
-pub async fn trigger_system_stop(action_type: String, delay_sec: u64) -> Result<(), String> {
-    #[cfg(target_os = "windows")]
-    {
-        use windows::core::{HSTRING, PCWSTR};
-        use windows::Win32::Foundation::{BOOLEAN, FALSE, TRUE};
-        use windows::Win32::System::Power::SetSuspendState;
-        use windows::Win32::System::Shutdown::{
-            InitiateSystemShutdownExW, SHTDN_REASON_FLAG_PLANNED, SHTDN_REASON_MAJOR_OTHER,
-            SHTDN_REASON_MINOR_OTHER,
-        };
+pub fn simulate_power_event(state: State<'_, SimulatorState>, event: SimEvent) {
+    info!("Simulation event: {:?}", event);
+    state
+        .0
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .apply(event, std::time::Instant::now());
+}
 
-        // Try to acquire permissions first
-        if let Err(e) = acquire_shutdown_privilege() {
-            eprintln!("Warning: Could not acquire shutdown privilege: {}", e);
-        }
+/// Relays monitor events to the webview and the tray icon for the lifetime of the app.
+pub async fn forward_monitor_events(app: AppHandle) {
+    let mut events = app.state::<MonitorState>().0.subscribe();
 
-        match action_type.as_str() {
-            "Shutdown" => unsafe {
-                let msg = HSTRING::from("UPS Shutdown Triggered");
-                let reason =
-                    SHTDN_REASON_MAJOR_OTHER | SHTDN_REASON_MINOR_OTHER | SHTDN_REASON_FLAG_PLANNED;
-
-                // InitiateSystemShutdownExW(machine, message, timeout, force_apps, reboot, reason)
-                if InitiateSystemShutdownExW(
-                    None,
-                    PCWSTR::from_raw(msg.as_ptr()),
-                    delay_sec as u32,
-                    TRUE,  // Force apps closed
-                    FALSE, // Reboot? No, Shutdown
-                    reason,
-                )
-                .is_err()
-                {
-                    return Err("Failed to initiate system shutdown".to_string());
-                }
-                Ok(())
-            },
-            "Hibernate" => unsafe {
-                // SetSuspendState uses BOOLEAN (u8), not BOOL (i32). TRUE/FALSE are BOOL.
-                if SetSuspendState(BOOLEAN(1), BOOLEAN(0), BOOLEAN(0)).as_bool() {
-                    Ok(())
-                } else {
-                    Err("Failed to trigger Hibernate".to_string())
+    loop {
+        let event = match events.recv().await {
+            Ok(event) => event,
+            Err(tokio::sync::broadcast::error::RecvError::Lagged(skipped)) => {
+                warn!("UI event relay lagged, skipped {} events", skipped);
+                continue;
+            }
+            Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+        };
+
+        match event {
+            MonitorEvent::Update(data) => {
+                if let Err(e) = app.emit("ups-update", &data) {
+                    error!("Failed to emit ups-update: {e}");
                 }
-            },
-            "Sleep" => unsafe {
-                // Hibernate = FALSE means Sleep
-                if SetSuspendState(BOOLEAN(0), BOOLEAN(0), BOOLEAN(0)).as_bool() {
-                    Ok(())
-                } else {
-                    Err("Failed to trigger Sleep".to_string())
+
+                if let Some(tray) = app.tray_by_id("main") {
+                    let icon = if data.status.contains("LB") {
+                        create_status_icon(239, 68, 68) // Red
+                    } else if data.status.contains("OB") {
+                        create_status_icon(249, 115, 22) // Orange
+                    } else {
+                        create_status_icon(34, 197, 94) // Green
+                    };
+                    let _ = tray.set_icon(Some(icon));
                 }
-            },
-            _ => Err("Invalid action type".to_string()),
+            }
+            MonitorEvent::ShutdownWarning(remaining) => {
+                let _ = app.emit("shutdown-warning", remaining);
+            }
+            MonitorEvent::ShutdownCancelled => {
+                let _ = app.emit("shutdown-cancelled", ());
+            }
+            MonitorEvent::LoadShedding { step, outcome } => {
+                let _ = app.emit(
+                    "load-shedding",
+                    serde_json::json!({ "step": step, "outcome": outcome }),
+                );
+            }
+            MonitorEvent::SelfTest(message) => {
+                let _ = app.emit("self-test", message);
+            }
+            MonitorEvent::Alert(event) => {
+                let _ = app.emit("alert", event);
+            }
+            MonitorEvent::ShutdownExecuted(_)
+            | MonitorEvent::ConnectionLost(_)
+            | MonitorEvent::ConnectionRestored => {}
         }
     }
+}
 
-    #[cfg(not(target_os = "windows"))]
-    {
-        Err("Offline actions only supported on Windows".to_string())
-    }
+#[tauri::command]
+pub async fn trigger_system_stop(action_type: String, delay_sec: u64) -> Result<(), String> {
+    shutdown::trigger_system_stop(action_type, delay_sec).await
 }
 
 #[tauri::command]
@@ -446,19 +460,11 @@ pub async fn abort_system_stop(shutdown_state: State<'_, ShutdownState>) -> Resu
         let mut guard = shutdown_state.0.lock().await;
         if guard.pending {
             info!("User requested shutdown abort.");
-            guard.pending = false;
+            guard.cancel();
         }
     }
 
-    #[cfg(target_os = "windows")]
-    {
-        use windows::Win32::System::Shutdown::AbortSystemShutdownW;
-
-        unsafe {
-            let _ = AbortSystemShutdownW(None);
-        }
-    }
-    Ok(())
+    shutdown::abort_system_stop().await
 }
 
 #[tauri::command]
@@ -510,7 +516,7 @@ pub async fn list_ups_on_server(host: String, port: u16) -> Result<Vec<String>,
         host: host.clone(),
         port,
         username: None,
-        password: None,
+        credential_id: None,
     });
 
     match client.connect().await {
@@ -533,7 +539,7 @@ pub async fn list_ups_commands(
 ) -> Result<Vec<String>, String> {
     let mut state_val = state.0.lock().await;
     if let Some(client) = state_val.as_mut() {
-        match client.list_ups_commands(&ups_name).await {
+        match client.list_commands(&ups_name).await {
             Ok(cmds) => Ok(cmds),
             Err(e) => Err(format!("Failed to list commands: {e}")),
         }
@@ -550,7 +556,7 @@ pub async fn run_ups_command(
 ) -> Result<(), String> {
     let mut state_val = state.0.lock().await;
     if let Some(client) = state_val.as_mut() {
-        match client.run_instant_cmd(&ups_name, &command).await {
+        match client.run_command(&ups_name, &command).await {
             Ok(_) => Ok(()),
             Err(e) => Err(format!("Command failed: {e}")),
         }
@@ -559,12 +565,70 @@ pub async fn run_ups_command(
     }
 }
 
+/// Checks an outlet switch against the device and holds it until `confirm_outlet_switch`.
 #[tauri::command]
-pub async fn get_chart_data(
+pub async fn prepare_outlet_switch(
+    state: State<'_, NutState>,
+    outlet_state: State<'_, OutletState>,
+    ups_name: String,
+    index: u32,
+    action: OutletAction,
+) -> Result<OutletSwitch, String> {
+    let mut state_val = state.0.lock().await;
+    let Some(client) = state_val.as_mut() else {
+        return Err("Not connected".to_string());
+    };
+    let switch = outlets::prepare(client.as_mut(), &ups_name, index, action)
+        .await
+        .map_err(|e| e.to_string())?;
+    outlet_state
+        .0
+        .lock()
+        .await
+        .insert(switch.clone(), std::time::Instant::now());
+    Ok(switch)
+}
+
+#[tauri::command]
+pub async fn confirm_outlet_switch(
+    state: State<'_, NutState>,
+    outlet_state: State<'_, OutletState>,
+    db_state: State<'_, DbState>,
+    token: String,
+) -> Result<CommandOutcome, String> {
+    let switch = outlet_state
+        .0
+        .lock()
+        .await
+        .take(&token, std::time::Instant::now())
+        .map_err(|e| e.to_string())?;
+    let mut state_val = state.0.lock().await;
+    let Some(client) = state_val.as_mut() else {
+        return Err("Not connected".to_string());
+    };
+    let db = db_state.0.lock().await;
+    outlets::execute(client.as_mut(), db.as_ref(), &switch)
+        .await
+        .map_err(|e| format!("Outlet switch failed: {e}"))
+}
+
+#[tauri::command]
+pub async fn get_journal(
     db_state: State<'_, DbState>,
     time_range: String,
-) -> Result<Vec<crate::db::HistoryEntry>, String> {
-    let hours = match time_range.as_str() {
+) -> Result<Vec<crate::db::JournalEntry>, String> {
+    let hours = range_hours(&time_range);
+    let guard = db_state.0.lock().await;
+    if let Some(db) = guard.as_ref() {
+        db.get_journal(hours).map_err(|e| e.to_string())
+    } else {
+        Err("Database not initialized".to_string())
+    }
+}
+
+/// Hours covered by a UI time range such as `24h` or `7d`.
+fn range_hours(time_range: &str) -> u64 {
+    match time_range {
         "1y" => 24 * 365,
         "30d" => 24 * 30,
         "7d" => 24 * 7,
@@ -573,7 +637,15 @@ pub async fn get_chart_data(
         "6h" => 6,
         "1h" => 1,
         _ => 24,
-    };
+    }
+}
+
+#[tauri::command]
+pub async fn get_chart_data(
+    db_state: State<'_, DbState>,
+    time_range: String,
+) -> Result<Vec<crate::db::HistoryEntry>, String> {
+    let hours = range_hours(&time_range);
 
     let guard = db_state.0.lock().await;
     if let Some(db) = guard.as_ref() {
@@ -588,16 +660,7 @@ pub async fn get_history_stats(
     db_state: State<'_, DbState>,
     time_range: String,
 ) -> Result<crate::db::HistoryStats, String> {
-    let hours = match time_range.as_str() {
-        "1y" => 24 * 365,
-        "30d" => 24 * 30,
-        "7d" => 24 * 7,
-        "24h" => 24,
-        "12h" => 12,
-        "6h" => 6,
-        "1h" => 1,
-        _ => 24,
-    };
+    let hours = range_hours(&time_range);
 
     let guard = db_state.0.lock().await;
     if let Some(db) = guard.as_ref() {
@@ -607,6 +670,128 @@ pub async fn get_history_stats(
     }
 }
 
+/// Energy used per hour, day or month over a UI time range.
+#[tauri::command]
+pub async fn get_energy(
+    db_state: State<'_, DbState>,
+    period: crate::db::EnergyPeriod,
+    time_range: String,
+) -> Result<Vec<crate::db::EnergyBucket>, String> {
+    let hours = range_hours(&time_range);
+    let guard = db_state.0.lock().await;
+    if let Some(db) = guard.as_ref() {
+        db.get_energy(period, hours).map_err(|e| e.to_string())
+    } else {
+        Err("Database not initialized".to_string())
+    }
+}
+
+/// kWh, cost and CO2 per day, week or month under the configured tariff.
+#[tauri::command]
+pub async fn get_cost_report(
+    db_state: State<'_, DbState>,
+    settings_state: State<'_, SettingsState>,
+    period: ReportPeriod,
+    time_range: String,
+) -> Result<CostReport, String> {
+    let hours = range_hours(&time_range);
+    let tariff = settings_state.0.lock().await.get().tariff.clone();
+    let guard = db_state.0.lock().await;
+    if let Some(db) = guard.as_ref() {
+        cost::build(db, &tariff, period, hours).map_err(|e| e.to_string())
+    } else {
+        Err("Database not initialized".to_string())
+    }
+}
+
+/// Battery health from the last year of history and, when connected, the
+/// latest reading of `ups_name` (for `RB` and `battery.date`).
+#[tauri::command]
+pub async fn get_battery_health(
+    state: State<'_, NutState>,
+    db_state: State<'_, DbState>,
+    ups_name: String,
+) -> Result<BatteryHealth, String> {
+    let current = match state.0.lock().await.as_mut() {
+        Some(client) => client.fetch_data(&ups_name).await.ok(),
+        None => None,
+    };
+    let history = {
+        let guard = db_state.0.lock().await;
+        let db = guard.as_ref().ok_or("Database not initialized")?;
+        db.get_history(range_hours("1y"))
+            .map_err(|e| e.to_string())?
+    };
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    Ok(battery::assess(&history, current.as_ref(), now))
+}
+
+/// Sags, swells, frequency, TRIM/BOOST and transfers over a UI time range.
+#[tauri::command]
+pub async fn get_power_quality(
+    state: State<'_, NutState>,
+    db_state: State<'_, DbState>,
+    ups_name: String,
+    time_range: String,
+) -> Result<PowerQualityReport, String> {
+    let hours = range_hours(&time_range);
+    let current = match state.0.lock().await.as_mut() {
+        Some(client) => client.fetch_data(&ups_name).await.ok(),
+        None => None,
+    };
+    let (history, buckets) = {
+        let guard = db_state.0.lock().await;
+        let db = guard.as_ref().ok_or("Database not initialized")?;
+        (
+            db.get_history(hours).map_err(|e| e.to_string())?,
+            db.get_quality(hours).map_err(|e| e.to_string())?,
+        )
+    };
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    Ok(quality::build(
+        &history,
+        &buckets,
+        current.as_ref(),
+        &chrono::Local,
+        now.saturating_sub(hours * 3600),
+        now,
+    ))
+}
+
+/// Writes history, journal and energy reports to the Downloads folder and
+/// returns the paths of the files created.
+#[tauri::command]
+pub async fn export_data(
+    app: AppHandle,
+    db_state: State<'_, DbState>,
+    settings_state: State<'_, SettingsState>,
+    time_range: String,
+    format: ExportFormat,
+) -> Result<Vec<String>, String> {
+    let hours = range_hours(&time_range);
+    let tariff = settings_state.0.lock().await.get().tariff.clone();
+    let dir = app
+        .path()
+        .download_dir()
+        .or_else(|_| app.path().app_data_dir())
+        .map_err(|e| e.to_string())?;
+    let guard = db_state.0.lock().await;
+    let db = guard.as_ref().ok_or("Database not initialized")?;
+    let data = export::collect(db, &tariff, hours).map_err(|e| e.to_string())?;
+    let files = export::write(&data, format, &dir).map_err(|e| e.to_string())?;
+    info!("Exported {} file(s) to {}", files.len(), dir.display());
+    Ok(files
+        .iter()
+        .map(|p| p.to_string_lossy().to_string())
+        .collect())
+}
+
 #[tauri::command]
 pub async fn clean_history_data(db_state: State<'_, DbState>) -> Result<usize, String> {
     let guard = db_state.0.lock().await;
@@ -0,0 +1,356 @@
+//! Secrets (NUT, SMTP, webhook and bot credentials) are kept out of settings.json.
+//! They go to the OS keyring where there is one. Without it they fall back to
+//! `credentials.enc.json`, sealed with a key kept in `credentials.key` in the same
+//! directory. That fallback only keeps secrets out of plain sight (backups of the
+//! settings, a casual `cat`): anyone who can read the data directory can read the
+//! key and decrypt them, so it is obfuscation, not encryption at rest. Protect the
+//! directory itself.
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+const KEYRING_SERVICE: &str = "com.admin.ups-client";
+const FALLBACK_FILE: &str = "credentials.enc.json";
+const KEY_FILE: &str = "credentials.key";
+
+#[derive(Error, Debug)]
+pub enum CredentialError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Malformed credential file: {0}")]
+    Parse(#[from] serde_json::Error),
+    #[error("Credential could not be decrypted")]
+    Decrypt,
+}
+
+/// Builds the id a NUT login is stored under, e.g. `nut/monuser@192.168.1.10:3493`.
+pub fn nut_credential_id(host: &str, port: u16, username: &str) -> String {
+    format!("nut/{username}@{host}:{port}")
+}
+
+/// Builds the id an SMTP login is stored under, e.g. `smtp/alerts@mail.example.com:587`.
+pub fn smtp_credential_id(host: &str, port: u16, username: &str) -> String {
+    format!("smtp/{username}@{host}:{port}")
+}
+
+/// Builds the id a webhook's secret is stored under, e.g. `webhook/On-call Slack`.
+pub fn webhook_credential_id(name: &str) -> String {
+    format!("webhook/{name}")
+}
+
+/// Id the Telegram bot token is stored under.
+pub const TELEGRAM_CREDENTIAL_ID: &str = "telegram/bot";
+
+#[derive(Serialize, Deserialize, Default)]
+struct SealedFile {
+    entries: HashMap<String, SealedSecret>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SealedSecret {
+    nonce: String,
+    ciphertext: String,
+}
+
+/// Stores secrets in the OS keyring (Secret Service, Credential Manager, Keychain).
+/// When no keyring is reachable, e.g. a headless box without a Secret Service daemon,
+/// secrets go to a ChaCha20-Poly1305 sealed file whose key sits next to it with
+/// owner-only permissions; see the module docs for what that does not protect.
+pub struct CredentialStore {
+    fallback_path: PathBuf,
+    key_path: PathBuf,
+    // Serializes read-modify-write cycles on the fallback file
+    file_lock: std::sync::Mutex<()>,
+}
+
+impl CredentialStore {
+    pub fn new<P: AsRef<Path>>(app_data_dir: P) -> Self {
+        Self {
+            fallback_path: app_data_dir.as_ref().join(FALLBACK_FILE),
+            key_path: app_data_dir.as_ref().join(KEY_FILE),
+            file_lock: std::sync::Mutex::new(()),
+        }
+    }
+
+    pub fn set(&self, id: &str, secret: &str) -> Result<(), CredentialError> {
+        match off_runtime(|| keyring::Entry::new(KEYRING_SERVICE, id)?.set_password(secret)) {
+            Ok(()) => {
+                // Don't leave an older copy behind in the fallback file. The secret
+                // is saved either way, so this must not fail the save
+                if let Err(e) = self.remove_sealed(id) {
+                    log::warn!("Failed to remove the old copy of {}: {}", id, e);
+                }
+                Ok(())
+            }
+            Err(e) => {
+                log::warn!("Keyring unavailable ({}), using encrypted file", e);
+                self.store_sealed(id, secret)
+            }
+        }
+    }
+
+    pub fn get(&self, id: &str) -> Result<Option<String>, CredentialError> {
+        match off_runtime(|| keyring::Entry::new(KEYRING_SERVICE, id)?.get_password()) {
+            Ok(secret) => Ok(Some(secret)),
+            Err(keyring::Error::NoEntry) => self.load_sealed(id),
+            Err(e) => {
+                log::warn!("Keyring unavailable ({}), using encrypted file", e);
+                self.load_sealed(id)
+            }
+        }
+    }
+
+    pub fn delete(&self, id: &str) -> Result<(), CredentialError> {
+        match off_runtime(|| keyring::Entry::new(KEYRING_SERVICE, id)?.delete_credential()) {
+            Ok(()) | Err(keyring::Error::NoEntry) => {}
+            Err(e) => log::warn!("Failed to delete keyring entry {}: {}", id, e),
+        }
+        self.remove_sealed(id)
+    }
+
+    /// Looks up an optional credential id, logging instead of failing so a missing
+    /// secret degrades to an anonymous NUT session rather than no session at all.
+    pub fn resolve(&self, id: Option<&str>) -> Option<String> {
+        let id = id?;
+        match self.get(id) {
+            Ok(secret) => {
+                if secret.is_none() {
+                    log::warn!("No stored secret for credential {}", id);
+                }
+                secret
+            }
+            Err(e) => {
+                log::error!("Failed to read credential {}: {}", id, e);
+                None
+            }
+        }
+    }
+
+    fn cipher(&self) -> Result<ChaCha20Poly1305, CredentialError> {
+        let key = match std::fs::read(&self.key_path) {
+            Ok(bytes) if bytes.len() == 32 => Key::clone_from_slice(&bytes),
+            Ok(_) => return Err(CredentialError::Decrypt),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                let key = ChaCha20Poly1305::generate_key(&mut OsRng);
+                write_private(&self.key_path, key.as_slice())?;
+                key
+            }
+            Err(e) => return Err(e.into()),
+        };
+        Ok(ChaCha20Poly1305::new(&key))
+    }
+
+    fn read_sealed(&self) -> Result<SealedFile, CredentialError> {
+        match std::fs::read_to_string(&self.fallback_path) {
+            Ok(raw) => Ok(serde_json::from_str(&raw)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(SealedFile::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn write_sealed(&self, file: &SealedFile) -> Result<(), CredentialError> {
+        write_private(
+            &self.fallback_path,
+            serde_json::to_string_pretty(file)?.as_bytes(),
+        )?;
+        Ok(())
+    }
+
+    fn store_sealed(&self, id: &str, secret: &str) -> Result<(), CredentialError> {
+        let _guard = self.file_lock.lock().unwrap_or_else(|e| e.into_inner());
+        let cipher = self.cipher()?;
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = cipher
+            .encrypt(&nonce, secret.as_bytes())
+            .map_err(|_| CredentialError::Decrypt)?;
+
+        let mut file = self.read_sealed()?;
+        file.entries.insert(
+            id.to_string(),
+            SealedSecret {
+                nonce: BASE64.encode(nonce),
+                ciphertext: BASE64.encode(ciphertext),
+            },
+        );
+        self.write_sealed(&file)
+    }
+
+    fn load_sealed(&self, id: &str) -> Result<Option<String>, CredentialError> {
+        let _guard = self.file_lock.lock().unwrap_or_else(|e| e.into_inner());
+        let file = self.read_sealed()?;
+        let Some(sealed) = file.entries.get(id) else {
+            return Ok(None);
+        };
+
+        let nonce = BASE64
+            .decode(&sealed.nonce)
+            .map_err(|_| CredentialError::Decrypt)?;
+        let ciphertext = BASE64
+            .decode(&sealed.ciphertext)
+            .map_err(|_| CredentialError::Decrypt)?;
+        if nonce.len() != 12 {
+            return Err(CredentialError::Decrypt);
+        }
+        let plaintext = self
+            .cipher()?
+            .decrypt(Nonce::from_slice(&nonce), ciphertext.as_ref())
+            .map_err(|_| CredentialError::Decrypt)?;
+        String::from_utf8(plaintext)
+            .map(Some)
+            .map_err(|_| CredentialError::Decrypt)
+    }
+
+    fn remove_sealed(&self, id: &str) -> Result<(), CredentialError> {
+        let _guard = self.file_lock.lock().unwrap_or_else(|e| e.into_inner());
+        let mut file = self.read_sealed()?;
+        if file.entries.remove(id).is_some() {
+            self.write_sealed(&file)?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs a keyring call on a thread of its own when called from async code. The
+/// Secret Service backend blocks on a private tokio runtime, and starting one from
+/// inside another panics; all callers of `CredentialStore` are async.
+fn off_runtime<T: Send>(call: impl FnOnce() -> T + Send) -> T {
+    if tokio::runtime::Handle::try_current().is_err() {
+        return call();
+    }
+    std::thread::scope(|scope| scope.spawn(call).join())
+        .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+}
+
+/// Writes a file readable only by the current user (on Unix; Windows relies on the
+/// per-user app data directory ACLs).
+fn write_private(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    use std::io::Write;
+    options.open(path)?.write_all(contents)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seals_round_trips_and_deletes_secrets() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = CredentialStore::new(dir.path());
+        store.store_sealed("nut/a@h:3493", "hunter2").unwrap();
+        store.store_sealed("smtp/b@h:587", "swordfish").unwrap();
+
+        assert_eq!(
+            store.load_sealed("nut/a@h:3493").unwrap().as_deref(),
+            Some("hunter2")
+        );
+        assert_eq!(
+            store.load_sealed("smtp/b@h:587").unwrap().as_deref(),
+            Some("swordfish")
+        );
+        assert_eq!(store.load_sealed("nut/other@h:3493").unwrap(), None);
+        let raw = std::fs::read_to_string(dir.path().join(FALLBACK_FILE)).unwrap();
+        assert!(!raw.contains("hunter2"));
+        assert_eq!(std::fs::read(dir.path().join(KEY_FILE)).unwrap().len(), 32);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            for file in [FALLBACK_FILE, KEY_FILE] {
+                let mode = std::fs::metadata(dir.path().join(file))
+                    .unwrap()
+                    .permissions()
+                    .mode();
+                assert_eq!(mode & 0o777, 0o600, "{file}");
+            }
+        }
+
+        // Overwriting keeps one entry; removing leaves the others
+        store.store_sealed("nut/a@h:3493", "correct horse").unwrap();
+        store.remove_sealed("nut/a@h:3493").unwrap();
+        assert_eq!(store.load_sealed("nut/a@h:3493").unwrap(), None);
+        assert_eq!(
+            store.load_sealed("smtp/b@h:587").unwrap().as_deref(),
+            Some("swordfish")
+        );
+        store.remove_sealed("nut/a@h:3493").unwrap();
+    }
+
+    #[tokio::test]
+    async fn resolves_from_async_code() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = CredentialStore::new(dir.path());
+        // Only in the fallback file, so a real keyring is asked and comes up empty
+        let id = format!("test/{}", dir.path().display());
+        store.store_sealed(&id, "hunter2").unwrap();
+
+        assert_eq!(store.resolve(Some(&id)).as_deref(), Some("hunter2"));
+        assert_eq!(store.resolve(Some("test/never-stored")), None);
+        store.delete(&id).unwrap();
+        assert_eq!(store.resolve(Some(&id)), None);
+    }
+
+    #[test]
+    fn rejects_a_corrupt_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = CredentialStore::new(dir.path());
+        store.store_sealed("id", "secret").unwrap();
+        let path = dir.path().join(FALLBACK_FILE);
+
+        let mut file: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        file["entries"]["id"]["ciphertext"] = BASE64.encode(b"not what was sealed").into();
+        std::fs::write(&path, file.to_string()).unwrap();
+        assert!(matches!(
+            store.load_sealed("id"),
+            Err(CredentialError::Decrypt)
+        ));
+
+        std::fs::write(&path, "{\"entries\": {").unwrap();
+        assert!(matches!(
+            store.load_sealed("id"),
+            Err(CredentialError::Parse(_))
+        ));
+        assert!(matches!(
+            store.store_sealed("id", "new"),
+            Err(CredentialError::Parse(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_a_key_of_the_wrong_length_or_another_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = CredentialStore::new(dir.path());
+        store.store_sealed("id", "secret").unwrap();
+        let key_path = dir.path().join(KEY_FILE);
+
+        std::fs::write(&key_path, [7u8; 16]).unwrap();
+        assert!(matches!(
+            store.load_sealed("id"),
+            Err(CredentialError::Decrypt)
+        ));
+        assert!(matches!(
+            store.store_sealed("other", "x"),
+            Err(CredentialError::Decrypt)
+        ));
+
+        std::fs::write(&key_path, [7u8; 32]).unwrap();
+        assert!(matches!(
+            store.load_sealed("id"),
+            Err(CredentialError::Decrypt)
+        ));
+    }
+}
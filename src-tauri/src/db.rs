@@ -12,6 +12,84 @@ pub struct HistoryEntry {
     pub load_percent: Option<f64>,
     pub battery_charge: Option<f64>,
     pub status: String,
+    // Per-phase values, only filled for multi-phase units
+    #[serde(default)]
+    pub input_l1_voltage: Option<f64>,
+    #[serde(default)]
+    pub input_l2_voltage: Option<f64>,
+    #[serde(default)]
+    pub input_l3_voltage: Option<f64>,
+    #[serde(default)]
+    pub output_l1_load: Option<f64>,
+    #[serde(default)]
+    pub output_l2_load: Option<f64>,
+    #[serde(default)]
+    pub output_l3_load: Option<f64>,
+    /// s
+    #[serde(default)]
+    pub battery_runtime: Option<f64>,
+    /// V
+    #[serde(default)]
+    pub battery_voltage: Option<f64>,
+}
+
+/// Columns added after the first release; `init` adds any that an older
+/// database is missing.
+const ADDED_COLUMNS: &[(&str, &str)] = &[
+    ("input_l1_voltage", "REAL"),
+    ("input_l2_voltage", "REAL"),
+    ("input_l3_voltage", "REAL"),
+    ("output_l1_load", "REAL"),
+    ("output_l2_load", "REAL"),
+    ("output_l3_load", "REAL"),
+    ("battery_runtime", "REAL"),
+    ("battery_voltage", "REAL"),
+];
+
+/// A notable action or occurrence: outlet switches, load shedding, self-tests...
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub id: Option<u64>,
+    pub timestamp: u64,
+    /// Short area tag, e.g. `outlet`
+    pub category: String,
+    pub message: String,
+    /// Outcome or other detail, e.g. `succeeded`
+    pub detail: Option<String>,
+}
+
+/// Bucket size for energy totals.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EnergyPeriod {
+    Hour,
+    Day,
+    Month,
+}
+
+/// Energy used in one hour, day or month (local time).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EnergyBucket {
+    /// Unix time the bucket starts at
+    pub start: u64,
+    pub kwh: f64,
+}
+
+/// Time a metric spent in one bin, see `crate::reports::quality`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct QualityBucket {
+    pub metric: String,
+    pub bin: i64,
+    pub secs: f64,
+}
+
+/// A delivery waiting in the outbox, see `crate::notify::webhook`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutboxEntry {
+    pub id: u64,
+    pub payload: String,
+    /// Failed tries so far
+    pub attempts: u32,
 }
 
 pub struct NutDB {
@@ -40,21 +118,89 @@ impl NutDB {
             )",
             [],
         )?;
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS journal (
+                id INTEGER PRIMARY KEY,
+                timestamp INTEGER NOT NULL,
+                category TEXT NOT NULL,
+                message TEXT NOT NULL,
+                detail TEXT
+            )",
+            [],
+        )?;
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS energy (
+                hour INTEGER PRIMARY KEY,
+                wh REAL NOT NULL
+            )",
+            [],
+        )?;
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS quality (
+                hour INTEGER NOT NULL,
+                metric TEXT NOT NULL,
+                bin INTEGER NOT NULL,
+                secs REAL NOT NULL,
+                PRIMARY KEY (hour, metric, bin)
+            )",
+            [],
+        )?;
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS outbox (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                channel TEXT NOT NULL,
+                created INTEGER NOT NULL,
+                next_attempt INTEGER NOT NULL,
+                attempts INTEGER NOT NULL DEFAULT 0,
+                payload TEXT NOT NULL
+            )",
+            [],
+        )?;
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS shed_undo (
+                position INTEGER PRIMARY KEY,
+                rule TEXT NOT NULL,
+                command TEXT NOT NULL
+            )",
+            [],
+        )?;
+        let existing: Vec<String> = conn
+            .prepare("SELECT name FROM pragma_table_info('history')")?
+            .query_map([], |row| row.get(0))?
+            .collect::<Result<_>>()?;
+        for (column, kind) in ADDED_COLUMNS {
+            if !existing.iter().any(|name| name == column) {
+                conn.execute(
+                    &format!("ALTER TABLE history ADD COLUMN {column} {kind}"),
+                    [],
+                )?;
+            }
+        }
         Ok(())
     }
 
     pub fn insert_entry(&self, entry: &HistoryEntry) -> Result<()> {
         let conn = Connection::open(&self.path)?;
         conn.execute(
-            "INSERT INTO history (timestamp, input_voltage, output_voltage, load_percent, battery_charge, status)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            "INSERT INTO history (timestamp, input_voltage, output_voltage, load_percent, battery_charge, status,
+                input_l1_voltage, input_l2_voltage, input_l3_voltage, output_l1_load, output_l2_load, output_l3_load,
+                battery_runtime, battery_voltage)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
             params![
                 entry.timestamp,
                 entry.input_voltage,
                 entry.output_voltage,
                 entry.load_percent,
                 entry.battery_charge,
-                entry.status
+                entry.status,
+                entry.input_l1_voltage,
+                entry.input_l2_voltage,
+                entry.input_l3_voltage,
+                entry.output_l1_load,
+                entry.output_l2_load,
+                entry.output_l3_load,
+                entry.battery_runtime,
+                entry.battery_voltage
             ],
         )?;
         Ok(())
@@ -69,7 +215,9 @@ impl NutDB {
             - (time_range_hours * 3600);
 
         let mut stmt = conn.prepare(
-            "SELECT id, timestamp, input_voltage, output_voltage, load_percent, battery_charge, status
+            "SELECT id, timestamp, input_voltage, output_voltage, load_percent, battery_charge, status,
+                input_l1_voltage, input_l2_voltage, input_l3_voltage, output_l1_load, output_l2_load, output_l3_load,
+                battery_runtime, battery_voltage
              FROM history
              WHERE timestamp >= ?1
              ORDER BY timestamp ASC",
@@ -84,6 +232,14 @@ impl NutDB {
                 load_percent: row.get(4)?,
                 battery_charge: row.get(5)?,
                 status: row.get(6)?,
+                input_l1_voltage: row.get(7)?,
+                input_l2_voltage: row.get(8)?,
+                input_l3_voltage: row.get(9)?,
+                output_l1_load: row.get(10)?,
+                output_l2_load: row.get(11)?,
+                output_l3_load: row.get(12)?,
+                battery_runtime: row.get(13)?,
+                battery_voltage: row.get(14)?,
             })
         })?;
 
@@ -94,6 +250,219 @@ impl NutDB {
         Ok(entries)
     }
 
+    pub fn add_journal_entry(
+        &self,
+        category: &str,
+        message: &str,
+        detail: Option<&str>,
+    ) -> Result<()> {
+        let conn = Connection::open(&self.path)?;
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        conn.execute(
+            "INSERT INTO journal (timestamp, category, message, detail) VALUES (?1, ?2, ?3, ?4)",
+            params![now, category, message, detail],
+        )?;
+        Ok(())
+    }
+
+    /// Journal entries of the last `time_range_hours`, newest first.
+    pub fn get_journal(&self, time_range_hours: u64) -> Result<Vec<JournalEntry>> {
+        let conn = Connection::open(&self.path)?;
+        let start_time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+            - (time_range_hours * 3600);
+
+        let mut stmt = conn.prepare(
+            "SELECT id, timestamp, category, message, detail
+             FROM journal
+             WHERE timestamp >= ?1
+             ORDER BY timestamp DESC, id DESC",
+        )?;
+        let rows = stmt.query_map(params![start_time], |row| {
+            Ok(JournalEntry {
+                id: row.get(0)?,
+                timestamp: row.get(1)?,
+                category: row.get(2)?,
+                message: row.get(3)?,
+                detail: row.get(4)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    /// Unix time of the newest journal entry in `category` whose message matches
+    /// the SQL `LIKE` pattern, however old.
+    pub fn last_journal_time(&self, category: &str, message_like: &str) -> Result<Option<u64>> {
+        let conn = Connection::open(&self.path)?;
+        conn.query_row(
+            "SELECT MAX(timestamp) FROM journal WHERE category = ?1 AND message LIKE ?2",
+            params![category, message_like],
+            |row| row.get(0),
+        )
+    }
+
+    /// Adds `wh` to the total of the hour starting at `hour` (unix time).
+    pub fn add_energy(&self, hour: u64, wh: f64) -> Result<()> {
+        let conn = Connection::open(&self.path)?;
+        conn.execute(
+            "INSERT INTO energy (hour, wh) VALUES (?1, ?2)
+             ON CONFLICT(hour) DO UPDATE SET wh = wh + excluded.wh",
+            params![hour, wh],
+        )?;
+        Ok(())
+    }
+
+    /// Energy per hour, day or month over the last `time_range_hours`, oldest first.
+    pub fn get_energy(
+        &self,
+        period: EnergyPeriod,
+        time_range_hours: u64,
+    ) -> Result<Vec<EnergyBucket>> {
+        let conn = Connection::open(&self.path)?;
+        let start_time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+            - (time_range_hours * 3600);
+
+        // Bucket start as a unix time; days and months follow the local calendar
+        let bucket = match period {
+            EnergyPeriod::Hour => "hour",
+            EnergyPeriod::Day => {
+                "CAST(strftime('%s', date(hour, 'unixepoch', 'localtime'), 'utc') AS INTEGER)"
+            }
+            EnergyPeriod::Month => {
+                "CAST(strftime('%s', date(hour, 'unixepoch', 'localtime', 'start of month'), 'utc') AS INTEGER)"
+            }
+        };
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {bucket} AS start, SUM(wh) / 1000.0
+             FROM energy
+             WHERE hour >= ?1
+             GROUP BY start
+             ORDER BY start ASC"
+        ))?;
+        let rows = stmt.query_map(params![start_time / 3600 * 3600], |row| {
+            Ok(EnergyBucket {
+                start: row.get(0)?,
+                kwh: row.get(1)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    /// Adds `secs` to the time a metric spent in `bin` during `hour`.
+    pub fn add_quality(&self, hour: u64, metric: &str, bin: i64, secs: f64) -> Result<()> {
+        let conn = Connection::open(&self.path)?;
+        conn.execute(
+            "INSERT INTO quality (hour, metric, bin, secs) VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT(hour, metric, bin) DO UPDATE SET secs = secs + excluded.secs",
+            params![hour, metric, bin, secs],
+        )?;
+        Ok(())
+    }
+
+    /// Time spent in each bin of each metric over the last `time_range_hours`.
+    pub fn get_quality(&self, time_range_hours: u64) -> Result<Vec<QualityBucket>> {
+        let conn = Connection::open(&self.path)?;
+        let start_time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+            - (time_range_hours * 3600);
+
+        let mut stmt = conn.prepare(
+            "SELECT metric, bin, SUM(secs)
+             FROM quality
+             WHERE hour >= ?1
+             GROUP BY metric, bin
+             ORDER BY metric, bin",
+        )?;
+        let rows = stmt.query_map(params![start_time / 3600 * 3600], |row| {
+            Ok(QualityBucket {
+                metric: row.get(0)?,
+                bin: row.get(1)?,
+                secs: row.get(2)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    /// Queues `payload` for `channel`, due right away.
+    pub fn enqueue_outbox(&self, channel: &str, payload: &str) -> Result<u64> {
+        let conn = Connection::open(&self.path)?;
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        conn.execute(
+            "INSERT INTO outbox (channel, created, next_attempt, payload) VALUES (?1, ?2, ?2, ?3)",
+            params![channel, now, payload],
+        )?;
+        Ok(conn.last_insert_rowid() as u64)
+    }
+
+    /// Entries of `channel` due at `now` (unix time), oldest first.
+    pub fn due_outbox(&self, channel: &str, now: u64) -> Result<Vec<OutboxEntry>> {
+        let conn = Connection::open(&self.path)?;
+        let mut stmt = conn.prepare(
+            "SELECT id, payload, attempts FROM outbox
+             WHERE channel = ?1 AND next_attempt <= ?2
+             ORDER BY id",
+        )?;
+        let rows = stmt.query_map(params![channel, now], |row| {
+            Ok(OutboxEntry {
+                id: row.get(0)?,
+                payload: row.get(1)?,
+                attempts: row.get(2)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    /// Counts a failed try and puts the entry off until `next_attempt`.
+    pub fn retry_outbox(&self, id: u64, next_attempt: u64) -> Result<()> {
+        let conn = Connection::open(&self.path)?;
+        conn.execute(
+            "UPDATE outbox SET attempts = attempts + 1, next_attempt = ?2 WHERE id = ?1",
+            params![id, next_attempt],
+        )?;
+        Ok(())
+    }
+
+    pub fn remove_outbox(&self, id: u64) -> Result<()> {
+        let conn = Connection::open(&self.path)?;
+        conn.execute("DELETE FROM outbox WHERE id = ?1", params![id])?;
+        Ok(())
+    }
+
+    /// Replaces the commands that undo the load shed so far, as `(rule, command)`
+    /// in the order they were shed.
+    pub fn set_pending_restore(&self, steps: &[(String, String)]) -> Result<()> {
+        let mut conn = Connection::open(&self.path)?;
+        let tx = conn.transaction()?;
+        tx.execute("DELETE FROM shed_undo", [])?;
+        for (position, (rule, command)) in steps.iter().enumerate() {
+            tx.execute(
+                "INSERT INTO shed_undo (position, rule, command) VALUES (?1, ?2, ?3)",
+                params![position, rule, command],
+            )?;
+        }
+        tx.commit()
+    }
+
+    pub fn pending_restore(&self) -> Result<Vec<(String, String)>> {
+        let conn = Connection::open(&self.path)?;
+        let mut stmt = conn.prepare("SELECT rule, command FROM shed_undo ORDER BY position")?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.collect()
+    }
+
     pub fn prune_old_data(&self, days_to_keep: u64) -> Result<()> {
         let conn = Connection::open(&self.path)?;
         let cutoff = SystemTime::now()
@@ -103,6 +472,10 @@ impl NutDB {
             - (days_to_keep * 24 * 3600);
 
         conn.execute("DELETE FROM history WHERE timestamp < ?1", params![cutoff])?;
+        conn.execute("DELETE FROM journal WHERE timestamp < ?1", params![cutoff])?;
+        conn.execute("DELETE FROM energy WHERE hour < ?1", params![cutoff])?;
+        conn.execute("DELETE FROM quality WHERE hour < ?1", params![cutoff])?;
+        conn.execute("DELETE FROM outbox WHERE created < ?1", params![cutoff])?;
         Ok(())
     }
 
@@ -0,0 +1,208 @@
+//! Data export: the history, journal and energy/cost reports of a time range,
+//! written as one JSON document or as a set of CSV files.
+
+use crate::db::{HistoryEntry, JournalEntry, NutDB};
+use crate::reports::cost::{self, CostReport, ReportPeriod};
+use crate::settings::TariffConfig;
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ExportError {
+    #[error("Database error: {0}")]
+    Db(#[from] rusqlite::Error),
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Json,
+    Csv,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Export {
+    /// Unix time
+    pub generated_at: u64,
+    pub time_range_hours: u64,
+    pub history: Vec<HistoryEntry>,
+    pub journal: Vec<JournalEntry>,
+    pub energy_daily: CostReport,
+    pub energy_monthly: CostReport,
+}
+
+/// Reads everything an export contains from `db`.
+pub fn collect(
+    db: &NutDB,
+    tariff: &TariffConfig,
+    time_range_hours: u64,
+) -> Result<Export, ExportError> {
+    let mut journal = db.get_journal(time_range_hours)?;
+    journal.reverse();
+    Ok(Export {
+        generated_at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs(),
+        time_range_hours,
+        history: db.get_history(time_range_hours)?,
+        journal,
+        energy_daily: cost::build(db, tariff, ReportPeriod::Day, time_range_hours)?,
+        energy_monthly: cost::build(db, tariff, ReportPeriod::Month, time_range_hours)?,
+    })
+}
+
+/// Writes `export` into `dir` and returns the files created.
+pub fn write(
+    export: &Export,
+    format: ExportFormat,
+    dir: &Path,
+) -> Result<Vec<PathBuf>, ExportError> {
+    std::fs::create_dir_all(dir)?;
+    let stamp = export.generated_at;
+    match format {
+        ExportFormat::Json => {
+            let path = dir.join(format!("ups-export-{stamp}.json"));
+            std::fs::write(&path, serde_json::to_vec_pretty(export)?)?;
+            Ok(vec![path])
+        }
+        ExportFormat::Csv => {
+            let history = dir.join(format!("ups-history-{stamp}.csv"));
+            std::fs::write(&history, history_csv(&export.history))?;
+            let journal = dir.join(format!("ups-journal-{stamp}.csv"));
+            std::fs::write(&journal, journal_csv(&export.journal))?;
+            let energy = dir.join(format!("ups-energy-{stamp}.csv"));
+            std::fs::write(
+                &energy,
+                energy_csv(&[&export.energy_daily, &export.energy_monthly]),
+            )?;
+            Ok(vec![history, journal, energy])
+        }
+    }
+}
+
+fn history_csv(rows: &[HistoryEntry]) -> String {
+    let mut out = String::from(
+        "timestamp,status,input_voltage,output_voltage,load_percent,battery_charge,\
+         input_l1_voltage,input_l2_voltage,input_l3_voltage,output_l1_load,output_l2_load,output_l3_load,\
+         battery_runtime,battery_voltage\n",
+    );
+    for r in rows {
+        let fields = [
+            r.timestamp.to_string(),
+            field(&r.status),
+            opt(r.input_voltage),
+            opt(r.output_voltage),
+            opt(r.load_percent),
+            opt(r.battery_charge),
+            opt(r.input_l1_voltage),
+            opt(r.input_l2_voltage),
+            opt(r.input_l3_voltage),
+            opt(r.output_l1_load),
+            opt(r.output_l2_load),
+            opt(r.output_l3_load),
+            opt(r.battery_runtime),
+            opt(r.battery_voltage),
+        ];
+        out.push_str(&fields.join(","));
+        out.push('\n');
+    }
+    out
+}
+
+fn journal_csv(rows: &[JournalEntry]) -> String {
+    let mut out = String::from("timestamp,category,message,detail\n");
+    for r in rows {
+        let fields = [
+            r.timestamp.to_string(),
+            field(&r.category),
+            field(&r.message),
+            r.detail.as_deref().map(field).unwrap_or_default(),
+        ];
+        out.push_str(&fields.join(","));
+        out.push('\n');
+    }
+    out
+}
+
+fn energy_csv(reports: &[&CostReport]) -> String {
+    let mut out = String::from("period,start,label,kwh,cost,currency,kg_co2\n");
+    for report in reports {
+        let period = serde_json::to_value(report.period)
+            .ok()
+            .and_then(|v| v.as_str().map(str::to_string))
+            .unwrap_or_default();
+        for r in &report.rows {
+            let fields = [
+                period.clone(),
+                r.start.to_string(),
+                field(&r.label),
+                format!("{:.3}", r.kwh),
+                format!("{:.2}", r.cost),
+                field(&report.currency),
+                format!("{:.3}", r.kg_co2),
+            ];
+            out.push_str(&fields.join(","));
+            out.push('\n');
+        }
+    }
+    out
+}
+
+fn opt<T: Display>(value: Option<T>) -> String {
+    value.map(|v| v.to_string()).unwrap_or_default()
+}
+
+/// Quotes a text field when it contains a separator, quote or line break (RFC 4180).
+fn field(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_json_and_csv() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = NutDB::new(dir.path());
+        db.init().unwrap();
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        db.add_energy(now / 3600 * 3600, 2000.0).unwrap();
+        db.add_journal_entry("outlet", "Switch \"printer\" off", Some("succeeded, late"))
+            .unwrap();
+
+        let export = collect(&db, &TariffConfig::default(), 24).unwrap();
+        assert_eq!(export.energy_daily.total.kwh, 2.0);
+
+        let out = dir.path().join("out");
+        let files = write(&export, ExportFormat::Csv, &out).unwrap();
+        assert_eq!(files.len(), 3);
+        let journal = std::fs::read_to_string(&files[1]).unwrap();
+        assert!(journal.contains(",outlet,\"Switch \"\"printer\"\" off\",\"succeeded, late\"\n"));
+        let energy = std::fs::read_to_string(&files[2]).unwrap();
+        assert!(energy
+            .lines()
+            .any(|l| l.starts_with("day,") && l.contains(",2.000,0.30,USD,0.800")));
+
+        let files = write(&export, ExportFormat::Json, &out).unwrap();
+        let json: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(&files[0]).unwrap()).unwrap();
+        assert_eq!(json["energy_monthly"]["currency"], "USD");
+        assert_eq!(json["journal"].as_array().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,263 @@
+//! User-defined alerts. `AlertEngine` checks every reading against the
+//! `AlertRule`s and says when an alert fires or resolves: a condition must hold
+//! for `for_sec` before it fires, the value must come back `hysteresis` past
+//! the threshold before it resolves, and a rule fires at most once per
+//! `cooldown_sec`. The monitor journals the result under `JOURNAL_CATEGORY`.
+
+use crate::nut::models::UpsData;
+use crate::settings::{AlertCondition, AlertRule, AlertSeverity};
+use serde::Serialize;
+use std::time::{Duration, Instant};
+
+pub const JOURNAL_CATEGORY: &str = "alert";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum AlertState {
+    Fired,
+    Resolved,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AlertEvent {
+    pub rule: String,
+    pub severity: AlertSeverity,
+    pub state: AlertState,
+    /// What was seen, e.g. `ups.load is 85, alert above 80`
+    pub detail: String,
+}
+
+impl AlertEvent {
+    /// Journal message, e.g. `High load fired`.
+    pub fn summary(&self) -> String {
+        let state = match self.state {
+            AlertState::Fired => "fired",
+            AlertState::Resolved => "resolved",
+        };
+        format!("{} {}", self.rule, state)
+    }
+}
+
+#[derive(Default)]
+struct RuleState {
+    active: bool,
+    /// When the condition started to hold while the alert was not active
+    pending_since: Option<Instant>,
+    last_fired: Option<Instant>,
+}
+
+pub struct AlertEngine {
+    rules: Vec<AlertRule>,
+    states: Vec<RuleState>,
+}
+
+impl AlertEngine {
+    pub fn new(rules: Vec<AlertRule>) -> Self {
+        let states = rules.iter().map(|_| RuleState::default()).collect();
+        Self { rules, states }
+    }
+
+    /// Alerts that fired or resolved with this reading. Empty most of the time.
+    pub fn evaluate(&mut self, data: &UpsData, now: Instant) -> Vec<AlertEvent> {
+        let mut events = Vec::new();
+        for (rule, state) in self.rules.iter().zip(self.states.iter_mut()) {
+            // A metric the UPS did not report leaves the alert as it was
+            let Some((holds, detail)) = check(rule, data, state.active) else {
+                continue;
+            };
+            let state_change = match (state.active, holds) {
+                (false, true) => {
+                    let since = *state.pending_since.get_or_insert(now);
+                    let held = now.saturating_duration_since(since);
+                    let cooled = state.last_fired.is_none_or(|last| {
+                        now.saturating_duration_since(last)
+                            >= Duration::from_secs(rule.cooldown_sec)
+                    });
+                    if held < Duration::from_secs(rule.for_sec) || !cooled {
+                        continue;
+                    }
+                    state.active = true;
+                    state.pending_since = None;
+                    state.last_fired = Some(now);
+                    AlertState::Fired
+                }
+                (false, false) => {
+                    state.pending_since = None;
+                    continue;
+                }
+                (true, true) => continue,
+                (true, false) => {
+                    state.active = false;
+                    AlertState::Resolved
+                }
+            };
+            events.push(AlertEvent {
+                rule: rule.name.clone(),
+                severity: rule.severity,
+                state: state_change,
+                detail,
+            });
+        }
+        events
+    }
+}
+
+/// Whether the rule's condition holds, and what was seen. An active alert is
+/// only cleared once the value is back past the threshold by the hysteresis.
+fn check(rule: &AlertRule, data: &UpsData, active: bool) -> Option<(bool, String)> {
+    let margin = if active { rule.hysteresis } else { 0.0 };
+    match &rule.condition {
+        AlertCondition::Above { metric, value } => {
+            let seen = data.metric(metric)?;
+            Some((
+                seen > value - margin,
+                format!("{metric} is {seen}, alert above {value}"),
+            ))
+        }
+        AlertCondition::Below { metric, value } => {
+            let seen = data.metric(metric)?;
+            Some((
+                seen < value + margin,
+                format!("{metric} is {seen}, alert below {value}"),
+            ))
+        }
+        AlertCondition::Flag { flag } => {
+            if data.status.is_empty() {
+                return None;
+            }
+            let set = data.status.split_whitespace().any(|f| f == flag);
+            Some((
+                set,
+                format!("ups.status is {}, alert on {flag}", data.status),
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(condition: AlertCondition) -> AlertRule {
+        AlertRule {
+            name: "Rule".to_string(),
+            condition,
+            severity: AlertSeverity::Warning,
+            for_sec: 0,
+            hysteresis: 0.0,
+            cooldown_sec: 0,
+        }
+    }
+
+    fn volts(v: f64) -> UpsData {
+        UpsData {
+            status: "OL".to_string(),
+            input_voltage: Some(v),
+            ..Default::default()
+        }
+    }
+
+    fn states(events: Vec<AlertEvent>) -> Vec<AlertState> {
+        events.into_iter().map(|e| e.state).collect()
+    }
+
+    #[test]
+    fn fires_after_the_delay_and_resolves_past_the_hysteresis() {
+        let mut engine = AlertEngine::new(vec![AlertRule {
+            for_sec: 10,
+            hysteresis: 5.0,
+            ..rule(AlertCondition::Below {
+                metric: "input.voltage".to_string(),
+                value: 200.0,
+            })
+        }]);
+        let t = Instant::now();
+        let s = Duration::from_secs(1);
+
+        assert!(engine.evaluate(&volts(195.0), t).is_empty());
+        // Dipping back above the threshold restarts the delay
+        assert!(engine.evaluate(&volts(201.0), t + 5 * s).is_empty());
+        assert!(engine.evaluate(&volts(190.0), t + 6 * s).is_empty());
+        assert!(engine.evaluate(&volts(190.0), t + 15 * s).is_empty());
+        let events = engine.evaluate(&volts(190.0), t + 16 * s);
+        assert_eq!(states(events.clone()), [AlertState::Fired]);
+        assert_eq!(events[0].detail, "input.voltage is 190, alert below 200");
+        assert_eq!(events[0].summary(), "Rule fired");
+
+        // Within the hysteresis band the alert stays active
+        assert!(engine.evaluate(&volts(203.0), t + 17 * s).is_empty());
+        // A reading without the metric changes nothing
+        assert!(engine.evaluate(&UpsData::default(), t + 18 * s).is_empty());
+        assert_eq!(
+            states(engine.evaluate(&volts(206.0), t + 19 * s)),
+            [AlertState::Resolved]
+        );
+    }
+
+    #[test]
+    fn cooldown_holds_back_a_flapping_alert() {
+        let mut engine = AlertEngine::new(vec![AlertRule {
+            cooldown_sec: 60,
+            ..rule(AlertCondition::Flag {
+                flag: "OB".to_string(),
+            })
+        }]);
+        let t = Instant::now();
+        let s = Duration::from_secs(1);
+        let status = |status: &str| UpsData {
+            status: status.to_string(),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            states(engine.evaluate(&status("OB DISCHRG"), t)),
+            [AlertState::Fired]
+        );
+        assert_eq!(
+            states(engine.evaluate(&status("OL CHRG"), t + 5 * s)),
+            [AlertState::Resolved]
+        );
+        assert!(engine
+            .evaluate(&status("OB DISCHRG"), t + 10 * s)
+            .is_empty());
+        // Still on battery once the cooldown is over: fires then
+        assert_eq!(
+            states(engine.evaluate(&status("OB DISCHRG"), t + 60 * s)),
+            [AlertState::Fired]
+        );
+        // The flag must match a whole word
+        assert_eq!(
+            states(engine.evaluate(&status("OL OBX"), t + 61 * s)),
+            [AlertState::Resolved]
+        );
+    }
+
+    #[test]
+    fn reads_any_reported_variable() {
+        let mut engine = AlertEngine::new(vec![
+            rule(AlertCondition::Above {
+                metric: "ups.temperature".to_string(),
+                value: 40.0,
+            }),
+            AlertRule {
+                name: "Load".to_string(),
+                severity: AlertSeverity::Critical,
+                ..rule(AlertCondition::Above {
+                    metric: "ups.load".to_string(),
+                    value: 80.0,
+                })
+            },
+        ]);
+        let data = UpsData {
+            status: "OL".to_string(),
+            ups_load: Some(85.0),
+            extended_vars: [("ups.temperature".to_string(), "42.5".to_string())].into(),
+            ..Default::default()
+        };
+        let events = engine.evaluate(&data, Instant::now());
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].detail, "ups.temperature is 42.5, alert above 40");
+        assert_eq!(events[1].rule, "Load");
+        assert_eq!(events[1].severity, AlertSeverity::Critical);
+    }
+}
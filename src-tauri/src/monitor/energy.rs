@@ -0,0 +1,242 @@
+//! Energy accounting. `EnergyMeter` integrates `UpsData::power_watts` over
+//! wall-clock time, splits the result at hour boundaries and hands it to
+//! `NutDB::add_energy`, which keeps one running total per hour. Days and months
+//! are summed from those rows when queried.
+
+use crate::db::NutDB;
+use crate::nut::models::UpsData;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::Mutex;
+
+/// Readings further apart than this are not joined: the app was closed, the
+/// machine slept or the UPS was unreachable, and we do not know what happened.
+pub const MAX_GAP_SECS: f64 = 300.0;
+
+/// How often accumulated energy is written to the database.
+const FLUSH_EVERY_SECS: f64 = 60.0;
+
+#[derive(Default)]
+pub struct EnergyMeter {
+    /// Time (unix seconds) and watts of the previous reading
+    last: Option<(f64, f64)>,
+    /// Wh not yet written, keyed by the unix time the hour starts at
+    pending: BTreeMap<u64, f64>,
+    last_flush: Option<f64>,
+}
+
+impl EnergyMeter {
+    /// Adds the energy since the previous reading, using the trapezoid rule.
+    /// A reading without a power figure breaks the chain.
+    pub fn sample(&mut self, watts: Option<f64>, at: f64) {
+        let Some(watts) = watts.filter(|w| w.is_finite() && *w >= 0.0) else {
+            self.last = None;
+            return;
+        };
+        if let Some((t0, w0)) = self.last {
+            if at > t0 && at - t0 <= MAX_GAP_SECS {
+                self.integrate(t0, w0, at, watts);
+            }
+        }
+        self.last = Some((at, watts));
+    }
+
+    fn integrate(&mut self, t0: f64, w0: f64, t1: f64, w1: f64) {
+        let slope = (w1 - w0) / (t1 - t0);
+        let mut start = t0;
+        while start < t1 {
+            let hour = (start / 3600.0).floor() * 3600.0;
+            let end = t1.min(hour + 3600.0);
+            let w_start = w0 + slope * (start - t0);
+            let w_end = w0 + slope * (end - t0);
+            *self.pending.entry(hour as u64).or_default() +=
+                (w_start + w_end) / 2.0 * (end - start) / 3600.0;
+            start = end;
+        }
+    }
+
+    /// Wh accumulated since the last call, per hour.
+    pub fn take_pending(&mut self) -> Vec<(u64, f64)> {
+        std::mem::take(&mut self.pending).into_iter().collect()
+    }
+
+    /// Writes everything accumulated so far. On failure the energy is kept for the next try.
+    pub fn flush(&mut self, db: &NutDB) {
+        for (hour, wh) in self.take_pending() {
+            if let Err(e) = db.add_energy(hour, wh) {
+                log::error!("Failed to record energy: {}", e);
+                *self.pending.entry(hour).or_default() += wh;
+            }
+        }
+    }
+
+    /// Feeds one poll result in and writes to `db` about once a minute.
+    pub fn record(&mut self, db: &NutDB, data: &UpsData) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs_f64();
+        self.sample(data.power_watts, now);
+        let last_flush = *self.last_flush.get_or_insert(now);
+        if now - last_flush >= FLUSH_EVERY_SECS {
+            self.flush(db);
+            self.last_flush = Some(now);
+        }
+    }
+}
+
+/// A meter that buffers totals per hour and writes them about once a minute.
+pub trait Rollup: Default {
+    fn has_pending(&self) -> bool;
+
+    /// Writes everything accumulated so far, keeping what fails for the next try.
+    fn flush_to(&mut self, db: &NutDB);
+}
+
+impl Rollup for EnergyMeter {
+    fn has_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    fn flush_to(&mut self, db: &NutDB) {
+        self.flush(db);
+    }
+}
+
+/// One of the poll loop's meters. Whatever is still pending when the loop is
+/// aborted is written on drop, so stopping monitoring does not lose up to a
+/// minute of readings.
+pub struct MeterGuard<M: Rollup> {
+    meter: M,
+    db: Arc<Mutex<Option<NutDB>>>,
+}
+
+impl<M: Rollup> MeterGuard<M> {
+    pub fn new(db: Arc<Mutex<Option<NutDB>>>) -> Self {
+        Self {
+            meter: M::default(),
+            db,
+        }
+    }
+}
+
+impl<M: Rollup> std::ops::Deref for MeterGuard<M> {
+    type Target = M;
+
+    fn deref(&self) -> &M {
+        &self.meter
+    }
+}
+
+impl<M: Rollup> std::ops::DerefMut for MeterGuard<M> {
+    fn deref_mut(&mut self) -> &mut M {
+        &mut self.meter
+    }
+}
+
+impl<M: Rollup> Drop for MeterGuard<M> {
+    fn drop(&mut self) {
+        if !self.meter.has_pending() {
+            return;
+        }
+        // Cannot wait in drop; the lock is only held briefly by whoever has it
+        match self.db.try_lock() {
+            Ok(db) => {
+                if let Some(db) = db.as_ref() {
+                    self.meter.flush_to(db);
+                }
+            }
+            Err(_) => log::warn!("Database busy, readings of the last minute not recorded"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::EnergyPeriod;
+
+    #[test]
+    fn splits_energy_at_hour_boundaries() {
+        let mut meter = EnergyMeter::default();
+        let hour = 1_700_002_800.0; // a whole hour
+        meter.sample(Some(100.0), hour - 144.0);
+        // Ramp from 100 W to 300 W across the boundary: 200 W at the hour
+        meter.sample(Some(300.0), hour + 144.0);
+        let pending = meter.take_pending();
+        assert_eq!(pending.len(), 2);
+        assert_eq!(pending[0], (hour as u64 - 3600, 6.0));
+        assert_eq!(pending[1], (hour as u64, 10.0));
+        assert!(meter.take_pending().is_empty());
+
+        // A long silence or a missing figure adds nothing
+        meter.sample(Some(300.0), hour + 144.0 + MAX_GAP_SECS + 1.0);
+        meter.sample(None, hour + 3000.0);
+        meter.sample(Some(300.0), hour + 3010.0);
+        assert!(meter.take_pending().is_empty());
+    }
+
+    #[test]
+    fn sums_hours_into_days_and_months() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = NutDB::new(dir.path());
+        db.init().unwrap();
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let this_hour = now / 3600 * 3600;
+
+        let mut meter = EnergyMeter::default();
+        let start = (this_hour - 3600) as f64;
+        for minute in 0..=90 {
+            meter.sample(Some(600.0), start + f64::from(minute) * 60.0);
+        }
+        meter.flush(&db);
+        // Adding to an hour that already has a row
+        db.add_energy(this_hour, 100.0).unwrap();
+
+        let hours = db.get_energy(EnergyPeriod::Hour, 24).unwrap();
+        let kwh: Vec<f64> = hours.iter().map(|b| b.kwh).collect();
+        assert_eq!(hours[0].start, this_hour - 3600);
+        assert_eq!(kwh, [0.6, 0.4]);
+
+        let total: f64 = db
+            .get_energy(EnergyPeriod::Day, 48)
+            .unwrap()
+            .iter()
+            .map(|b| b.kwh)
+            .sum();
+        assert!((total - 1.0).abs() < 1e-9);
+        let months = db.get_energy(EnergyPeriod::Month, 24 * 62).unwrap();
+        assert!(!months.is_empty() && months.len() <= 2);
+    }
+
+    #[test]
+    fn flushes_pending_energy_when_dropped() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = NutDB::new(dir.path());
+        db.init().unwrap();
+        let db = Arc::new(Mutex::new(Some(db)));
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let this_hour = now / 3600 * 3600;
+
+        let mut guard = MeterGuard::<EnergyMeter>::new(db.clone());
+        guard.sample(Some(360.0), this_hour as f64);
+        guard.sample(Some(360.0), this_hour as f64 + 10.0);
+        drop(guard);
+
+        let db = db.try_lock().unwrap();
+        let hours = db
+            .as_ref()
+            .unwrap()
+            .get_energy(EnergyPeriod::Hour, 1)
+            .unwrap();
+        assert_eq!(hours.len(), 1);
+        assert!((hours[0].kwh - 0.001).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,224 @@
+use crate::db::{HistoryEntry, NutDB};
+use crate::nut::models::UpsData;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// Log to DB Logic
+/// "The Digital Observer" Strategy:
+/// 1. Status Change: Log always.
+/// 2. Volatility Velocity: Log if input voltage changes fast (> 0.5V/s).
+/// 3. Load/Battery: Log if change > 5% / 2%.
+/// 4. Heartbeat: Log every 10 minutes (600s) to keep the chart alive during stability.
+pub struct HistoryRecorder {
+    last_log_time: Instant,
+    last_logged_data: Option<UpsData>,
+    first_run: bool,
+}
+
+impl Default for HistoryRecorder {
+    fn default() -> Self {
+        Self {
+            last_log_time: Instant::now(),
+            last_logged_data: None,
+            first_run: true,
+        }
+    }
+}
+
+impl HistoryRecorder {
+    pub fn should_log(&self, data: &UpsData, now: Instant) -> bool {
+        let since_last = now.saturating_duration_since(self.last_log_time);
+        let mut significant_event = false;
+
+        if let Some(last) = self.last_logged_data.as_ref() {
+            if last.status != data.status {
+                significant_event = true;
+            } else {
+                // Calculate Volatility Velocity
+                // How much did it change per second?
+                let time_delta = since_last.as_secs_f64().max(1.0);
+
+                let v_now = data.input_voltage.unwrap_or(0.0);
+                let v_last = last.input_voltage.unwrap_or(0.0);
+                let v_velocity = (v_now - v_last).abs() / time_delta;
+
+                // 0.5V per second is a "spike" or "sag" even if small amplitude
+                if v_velocity > 0.5 {
+                    significant_event = true;
+                }
+
+                // Load Change > 5% (Significant load switch)
+                let l_diff = (data.ups_load.unwrap_or(0.0) - last.ups_load.unwrap_or(0.0)).abs();
+                if l_diff > 5.0 {
+                    significant_event = true;
+                }
+
+                // Same for any single phase of a multi-phase unit
+                for phase in 1..=3 {
+                    let p_diff = (phase_load(data, phase).unwrap_or(0.0)
+                        - phase_load(last, phase).unwrap_or(0.0))
+                    .abs();
+                    if p_diff > 5.0 {
+                        significant_event = true;
+                    }
+                }
+
+                // Battery Change > 2% (Charging/Discharging)
+                let b_diff =
+                    (data.battery_charge.unwrap_or(0.0) - last.battery_charge.unwrap_or(0.0)).abs();
+                if b_diff > 2.0 {
+                    significant_event = true;
+                }
+            }
+        } else {
+            significant_event = true; // First run
+        }
+
+        self.first_run || since_last.as_secs() >= 600 || significant_event
+    }
+
+    /// Writes the reading if the strategy above considers it worth keeping.
+    /// Returns whether a row was inserted.
+    pub fn record(&mut self, db: &NutDB, data: &UpsData, now: Instant) -> bool {
+        if !self.should_log(data, now) {
+            return false;
+        }
+
+        let entry = HistoryEntry {
+            id: None,
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+            input_voltage: data.input_voltage,
+            output_voltage: data.output_voltage,
+            load_percent: data.ups_load,
+            battery_charge: data.battery_charge,
+            status: data.status.clone(),
+            input_l1_voltage: phase_voltage(data, 1),
+            input_l2_voltage: phase_voltage(data, 2),
+            input_l3_voltage: phase_voltage(data, 3),
+            output_l1_load: phase_load(data, 1),
+            output_l2_load: phase_load(data, 2),
+            output_l3_load: phase_load(data, 3),
+            battery_runtime: data.battery_runtime,
+            battery_voltage: data.battery_voltage,
+        };
+        if let Err(e) = db.insert_entry(&entry) {
+            log::error!("Failed to log history: {}", e);
+            return false;
+        }
+
+        self.last_log_time = now;
+        self.last_logged_data = Some(data.clone());
+
+        // Prune once a day (approx check)
+        if self.first_run {
+            if let Err(e) = db.prune_old_data(365) {
+                log::error!("Failed to prune old data: {}", e);
+            }
+        }
+        self.first_run = false;
+        true
+    }
+}
+
+fn phase_voltage(data: &UpsData, phase: u8) -> Option<f64> {
+    data.input
+        .per_phase
+        .iter()
+        .find(|p| p.phase == phase)
+        .and_then(|p| p.voltage)
+}
+
+fn phase_load(data: &UpsData, phase: u8) -> Option<f64> {
+    data.output
+        .per_phase
+        .iter()
+        .find(|p| p.phase == phase)
+        .and_then(|p| p.load)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn reading(status: &str, voltage: f64, load: f64) -> UpsData {
+        UpsData {
+            status: status.to_string(),
+            input_voltage: Some(voltage),
+            ups_load: Some(load),
+            battery_charge: Some(100.0),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn logs_only_significant_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = NutDB::new(dir.path());
+        db.init().unwrap();
+        let mut recorder = HistoryRecorder::default();
+        let start = Instant::now();
+
+        assert!(recorder.record(&db, &reading("OL", 230.0, 20.0), start));
+        // Small drift is ignored
+        assert!(!recorder.record(
+            &db,
+            &reading("OL", 230.4, 22.0),
+            start + Duration::from_secs(5)
+        ));
+        // Load step and status change are not
+        assert!(recorder.record(
+            &db,
+            &reading("OL", 230.0, 40.0),
+            start + Duration::from_secs(10)
+        ));
+        assert!(recorder.record(
+            &db,
+            &reading("OB", 230.0, 40.0),
+            start + Duration::from_secs(11)
+        ));
+        // Heartbeat after ten quiet minutes
+        assert!(recorder.record(
+            &db,
+            &reading("OB", 230.0, 40.0),
+            start + Duration::from_secs(700)
+        ));
+
+        assert_eq!(db.get_history(1).unwrap().len(), 4);
+    }
+
+    #[test]
+    fn adds_phase_columns_to_an_old_database() {
+        let dir = tempfile::tempdir().unwrap();
+        let conn = rusqlite::Connection::open(dir.path().join("history.db")).unwrap();
+        conn.execute(
+            "CREATE TABLE history (id INTEGER PRIMARY KEY, timestamp INTEGER NOT NULL,
+                input_voltage REAL, output_voltage REAL, load_percent REAL,
+                battery_charge REAL, status TEXT)",
+            [],
+        )
+        .unwrap();
+        let db = NutDB::new(dir.path());
+        db.init().unwrap();
+        // A second init must not try to add them again
+        db.init().unwrap();
+
+        let mut data = reading("OL", 230.0, 20.0);
+        data.extended_vars = [
+            ("input.L2-N.voltage", "228"),
+            ("output.L3.power.percent", "35"),
+        ]
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+        data.build_details();
+        assert!(HistoryRecorder::default().record(&db, &data, Instant::now()));
+
+        let rows = db.get_history(1).unwrap();
+        assert_eq!(rows[0].input_l2_voltage, Some(228.0));
+        assert_eq!(rows[0].output_l3_load, Some(35.0));
+        assert_eq!(rows[0].input_l1_voltage, None);
+    }
+}
@@ -0,0 +1,919 @@
+//! Polling, history logging and shutdown protection, independent of Tauri.
+//! The desktop app and the `ups-clientd` daemon both drive this and only differ
+//! in what they do with the emitted `MonitorEvent`s.
+
+pub mod alerts;
+pub mod energy;
+pub mod history;
+pub mod outlets;
+pub mod quality;
+pub mod selftest;
+pub mod shedding;
+pub mod shutdown;
+
+use crate::db::NutDB;
+use crate::notify::Dispatcher;
+use crate::nut::models::UpsData;
+use crate::reports::battery::find_discharges;
+use crate::reports::runtime::RuntimeModel;
+use crate::settings::{AlertsConfig, LoadSheddingConfig, SelfTestConfig, ShutdownConfig};
+use crate::source::SharedSource;
+use crate::telegram::Bot;
+use alerts::{AlertEngine, AlertEvent, AlertState};
+use chrono::{Local, NaiveDateTime, TimeZone};
+use energy::{EnergyMeter, MeterGuard};
+use history::HistoryRecorder;
+use log::{error, info, warn};
+use quality::QualityMeter;
+use selftest::{SelfTestScheduler, SelfTestStep};
+use shedding::{LoadShedder, ShedStep};
+use shutdown::{ShutdownStep, ShutdownTracker};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{broadcast, Mutex};
+use tokio::task::{JoinHandle, JoinSet};
+
+/// History the runtime model is fitted on; older discharges describe a younger battery.
+const RUNTIME_MODEL_DAYS: u64 = 180;
+
+#[derive(Debug, Clone)]
+pub enum MonitorEvent {
+    Update(Box<UpsData>),
+    /// Seconds left before the configured system stop runs.
+    ShutdownWarning(u64),
+    ShutdownCancelled,
+    ShutdownExecuted(String),
+    ConnectionLost(String),
+    ConnectionRestored,
+    /// A load shedding step ran (or would have, in a dry run).
+    LoadShedding {
+        step: String,
+        outcome: String,
+    },
+    /// A scheduled self-test started, was skipped or ended; as journalled.
+    SelfTest(String),
+    /// A user-defined alert fired or resolved.
+    Alert(AlertEvent),
+}
+
+/// Shared handles the poll loop works on. Cloning is cheap; every clone
+/// publishes to the same event channel.
+#[derive(Clone)]
+pub struct Monitor {
+    pub source: SharedSource,
+    pub db: Arc<Mutex<Option<NutDB>>>,
+    pub shutdown: Arc<Mutex<ShutdownTracker>>,
+    events: broadcast::Sender<MonitorEvent>,
+    /// When false, an expired countdown is reported but the OS is left running.
+    system_stop: bool,
+    load_shedding: LoadSheddingConfig,
+    /// Held while a shedding sequence runs, so sequences never interleave
+    shedding_sequence: Arc<Mutex<()>>,
+    self_test: SelfTestConfig,
+    alerts: AlertsConfig,
+    notifier: Dispatcher,
+    telegram: Option<Bot>,
+}
+
+impl Monitor {
+    pub fn new(
+        source: SharedSource,
+        db: Arc<Mutex<Option<NutDB>>>,
+        shutdown: Arc<Mutex<ShutdownTracker>>,
+    ) -> Self {
+        let (events, _) = broadcast::channel(64);
+        Self {
+            source,
+            db,
+            shutdown,
+            events,
+            system_stop: true,
+            load_shedding: LoadSheddingConfig::default(),
+            shedding_sequence: Arc::new(Mutex::new(())),
+            self_test: SelfTestConfig::default(),
+            alerts: AlertsConfig::default(),
+            notifier: Dispatcher::default(),
+            telegram: None,
+        }
+    }
+
+    /// Reports shutdown steps as events without ever stopping the machine.
+    pub fn without_system_stop(mut self) -> Self {
+        self.system_stop = false;
+        self
+    }
+
+    pub fn with_load_shedding(mut self, config: LoadSheddingConfig) -> Self {
+        self.load_shedding = config;
+        self
+    }
+
+    pub fn with_self_test(mut self, config: SelfTestConfig) -> Self {
+        self.self_test = config;
+        self
+    }
+
+    pub fn with_alerts(mut self, config: AlertsConfig) -> Self {
+        self.alerts = config;
+        self
+    }
+
+    /// Delivers notifications for this monitor's events while it runs.
+    pub fn with_notifier(mut self, notifier: Dispatcher) -> Self {
+        self.notifier = notifier;
+        self
+    }
+
+    /// Answers Telegram commands about the polled UPS while it runs.
+    pub fn with_telegram(mut self, bot: Bot) -> Self {
+        self.telegram = Some(bot);
+        self
+    }
+
+    /// Whether an expired countdown stops the OS, see `without_system_stop`.
+    pub fn stops_system(&self) -> bool {
+        self.system_stop
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<MonitorEvent> {
+        self.events.subscribe()
+    }
+
+    fn emit(&self, event: MonitorEvent) {
+        // Nobody listening is fine (e.g. the window has not subscribed yet)
+        let _ = self.events.send(event);
+    }
+
+    /// Starts the poll loop. The caller owns the handle and aborts it to stop monitoring.
+    pub fn spawn(
+        &self,
+        ups_name: String,
+        interval_ms: u64,
+        shutdown_config: ShutdownConfig,
+    ) -> JoinHandle<()> {
+        info!(
+            "Starting background polling for {} with interval {}ms",
+            ups_name, interval_ms
+        );
+        info!("Shutdown Config: {:?}", shutdown_config);
+
+        let monitor = self.clone();
+        // Subscribed before the first poll, so no event goes unnotified
+        let events = self.subscribe();
+        let bot = self.telegram.clone();
+        tokio::spawn(async move {
+            // In its own task, so a panic in the bot cannot stop polling; it ends
+            // with this one when the handle is aborted
+            let _chats = bot
+                .map(|bot| AbortOnDrop(tokio::spawn(bot.run(monitor.clone(), ups_name.clone()))));
+            futures::join!(
+                monitor.run(ups_name.clone(), interval_ms, shutdown_config),
+                monitor.notifier.clone().run(events),
+            );
+        })
+    }
+
+    async fn run(&self, ups_name: String, interval_ms: u64, shutdown_config: ShutdownConfig) {
+        let mut interval = tokio::time::interval(Duration::from_millis(interval_ms));
+        let mut recorder = HistoryRecorder::default();
+        let mut meter = MeterGuard::<EnergyMeter>::new(self.db.clone());
+        let mut quality = MeterGuard::<QualityMeter>::new(self.db.clone());
+        let mut shedder = LoadShedder::new(
+            self.load_shedding.clone(),
+            self.load_pending_restore().await,
+        );
+        // Aborted with the poll loop, like the bot, so no sequence outlives it
+        let mut sequences = JoinSet::new();
+        let mut tester =
+            SelfTestScheduler::new(self.self_test.clone(), self.last_self_test().await);
+        let mut runtime_model = self.fit_runtime_model().await;
+        let mut alerts = AlertEngine::new(self.alerts.rules.clone());
+        let mut on_battery = false;
+        let mut connected = true;
+
+        loop {
+            interval.tick().await;
+
+            match self.poll(&ups_name).await {
+                Ok(mut data) => {
+                    if !connected {
+                        info!("Connection to UPS restored");
+                        connected = true;
+                        self.emit(MonitorEvent::ConnectionRestored);
+                    }
+                    data.runtime_estimate = runtime_model.as_ref().and_then(|m| m.estimate(&data));
+                    self.emit(MonitorEvent::Update(Box::new(data.clone())));
+                    let stopping = self.check_shutdown(&shutdown_config, &data).await;
+                    while sequences.try_join_next().is_some() {}
+                    let pending = shedder.pending_restore().len();
+                    let steps = shedder.evaluate(&data, Instant::now());
+                    let restoring = steps.first().is_some_and(|step| step.restore);
+                    if shedder.pending_restore().len() != pending && !restoring {
+                        // Saved before the steps run, so a restart still restores them
+                        self.save_pending_restore(shedder.pending_restore()).await;
+                    }
+                    if !steps.is_empty() {
+                        self.spawn_shedding(&mut sequences, ups_name.clone(), steps);
+                    }
+                    if let Some(step) = tester.evaluate(&data, Local::now().naive_local()) {
+                        self.run_self_test_step(&ups_name, &mut tester, step).await;
+                    }
+                    for event in alerts.evaluate(&data, Instant::now()) {
+                        self.raise_alert(event).await;
+                    }
+
+                    let db_guard = self.db.lock().await;
+                    if let Some(db) = db_guard.as_ref() {
+                        recorder.record(db, &data, Instant::now());
+                        meter.record(db, &data);
+                        quality.record(db, &data);
+                        if stopping {
+                            // The OS is going down; there is no later flush
+                            meter.flush(db);
+                            quality.flush(db);
+                        }
+                    }
+                    drop(db_guard);
+
+                    // Learn from an outage once the reading that ends it is recorded
+                    let was_on_battery = on_battery;
+                    on_battery = data.status.split_whitespace().any(|f| f == "OB");
+                    if was_on_battery && !on_battery {
+                        runtime_model = self.fit_runtime_model().await;
+                    }
+                }
+                Err(e) => {
+                    if connected {
+                        warn!("Connection to UPS lost: {}", e);
+                        connected = false;
+                        self.emit(MonitorEvent::ConnectionLost(e));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Runs a shedding sequence next to the poll loop; its step delays must not stall polling.
+    /// A restore sequence is only forgotten once it has run.
+    fn spawn_shedding(&self, sequences: &mut JoinSet<()>, ups_name: String, steps: Vec<ShedStep>) {
+        let monitor = self.clone();
+        sequences.spawn(async move {
+            let _sequence = monitor.shedding_sequence.lock().await;
+            let config = &monitor.load_shedding;
+            let outcomes = shedding::execute(
+                &monitor.source,
+                &monitor.db,
+                &ups_name,
+                &steps,
+                config.dry_run,
+                Duration::from_millis(config.step_delay_ms),
+            )
+            .await;
+            if steps.first().is_some_and(|step| step.restore) {
+                monitor.forget_pending_restore(&steps).await;
+            }
+            for (step, outcome) in steps.iter().zip(outcomes) {
+                monitor.emit(MonitorEvent::LoadShedding {
+                    step: step.command.clone(),
+                    outcome,
+                });
+            }
+        });
+    }
+
+    /// What an earlier run shed and has not restored.
+    async fn load_pending_restore(&self) -> Vec<ShedStep> {
+        let db_guard = self.db.lock().await;
+        let Some(db) = db_guard.as_ref() else {
+            return Vec::new();
+        };
+        match db.pending_restore() {
+            Ok(steps) => steps
+                .into_iter()
+                .map(|(rule, command)| ShedStep {
+                    rule,
+                    command,
+                    restore: true,
+                })
+                .collect(),
+            Err(e) => {
+                error!("Failed to read the shed loads to restore: {}", e);
+                Vec::new()
+            }
+        }
+    }
+
+    async fn save_pending_restore(&self, steps: &[ShedStep]) {
+        let steps: Vec<(String, String)> = steps
+            .iter()
+            .map(|step| (step.rule.clone(), step.command.clone()))
+            .collect();
+        if let Some(db) = self.db.lock().await.as_ref() {
+            if let Err(e) = db.set_pending_restore(&steps) {
+                error!("Failed to save the shed loads to restore: {}", e);
+            }
+        }
+    }
+
+    /// Clears the saved undo steps once `restored` (in restore order) has run,
+    /// unless a later outage has replaced them meanwhile.
+    async fn forget_pending_restore(&self, restored: &[ShedStep]) {
+        let db_guard = self.db.lock().await;
+        let Some(db) = db_guard.as_ref() else {
+            return;
+        };
+        let result = db.pending_restore().and_then(|saved| {
+            let done = saved
+                .iter()
+                .rev()
+                .map(|(rule, command)| (rule, command))
+                .eq(restored.iter().map(|step| (&step.rule, &step.command)));
+            if done {
+                db.set_pending_restore(&[])?;
+            }
+            Ok(())
+        });
+        if let Err(e) = result {
+            error!("Failed to clear the restored shed loads: {}", e);
+        }
+    }
+
+    /// Runtime model fitted on the discharges of the last `RUNTIME_MODEL_DAYS`.
+    async fn fit_runtime_model(&self) -> Option<RuntimeModel> {
+        let db_guard = self.db.lock().await;
+        let history = match db_guard.as_ref()?.get_history(RUNTIME_MODEL_DAYS * 24) {
+            Ok(history) => history,
+            Err(e) => {
+                error!("Failed to read history for the runtime model: {}", e);
+                return None;
+            }
+        };
+        let model = RuntimeModel::fit(&find_discharges(&history));
+        if let Some(model) = &model {
+            info!(
+                "Runtime model: {:.0}s at full load, exponent {:.2}, from {} discharges",
+                model.coefficient, model.exponent, model.samples
+            );
+        }
+        model
+    }
+
+    /// Start of the last scheduled self-test, so a restart does not repeat it;
+    /// now if none is on record.
+    async fn last_self_test(&self) -> NaiveDateTime {
+        let now = Local::now().naive_local();
+        let db_guard = self.db.lock().await;
+        let Some(db) = db_guard.as_ref() else {
+            return now;
+        };
+        match db.last_journal_time(selftest::JOURNAL_CATEGORY, selftest::STARTED_LIKE) {
+            Ok(Some(unix)) => Local
+                .timestamp_opt(unix as i64, 0)
+                .earliest()
+                .map_or(now, |t| t.naive_local()),
+            Ok(None) => now,
+            Err(e) => {
+                error!("Failed to read the journal: {}", e);
+                now
+            }
+        }
+    }
+
+    async fn run_self_test_step(
+        &self,
+        ups_name: &str,
+        tester: &mut SelfTestScheduler,
+        step: SelfTestStep,
+    ) {
+        let (message, detail) = match step {
+            SelfTestStep::Start(kind) => {
+                let name = selftest::kind_name(kind);
+                match selftest::start(&self.source, ups_name, kind).await {
+                    Ok(command) => (format!("{name} self-test started"), command),
+                    Err(e) => {
+                        tester.abandon();
+                        (format!("{name} self-test not started"), e)
+                    }
+                }
+            }
+            SelfTestStep::Skipped(reason) => ("Self-test skipped".to_string(), reason),
+            SelfTestStep::Finished(result) => ("Self-test finished".to_string(), result),
+            SelfTestStep::TimedOut => (
+                "Self-test finished".to_string(),
+                format!("no result after {} min", self.self_test.timeout_min),
+            ),
+        };
+        selftest::journal(&self.db, &message, &detail).await;
+        self.emit(MonitorEvent::SelfTest(format!("{message}: {detail}")));
+    }
+
+    async fn raise_alert(&self, event: AlertEvent) {
+        match event.state {
+            AlertState::Fired => warn!("Alert {}: {}", event.summary(), event.detail),
+            AlertState::Resolved => info!("Alert {}: {}", event.summary(), event.detail),
+        }
+        if let Some(db) = self.db.lock().await.as_ref() {
+            if let Err(e) = db.add_journal_entry(
+                alerts::JOURNAL_CATEGORY,
+                &event.summary(),
+                Some(&event.detail),
+            ) {
+                error!("Failed to write journal: {}", e);
+            }
+        }
+        self.emit(MonitorEvent::Alert(event));
+    }
+
+    /// Fetches one reading with the Auto-Reconnect Watchdog & Timeout.
+    async fn poll(&self, ups_name: &str) -> Result<UpsData, String> {
+        let mut guard = self.source.lock().await;
+        let Some(client) = guard.as_mut() else {
+            return Err("Not connected".to_string());
+        };
+
+        // Add timeout to prevent locking for too long
+        match tokio::time::timeout(Duration::from_secs(2), client.fetch_data(ups_name)).await {
+            Ok(Ok(data)) => Ok(data),
+            Ok(Err(e)) => {
+                warn!("Watchdog: Failed to get data: {}", e);
+                // Attempt reconnect logic
+                match client.connect().await {
+                    Ok(_) => {
+                        info!("Watchdog: Reconnected.");
+                        // Retry once
+                        match tokio::time::timeout(
+                            Duration::from_secs(2),
+                            client.fetch_data(ups_name),
+                        )
+                        .await
+                        {
+                            Ok(Ok(data)) => Ok(data),
+                            _ => Err(format!("Fetch failed after reconnect: {}", e)),
+                        }
+                    }
+                    Err(re_err) => {
+                        error!("Watchdog: Reconnect failed: {}", re_err);
+                        Err(format!("Disconnected: {}", e))
+                    }
+                }
+            }
+            Err(_) => {
+                error!("Watchdog: UPS data fetch timed out (lock held too long?)");
+                // If we time out, the socket might be stuck, so drop the connection
+                // and let the next tick reconnect.
+                let _ = client.disconnect().await;
+                Err("Timeout".to_string())
+            }
+        }
+    }
+
+    /// Returns whether the system stop was started.
+    async fn check_shutdown(&self, config: &ShutdownConfig, data: &UpsData) -> bool {
+        let step = self
+            .shutdown
+            .lock()
+            .await
+            .evaluate(config, data, Instant::now());
+
+        match step {
+            ShutdownStep::Idle => false,
+            ShutdownStep::Countdown(remaining) => {
+                self.emit(MonitorEvent::ShutdownWarning(remaining));
+                false
+            }
+            ShutdownStep::Execute(action) => {
+                self.emit(MonitorEvent::ShutdownWarning(0));
+                self.emit(MonitorEvent::ShutdownExecuted(action.clone()));
+                if !self.system_stop {
+                    info!("System stop disabled, not executing {}", action);
+                    return false;
+                }
+                tokio::spawn(async move {
+                    if let Err(e) = shutdown::trigger_system_stop(action, 0).await {
+                        error!("CRITICAL: Failed to execute system stop: {}", e);
+                    }
+                });
+                true
+            }
+            ShutdownStep::Cancelled => {
+                self.emit(MonitorEvent::ShutdownCancelled);
+                false
+            }
+        }
+    }
+}
+
+/// Aborts a helper task once the poll loop that started it is gone.
+struct AbortOnDrop(JoinHandle<()>);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nut::capture::Capture;
+    use crate::nut::client::NutClient;
+    use crate::nut::emulator::FakeUpsd;
+    use crate::nut::simulator::{SimEvent, VirtualUps};
+
+    const WAIT: Duration = Duration::from_secs(5);
+
+    async fn monitor_for(server: &FakeUpsd, db: Option<NutDB>) -> Monitor {
+        let mut client = NutClient::new(server.config());
+        client.connect().await.unwrap();
+        Monitor::new(
+            Arc::new(Mutex::new(Some(Box::new(client)))),
+            Arc::new(Mutex::new(db)),
+            Arc::new(Mutex::new(ShutdownTracker::default())),
+        )
+        .without_system_stop()
+    }
+
+    /// Waits for the first event matching `pred`, failing the test after `WAIT`.
+    async fn expect_event<F: Fn(&MonitorEvent) -> bool>(
+        events: &mut broadcast::Receiver<MonitorEvent>,
+        pred: F,
+    ) -> MonitorEvent {
+        tokio::time::timeout(WAIT, async {
+            loop {
+                match events.recv().await {
+                    Ok(event) if pred(&event) => return event,
+                    Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+                    Err(broadcast::error::RecvError::Closed) => panic!("event channel closed"),
+                }
+            }
+        })
+        .await
+        .expect("timed out waiting for monitor event")
+    }
+
+    fn protection(timer_sec: u64) -> ShutdownConfig {
+        ShutdownConfig {
+            enabled: true,
+            battery_threshold: 50,
+            runtime_threshold: 60,
+            timer_sec,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn polls_and_records_history() {
+        let server = FakeUpsd::start().await;
+        let dir = tempfile::tempdir().unwrap();
+        let db = NutDB::new(dir.path());
+        db.init().unwrap();
+        let monitor = monitor_for(&server, Some(db)).await;
+        let mut events = monitor.subscribe();
+        let poller = monitor.spawn("ups".to_string(), 50, ShutdownConfig::default());
+
+        let first = expect_event(&mut events, |e| matches!(e, MonitorEvent::Update(_))).await;
+        let MonitorEvent::Update(data) = first else {
+            unreachable!()
+        };
+        assert_eq!(data.status, "OL");
+
+        server.set_status("ups", "OB DISCHRG");
+        expect_event(
+            &mut events,
+            |e| matches!(e, MonitorEvent::Update(d) if d.status == "OB DISCHRG"),
+        )
+        .await;
+        poller.abort();
+
+        let history = NutDB::new(dir.path()).get_history(1).unwrap();
+        let statuses: Vec<&str> = history.iter().map(|h| h.status.as_str()).collect();
+        assert_eq!(statuses, vec!["OL", "OB DISCHRG"]);
+    }
+
+    #[tokio::test]
+    async fn reconnects_transparently_after_a_dropped_connection() {
+        let server = FakeUpsd::start().await;
+        let monitor = monitor_for(&server, None).await;
+        let mut events = monitor.subscribe();
+        let poller = monitor.spawn("ups".to_string(), 50, ShutdownConfig::default());
+
+        expect_event(&mut events, |e| matches!(e, MonitorEvent::Update(_))).await;
+        server.drop_connections();
+        server.set_var("ups", "battery.charge", "99");
+        let event = expect_event(&mut events, |e| {
+            matches!(e, MonitorEvent::Update(d) if d.battery_charge == Some(99.0))
+                || matches!(e, MonitorEvent::ConnectionLost(_))
+        })
+        .await;
+        poller.abort();
+
+        assert!(matches!(event, MonitorEvent::Update(_)));
+        assert_eq!(server.connections(), 2);
+    }
+
+    #[tokio::test]
+    async fn reports_outage_of_the_server_and_recovery() {
+        let server = FakeUpsd::start().await;
+        let monitor = monitor_for(&server, None).await;
+        let mut events = monitor.subscribe();
+        let poller = monitor.spawn("ups".to_string(), 50, ShutdownConfig::default());
+
+        expect_event(&mut events, |e| matches!(e, MonitorEvent::Update(_))).await;
+        server.refuse_connections(true);
+        server.drop_connections();
+        expect_event(&mut events, |e| {
+            matches!(e, MonitorEvent::ConnectionLost(_))
+        })
+        .await;
+
+        server.refuse_connections(false);
+        expect_event(&mut events, |e| {
+            matches!(e, MonitorEvent::ConnectionRestored)
+        })
+        .await;
+        expect_event(&mut events, |e| matches!(e, MonitorEvent::Update(_))).await;
+        poller.abort();
+    }
+
+    #[tokio::test]
+    async fn watchdog_gives_up_on_slow_replies() {
+        let server = FakeUpsd::start().await;
+        let monitor = monitor_for(&server, None).await;
+        let mut events = monitor.subscribe();
+        let poller = monitor.spawn("ups".to_string(), 100, ShutdownConfig::default());
+
+        expect_event(&mut events, |e| matches!(e, MonitorEvent::Update(_))).await;
+        server.set_reply_delay(Duration::from_secs(3));
+        let lost = expect_event(&mut events, |e| {
+            matches!(e, MonitorEvent::ConnectionLost(_))
+        })
+        .await;
+        assert!(matches!(lost, MonitorEvent::ConnectionLost(reason) if reason == "Timeout"));
+
+        server.set_reply_delay(Duration::ZERO);
+        expect_event(&mut events, |e| {
+            matches!(e, MonitorEvent::ConnectionRestored)
+        })
+        .await;
+        poller.abort();
+    }
+
+    #[tokio::test]
+    async fn countdown_is_cancelled_when_power_returns() {
+        let server = FakeUpsd::start().await;
+        let monitor = monitor_for(&server, None).await;
+        let mut events = monitor.subscribe();
+        let poller = monitor.spawn("ups".to_string(), 50, protection(60));
+
+        server.schedule(
+            Duration::from_millis(100),
+            "ups",
+            &[("ups.status", "OB DISCHRG LB"), ("battery.charge", "20")],
+        );
+        let warning = expect_event(&mut events, |e| {
+            matches!(e, MonitorEvent::ShutdownWarning(_))
+        })
+        .await;
+        assert!(matches!(warning, MonitorEvent::ShutdownWarning(s) if s > 0 && s <= 60));
+        assert!(monitor.shutdown.lock().await.pending);
+
+        server.set_status("ups", "OL CHRG");
+        server.set_var("ups", "battery.charge", "60");
+        expect_event(&mut events, |e| {
+            matches!(e, MonitorEvent::ShutdownCancelled)
+        })
+        .await;
+        assert!(!monitor.shutdown.lock().await.pending);
+        poller.abort();
+    }
+
+    fn replay_monitor(fixture: &str, speed: f64) -> Monitor {
+        let path = format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), fixture);
+        let client = NutClient::replay(Capture::load(path).unwrap(), speed);
+        Monitor::new(
+            Arc::new(Mutex::new(Some(Box::new(client)))),
+            Arc::new(Mutex::new(None)),
+            Arc::new(Mutex::new(ShutdownTracker::default())),
+        )
+        .without_system_stop()
+    }
+
+    /// Thresholds the recorded outage crosses at 33 % and recovers from at 35 %.
+    fn outage_protection(timer_sec: u64) -> ShutdownConfig {
+        ShutdownConfig {
+            battery_threshold: 35,
+            runtime_threshold: 0,
+            ..protection(timer_sec)
+        }
+    }
+
+    #[tokio::test]
+    async fn replayed_outage_warns_then_cancels() {
+        let monitor = replay_monitor("outage-low-battery.nutcap", 100.0);
+        let mut events = monitor.subscribe();
+        let poller = monitor.spawn("ups".to_string(), 10, outage_protection(60));
+
+        let warning = expect_event(&mut events, |e| {
+            matches!(e, MonitorEvent::ShutdownWarning(_))
+        })
+        .await;
+        assert!(matches!(warning, MonitorEvent::ShutdownWarning(s) if s > 55));
+        let next = expect_event(&mut events, |e| {
+            matches!(
+                e,
+                MonitorEvent::ShutdownCancelled | MonitorEvent::ShutdownExecuted(_)
+            )
+        })
+        .await;
+        assert!(matches!(next, MonitorEvent::ShutdownCancelled));
+
+        // The poll loop notices when the capture runs out
+        expect_event(&mut events, |e| {
+            matches!(e, MonitorEvent::ConnectionLost(_))
+        })
+        .await;
+        poller.abort();
+    }
+
+    #[tokio::test]
+    async fn replayed_outage_executes_without_delay() {
+        let monitor = replay_monitor("outage-low-battery.nutcap", 0.0);
+        let mut events = monitor.subscribe();
+        let poller = monitor.spawn("ups".to_string(), 10, outage_protection(0));
+
+        let executed = expect_event(&mut events, |e| {
+            matches!(e, MonitorEvent::ShutdownExecuted(_))
+        })
+        .await;
+        poller.abort();
+        assert!(matches!(executed, MonitorEvent::ShutdownExecuted(a) if a == "Shutdown"));
+    }
+
+    #[tokio::test]
+    async fn simulated_outage_drives_the_countdown() {
+        let simulator = VirtualUps::shared();
+        let monitor = Monitor::new(
+            Arc::new(Mutex::new(Some(Box::new(simulator.clone())))),
+            Arc::new(Mutex::new(None)),
+            Arc::new(Mutex::new(ShutdownTracker::default())),
+        )
+        .without_system_stop();
+        let mut events = monitor.subscribe();
+        let poller = monitor.spawn("ups".to_string(), 20, protection(60));
+
+        expect_event(
+            &mut events,
+            |e| matches!(e, MonitorEvent::Update(d) if d.status == "OL"),
+        )
+        .await;
+        {
+            let mut ups = simulator.lock().unwrap();
+            ups.apply(SimEvent::SetSpeed { factor: 1000.0 }, Instant::now());
+            ups.apply(SimEvent::Outage, Instant::now());
+        }
+        expect_event(&mut events, |e| {
+            matches!(e, MonitorEvent::ShutdownWarning(_))
+        })
+        .await;
+
+        simulator
+            .lock()
+            .unwrap()
+            .apply(SimEvent::Restore, Instant::now());
+        expect_event(&mut events, |e| {
+            matches!(e, MonitorEvent::ShutdownCancelled)
+        })
+        .await;
+        poller.abort();
+    }
+
+    #[tokio::test]
+    async fn expired_countdown_executes_the_stop() {
+        let server = FakeUpsd::start().await;
+        server.set_status("ups", "OB DISCHRG");
+        server.set_var("ups", "battery.runtime", "30");
+        let monitor = monitor_for(&server, None).await;
+        let mut events = monitor.subscribe();
+        let poller = monitor.spawn("ups".to_string(), 50, protection(0));
+
+        let executed = expect_event(&mut events, |e| {
+            matches!(e, MonitorEvent::ShutdownExecuted(_))
+        })
+        .await;
+        poller.abort();
+        assert!(matches!(executed, MonitorEvent::ShutdownExecuted(a) if a == "Shutdown"));
+    }
+
+    fn shed_printers(dry_run: bool) -> LoadSheddingConfig {
+        use crate::settings::{SheddingAction, SheddingRule, SheddingTrigger};
+        LoadSheddingConfig {
+            enabled: true,
+            dry_run,
+            restore_delay_sec: 0,
+            step_delay_ms: 0,
+            rules: vec![SheddingRule {
+                name: "printers".to_string(),
+                trigger: SheddingTrigger::ChargeBelow { percent: 50 },
+                actions: vec![SheddingAction::OutletOff { outlet: 2 }],
+            }],
+            ..Default::default()
+        }
+    }
+
+    async fn pdu_monitor(dry_run: bool) -> (FakeUpsd, Monitor, tempfile::TempDir) {
+        let mut device = crate::nut::emulator::FakeDevice::online();
+        device
+            .vars
+            .insert("outlet.2.status".to_string(), "on".to_string());
+        for cmd in ["outlet.2.load.off", "outlet.2.load.on"] {
+            device.commands.insert(cmd.to_string());
+        }
+        let server = FakeUpsd::with_devices([("ups".to_string(), device)]).await;
+        let dir = tempfile::tempdir().unwrap();
+        let db = NutDB::new(dir.path());
+        db.init().unwrap();
+        let monitor = monitor_for(&server, Some(db))
+            .await
+            .with_load_shedding(shed_printers(dry_run));
+        (server, monitor, dir)
+    }
+
+    #[tokio::test]
+    async fn sheds_outlets_on_battery_and_restores_them() {
+        let (server, monitor, dir) = pdu_monitor(false).await;
+        let mut events = monitor.subscribe();
+        let poller = monitor.spawn("ups".to_string(), 50, ShutdownConfig::default());
+
+        server.set_var("ups", "battery.charge", "40");
+        server.set_status("ups", "OB DISCHRG");
+        let shed = expect_event(&mut events, |e| {
+            matches!(e, MonitorEvent::LoadShedding { .. })
+        })
+        .await;
+        assert!(matches!(shed, MonitorEvent::LoadShedding { step, outcome }
+            if step == "outlet.2.load.off" && outcome == "succeeded"));
+        assert_eq!(server.var("ups", "outlet.2.status").as_deref(), Some("off"));
+
+        server.set_status("ups", "OL CHRG");
+        expect_event(
+            &mut events,
+            |e| matches!(e, MonitorEvent::LoadShedding { step, .. } if step == "outlet.2.load.on"),
+        )
+        .await;
+        poller.abort();
+
+        let cmds: Vec<String> = server.instcmds().into_iter().map(|c| c.1).collect();
+        assert_eq!(cmds, ["outlet.2.load.off", "outlet.2.load.on"]);
+        let journal = NutDB::new(dir.path()).get_journal(1).unwrap();
+        assert_eq!(journal.len(), 2);
+        assert!(journal.iter().all(|j| j.category == "shedding"));
+    }
+
+    #[tokio::test]
+    async fn restores_outlets_shed_before_a_restart() {
+        let (server, monitor, dir) = pdu_monitor(false).await;
+        let mut events = monitor.subscribe();
+        let poller = monitor.spawn("ups".to_string(), 50, ShutdownConfig::default());
+
+        server.set_var("ups", "battery.charge", "40");
+        server.set_status("ups", "OB DISCHRG");
+        expect_event(&mut events, |e| {
+            matches!(e, MonitorEvent::LoadShedding { .. })
+        })
+        .await;
+        poller.abort();
+        let _ = poller.await;
+
+        server.set_status("ups", "OL CHRG");
+        let poller = monitor.spawn("ups".to_string(), 50, ShutdownConfig::default());
+        expect_event(
+            &mut events,
+            |e| matches!(e, MonitorEvent::LoadShedding { step, .. } if step == "outlet.2.load.on"),
+        )
+        .await;
+        poller.abort();
+
+        assert_eq!(server.var("ups", "outlet.2.status").as_deref(), Some("on"));
+        assert!(NutDB::new(dir.path()).pending_restore().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn dry_run_sheds_nothing() {
+        let (server, monitor, dir) = pdu_monitor(true).await;
+        let mut events = monitor.subscribe();
+        let poller = monitor.spawn("ups".to_string(), 50, ShutdownConfig::default());
+
+        server.set_var("ups", "battery.charge", "40");
+        server.set_status("ups", "OB DISCHRG");
+        let shed = expect_event(&mut events, |e| {
+            matches!(e, MonitorEvent::LoadShedding { .. })
+        })
+        .await;
+        poller.abort();
+
+        assert!(matches!(shed, MonitorEvent::LoadShedding { outcome, .. } if outcome == "dry run"));
+        assert!(server.instcmds().is_empty());
+        let journal = NutDB::new(dir.path()).get_journal(1).unwrap();
+        assert_eq!(journal[0].detail.as_deref(), Some("dry run"));
+    }
+}
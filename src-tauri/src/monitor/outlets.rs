@@ -0,0 +1,237 @@
+//! Switching individual outlets (`outlet.n.load.off` and friends). A switch is
+//! prepared first, which checks the outlet and the command against the device,
+//! and only runs once the returned token is confirmed. Every attempt is written
+//! to the journal.
+
+use crate::db::NutDB;
+use crate::source::{CommandOutcome, SourceError, UpsSource};
+use chacha20poly1305::aead::{rand_core::RngCore, OsRng};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+/// How long a prepared switch waits for confirmation.
+pub const CONFIRM_WINDOW: Duration = Duration::from_secs(30);
+
+#[derive(Error, Debug)]
+pub enum OutletError {
+    #[error("The UPS reports no outlet {0}")]
+    NoSuchOutlet(u32),
+    #[error("Outlet {0} is not switchable")]
+    NotSwitchable(u32),
+    #[error("The UPS does not offer {0}")]
+    UnsupportedCommand(String),
+    #[error("Unknown or expired confirmation")]
+    NotPending,
+    #[error(transparent)]
+    Source(#[from] SourceError),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutletAction {
+    On,
+    Off,
+    /// Off, then on again after the outlet's `delay.start`
+    Cycle,
+}
+
+impl OutletAction {
+    /// The NUT instant command for outlet `index`, e.g. `outlet.2.load.off`.
+    pub fn command(self, index: u32) -> String {
+        let verb = match self {
+            OutletAction::On => "on",
+            OutletAction::Off => "off",
+            OutletAction::Cycle => "cycle",
+        };
+        format!("outlet.{index}.load.{verb}")
+    }
+}
+
+/// A checked switch waiting for confirmation.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OutletSwitch {
+    pub token: String,
+    pub ups_name: String,
+    pub index: u32,
+    /// Outlet name as the device reports it, or `Outlet <n>`
+    pub name: String,
+    pub action: OutletAction,
+    pub command: String,
+    /// `outlet.n.status` when the switch was prepared
+    pub status: Option<String>,
+}
+
+impl OutletSwitch {
+    fn describe(&self) -> String {
+        format!("{} {} ({})", self.name, self.command, self.ups_name)
+    }
+}
+
+/// Prepared switches by token.
+#[derive(Default)]
+pub struct PendingSwitches {
+    pending: HashMap<String, (OutletSwitch, Instant)>,
+}
+
+impl PendingSwitches {
+    pub fn insert(&mut self, switch: OutletSwitch, now: Instant) {
+        self.pending
+            .retain(|_, (_, at)| now.saturating_duration_since(*at) < CONFIRM_WINDOW);
+        self.pending.insert(switch.token.clone(), (switch, now));
+    }
+
+    /// Removes and returns the switch for `token` if it has not expired.
+    pub fn take(&mut self, token: &str, now: Instant) -> Result<OutletSwitch, OutletError> {
+        match self.pending.remove(token) {
+            Some((switch, at)) if now.saturating_duration_since(at) < CONFIRM_WINDOW => Ok(switch),
+            _ => Err(OutletError::NotPending),
+        }
+    }
+}
+
+/// Checks that outlet `index` exists, may be switched and that the device
+/// offers the command, and returns the switch to confirm.
+pub async fn prepare(
+    source: &mut dyn UpsSource,
+    ups_name: &str,
+    index: u32,
+    action: OutletAction,
+) -> Result<OutletSwitch, OutletError> {
+    let data = source.fetch_data(ups_name).await?;
+    // Index 0 is the main outlet, switched with the plain `load.*` commands
+    let outlet = data
+        .outlets
+        .iter()
+        .find(|o| o.index == index && index > 0)
+        .ok_or(OutletError::NoSuchOutlet(index))?;
+    if outlet.switchable == Some(false) {
+        return Err(OutletError::NotSwitchable(index));
+    }
+    let command = action.command(index);
+    if !source
+        .list_commands(ups_name)
+        .await?
+        .iter()
+        .any(|c| c == &command)
+    {
+        return Err(OutletError::UnsupportedCommand(command));
+    }
+    Ok(OutletSwitch {
+        token: format!("{:016x}", OsRng.next_u64()),
+        ups_name: ups_name.to_string(),
+        index,
+        name: outlet
+            .name
+            .clone()
+            .unwrap_or_else(|| format!("Outlet {index}")),
+        action,
+        command,
+        status: outlet.status.clone(),
+    })
+}
+
+/// Runs a confirmed switch and journals the outcome.
+pub async fn execute(
+    source: &mut dyn UpsSource,
+    db: Option<&NutDB>,
+    switch: &OutletSwitch,
+) -> Result<CommandOutcome, OutletError> {
+    let result = source
+        .run_tracked_command(&switch.ups_name, &switch.command)
+        .await;
+    let detail = match &result {
+        Ok(outcome) => outcome.to_string(),
+        Err(e) => format!("error: {e}"),
+    };
+    log::info!("Outlet switch {}: {}", switch.describe(), detail);
+    if let Some(db) = db {
+        if let Err(e) = db.add_journal_entry("outlet", &switch.describe(), Some(&detail)) {
+            log::error!("Failed to write journal: {}", e);
+        }
+    }
+    Ok(result?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nut::client::NutClient;
+    use crate::nut::emulator::{FakeDevice, FakeUpsd};
+
+    async fn pdu() -> (FakeUpsd, NutClient) {
+        let mut device = FakeDevice::online();
+        for (k, v) in [
+            ("outlet.1.desc", "Servers"),
+            ("outlet.1.status", "on"),
+            ("outlet.1.switchable", "no"),
+            ("outlet.2.desc", "Printers"),
+            ("outlet.2.status", "on"),
+            ("outlet.2.switchable", "yes"),
+        ] {
+            device.vars.insert(k.to_string(), v.to_string());
+        }
+        device.commands.insert("outlet.2.load.off".to_string());
+        let server = FakeUpsd::with_devices([("ups".to_string(), device)]).await;
+        let mut client = NutClient::new(server.config());
+        client.connect().await.unwrap();
+        (server, client)
+    }
+
+    #[tokio::test]
+    async fn switches_a_confirmed_outlet_and_journals_it() {
+        let (server, mut client) = pdu().await;
+        let dir = tempfile::tempdir().unwrap();
+        let db = NutDB::new(dir.path());
+        db.init().unwrap();
+
+        let switch = prepare(&mut client, "ups", 2, OutletAction::Off)
+            .await
+            .unwrap();
+        assert_eq!(switch.name, "Printers");
+        assert_eq!(switch.status.as_deref(), Some("on"));
+
+        let mut pending = PendingSwitches::default();
+        let now = Instant::now();
+        pending.insert(switch.clone(), now);
+        assert!(pending.take("nope", now).is_err());
+        let confirmed = pending.take(&switch.token, now).unwrap();
+        // Tokens are single use
+        assert!(pending.take(&switch.token, now).is_err());
+
+        let outcome = execute(&mut client, Some(&db), &confirmed).await.unwrap();
+        assert_eq!(outcome, CommandOutcome::Succeeded);
+        assert_eq!(server.var("ups", "outlet.2.status").as_deref(), Some("off"));
+
+        let journal = db.get_journal(1).unwrap();
+        assert_eq!(journal[0].category, "outlet");
+        assert_eq!(journal[0].detail.as_deref(), Some("succeeded"));
+    }
+
+    #[tokio::test]
+    async fn refuses_outlets_that_cannot_be_switched() {
+        let (_server, mut client) = pdu().await;
+        assert!(matches!(
+            prepare(&mut client, "ups", 1, OutletAction::Off).await,
+            Err(OutletError::NotSwitchable(1))
+        ));
+        assert!(matches!(
+            prepare(&mut client, "ups", 7, OutletAction::Off).await,
+            Err(OutletError::NoSuchOutlet(7))
+        ));
+        assert!(matches!(
+            prepare(&mut client, "ups", 2, OutletAction::Cycle).await,
+            Err(OutletError::UnsupportedCommand(_))
+        ));
+
+        let mut pending = PendingSwitches::default();
+        let switch = prepare(&mut client, "ups", 2, OutletAction::Off)
+            .await
+            .unwrap();
+        let then = Instant::now();
+        pending.insert(switch.clone(), then);
+        assert!(pending.take(&switch.token, then + CONFIRM_WINDOW).is_err());
+    }
+}
@@ -0,0 +1,161 @@
+//! Power-quality rollup. `QualityMeter` holds each reading's input voltage,
+//! frequency and operating mode until the next one, adds the time between
+//! them to the bins `crate::reports::quality::bins` puts the reading in, and
+//! hands the totals per hour to `NutDB::add_quality`.
+
+use super::energy::{Rollup, MAX_GAP_SECS};
+use crate::db::NutDB;
+use crate::nut::models::UpsData;
+use crate::reports::quality;
+use std::collections::BTreeMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// How often accumulated time is written to the database.
+const FLUSH_EVERY_SECS: f64 = 60.0;
+
+type Bin = (&'static str, i64);
+
+#[derive(Default)]
+pub struct QualityMeter {
+    /// Time (unix seconds) and bins of the previous reading
+    last: Option<(f64, Vec<Bin>)>,
+    /// Seconds not yet written, keyed by the hour they fall in and the bin
+    pending: BTreeMap<(u64, Bin), f64>,
+    last_flush: Option<f64>,
+}
+
+impl QualityMeter {
+    /// Credits the time since the previous reading to that reading's bins.
+    pub fn sample(&mut self, bins: Vec<Bin>, at: f64) {
+        if let Some((t0, previous)) = self.last.take() {
+            if at > t0 && at - t0 <= MAX_GAP_SECS {
+                let mut start = t0;
+                while start < at {
+                    let hour = (start / 3600.0).floor() * 3600.0;
+                    let end = at.min(hour + 3600.0);
+                    for bin in &previous {
+                        *self.pending.entry((hour as u64, *bin)).or_default() += end - start;
+                    }
+                    start = end;
+                }
+            }
+        }
+        self.last = Some((at, bins));
+    }
+
+    /// Seconds accumulated since the last call, per hour and bin.
+    pub fn take_pending(&mut self) -> Vec<((u64, Bin), f64)> {
+        std::mem::take(&mut self.pending).into_iter().collect()
+    }
+
+    /// Writes everything accumulated so far. On failure the time is kept for the next try.
+    pub fn flush(&mut self, db: &NutDB) {
+        for ((hour, (metric, bin)), secs) in self.take_pending() {
+            if let Err(e) = db.add_quality(hour, metric, bin, secs) {
+                log::error!("Failed to record power quality: {}", e);
+                *self.pending.entry((hour, (metric, bin))).or_default() += secs;
+            }
+        }
+    }
+
+    /// Feeds one poll result in and writes to `db` about once a minute.
+    pub fn record(&mut self, db: &NutDB, data: &UpsData) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs_f64();
+        self.sample(quality::bins(data), now);
+        let last_flush = *self.last_flush.get_or_insert(now);
+        if now - last_flush >= FLUSH_EVERY_SECS {
+            self.flush(db);
+            self.last_flush = Some(now);
+        }
+    }
+}
+
+impl Rollup for QualityMeter {
+    fn has_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    fn flush_to(&mut self, db: &NutDB) {
+        self.flush(db);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::monitor::energy::MeterGuard;
+    use crate::reports::quality::{METRIC_MODE, METRIC_VOLTAGE, MODE_BATTERY, MODE_LINE};
+
+    #[test]
+    fn holds_each_reading_until_the_next() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = NutDB::new(dir.path());
+        db.init().unwrap();
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let hour = (now / 3600 * 3600) as f64;
+
+        let reading = |status: &str, volts: f64| UpsData {
+            status: status.to_string(),
+            input_voltage: Some(volts),
+            ..Default::default()
+        };
+        let mut meter = QualityMeter::default();
+        meter.sample(quality::bins(&reading("OL", 231.0)), hour - 60.0);
+        meter.sample(quality::bins(&reading("OB", 0.0)), hour + 30.0);
+        meter.sample(quality::bins(&reading("OL", 229.0)), hour + 50.0);
+        // A gap is not credited to anything
+        meter.sample(
+            quality::bins(&reading("OL", 229.0)),
+            hour + 50.0 + MAX_GAP_SECS + 1.0,
+        );
+        meter.flush(&db);
+
+        let buckets = db.get_quality(2).unwrap();
+        let secs = |metric: &str, bin: i64| {
+            buckets
+                .iter()
+                .find(|b| b.metric == metric && b.bin == bin)
+                .map(|b| b.secs)
+        };
+        assert_eq!(secs(METRIC_MODE, MODE_LINE), Some(90.0));
+        assert_eq!(secs(METRIC_MODE, MODE_BATTERY), Some(20.0));
+        assert_eq!(secs(METRIC_VOLTAGE, 230), Some(90.0));
+        assert_eq!(secs(METRIC_VOLTAGE, 0), Some(20.0));
+        assert_eq!(secs(METRIC_VOLTAGE, 228), None);
+    }
+
+    #[test]
+    fn flushes_pending_time_when_dropped() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = NutDB::new(dir.path());
+        db.init().unwrap();
+        let db = std::sync::Arc::new(tokio::sync::Mutex::new(Some(db)));
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let hour = (now / 3600 * 3600) as f64;
+
+        let online = UpsData {
+            status: "OL".to_string(),
+            input_voltage: Some(231.0),
+            ..Default::default()
+        };
+        let mut guard = MeterGuard::<QualityMeter>::new(db.clone());
+        guard.sample(quality::bins(&online), hour);
+        guard.sample(quality::bins(&online), hour + 10.0);
+        drop(guard);
+
+        let db = db.try_lock().unwrap();
+        let buckets = db.as_ref().unwrap().get_quality(1).unwrap();
+        assert!(buckets
+            .iter()
+            .any(|b| b.metric == METRIC_MODE && b.bin == MODE_LINE && b.secs == 10.0));
+    }
+}
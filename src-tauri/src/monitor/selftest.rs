@@ -0,0 +1,368 @@
+//! Scheduled battery self-tests. `SelfTestScheduler` decides, reading by reading,
+//! when a `SelfTestConfig` calls for a test: once per weekly or monthly
+//! occurrence, outside the quiet hours, and only on mains power with enough
+//! charge. Once `start` has sent the command, the scheduler follows
+//! `ups.test.result` until the UPS reports how the test went. Every step is
+//! journalled under `JOURNAL_CATEGORY`.
+
+use crate::db::NutDB;
+use crate::nut::models::UpsData;
+use crate::settings::{SelfTestConfig, SelfTestKind, SelfTestSchedule};
+use crate::source::{CommandOutcome, SharedSource};
+use chrono::{Datelike, Days, Months, NaiveDateTime, NaiveTime, Timelike};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+pub const JOURNAL_CATEGORY: &str = "selftest";
+
+/// `LIKE` pattern matching the journal message of a test that was started.
+pub const STARTED_LIKE: &str = "% self-test started";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SelfTestStep {
+    /// Send the test command now
+    Start(SelfTestKind),
+    /// A test is due but may not run yet; reported once per occurrence
+    Skipped(String),
+    /// `ups.test.result` once the test is over
+    Finished(String),
+    /// No outcome within the configured timeout
+    TimedOut,
+}
+
+struct Running {
+    since: NaiveDateTime,
+    /// `ups.test.result` before the test, to tell a new outcome from the last one
+    before: Option<String>,
+    seen_progress: bool,
+}
+
+pub struct SelfTestScheduler {
+    config: SelfTestConfig,
+    /// Occurrences up to this time have been handled
+    last_run: NaiveDateTime,
+    /// Occurrence a skip has been reported for
+    skipped: Option<NaiveDateTime>,
+    running: Option<Running>,
+}
+
+impl SelfTestScheduler {
+    /// `last_run` is when the previous scheduled test started (local time);
+    /// occurrences up to then are not due.
+    pub fn new(config: SelfTestConfig, last_run: NaiveDateTime) -> Self {
+        Self {
+            config,
+            last_run,
+            skipped: None,
+            running: None,
+        }
+    }
+
+    /// What to do after a reading taken at local time `now`. `None` most of the time.
+    pub fn evaluate(&mut self, data: &UpsData, now: NaiveDateTime) -> Option<SelfTestStep> {
+        if data.status.is_empty() {
+            return None;
+        }
+        let result = data.device.test_result.as_deref();
+
+        if let Some(running) = &mut self.running {
+            let busy = result.is_some_and(in_progress);
+            running.seen_progress |= busy;
+            if let Some(result) = result {
+                let new_outcome =
+                    running.seen_progress || running.before.as_deref() != Some(result);
+                if !busy && new_outcome {
+                    self.running = None;
+                    return Some(SelfTestStep::Finished(result.to_string()));
+                }
+            }
+            let timeout = chrono::Duration::minutes(i64::from(self.config.timeout_min));
+            if now - running.since >= timeout {
+                self.running = None;
+                return Some(SelfTestStep::TimedOut);
+            }
+            return None;
+        }
+
+        if !self.config.enabled {
+            return None;
+        }
+        let due = previous_occurrence(&self.config, now);
+        if due <= self.last_run {
+            return None;
+        }
+        if let Some(quiet) = &self.config.quiet_hours {
+            if quiet.contains(now.hour()) {
+                return None;
+            }
+        }
+
+        let on_battery = data.status.split_whitespace().any(|flag| flag == "OB");
+        let reason = if on_battery {
+            Some("the UPS is on battery".to_string())
+        } else if let Some(charge) = data
+            .battery_charge
+            .filter(|c| *c < f64::from(self.config.min_charge))
+        {
+            Some(format!(
+                "battery charge {charge:.0}% is below {}%",
+                self.config.min_charge
+            ))
+        } else if result.is_some_and(in_progress) {
+            Some("another test is in progress".to_string())
+        } else {
+            None
+        };
+        if let Some(reason) = reason {
+            if self.skipped == Some(due) {
+                return None;
+            }
+            self.skipped = Some(due);
+            return Some(SelfTestStep::Skipped(reason));
+        }
+
+        self.last_run = now;
+        self.running = Some(Running {
+            since: now,
+            before: result.map(str::to_string),
+            seen_progress: false,
+        });
+        Some(SelfTestStep::Start(self.config.kind))
+    }
+
+    /// The test could not be started; the occurrence is not retried.
+    pub fn abandon(&mut self) {
+        self.running = None;
+    }
+}
+
+fn in_progress(result: &str) -> bool {
+    result.to_ascii_lowercase().contains("progress")
+}
+
+/// Latest scheduled time at or before `now`.
+fn previous_occurrence(config: &SelfTestConfig, now: NaiveDateTime) -> NaiveDateTime {
+    let at = NaiveTime::from_hms_opt(config.hour, 0, 0).unwrap_or_default();
+    let today = now.date();
+    match config.schedule {
+        SelfTestSchedule::Weekly { weekday } => {
+            let back = (today.weekday().num_days_from_monday() + 7 - weekday % 7) % 7;
+            let candidate = (today - Days::new(u64::from(back))).and_time(at);
+            if candidate > now {
+                candidate - Days::new(7)
+            } else {
+                candidate
+            }
+        }
+        SelfTestSchedule::Monthly { day } => {
+            let candidate = today.with_day(day).unwrap_or(today).and_time(at);
+            if candidate > now {
+                candidate - Months::new(1)
+            } else {
+                candidate
+            }
+        }
+    }
+}
+
+pub fn kind_name(kind: SelfTestKind) -> &'static str {
+    match kind {
+        SelfTestKind::Quick => "Quick",
+        SelfTestKind::Deep => "Deep",
+    }
+}
+
+/// Commands that start `kind`, best first. Drivers without a deep variant
+/// run their one long test on plain `test.battery.start`.
+fn commands_for(kind: SelfTestKind) -> &'static [&'static str] {
+    match kind {
+        SelfTestKind::Quick => &["test.battery.start.quick"],
+        SelfTestKind::Deep => &["test.battery.start.deep", "test.battery.start"],
+    }
+}
+
+/// Sends the first command for `kind` the UPS supports and returns it.
+pub async fn start(
+    source: &SharedSource,
+    ups_name: &str,
+    kind: SelfTestKind,
+) -> Result<String, String> {
+    let mut guard = source.lock().await;
+    let client = guard.as_mut().ok_or("not connected")?;
+    let supported = client
+        .list_commands(ups_name)
+        .await
+        .map_err(|e| e.to_string())?;
+    let command = commands_for(kind)
+        .iter()
+        .find(|c| supported.iter().any(|s| s == *c))
+        .ok_or_else(|| {
+            format!(
+                "the UPS does not support a {} test",
+                kind_name(kind).to_lowercase()
+            )
+        })?;
+    match client.run_tracked_command(ups_name, command).await {
+        Ok(CommandOutcome::Failed(reason)) => Err(format!("{command}: {reason}")),
+        Ok(_) => Ok(command.to_string()),
+        Err(e) => Err(format!("{command}: {e}")),
+    }
+}
+
+pub async fn journal(db: &Arc<Mutex<Option<NutDB>>>, message: &str, detail: &str) {
+    log::info!("Self-test: {} ({})", message, detail);
+    if let Some(db) = db.lock().await.as_ref() {
+        if let Err(e) = db.add_journal_entry(JOURNAL_CATEGORY, message, Some(detail)) {
+            log::error!("Failed to write journal: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nut::simulator::{SimEvent, VirtualUps};
+    use crate::settings::QuietHours;
+    use chrono::NaiveDate;
+
+    fn at(day: u32, hour: u32, min: u32) -> NaiveDateTime {
+        // May 2024; the 20th is a Monday
+        NaiveDate::from_ymd_opt(2024, 5, day)
+            .unwrap()
+            .and_hms_opt(hour, min, 0)
+            .unwrap()
+    }
+
+    fn reading(status: &str, charge: f64, result: &str) -> UpsData {
+        let mut data = UpsData {
+            status: status.to_string(),
+            battery_charge: Some(charge),
+            ..Default::default()
+        };
+        data.device.test_result = Some(result.to_string());
+        data
+    }
+
+    #[test]
+    fn waits_for_mains_charge_and_the_end_of_quiet_hours() {
+        let config = SelfTestConfig {
+            enabled: true,
+            schedule: SelfTestSchedule::Weekly { weekday: 0 },
+            hour: 22,
+            quiet_hours: Some(QuietHours {
+                start_hour: 23,
+                end_hour: 6,
+            }),
+            ..Default::default()
+        };
+        let mut scheduler = SelfTestScheduler::new(config, at(19, 12, 0));
+        let idle = "No test initiated";
+
+        assert_eq!(
+            scheduler.evaluate(&reading("OL", 100.0, idle), at(20, 21, 0)),
+            None
+        );
+        assert_eq!(
+            scheduler.evaluate(&reading("OB DISCHRG", 100.0, idle), at(20, 22, 30)),
+            Some(SelfTestStep::Skipped("the UPS is on battery".to_string()))
+        );
+        // Reported once; quiet hours hold it back; the skip is not repeated
+        assert_eq!(
+            scheduler.evaluate(&reading("OB", 99.0, idle), at(20, 22, 31)),
+            None
+        );
+        assert_eq!(
+            scheduler.evaluate(&reading("OL", 100.0, idle), at(20, 23, 10)),
+            None
+        );
+        assert_eq!(
+            scheduler.evaluate(&reading("OL", 80.0, idle), at(21, 6, 10)),
+            None
+        );
+
+        assert_eq!(
+            scheduler.evaluate(&reading("OL", 95.0, idle), at(21, 7, 0)),
+            Some(SelfTestStep::Start(SelfTestKind::Quick))
+        );
+        let busy = reading("OB DISCHRG CAL", 95.0, "In progress");
+        assert_eq!(scheduler.evaluate(&busy, at(21, 7, 1)), None);
+        assert_eq!(
+            scheduler.evaluate(&reading("OL", 94.0, "Done and passed"), at(21, 7, 2)),
+            Some(SelfTestStep::Finished("Done and passed".to_string()))
+        );
+        assert_eq!(
+            scheduler.evaluate(&reading("OL", 95.0, idle), at(21, 8, 0)),
+            None
+        );
+
+        // Next Monday
+        assert_eq!(
+            scheduler.evaluate(&reading("OL", 100.0, idle), at(27, 22, 0)),
+            Some(SelfTestStep::Start(SelfTestKind::Quick))
+        );
+    }
+
+    #[test]
+    fn monthly_tests_time_out_without_a_new_result() {
+        let config = SelfTestConfig {
+            enabled: true,
+            kind: SelfTestKind::Deep,
+            schedule: SelfTestSchedule::Monthly { day: 1 },
+            hour: 10,
+            timeout_min: 30,
+            ..Default::default()
+        };
+        let start = NaiveDate::from_ymd_opt(2024, 6, 1).unwrap();
+        let mut scheduler = SelfTestScheduler::new(config, at(15, 0, 0));
+        let passed = reading("OL", 100.0, "Done and passed");
+
+        assert_eq!(
+            scheduler.evaluate(&passed, start.and_hms_opt(9, 0, 0).unwrap()),
+            None
+        );
+        assert_eq!(
+            scheduler.evaluate(&passed, start.and_hms_opt(10, 5, 0).unwrap()),
+            Some(SelfTestStep::Start(SelfTestKind::Deep))
+        );
+        // The previous outcome is not taken for this test's
+        assert_eq!(
+            scheduler.evaluate(&passed, start.and_hms_opt(10, 20, 0).unwrap()),
+            None
+        );
+        assert_eq!(
+            scheduler.evaluate(&passed, start.and_hms_opt(10, 35, 0).unwrap()),
+            Some(SelfTestStep::TimedOut)
+        );
+    }
+
+    #[tokio::test]
+    async fn starts_the_best_supported_test() {
+        let simulator = VirtualUps::shared();
+        let source: SharedSource = Arc::new(Mutex::new(Some(Box::new(simulator.clone()))));
+
+        // The simulator has no deep variant and runs its long test on the plain command
+        assert_eq!(
+            start(&source, "ups", SelfTestKind::Deep).await.as_deref(),
+            Ok("test.battery.start")
+        );
+        simulator
+            .lock()
+            .unwrap()
+            .instant_command("test.battery.stop")
+            .unwrap();
+
+        simulator
+            .lock()
+            .unwrap()
+            .apply(SimEvent::Outage, std::time::Instant::now());
+        assert!(start(&source, "ups", SelfTestKind::Quick).await.is_err());
+        simulator
+            .lock()
+            .unwrap()
+            .apply(SimEvent::Restore, std::time::Instant::now());
+        assert_eq!(
+            start(&source, "ups", SelfTestKind::Quick).await.as_deref(),
+            Ok("test.battery.start.quick")
+        );
+    }
+}
@@ -0,0 +1,304 @@
+//! Automatic load shedding. `LoadShedder` decides, reading by reading, which
+//! commands a `LoadSheddingConfig` calls for: a rule fires once per outage when
+//! its trigger is met, and what it shed is undone in reverse order after power
+//! has been back for the restore delay. `execute` runs such a plan one command
+//! at a time through `UpsSource::run_tracked_command` and journals each step.
+
+use super::outlets::OutletAction;
+use crate::db::NutDB;
+use crate::nut::models::UpsData;
+use crate::settings::{LoadSheddingConfig, SheddingAction, SheddingTrigger};
+use crate::source::SharedSource;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+/// One command of a shedding or restore sequence.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShedStep {
+    /// Name of the rule the step belongs to
+    pub rule: String,
+    pub command: String,
+    /// Undoes an earlier step
+    pub restore: bool,
+}
+
+impl ShedStep {
+    fn describe(&self) -> String {
+        let verb = if self.restore { "Restore" } else { "Shed" };
+        format!("{verb} ({}): {}", self.rule, self.command)
+    }
+}
+
+pub struct LoadShedder {
+    config: LoadSheddingConfig,
+    on_battery_since: Option<Instant>,
+    power_back_since: Option<Instant>,
+    fired: Vec<bool>,
+    /// Steps that undo what has been shed, in the order they were shed
+    undo: Vec<ShedStep>,
+}
+
+impl LoadShedder {
+    /// `pending` is what an earlier run shed and had not restored yet; its rules
+    /// count as fired and it is restored like anything shed from now on.
+    pub fn new(config: LoadSheddingConfig, pending: Vec<ShedStep>) -> Self {
+        let fired = config
+            .rules
+            .iter()
+            .map(|rule| pending.iter().any(|step| step.rule == rule.name))
+            .collect();
+        Self {
+            config,
+            on_battery_since: None,
+            power_back_since: None,
+            fired,
+            undo: pending,
+        }
+    }
+
+    /// Steps that undo what has been shed, in the order they were shed.
+    pub fn pending_restore(&self) -> &[ShedStep] {
+        &self.undo
+    }
+
+    /// Steps to run now, in order. Empty most of the time.
+    pub fn evaluate(&mut self, data: &UpsData, now: Instant) -> Vec<ShedStep> {
+        if !self.config.enabled || data.status.is_empty() {
+            return Vec::new();
+        }
+
+        let on_battery = data.status.split_whitespace().any(|flag| flag == "OB");
+        if !on_battery {
+            self.on_battery_since = None;
+            if !self.fired.contains(&true) && self.undo.is_empty() {
+                return Vec::new();
+            }
+            let back = *self.power_back_since.get_or_insert(now);
+            if now.saturating_duration_since(back)
+                < Duration::from_secs(self.config.restore_delay_sec)
+            {
+                return Vec::new();
+            }
+            self.fired.iter_mut().for_each(|f| *f = false);
+            self.power_back_since = None;
+            let mut undo = std::mem::take(&mut self.undo);
+            if !self.config.restore {
+                return Vec::new();
+            }
+            undo.reverse();
+            return undo;
+        }
+
+        self.power_back_since = None;
+        let since = *self.on_battery_since.get_or_insert(now);
+        let on_battery_for = now.saturating_duration_since(since);
+        let mut steps = Vec::new();
+        for (rule, fired) in self.config.rules.iter().zip(self.fired.iter_mut()) {
+            let due = match rule.trigger {
+                SheddingTrigger::OnBatteryFor { minutes } => {
+                    on_battery_for >= Duration::from_secs(u64::from(minutes) * 60)
+                }
+                SheddingTrigger::ChargeBelow { percent } => data
+                    .battery_charge
+                    .is_some_and(|charge| charge < f64::from(percent)),
+            };
+            if *fired || !due {
+                continue;
+            }
+            *fired = true;
+            for action in &rule.actions {
+                let (command, undo) = match action {
+                    SheddingAction::OutletOff { outlet } => (
+                        OutletAction::Off.command(*outlet),
+                        Some(OutletAction::On.command(*outlet)),
+                    ),
+                    SheddingAction::Command {
+                        command,
+                        restore_command,
+                    } => (command.clone(), restore_command.clone()),
+                };
+                steps.push(ShedStep {
+                    rule: rule.name.clone(),
+                    command,
+                    restore: false,
+                });
+                if let Some(command) = undo {
+                    self.undo.push(ShedStep {
+                        rule: rule.name.clone(),
+                        command,
+                        restore: true,
+                    });
+                }
+            }
+        }
+        steps
+    }
+}
+
+/// Runs `steps` in order, `step_delay` apart, and returns the outcome of each
+/// as journalled. In a dry run nothing is sent.
+pub async fn execute(
+    source: &SharedSource,
+    db: &Arc<Mutex<Option<NutDB>>>,
+    ups_name: &str,
+    steps: &[ShedStep],
+    dry_run: bool,
+    step_delay: Duration,
+) -> Vec<String> {
+    let mut outcomes = Vec::new();
+    for (i, step) in steps.iter().enumerate() {
+        if i > 0 && !step_delay.is_zero() {
+            tokio::time::sleep(step_delay).await;
+        }
+        let detail = if dry_run {
+            "dry run".to_string()
+        } else {
+            let mut guard = source.lock().await;
+            match guard.as_mut() {
+                None => "error: not connected".to_string(),
+                Some(client) => match client.run_tracked_command(ups_name, &step.command).await {
+                    Ok(outcome) => outcome.to_string(),
+                    Err(e) => format!("error: {e}"),
+                },
+            }
+        };
+        log::warn!("Load shedding: {} -> {}", step.describe(), detail);
+        if let Some(db) = db.lock().await.as_ref() {
+            if let Err(e) = db.add_journal_entry("shedding", &step.describe(), Some(&detail)) {
+                log::error!("Failed to write journal: {}", e);
+            }
+        }
+        outcomes.push(detail);
+    }
+    outcomes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::SheddingRule;
+
+    fn reading(status: &str, charge: f64) -> UpsData {
+        UpsData {
+            status: status.to_string(),
+            battery_charge: Some(charge),
+            ..Default::default()
+        }
+    }
+
+    fn policy() -> LoadSheddingConfig {
+        LoadSheddingConfig {
+            enabled: true,
+            restore_delay_sec: 30,
+            rules: vec![
+                SheddingRule {
+                    name: "printers".to_string(),
+                    trigger: SheddingTrigger::OnBatteryFor { minutes: 2 },
+                    actions: vec![
+                        SheddingAction::OutletOff { outlet: 2 },
+                        SheddingAction::OutletOff { outlet: 3 },
+                    ],
+                },
+                SheddingRule {
+                    name: "beeper".to_string(),
+                    trigger: SheddingTrigger::ChargeBelow { percent: 50 },
+                    actions: vec![SheddingAction::Command {
+                        command: "beeper.disable".to_string(),
+                        restore_command: None,
+                    }],
+                },
+            ],
+            ..Default::default()
+        }
+    }
+
+    fn commands(steps: &[ShedStep]) -> Vec<&str> {
+        steps.iter().map(|s| s.command.as_str()).collect()
+    }
+
+    #[test]
+    fn sheds_once_per_outage_and_restores_in_reverse() {
+        let mut shedder = LoadShedder::new(policy(), Vec::new());
+        let t = Instant::now();
+        let min = Duration::from_secs(60);
+
+        assert!(shedder.evaluate(&reading("OL", 100.0), t).is_empty());
+        assert!(shedder.evaluate(&reading("OB DISCHRG", 90.0), t).is_empty());
+        let steps = shedder.evaluate(&reading("OB DISCHRG", 80.0), t + 2 * min);
+        assert_eq!(commands(&steps), ["outlet.2.load.off", "outlet.3.load.off"]);
+        assert!(shedder
+            .evaluate(&reading("OB DISCHRG", 70.0), t + 3 * min)
+            .is_empty());
+        let steps = shedder.evaluate(&reading("OB DISCHRG", 45.0), t + 4 * min);
+        assert_eq!(commands(&steps), ["beeper.disable"]);
+
+        // Power back, but not for long enough yet
+        assert!(shedder
+            .evaluate(&reading("OL CHRG", 45.0), t + 5 * min)
+            .is_empty());
+        let steps = shedder.evaluate(&reading("OL CHRG", 46.0), t + 6 * min);
+        assert_eq!(commands(&steps), ["outlet.3.load.on", "outlet.2.load.on"]);
+        assert!(steps.iter().all(|s| s.restore));
+
+        // The next outage starts from scratch
+        assert!(shedder
+            .evaluate(&reading("OB DISCHRG", 90.0), t + 7 * min)
+            .is_empty());
+        assert_eq!(
+            shedder
+                .evaluate(&reading("OB DISCHRG", 85.0), t + 9 * min)
+                .len(),
+            2
+        );
+    }
+
+    #[test]
+    fn a_short_return_of_power_does_not_restore() {
+        let mut shedder = LoadShedder::new(policy(), Vec::new());
+        let t = Instant::now();
+        shedder.evaluate(&reading("OB", 40.0), t);
+        assert!(shedder
+            .evaluate(&reading("OL", 40.0), t + Duration::from_secs(10))
+            .is_empty());
+        assert!(shedder
+            .evaluate(&reading("OB", 40.0), t + Duration::from_secs(20))
+            .is_empty());
+        assert!(shedder
+            .evaluate(&reading("OL", 40.0), t + Duration::from_secs(45))
+            .is_empty());
+
+        let mut disabled = LoadShedder::new(
+            LoadSheddingConfig {
+                enabled: false,
+                ..policy()
+            },
+            Vec::new(),
+        );
+        assert!(disabled.evaluate(&reading("OB", 10.0), t).is_empty());
+    }
+
+    #[test]
+    fn restores_what_an_earlier_run_shed() {
+        let pending = ["outlet.2.load.on", "outlet.3.load.on"].map(|command| ShedStep {
+            rule: "printers".to_string(),
+            command: command.to_string(),
+            restore: true,
+        });
+        let mut shedder = LoadShedder::new(policy(), pending.to_vec());
+        let t = Instant::now();
+
+        // Still the same outage: the rule does not fire a second time
+        assert!(shedder.evaluate(&reading("OB DISCHRG", 80.0), t).is_empty());
+        assert!(shedder
+            .evaluate(&reading("OB DISCHRG", 80.0), t + Duration::from_secs(180))
+            .is_empty());
+
+        assert!(shedder
+            .evaluate(&reading("OL", 80.0), t + Duration::from_secs(200))
+            .is_empty());
+        let steps = shedder.evaluate(&reading("OL", 80.0), t + Duration::from_secs(230));
+        assert_eq!(commands(&steps), ["outlet.3.load.on", "outlet.2.load.on"]);
+        assert!(shedder.pending_restore().is_empty());
+    }
+}
@@ -0,0 +1,406 @@
+use crate::nut::models::UpsData;
+use crate::settings::{RuntimeSource, ShutdownConfig};
+use std::time::{Duration, Instant};
+
+#[derive(Default)]
+pub struct ShutdownTracker {
+    pub pending: bool,
+    pub countdown_remaining: u64,
+    pub action_type: String,
+    deadline: Option<Instant>,
+}
+
+/// What the poll loop should do after feeding a reading into the tracker.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ShutdownStep {
+    /// Conditions are fine and nothing is pending.
+    Idle,
+    /// Thresholds are breached; seconds left until the system stop.
+    Countdown(u64),
+    /// The countdown has expired; run this stop type now.
+    Execute(String),
+    /// A pending stop was called off because conditions recovered.
+    Cancelled,
+}
+
+impl ShutdownTracker {
+    /// Checks a reading against the thresholds and advances the countdown.
+    /// `now` is passed in so the countdown does not depend on the poll interval.
+    pub fn evaluate(
+        &mut self,
+        config: &ShutdownConfig,
+        data: &UpsData,
+        now: Instant,
+    ) -> ShutdownStep {
+        if !config.enabled {
+            return ShutdownStep::Idle;
+        }
+
+        let bat_charge = data.battery_charge.unwrap_or(100.0);
+        let bat_critical = bat_charge < config.battery_threshold as f64;
+
+        // The model predicts a discharge; on mains its estimate means nothing yet
+        let on_battery = data.status.split_whitespace().any(|f| f == "OB");
+        let predicted = match config.runtime_source {
+            RuntimeSource::Predicted if on_battery => data.runtime_estimate.map(|e| e.low),
+            _ => None,
+        };
+        let runtime_val = predicted.or(data.battery_runtime).unwrap_or(f64::MAX);
+        let runtime_critical = runtime_val < config.runtime_threshold as f64;
+
+        if bat_critical || runtime_critical {
+            if !self.pending {
+                log::info!(
+                    "Shutdown Triggered! Battery: {}%, Runtime: {}s",
+                    bat_charge,
+                    runtime_val
+                );
+                self.pending = true;
+                self.action_type = config.stop_type.clone();
+                self.deadline = Some(now + Duration::from_secs(config.timer_sec));
+            }
+
+            let deadline = self.deadline.unwrap_or(now);
+            // Round up so the UI shows "1" rather than "0" during the last second
+            let remaining = deadline.saturating_duration_since(now);
+            self.countdown_remaining =
+                remaining.as_secs() + u64::from(remaining.subsec_nanos() > 0);
+
+            if self.countdown_remaining == 0 {
+                log::info!(
+                    "Countdown reached 0. Executing system stop: {}",
+                    self.action_type
+                );
+                self.pending = false;
+                self.deadline = None;
+                ShutdownStep::Execute(self.action_type.clone())
+            } else {
+                ShutdownStep::Countdown(self.countdown_remaining)
+            }
+        } else if self.pending {
+            // Conditions met (Power restored or charged enough)
+            log::info!("Power conditions restored. Shutdown cancelled.");
+            self.cancel();
+            ShutdownStep::Cancelled
+        } else {
+            ShutdownStep::Idle
+        }
+    }
+
+    pub fn cancel(&mut self) {
+        self.pending = false;
+        self.deadline = None;
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn acquire_shutdown_privilege() -> Result<(), String> {
+    use windows::Win32::Foundation::{FALSE, HANDLE, LUID};
+    use windows::Win32::Security::{
+        AdjustTokenPrivileges, LookupPrivilegeValueW, LUID_AND_ATTRIBUTES, SE_PRIVILEGE_ENABLED,
+        SE_SHUTDOWN_NAME, TOKEN_ADJUST_PRIVILEGES, TOKEN_PRIVILEGES, TOKEN_QUERY,
+    };
+    use windows::Win32::System::Threading::{GetCurrentProcess, OpenProcessToken};
+
+    unsafe {
+        let mut token: HANDLE = HANDLE::default();
+        if OpenProcessToken(
+            GetCurrentProcess(),
+            TOKEN_ADJUST_PRIVILEGES | TOKEN_QUERY,
+            &mut token,
+        )
+        .is_err()
+        {
+            return Err("Failed to open process token".to_string());
+        }
+
+        let mut luid = LUID::default();
+        if LookupPrivilegeValueW(None, SE_SHUTDOWN_NAME, &mut luid).is_err() {
+            return Err("Failed to lookup privilege".to_string());
+        }
+
+        let tp = TOKEN_PRIVILEGES {
+            PrivilegeCount: 1,
+            Privileges: [LUID_AND_ATTRIBUTES {
+                Luid: luid,
+                Attributes: SE_PRIVILEGE_ENABLED,
+            }],
+        };
+
+        if AdjustTokenPrivileges(token, FALSE, Some(&tp), 0, None, None).is_err() {
+            return Err("Failed to adjust token privileges".to_string());
+        }
+    }
+    Ok(())
+}
+
+/// Executes the configured system stop (`Shutdown`, `Hibernate` or `Sleep`).
+pub async fn trigger_system_stop(action_type: String, delay_sec: u64) -> Result<(), String> {
+    #[cfg(target_os = "windows")]
+    {
+        use windows::core::{HSTRING, PCWSTR};
+        use windows::Win32::Foundation::{BOOLEAN, FALSE, TRUE};
+        use windows::Win32::System::Power::SetSuspendState;
+        use windows::Win32::System::Shutdown::{
+            InitiateSystemShutdownExW, SHTDN_REASON_FLAG_PLANNED, SHTDN_REASON_MAJOR_OTHER,
+            SHTDN_REASON_MINOR_OTHER,
+        };
+
+        // Try to acquire permissions first
+        if let Err(e) = acquire_shutdown_privilege() {
+            log::warn!("Could not acquire shutdown privilege: {}", e);
+        }
+
+        match action_type.as_str() {
+            "Shutdown" => unsafe {
+                let msg = HSTRING::from("UPS Shutdown Triggered");
+                let reason =
+                    SHTDN_REASON_MAJOR_OTHER | SHTDN_REASON_MINOR_OTHER | SHTDN_REASON_FLAG_PLANNED;
+
+                // InitiateSystemShutdownExW(machine, message, timeout, force_apps, reboot, reason)
+                if InitiateSystemShutdownExW(
+                    None,
+                    PCWSTR::from_raw(msg.as_ptr()),
+                    delay_sec as u32,
+                    TRUE,  // Force apps closed
+                    FALSE, // Reboot? No, Shutdown
+                    reason,
+                )
+                .is_err()
+                {
+                    return Err("Failed to initiate system shutdown".to_string());
+                }
+                Ok(())
+            },
+            "Hibernate" => unsafe {
+                // SetSuspendState uses BOOLEAN (u8), not BOOL (i32). TRUE/FALSE are BOOL.
+                if SetSuspendState(BOOLEAN(1), BOOLEAN(0), BOOLEAN(0)).as_bool() {
+                    Ok(())
+                } else {
+                    Err("Failed to trigger Hibernate".to_string())
+                }
+            },
+            "Sleep" => unsafe {
+                // Hibernate = FALSE means Sleep
+                if SetSuspendState(BOOLEAN(0), BOOLEAN(0), BOOLEAN(0)).as_bool() {
+                    Ok(())
+                } else {
+                    Err("Failed to trigger Sleep".to_string())
+                }
+            },
+            _ => Err("Invalid action type".to_string()),
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        // Headless installs run this from the daemon; assumes systemd for hibernate/suspend
+        let args: Vec<String> = match action_type.as_str() {
+            "Shutdown" if delay_sec == 0 => vec!["-h".into(), "now".into()],
+            // `shutdown` only takes whole minutes
+            "Shutdown" => vec!["-h".into(), format!("+{}", delay_sec.div_ceil(60))],
+            "Hibernate" => return run_system_command("systemctl", &["hibernate"]).await,
+            "Sleep" => return run_system_command("systemctl", &["suspend"]).await,
+            _ => return Err("Invalid action type".to_string()),
+        };
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        run_system_command("shutdown", &args).await
+    }
+
+    #[cfg(not(any(target_os = "windows", target_os = "linux")))]
+    {
+        let _ = (action_type, delay_sec);
+        Err("System stop is only supported on Windows and Linux".to_string())
+    }
+}
+
+/// Cancels a system stop that was scheduled with a delay.
+pub async fn abort_system_stop() -> Result<(), String> {
+    #[cfg(target_os = "windows")]
+    {
+        use windows::Win32::System::Shutdown::AbortSystemShutdownW;
+
+        unsafe {
+            let _ = AbortSystemShutdownW(None);
+        }
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        // Fails when nothing is scheduled, which is the common case
+        if let Err(e) = run_system_command("shutdown", &["-c"]).await {
+            log::debug!("No scheduled shutdown to cancel: {}", e);
+        }
+        Ok(())
+    }
+
+    #[cfg(not(any(target_os = "windows", target_os = "linux")))]
+    {
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "linux")]
+async fn run_system_command(program: &str, args: &[&str]) -> Result<(), String> {
+    let status = tokio::process::Command::new(program)
+        .args(args)
+        .status()
+        .await
+        .map_err(|e| format!("Failed to run {program}: {e}"))?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("{program} exited with {status}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nut::models::RuntimeEstimate;
+
+    /// 30% battery and 120 s runtime thresholds, as by default
+    fn config() -> ShutdownConfig {
+        ShutdownConfig {
+            enabled: true,
+            stop_type: "Hibernate".to_string(),
+            timer_sec: 60,
+            ..ShutdownConfig::default()
+        }
+    }
+
+    fn reading(status: &str, charge: f64) -> UpsData {
+        UpsData {
+            status: status.to_string(),
+            battery_charge: Some(charge),
+            battery_runtime: Some(600.0),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn counts_down_to_the_deadline_whatever_the_poll_interval() {
+        let mut tracker = ShutdownTracker::default();
+        let start = Instant::now();
+        let low = reading("OB", 20.0);
+
+        assert_eq!(
+            tracker.evaluate(&config(), &reading("OB", 80.0), start),
+            ShutdownStep::Idle
+        );
+        assert_eq!(
+            tracker.evaluate(&config(), &low, start),
+            ShutdownStep::Countdown(60)
+        );
+        assert!(tracker.pending);
+        assert_eq!(tracker.action_type, "Hibernate");
+        // A late poll sees the time that really passed
+        assert_eq!(
+            tracker.evaluate(&config(), &low, start + Duration::from_millis(45_500)),
+            ShutdownStep::Countdown(15)
+        );
+        assert_eq!(
+            tracker.evaluate(&config(), &low, start + Duration::from_secs(60)),
+            ShutdownStep::Execute("Hibernate".to_string())
+        );
+        assert!(!tracker.pending);
+    }
+
+    #[test]
+    fn recovery_cancels_and_a_new_breach_restarts_the_delay() {
+        let mut tracker = ShutdownTracker::default();
+        let start = Instant::now();
+        let low = reading("OB", 20.0);
+
+        tracker.evaluate(&config(), &low, start);
+        assert_eq!(
+            tracker.evaluate(
+                &config(),
+                &reading("OL", 35.0),
+                start + Duration::from_secs(30)
+            ),
+            ShutdownStep::Cancelled
+        );
+        assert!(!tracker.pending);
+        assert_eq!(
+            tracker.evaluate(&config(), &low, start + Duration::from_secs(50)),
+            ShutdownStep::Countdown(60)
+        );
+    }
+
+    #[test]
+    fn compares_the_reported_runtime_by_default() {
+        let mut tracker = ShutdownTracker::default();
+        let mut data = reading("OB", 80.0);
+        data.runtime_estimate = Some(RuntimeEstimate {
+            runtime: 150.0,
+            low: 90.0,
+            high: 210.0,
+            samples: 4,
+        });
+        assert_eq!(
+            tracker.evaluate(&config(), &data, Instant::now()),
+            ShutdownStep::Idle
+        );
+
+        data.battery_runtime = Some(100.0);
+        assert_eq!(
+            tracker.evaluate(&config(), &data, Instant::now()),
+            ShutdownStep::Countdown(60)
+        );
+    }
+
+    #[test]
+    fn uses_the_predicted_lower_bound_only_on_battery() {
+        let mut tracker = ShutdownTracker::default();
+        let predicted = ShutdownConfig {
+            runtime_source: RuntimeSource::Predicted,
+            ..config()
+        };
+        let estimate = Some(RuntimeEstimate {
+            runtime: 150.0,
+            low: 90.0,
+            high: 210.0,
+            samples: 4,
+        });
+        let start = Instant::now();
+
+        let mut online = reading("OL", 80.0);
+        online.runtime_estimate = estimate;
+        assert_eq!(
+            tracker.evaluate(&predicted, &online, start),
+            ShutdownStep::Idle
+        );
+
+        let mut discharging = reading("OB DISCHRG", 80.0);
+        discharging.runtime_estimate = estimate;
+        assert_eq!(
+            tracker.evaluate(&predicted, &discharging, start),
+            ShutdownStep::Countdown(60)
+        );
+
+        // Without a model the reported runtime still counts
+        let mut tracker = ShutdownTracker::default();
+        let mut unfitted = reading("OB", 80.0);
+        unfitted.battery_runtime = Some(100.0);
+        assert_eq!(
+            tracker.evaluate(&predicted, &unfitted, start),
+            ShutdownStep::Countdown(60)
+        );
+    }
+
+    #[test]
+    fn stays_idle_when_disabled() {
+        let mut tracker = ShutdownTracker::default();
+        let disabled = ShutdownConfig {
+            enabled: false,
+            ..config()
+        };
+        assert_eq!(
+            tracker.evaluate(&disabled, &reading("OB", 5.0), Instant::now()),
+            ShutdownStep::Idle
+        );
+        assert!(!tracker.pending);
+    }
+}
@@ -0,0 +1,48 @@
+//! Runs a program for every notification, in the spirit of upsmon's
+//! NOTIFYCMD. The message is the only argument; `UPS_EVENT` (e.g.
+//! `powerLost`), `UPS_SEVERITY`, `UPS_TITLE`, `UPS_BODY` and `UPS_TIMESTAMP`
+//! carry the parts for scripts that want them.
+
+use super::{tag, Channel, Notification};
+use async_trait::async_trait;
+use std::time::Duration;
+
+/// A program that has not finished by then is left to run on its own.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+pub struct CommandChannel {
+    program: String,
+}
+
+impl CommandChannel {
+    pub fn new(program: String) -> Self {
+        Self { program }
+    }
+}
+
+#[async_trait]
+impl Channel for CommandChannel {
+    fn name(&self) -> &str {
+        "command"
+    }
+
+    async fn send(&self, notification: &Notification) -> Result<(), String> {
+        let child = tokio::process::Command::new(&self.program)
+            .arg(format!("{}: {}", notification.title, notification.body))
+            .env("UPS_EVENT", tag(notification.kind))
+            .env("UPS_SEVERITY", tag(notification.severity))
+            .env("UPS_TITLE", &notification.title)
+            .env("UPS_BODY", &notification.body)
+            .env("UPS_TIMESTAMP", notification.timestamp.to_string())
+            .status();
+        match tokio::time::timeout(TIMEOUT, child).await {
+            Ok(Ok(status)) if status.success() => Ok(()),
+            Ok(Ok(status)) => Err(format!("{} exited with {status}", self.program)),
+            Ok(Err(e)) => Err(format!("Failed to run {}: {e}", self.program)),
+            Err(_) => Err(format!(
+                "{} did not finish within {TIMEOUT:?}",
+                self.program
+            )),
+        }
+    }
+}
@@ -0,0 +1,32 @@
+//! Native OS notifications through `tauri-plugin-notification`. Sent from
+//! the backend, so they appear while the window is hidden in the tray.
+
+use super::{Channel, Notification};
+use async_trait::async_trait;
+use tauri::AppHandle;
+use tauri_plugin_notification::NotificationExt;
+
+pub struct DesktopChannel {
+    app: AppHandle,
+}
+
+impl DesktopChannel {
+    pub fn new(app: AppHandle) -> Self {
+        Self { app }
+    }
+}
+
+#[async_trait]
+impl Channel for DesktopChannel {
+    fn name(&self) -> &str {
+        "desktop"
+    }
+
+    async fn send(&self, notification: &Notification) -> Result<(), String> {
+        let mut builder = self.app.notification().builder().title(&notification.title);
+        if !notification.body.is_empty() {
+            builder = builder.body(&notification.body);
+        }
+        builder.show().map_err(|e| e.to_string())
+    }
+}
@@ -0,0 +1,424 @@
+//! Mail for the people on call. Each notification is rendered through the
+//! subject and body templates of `EmailNotifications`. A line that keeps
+//! dropping out would fill an inbox, so once power is lost `Batcher` holds
+//! back everything but critical notifications, and when power has stayed back
+//! for `settle_sec` it sends one summary of the whole outage instead.
+
+use super::{tag, Channel, Notification};
+use crate::settings::{AlertSeverity, EmailNotifications, NotificationKind, SmtpSecurity};
+use async_trait::async_trait;
+use chrono::TimeZone;
+use lettre::message::header::ContentType;
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// A server that stops answering is given up on after this long.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+pub struct EmailChannel {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+    to: Vec<Mailbox>,
+    events: Vec<NotificationKind>,
+    subject: String,
+    body: String,
+    batcher: Mutex<Batcher>,
+}
+
+impl EmailChannel {
+    pub fn new(config: &EmailNotifications, password: Option<String>) -> Result<Self, String> {
+        let builder = match config.security {
+            SmtpSecurity::StartTls => {
+                AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&config.host)
+                    .map_err(|e| e.to_string())?
+            }
+            SmtpSecurity::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&config.host)
+                .map_err(|e| e.to_string())?,
+            SmtpSecurity::None => {
+                AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&config.host)
+            }
+        };
+        let mut builder = builder.port(config.port).timeout(Some(TIMEOUT));
+        if let Some(username) = config.username.as_deref().filter(|u| !u.is_empty()) {
+            builder = builder.credentials(Credentials::new(
+                username.to_string(),
+                password.unwrap_or_default(),
+            ));
+        }
+
+        let mailbox = |address: &str| {
+            address
+                .parse::<Mailbox>()
+                .map_err(|e| format!("Invalid address {address}: {e}"))
+        };
+        Ok(Self {
+            transport: builder.build(),
+            from: mailbox(&config.from)?,
+            to: config
+                .to
+                .iter()
+                .map(|to| mailbox(to))
+                .collect::<Result<_, _>>()?,
+            events: config.events.clone(),
+            subject: config.subject.clone(),
+            body: config.body.clone(),
+            batcher: Mutex::new(Batcher::new(Duration::from_secs(config.settle_sec))),
+        })
+    }
+
+    async fn mail(&self, notification: &Notification) -> Result<(), String> {
+        let mut builder = Message::builder()
+            .from(self.from.clone())
+            .subject(render(&self.subject, notification))
+            .header(ContentType::TEXT_PLAIN);
+        for to in &self.to {
+            builder = builder.to(to.clone());
+        }
+        let message = builder
+            .body(render(&self.body, notification))
+            .map_err(|e| e.to_string())?;
+        self.transport
+            .send(message)
+            .await
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+
+    fn batcher(&self) -> std::sync::MutexGuard<'_, Batcher> {
+        self.batcher.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[async_trait]
+impl Channel for EmailChannel {
+    fn name(&self) -> &str {
+        "email"
+    }
+
+    async fn send(&self, notification: &Notification) -> Result<(), String> {
+        if !self.events.contains(&notification.kind) {
+            return Ok(());
+        }
+        let due = self.batcher().push(notification.clone(), Instant::now());
+        match due {
+            Some(notification) => self.mail(&notification).await,
+            None => Ok(()),
+        }
+    }
+
+    async fn tick(&self) -> Result<(), String> {
+        let summary = self.batcher().tick(Instant::now());
+        match summary {
+            Some(summary) => self.mail(&summary).await,
+            None => Ok(()),
+        }
+    }
+}
+
+/// Fills in `{title}`, `{body}`, `{severity}`, `{event}` and `{time}`.
+/// Anything else in braces is left as written.
+fn render(template: &str, notification: &Notification) -> String {
+    let mut out = String::with_capacity(template.len() + notification.body.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let Some(end) = rest.find('}') else {
+            break;
+        };
+        match &rest[1..end] {
+            "title" => out.push_str(&notification.title),
+            "body" => out.push_str(&notification.body),
+            "severity" => out.push_str(&tag(notification.severity)),
+            "event" => out.push_str(&tag(notification.kind)),
+            "time" => out.push_str(&local_time(notification.timestamp)),
+            _ => out.push_str(&rest[..=end]),
+        }
+        rest = &rest[end + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+fn local_time(timestamp: u64) -> String {
+    chrono::Local
+        .timestamp_opt(timestamp as i64, 0)
+        .single()
+        .map(|t| t.format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_default()
+}
+
+struct Outage {
+    events: Vec<Notification>,
+    /// When power came back, unless it has been lost again since
+    restored_at: Option<Instant>,
+}
+
+/// Decides which notifications are mailed right away. Outside an outage that
+/// is all of them. The first `PowerLost` opens an outage: from then on only
+/// critical notifications go out at once and the rest are kept for the
+/// summary, which `tick` hands out once power has been back for `settle`.
+pub struct Batcher {
+    settle: Duration,
+    outage: Option<Outage>,
+}
+
+impl Batcher {
+    pub fn new(settle: Duration) -> Self {
+        Self {
+            settle,
+            outage: None,
+        }
+    }
+
+    pub fn push(&mut self, notification: Notification, now: Instant) -> Option<Notification> {
+        let Some(outage) = &mut self.outage else {
+            if notification.kind == NotificationKind::PowerLost {
+                self.outage = Some(Outage {
+                    events: vec![notification.clone()],
+                    restored_at: None,
+                });
+            }
+            return Some(notification);
+        };
+        match notification.kind {
+            NotificationKind::PowerLost => outage.restored_at = None,
+            NotificationKind::PowerRestored => outage.restored_at = Some(now),
+            _ => {}
+        }
+        outage.events.push(notification.clone());
+        (notification.severity == AlertSeverity::Critical).then_some(notification)
+    }
+
+    /// The outage summary, once power has stayed back long enough.
+    pub fn tick(&mut self, now: Instant) -> Option<Notification> {
+        let restored_at = self.outage.as_ref()?.restored_at?;
+        if now.saturating_duration_since(restored_at) < self.settle {
+            return None;
+        }
+        self.outage.take().map(|outage| summary(&outage.events))
+    }
+}
+
+fn summary(events: &[Notification]) -> Notification {
+    let cuts = events
+        .iter()
+        .filter(|n| n.kind == NotificationKind::PowerLost)
+        .count();
+    let mut on_battery = 0;
+    let mut since = None;
+    for n in events {
+        match n.kind {
+            NotificationKind::PowerLost => since = since.or(Some(n.timestamp)),
+            NotificationKind::PowerRestored => {
+                if let Some(since) = since.take() {
+                    on_battery += n.timestamp.saturating_sub(since);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let title = if cuts > 1 {
+        format!("Power restored after {cuts} interruptions")
+    } else {
+        "Power restored".to_string()
+    };
+    let mut body = format!(
+        "The UPS ran on battery for {} min {} s in total.\n",
+        on_battery / 60,
+        on_battery % 60
+    );
+    for n in events {
+        let _ = write!(body, "\n{}  {}", local_time(n.timestamp), n.title);
+        if !n.body.is_empty() {
+            let _ = write!(body, ": {}", n.body);
+        }
+    }
+    let mut notification = Notification::new(
+        NotificationKind::PowerRestored,
+        events
+            .iter()
+            .map(|n| n.severity)
+            .max()
+            .unwrap_or(AlertSeverity::Info),
+        title,
+        body,
+    );
+    if let Some(last) = events.last() {
+        notification.timestamp = last.timestamp;
+    }
+    notification
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    fn at(kind: NotificationKind, severity: AlertSeverity, title: &str, ts: u64) -> Notification {
+        Notification {
+            timestamp: ts,
+            ..Notification::new(kind, severity, title, "")
+        }
+    }
+
+    #[test]
+    fn renders_known_placeholders() {
+        let n = Notification::new(
+            NotificationKind::LowBattery,
+            AlertSeverity::Critical,
+            "Battery low",
+            "Battery at 9%",
+        );
+        assert_eq!(
+            render("[{severity}] {title} ({event}) {unknown} {", &n),
+            "[critical] Battery low (lowBattery) {unknown} {"
+        );
+        assert_eq!(render("{body}", &n), "Battery at 9%");
+    }
+
+    #[test]
+    fn batches_a_flapping_line_into_one_summary() {
+        use AlertSeverity::*;
+        use NotificationKind::*;
+        let mut batcher = Batcher::new(Duration::from_secs(300));
+        let t = Instant::now();
+        let s = Duration::from_secs(1);
+
+        // Outside an outage everything goes out at once
+        assert!(batcher
+            .push(at(ReplaceBattery, Warning, "Replace battery", 0), t)
+            .is_some());
+        assert!(batcher
+            .push(at(PowerLost, Warning, "Power lost", 1000), t)
+            .is_some());
+        assert!(batcher
+            .push(at(PowerRestored, Info, "Power restored", 1030), t + 30 * s)
+            .is_none());
+        assert!(batcher
+            .push(at(PowerLost, Warning, "Power lost", 1060), t + 60 * s)
+            .is_none());
+        // Critical news does not wait for the summary
+        assert!(batcher
+            .push(at(LowBattery, Critical, "Battery low", 1100), t + 100 * s)
+            .is_some());
+        assert!(batcher
+            .push(at(PowerRestored, Info, "Power restored", 1150), t + 150 * s)
+            .is_none());
+
+        assert!(batcher.tick(t + 400 * s).is_none());
+        let summary = batcher.tick(t + 450 * s).unwrap();
+        assert_eq!(summary.title, "Power restored after 2 interruptions");
+        assert_eq!(summary.severity, Critical);
+        assert_eq!(summary.timestamp, 1150);
+        assert!(summary
+            .body
+            .starts_with("The UPS ran on battery for 2 min 0 s in total.\n"));
+        assert_eq!(summary.body.lines().count(), 7);
+        assert!(batcher.tick(t + 500 * s).is_none());
+
+        // The outage is over, so the next one is announced again
+        assert!(batcher
+            .push(at(PowerLost, Warning, "Power lost", 2000), t)
+            .is_some());
+    }
+
+    /// Just enough of an SMTP server to accept one message. Returns the
+    /// commands it saw and the message text.
+    async fn smtp_sink(listener: TcpListener) -> (Vec<String>, String) {
+        let (stream, _) = listener.accept().await.unwrap();
+        let (read, mut write) = stream.into_split();
+        let mut lines = BufReader::new(read).lines();
+        write.write_all(b"220 sink ESMTP\r\n").await.unwrap();
+        let (mut commands, mut data, mut in_data) = (Vec::new(), String::new(), false);
+        while let Some(line) = lines.next_line().await.unwrap() {
+            if in_data {
+                if line == "." {
+                    in_data = false;
+                    write.write_all(b"250 queued\r\n").await.unwrap();
+                } else {
+                    data.push_str(&line);
+                    data.push('\n');
+                }
+                continue;
+            }
+            let command = line.to_ascii_uppercase();
+            let reply: &[u8] = if command.starts_with("EHLO") {
+                b"250-sink\r\n250 AUTH PLAIN LOGIN\r\n"
+            } else if command.starts_with("AUTH") {
+                b"235 accepted\r\n"
+            } else if command == "DATA" {
+                in_data = true;
+                b"354 go ahead\r\n"
+            } else if command == "QUIT" {
+                b"221 bye\r\n"
+            } else {
+                b"250 ok\r\n"
+            };
+            commands.push(line);
+            write.write_all(reply).await.unwrap();
+            if command == "QUIT" {
+                break;
+            }
+        }
+        (commands, data)
+    }
+
+    #[tokio::test]
+    async fn mails_through_an_smtp_server() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let sink = tokio::spawn(smtp_sink(listener));
+
+        let channel = EmailChannel::new(
+            &EmailNotifications {
+                enabled: true,
+                host: "127.0.0.1".to_string(),
+                port,
+                security: SmtpSecurity::None,
+                username: Some("alerts".to_string()),
+                from: "UPS <ups@example.com>".to_string(),
+                to: vec!["oncall@example.com".to_string()],
+                events: vec![NotificationKind::PowerLost],
+                ..Default::default()
+            },
+            Some("secret".to_string()),
+        )
+        .unwrap();
+        // Kinds not chosen never reach the server
+        channel
+            .send(&Notification::new(
+                NotificationKind::ConnectionLost,
+                AlertSeverity::Warning,
+                "Connection to UPS lost",
+                "",
+            ))
+            .await
+            .unwrap();
+        channel
+            .send(&Notification::new(
+                NotificationKind::PowerLost,
+                AlertSeverity::Warning,
+                "Power lost",
+                "The UPS is running on battery.",
+            ))
+            .await
+            .unwrap();
+        drop(channel);
+
+        let (commands, data) = sink.await.unwrap();
+        // "\0alerts\0secret" in base64
+        assert!(commands.contains(&"AUTH PLAIN AGFsZXJ0cwBzZWNyZXQ=".to_string()));
+        assert!(commands.contains(&"MAIL FROM:<ups@example.com>".to_string()));
+        assert!(commands.contains(&"RCPT TO:<oncall@example.com>".to_string()));
+        assert!(data.contains("Subject: [UPS] Power lost\n"));
+        assert!(data.contains("To: oncall@example.com\n"));
+        assert!(data.contains("The UPS is running on battery.\n\nSeverity: warning\n"));
+    }
+}
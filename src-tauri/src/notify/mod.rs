@@ -0,0 +1,526 @@
+//! Outbound notifications. `Dispatcher` turns monitor events into
+//! `Notification`s (see `EventMapper`) and hands each to every configured
+//! `Channel`. Every channel has its own queue, so a slow one (a mail server
+//! timing out) neither delays the others nor makes the relay miss events.
+
+pub mod command;
+#[cfg(feature = "desktop")]
+pub mod desktop;
+pub mod email;
+pub mod webhook;
+
+use crate::credentials::smtp_credential_id;
+use crate::db::NutDB;
+use crate::monitor::alerts::AlertState;
+use crate::monitor::MonitorEvent;
+use crate::settings::{AlertSeverity, NotificationKind, NotificationsConfig};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::{broadcast, mpsc};
+
+/// How often channels get a `Channel::tick`.
+const TICK: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Notification {
+    pub kind: NotificationKind,
+    pub severity: AlertSeverity,
+    /// One line, e.g. `Power lost` or `High load fired`
+    pub title: String,
+    pub body: String,
+    /// Unix seconds
+    pub timestamp: u64,
+}
+
+impl Notification {
+    pub fn new(
+        kind: NotificationKind,
+        severity: AlertSeverity,
+        title: impl Into<String>,
+        body: impl Into<String>,
+    ) -> Self {
+        Self {
+            kind,
+            severity,
+            title: title.into(),
+            body: body.into(),
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+        }
+    }
+}
+
+/// Turns the event stream into notifications. Status flags are compared with
+/// the previous reading, so only changes are notified, and a shutdown
+/// countdown is notified once rather than every second.
+#[derive(Default)]
+pub struct EventMapper {
+    /// `ups.status` flags of the previous reading
+    flags: Option<Vec<String>>,
+    countdown: bool,
+}
+
+impl EventMapper {
+    pub fn map(&mut self, event: &MonitorEvent) -> Vec<Notification> {
+        use AlertSeverity::{Critical, Info, Warning};
+        use NotificationKind as Kind;
+
+        match event {
+            MonitorEvent::Update(data) => {
+                if data.status.is_empty() {
+                    return Vec::new();
+                }
+                let flags: Vec<String> =
+                    data.status.split_whitespace().map(str::to_string).collect();
+                let previous = self.flags.replace(flags.clone());
+                let had = |flag: &str| {
+                    previous
+                        .as_ref()
+                        .is_some_and(|p| p.iter().any(|f| f == flag))
+                };
+                let has = |flag: &str| flags.iter().any(|f| f == flag);
+                let charge = data
+                    .battery_charge
+                    .map(|c| format!("Battery at {c:.0}%"))
+                    .unwrap_or_default();
+
+                let mut out = Vec::new();
+                if has("OB") && !had("OB") {
+                    let body = format!("The UPS is running on battery. {charge}");
+                    out.push(Notification::new(
+                        Kind::PowerLost,
+                        Warning,
+                        "Power lost",
+                        body.trim(),
+                    ));
+                }
+                if !has("OB") && had("OB") {
+                    out.push(Notification::new(
+                        Kind::PowerRestored,
+                        Info,
+                        "Power restored",
+                        "The UPS is back on mains power.",
+                    ));
+                }
+                if has("LB") && !had("LB") {
+                    let body = format!("The UPS reports a low battery. {charge}");
+                    out.push(Notification::new(
+                        Kind::LowBattery,
+                        Critical,
+                        "Battery low",
+                        body.trim(),
+                    ));
+                }
+                if has("RB") && !had("RB") {
+                    out.push(Notification::new(
+                        Kind::ReplaceBattery,
+                        Warning,
+                        "Replace battery",
+                        "The UPS reports that its battery needs replacing.",
+                    ));
+                }
+                out
+            }
+            MonitorEvent::ShutdownWarning(remaining) => {
+                if std::mem::replace(&mut self.countdown, true) {
+                    return Vec::new();
+                }
+                vec![Notification::new(
+                    Kind::ShutdownScheduled,
+                    Critical,
+                    "Shutdown scheduled",
+                    format!("This computer will shut down in {remaining} seconds."),
+                )]
+            }
+            MonitorEvent::ShutdownCancelled => {
+                if !std::mem::replace(&mut self.countdown, false) {
+                    return Vec::new();
+                }
+                vec![Notification::new(
+                    Kind::ShutdownCancelled,
+                    Info,
+                    "Shutdown cancelled",
+                    "The scheduled shutdown was cancelled.",
+                )]
+            }
+            MonitorEvent::ShutdownExecuted(_) => {
+                self.countdown = false;
+                Vec::new()
+            }
+            MonitorEvent::ConnectionLost(reason) => vec![Notification::new(
+                Kind::ConnectionLost,
+                Warning,
+                "Connection to UPS lost",
+                reason.clone(),
+            )],
+            MonitorEvent::ConnectionRestored => vec![Notification::new(
+                Kind::ConnectionRestored,
+                Info,
+                "Connection to UPS restored",
+                "",
+            )],
+            MonitorEvent::Alert(alert) => {
+                let severity = match alert.state {
+                    AlertState::Fired => alert.severity,
+                    AlertState::Resolved => Info,
+                };
+                vec![Notification::new(
+                    Kind::Alert,
+                    severity,
+                    alert.summary(),
+                    alert.detail.clone(),
+                )]
+            }
+            MonitorEvent::LoadShedding { .. } | MonitorEvent::SelfTest(_) => Vec::new(),
+        }
+    }
+}
+
+/// Somewhere notifications can be delivered.
+#[async_trait]
+pub trait Channel: Send + Sync {
+    /// Short id for logs, e.g. `command`
+    fn name(&self) -> &str;
+
+    async fn send(&self, notification: &Notification) -> Result<(), String>;
+
+    /// Called every `TICK`, for channels that hold notifications back and
+    /// send them later.
+    async fn tick(&self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// Passes on only the chosen kinds of notification, and at most one per kind
+/// and title every `min_interval`, so a flapping line does not flood the channel.
+pub struct Throttled {
+    inner: Arc<dyn Channel>,
+    events: Vec<NotificationKind>,
+    min_interval: Duration,
+    last_sent: Mutex<HashMap<(NotificationKind, String), Instant>>,
+}
+
+impl Throttled {
+    pub fn new(
+        inner: Arc<dyn Channel>,
+        events: Vec<NotificationKind>,
+        min_interval: Duration,
+    ) -> Self {
+        Self {
+            inner,
+            events,
+            min_interval,
+            last_sent: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn allow(&self, notification: &Notification, now: Instant) -> bool {
+        if !self.events.contains(&notification.kind) {
+            return false;
+        }
+        let mut last_sent = self.last_sent.lock().unwrap_or_else(|e| e.into_inner());
+        let key = (notification.kind, notification.title.clone());
+        if last_sent
+            .get(&key)
+            .is_some_and(|last| now.saturating_duration_since(*last) < self.min_interval)
+        {
+            return false;
+        }
+        last_sent.insert(key, now);
+        true
+    }
+}
+
+#[async_trait]
+impl Channel for Throttled {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    async fn send(&self, notification: &Notification) -> Result<(), String> {
+        if !self.allow(notification, Instant::now()) {
+            log::debug!(
+                "{} notification held back: {}",
+                self.name(),
+                notification.title
+            );
+            return Ok(());
+        }
+        self.inner.send(notification).await
+    }
+
+    async fn tick(&self) -> Result<(), String> {
+        self.inner.tick().await
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct Dispatcher {
+    channels: Vec<Arc<dyn Channel>>,
+}
+
+impl Dispatcher {
+    pub fn new(channels: Vec<Arc<dyn Channel>>) -> Self {
+        Self { channels }
+    }
+
+    /// The channels `config` enables that need nothing from the desktop app.
+    /// `secret` looks up a credential id; webhooks queue in the database in
+    /// `data_dir`.
+    pub fn from_settings(
+        config: &NotificationsConfig,
+        data_dir: &Path,
+        secret: impl Fn(&str) -> Option<String>,
+    ) -> Self {
+        let mut channels: Vec<Arc<dyn Channel>> = Vec::new();
+        if let Some(program) = &config.command {
+            channels.push(Arc::new(command::CommandChannel::new(program.clone())));
+        }
+        let email = &config.email;
+        if email.enabled {
+            let password = email.username.as_deref().and_then(|username| {
+                secret(
+                    &email
+                        .credential_id
+                        .clone()
+                        .unwrap_or_else(|| smtp_credential_id(&email.host, email.port, username)),
+                )
+            });
+            match email::EmailChannel::new(email, password) {
+                Ok(channel) => channels.push(Arc::new(channel)),
+                Err(e) => log::error!("Email notifications disabled: {}", e),
+            }
+        }
+        for webhook in &config.webhooks {
+            let token = webhook.credential_id.as_deref().and_then(&secret);
+            match webhook::WebhookChannel::new(webhook, token, NutDB::new(data_dir)) {
+                Ok(channel) => channels.push(Arc::new(channel)),
+                Err(e) => log::error!("Webhook {} disabled: {}", webhook.name, e),
+            }
+        }
+        Self::new(channels)
+    }
+
+    pub fn with_channel(mut self, channel: Arc<dyn Channel>) -> Self {
+        self.channels.push(channel);
+        self
+    }
+
+    /// Delivers notifications for `events` until the monitor goes away.
+    pub async fn run(self, mut events: broadcast::Receiver<MonitorEvent>) {
+        if self.channels.is_empty() {
+            return;
+        }
+        let (queues, workers): (Vec<_>, Vec<_>) = self
+            .channels
+            .into_iter()
+            .map(|channel| {
+                let (tx, mut rx) = mpsc::unbounded_channel::<Arc<Notification>>();
+                let worker = async move {
+                    let mut ticks = tokio::time::interval(TICK);
+                    ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+                    loop {
+                        let result = tokio::select! {
+                            notification = rx.recv() => match notification {
+                                Some(notification) => channel.send(&notification).await,
+                                None => break,
+                            },
+                            _ = ticks.tick() => channel.tick().await,
+                        };
+                        if let Err(e) = result {
+                            log::error!("Failed to notify via {}: {}", channel.name(), e);
+                        }
+                    }
+                };
+                (tx, worker)
+            })
+            .unzip();
+
+        let relay = async move {
+            let mut mapper = EventMapper::default();
+            loop {
+                let event = match events.recv().await {
+                    Ok(event) => event,
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        log::warn!("Notification relay lagged, skipped {} events", skipped);
+                        continue;
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                };
+                for notification in mapper.map(&event) {
+                    let notification = Arc::new(notification);
+                    for queue in &queues {
+                        let _ = queue.send(notification.clone());
+                    }
+                }
+            }
+        };
+        futures::join!(relay, futures::future::join_all(workers));
+    }
+}
+
+/// The serialized name of a unit enum variant, e.g. `powerLost`.
+fn tag(value: impl Serialize) -> String {
+    serde_json::to_value(value)
+        .ok()
+        .and_then(|v| v.as_str().map(str::to_string))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::monitor::alerts::AlertEvent;
+    use crate::nut::models::UpsData;
+
+    #[derive(Default)]
+    struct Recorder {
+        sent: Mutex<Vec<Notification>>,
+        fail: bool,
+    }
+
+    #[async_trait]
+    impl Channel for Recorder {
+        fn name(&self) -> &str {
+            "recorder"
+        }
+
+        async fn send(&self, notification: &Notification) -> Result<(), String> {
+            self.sent.lock().unwrap().push(notification.clone());
+            if self.fail {
+                Err("unreachable".to_string())
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    fn update(status: &str) -> MonitorEvent {
+        MonitorEvent::Update(Box::new(UpsData {
+            status: status.to_string(),
+            battery_charge: Some(80.0),
+            ..Default::default()
+        }))
+    }
+
+    fn kinds(notifications: Vec<Notification>) -> Vec<NotificationKind> {
+        notifications.into_iter().map(|n| n.kind).collect()
+    }
+
+    #[test]
+    fn notifies_changes_only() {
+        use NotificationKind::*;
+        let mut mapper = EventMapper::default();
+
+        assert!(mapper.map(&update("OL")).is_empty());
+        let lost = mapper.map(&update("OB DISCHRG"));
+        assert_eq!(
+            lost[0].body,
+            "The UPS is running on battery. Battery at 80%"
+        );
+        assert_eq!(kinds(lost), [PowerLost]);
+        assert!(mapper.map(&update("OB DISCHRG")).is_empty());
+        assert_eq!(
+            kinds(mapper.map(&update("OB DISCHRG LB RB"))),
+            [LowBattery, ReplaceBattery]
+        );
+        assert_eq!(
+            kinds(mapper.map(&MonitorEvent::ShutdownWarning(30))),
+            [ShutdownScheduled]
+        );
+        assert!(mapper.map(&MonitorEvent::ShutdownWarning(29)).is_empty());
+        assert_eq!(kinds(mapper.map(&update("OL CHRG RB"))), [PowerRestored]);
+        assert_eq!(
+            kinds(mapper.map(&MonitorEvent::ShutdownCancelled)),
+            [ShutdownCancelled]
+        );
+        assert!(mapper.map(&MonitorEvent::ShutdownCancelled).is_empty());
+        // A reading without a status says nothing about the flags
+        assert!(mapper.map(&update("")).is_empty());
+        assert!(mapper.map(&update("OL RB")).is_empty());
+
+        // Starting up on battery is worth knowing too
+        let mut mapper = EventMapper::default();
+        assert_eq!(kinds(mapper.map(&update("OB"))), [PowerLost]);
+    }
+
+    #[tokio::test]
+    async fn throttles_by_kind_and_title() {
+        let recorder = Arc::new(Recorder::default());
+        let throttled = Throttled::new(
+            recorder.clone(),
+            vec![NotificationKind::PowerLost, NotificationKind::PowerRestored],
+            Duration::from_secs(60),
+        );
+        let lost = Notification::new(
+            NotificationKind::PowerLost,
+            AlertSeverity::Warning,
+            "Power lost",
+            "",
+        );
+        let restored = Notification::new(
+            NotificationKind::PowerRestored,
+            AlertSeverity::Info,
+            "Power restored",
+            "",
+        );
+        let lost_conn = Notification::new(
+            NotificationKind::ConnectionLost,
+            AlertSeverity::Warning,
+            "Lost",
+            "",
+        );
+
+        let t = Instant::now();
+        assert!(throttled.allow(&lost, t));
+        assert!(throttled.allow(&restored, t));
+        assert!(!throttled.allow(&lost, t + Duration::from_secs(30)));
+        assert!(throttled.allow(&lost, t + Duration::from_secs(61)));
+        // Kinds not chosen are dropped
+        assert!(!throttled.allow(&lost_conn, t));
+
+        throttled.send(&restored).await.unwrap();
+        throttled.send(&lost_conn).await.unwrap();
+        assert!(recorder.sent.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn every_channel_gets_every_notification() {
+        let ok = Arc::new(Recorder::default());
+        let failing = Arc::new(Recorder {
+            fail: true,
+            ..Default::default()
+        });
+        let (tx, rx) = broadcast::channel(16);
+        let dispatcher = Dispatcher::new(vec![ok.clone(), failing.clone()]);
+        let task = tokio::spawn(dispatcher.run(rx));
+
+        let alert = |state| {
+            MonitorEvent::Alert(AlertEvent {
+                rule: "Hot".to_string(),
+                severity: AlertSeverity::Critical,
+                state,
+                detail: "ups.temperature is 45, alert above 40".to_string(),
+            })
+        };
+        tx.send(update("OL")).unwrap();
+        tx.send(alert(AlertState::Fired)).unwrap();
+        tx.send(alert(AlertState::Resolved)).unwrap();
+        drop(tx);
+        task.await.unwrap();
+
+        for recorder in [ok, failing] {
+            let sent = recorder.sent.lock().unwrap();
+            let titles: Vec<&str> = sent.iter().map(|n| n.title.as_str()).collect();
+            assert_eq!(titles, ["Hot fired", "Hot resolved"]);
+            assert_eq!(sent[0].severity, AlertSeverity::Critical);
+            assert_eq!(sent[1].severity, AlertSeverity::Info);
+        }
+    }
+}
@@ -0,0 +1,539 @@
+//! HTTP notifications: a plain JSON webhook, signed with HMAC-SHA256 when a
+//! secret is set, or a message shaped for Slack, Microsoft Teams, Discord,
+//! ntfy or Gotify.
+//!
+//! Every notification is written to the `outbox` table before it is sent and
+//! only removed once the server took it, so what was raised just before a
+//! shutdown goes out after the next start. Failed deliveries are retried with
+//! a growing delay, in order; an answer like 400 or 404 will not change on
+//! retry, so those are dropped.
+//!
+//! A signed request carries `X-UPS-Timestamp` and `X-UPS-Signature:
+//! sha256=<hex>`, the HMAC of `<timestamp>.<body>`. `X-UPS-Delivery` stays
+//! the same across retries, so receivers can drop duplicates.
+
+use super::{tag, Channel, Notification};
+use crate::db::NutDB;
+use crate::settings::{AlertSeverity, NotificationKind, Webhook, WebhookFormat};
+use async_trait::async_trait;
+use hmac::{Hmac, Mac};
+use reqwest::{StatusCode, Url};
+use serde_json::json;
+use sha2::Sha256;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+/// First retry delay in seconds; it doubles with every failure up to `RETRY_MAX`.
+const RETRY_BASE: u64 = 10;
+const RETRY_MAX: u64 = 3600;
+/// Tries before a delivery is given up, about a day with the delays above.
+const MAX_ATTEMPTS: u32 = 30;
+
+enum Failure {
+    /// Worth another try, e.g. a timeout or a 503
+    Temporary(String),
+    Permanent(String),
+}
+
+pub struct WebhookChannel {
+    /// `webhook/<name>`, also the queue the outbox keeps for it
+    name: String,
+    url: Url,
+    format: WebhookFormat,
+    secret: Option<String>,
+    events: Vec<NotificationKind>,
+    client: reqwest::Client,
+    outbox: NutDB,
+}
+
+impl WebhookChannel {
+    pub fn new(webhook: &Webhook, secret: Option<String>, outbox: NutDB) -> Result<Self, String> {
+        let url = Url::parse(&webhook.url).map_err(|e| format!("Invalid URL: {e}"))?;
+        let client = reqwest::Client::builder()
+            .timeout(TIMEOUT)
+            .build()
+            .map_err(|e| e.to_string())?;
+        Ok(Self {
+            name: format!("webhook/{}", webhook.name),
+            url,
+            format: webhook.format,
+            secret,
+            events: webhook.events.clone(),
+            client,
+            outbox,
+        })
+    }
+
+    /// Sends whatever is due at `now` (unix time), oldest first. Stops at the
+    /// first delivery to fail for now, so the rest keep their order.
+    async fn flush(&self, now: u64) -> Result<(), String> {
+        let due = self
+            .outbox
+            .due_outbox(&self.name, now)
+            .map_err(|e| e.to_string())?;
+        let mut result = Ok(());
+        for entry in due {
+            let outcome = match serde_json::from_str::<Notification>(&entry.payload) {
+                Ok(notification) => self.deliver(entry.id, &notification, now).await,
+                Err(e) => Err(Failure::Permanent(format!("unreadable queue entry: {e}"))),
+            };
+            match outcome {
+                Ok(()) => {}
+                Err(Failure::Temporary(e)) if entry.attempts + 1 < MAX_ATTEMPTS => {
+                    let delay = (RETRY_BASE << entry.attempts.min(16)).min(RETRY_MAX);
+                    self.outbox
+                        .retry_outbox(entry.id, now + delay)
+                        .map_err(|e| e.to_string())?;
+                    return Err(format!("{e}, retrying in {delay} s"));
+                }
+                Err(Failure::Temporary(e)) => {
+                    result = Err(format!("{e}, giving up after {MAX_ATTEMPTS} tries"));
+                }
+                Err(Failure::Permanent(e)) => result = Err(format!("{e}, dropped")),
+            }
+            self.outbox
+                .remove_outbox(entry.id)
+                .map_err(|e| e.to_string())?;
+        }
+        result
+    }
+
+    async fn deliver(&self, id: u64, notification: &Notification, now: u64) -> Result<(), Failure> {
+        let request = request(
+            self.format,
+            &self.url,
+            notification,
+            self.secret.as_deref(),
+            id,
+            now,
+        );
+        let mut builder = self
+            .client
+            .post(request.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(request.body);
+        for (name, value) in request.headers {
+            builder = builder.header(name, value);
+        }
+        let response = builder
+            .send()
+            .await
+            // Chat services put their secret in the URL; keep it out of the logs
+            .map_err(|e| Failure::Temporary(e.without_url().to_string()))?;
+        let status = response.status();
+        if status.is_success() {
+            Ok(())
+        } else if status.is_client_error()
+            && status != StatusCode::REQUEST_TIMEOUT
+            && status != StatusCode::TOO_MANY_REQUESTS
+        {
+            Err(Failure::Permanent(format!("server answered {status}")))
+        } else {
+            Err(Failure::Temporary(format!("server answered {status}")))
+        }
+    }
+}
+
+#[async_trait]
+impl Channel for WebhookChannel {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn send(&self, notification: &Notification) -> Result<(), String> {
+        if !self.events.contains(&notification.kind) {
+            return Ok(());
+        }
+        let payload = serde_json::to_string(notification).map_err(|e| e.to_string())?;
+        self.outbox
+            .enqueue_outbox(&self.name, &payload)
+            .map_err(|e| e.to_string())?;
+        self.flush(unix_now()).await
+    }
+
+    async fn tick(&self) -> Result<(), String> {
+        self.flush(unix_now()).await
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+struct Request {
+    url: Url,
+    headers: Vec<(&'static str, String)>,
+    body: String,
+}
+
+/// The request `format` calls for. `delivery` is the outbox id and `now` the
+/// unix time the request is signed at.
+fn request(
+    format: WebhookFormat,
+    url: &Url,
+    notification: &Notification,
+    secret: Option<&str>,
+    delivery: u64,
+    now: u64,
+) -> Request {
+    let Notification {
+        title,
+        body,
+        severity,
+        ..
+    } = notification;
+    let mut url = url.clone();
+    let mut headers = Vec::new();
+    let payload = match format {
+        WebhookFormat::Json => {
+            let payload = json!(notification);
+            headers.push(("X-UPS-Delivery", delivery.to_string()));
+            if let Some(secret) = secret {
+                let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+                    .expect("HMAC accepts any key length");
+                mac.update(format!("{now}.{payload}").as_bytes());
+                let signature: String = mac
+                    .finalize()
+                    .into_bytes()
+                    .iter()
+                    .map(|b| format!("{b:02x}"))
+                    .collect();
+                headers.push(("X-UPS-Timestamp", now.to_string()));
+                headers.push(("X-UPS-Signature", format!("sha256={signature}")));
+            }
+            payload
+        }
+        WebhookFormat::Slack => {
+            let icon = match severity {
+                AlertSeverity::Info => ":information_source:",
+                AlertSeverity::Warning => ":warning:",
+                AlertSeverity::Critical => ":rotating_light:",
+            };
+            json!({ "text": format!("{icon} *{title}*\n{body}") })
+        }
+        WebhookFormat::Teams => {
+            let color = match severity {
+                AlertSeverity::Info => "Good",
+                AlertSeverity::Warning => "Warning",
+                AlertSeverity::Critical => "Attention",
+            };
+            json!({
+                "type": "message",
+                "attachments": [{
+                    "contentType": "application/vnd.microsoft.card.adaptive",
+                    "content": {
+                        "$schema": "http://adaptivecards.io/schemas/adaptive-card.json",
+                        "type": "AdaptiveCard",
+                        "version": "1.4",
+                        "body": [
+                            { "type": "TextBlock", "text": title, "weight": "Bolder", "size": "Medium", "color": color },
+                            { "type": "TextBlock", "text": body, "wrap": true },
+                        ],
+                    },
+                }],
+            })
+        }
+        WebhookFormat::Discord => {
+            let color = match severity {
+                AlertSeverity::Info => 0x2f80ed,
+                AlertSeverity::Warning => 0xf7a600,
+                AlertSeverity::Critical => 0xd13438,
+            };
+            let timestamp = chrono::DateTime::from_timestamp(notification.timestamp as i64, 0)
+                .map(|t| t.to_rfc3339())
+                .unwrap_or_default();
+            json!({
+                "embeds": [{ "title": title, "description": body, "color": color, "timestamp": timestamp }],
+            })
+        }
+        WebhookFormat::Ntfy => {
+            // JSON publishing goes to the server root with the topic in the
+            // body, which unlike headers takes any text
+            let topic = url
+                .path_segments()
+                .and_then(|mut segments| segments.next_back())
+                .unwrap_or_default()
+                .to_string();
+            if let Ok(mut segments) = url.path_segments_mut() {
+                segments.pop().push("");
+            }
+            let (priority, emoji) = match severity {
+                AlertSeverity::Info => (3, "information_source"),
+                AlertSeverity::Warning => (4, "warning"),
+                AlertSeverity::Critical => (5, "rotating_light"),
+            };
+            if let Some(token) = secret {
+                headers.push(("Authorization", format!("Bearer {token}")));
+            }
+            json!({
+                "topic": topic,
+                "title": title,
+                "message": body,
+                "priority": priority,
+                "tags": [emoji, tag(notification.kind)],
+            })
+        }
+        WebhookFormat::Gotify => {
+            let priority = match severity {
+                AlertSeverity::Info => 4,
+                AlertSeverity::Warning => 6,
+                AlertSeverity::Critical => 9,
+            };
+            if let Some(token) = secret {
+                headers.push(("X-Gotify-Key", token.to_string()));
+            }
+            json!({ "title": title, "message": body, "priority": priority })
+        }
+    };
+    Request {
+        url,
+        headers,
+        body: payload.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    fn power_lost() -> Notification {
+        Notification {
+            timestamp: 1_700_000_000,
+            ..Notification::new(
+                NotificationKind::PowerLost,
+                AlertSeverity::Warning,
+                "Power lost",
+                "The UPS is running on battery.",
+            )
+        }
+    }
+
+    fn body(request: &Request) -> Value {
+        serde_json::from_str(&request.body).unwrap()
+    }
+
+    #[test]
+    fn shapes_messages_for_each_service() {
+        let n = power_lost();
+        let at = |url: &str| Url::parse(url).unwrap();
+
+        let slack = request(
+            WebhookFormat::Slack,
+            &at("https://hooks.slack.com/x"),
+            &n,
+            None,
+            1,
+            0,
+        );
+        assert_eq!(
+            body(&slack)["text"],
+            ":warning: *Power lost*\nThe UPS is running on battery."
+        );
+
+        let discord = request(
+            WebhookFormat::Discord,
+            &at("https://discord.com/api/webhooks/1/t"),
+            &n,
+            None,
+            1,
+            0,
+        );
+        let embed = &body(&discord)["embeds"][0];
+        assert_eq!(embed["title"], "Power lost");
+        assert_eq!(embed["color"], 0xf7a600);
+        assert_eq!(embed["timestamp"], "2023-11-14T22:13:20+00:00");
+
+        let teams = request(
+            WebhookFormat::Teams,
+            &at("https://example.com/teams"),
+            &n,
+            None,
+            1,
+            0,
+        );
+        let card = &body(&teams)["attachments"][0]["content"];
+        assert_eq!(card["type"], "AdaptiveCard");
+        assert_eq!(card["body"][0]["color"], "Warning");
+
+        let ntfy = request(
+            WebhookFormat::Ntfy,
+            &at("https://ntfy.example.com/base/my-ups"),
+            &n,
+            Some("tk_1"),
+            1,
+            0,
+        );
+        assert_eq!(ntfy.url.as_str(), "https://ntfy.example.com/base/");
+        assert_eq!(ntfy.headers, [("Authorization", "Bearer tk_1".to_string())]);
+        assert_eq!(body(&ntfy)["topic"], "my-ups");
+        assert_eq!(body(&ntfy)["tags"], json!(["warning", "powerLost"]));
+
+        let gotify = request(
+            WebhookFormat::Gotify,
+            &at("https://gotify.example.com/message"),
+            &n,
+            Some("A1"),
+            1,
+            0,
+        );
+        assert_eq!(gotify.url.as_str(), "https://gotify.example.com/message");
+        assert_eq!(gotify.headers, [("X-Gotify-Key", "A1".to_string())]);
+        assert_eq!(body(&gotify)["priority"], 6);
+
+        let json = request(
+            WebhookFormat::Json,
+            &at("https://example.com/hook"),
+            &n,
+            None,
+            7,
+            0,
+        );
+        assert_eq!(json.headers, [("X-UPS-Delivery", "7".to_string())]);
+        assert_eq!(body(&json)["kind"], "powerLost");
+        assert_eq!(body(&json)["severity"], "warning");
+    }
+
+    struct Received {
+        headers: HashMap<String, String>,
+        body: String,
+    }
+
+    /// Answers one request per connection with the next of `statuses` and
+    /// records what it got.
+    async fn http_sink(
+        listener: TcpListener,
+        statuses: Vec<u16>,
+        received: Arc<Mutex<Vec<Received>>>,
+    ) {
+        for status in statuses {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut headers = HashMap::new();
+            let mut line = String::new();
+            reader.read_line(&mut line).await.unwrap();
+            loop {
+                line.clear();
+                reader.read_line(&mut line).await.unwrap();
+                let Some((name, value)) = line.trim_end().split_once(": ") else {
+                    break;
+                };
+                headers.insert(name.to_ascii_lowercase(), value.to_string());
+            }
+            let length = headers["content-length"].parse().unwrap();
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).await.unwrap();
+            received.lock().unwrap().push(Received {
+                headers,
+                body: String::from_utf8(body).unwrap(),
+            });
+            let response =
+                format!("HTTP/1.1 {status} X\r\nContent-Length: 0\r\nConnection: close\r\n\r\n");
+            reader
+                .into_inner()
+                .write_all(response.as_bytes())
+                .await
+                .unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn signs_retries_and_survives_a_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        NutDB::new(dir.path()).init().unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let webhook = Webhook {
+            name: "ops".to_string(),
+            url: format!("http://{}/hook", listener.local_addr().unwrap()),
+            ..Default::default()
+        };
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let sink = tokio::spawn(http_sink(listener, vec![503, 200, 404], received.clone()));
+        let channel = || {
+            WebhookChannel::new(&webhook, Some("key".to_string()), NutDB::new(dir.path())).unwrap()
+        };
+        let queued = || {
+            NutDB::new(dir.path())
+                .due_outbox("webhook/ops", i64::MAX as u64)
+                .unwrap()
+        };
+
+        // The server is down: the notification stays queued for a retry
+        let err = channel().send(&power_lost()).await.unwrap_err();
+        assert_eq!(
+            err,
+            "server answered 503 Service Unavailable, retrying in 10 s"
+        );
+        assert_eq!(queued()[0].attempts, 1);
+        // Not due again yet
+        channel().tick().await.unwrap();
+        assert_eq!(received.lock().unwrap().len(), 1);
+
+        // A new instance, as after a restart, picks it up once it is due
+        channel().flush(unix_now() + 10).await.unwrap();
+        assert!(queued().is_empty());
+
+        // A request the server rejects is not retried
+        let err = channel().send(&power_lost()).await.unwrap_err();
+        assert!(err.ends_with("404 Not Found, dropped"));
+        assert!(queued().is_empty());
+        sink.await.unwrap();
+
+        let received = received.lock().unwrap();
+        let (first, retry) = (&received[0], &received[1]);
+        assert_eq!(first.body, retry.body);
+        assert_eq!(
+            first.headers["x-ups-delivery"],
+            retry.headers["x-ups-delivery"]
+        );
+        assert_ne!(
+            received[2].headers["x-ups-delivery"],
+            retry.headers["x-ups-delivery"]
+        );
+        let timestamp = &retry.headers["x-ups-timestamp"];
+        let mut mac = Hmac::<Sha256>::new_from_slice(b"key").unwrap();
+        mac.update(format!("{timestamp}.{}", retry.body).as_bytes());
+        let expected = mac
+            .finalize()
+            .into_bytes()
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect::<String>();
+        assert_eq!(
+            retry.headers["x-ups-signature"],
+            format!("sha256={expected}")
+        );
+        let sent: Notification = serde_json::from_str(&retry.body).unwrap();
+        assert_eq!(sent, power_lost());
+    }
+
+    #[tokio::test]
+    async fn errors_do_not_reveal_the_url() {
+        let dir = tempfile::tempdir().unwrap();
+        NutDB::new(dir.path()).init().unwrap();
+        // A port nothing listens on
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let webhook = Webhook {
+            name: "slack".to_string(),
+            url: format!(
+                "http://{}/services/T000/B000/hush",
+                listener.local_addr().unwrap()
+            ),
+            format: WebhookFormat::Slack,
+            ..Default::default()
+        };
+        drop(listener);
+
+        let channel = WebhookChannel::new(&webhook, None, NutDB::new(dir.path())).unwrap();
+        let err = channel.send(&power_lost()).await.unwrap_err();
+        assert!(err.ends_with("retrying in 10 s"), "{err}");
+        assert!(!err.contains("hush"), "{err}");
+    }
+}
@@ -0,0 +1,237 @@
+//! Recording and replay of NUT sessions.
+//!
+//! A capture is a JSON Lines file: one header line followed by one line per
+//! command/response exchange, timestamped relative to the start of the session.
+//! Replaying a capture through `NutClient::replay` feeds the recorded responses
+//! back through the parser and the poll loop, which turns field reports into
+//! reproducible regression tests.
+
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+pub const CAPTURE_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CaptureHeader {
+    pub version: u32,
+    pub host: String,
+    pub port: u16,
+    /// Wall clock time the recording started, in Unix milliseconds.
+    pub started_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct CaptureEntry {
+    /// Milliseconds since the start of the recording.
+    pub at_ms: u64,
+    pub command: String,
+    pub response: String,
+}
+
+/// Never write the NUT password to disk; replay matches on the masked form.
+fn redact(command: &str) -> &str {
+    if command.starts_with("PASSWORD ") {
+        "PASSWORD ********"
+    } else {
+        command
+    }
+}
+
+/// Appends exchanges to a capture file as they happen, so a crash still leaves
+/// everything up to the last command on disk.
+pub struct CaptureWriter {
+    out: BufWriter<File>,
+    started: Instant,
+}
+
+impl CaptureWriter {
+    pub fn create<P: AsRef<Path>>(path: P, host: &str, port: u16) -> std::io::Result<Self> {
+        let mut out = BufWriter::new(File::create(path)?);
+        let header = CaptureHeader {
+            version: CAPTURE_VERSION,
+            host: host.to_string(),
+            port,
+            started_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or(0),
+        };
+        writeln!(out, "{}", serde_json::to_string(&header)?)?;
+        out.flush()?;
+        Ok(Self {
+            out,
+            started: Instant::now(),
+        })
+    }
+
+    pub fn record(&mut self, command: &str, response: &str) -> std::io::Result<()> {
+        let entry = CaptureEntry {
+            at_ms: self.started.elapsed().as_millis() as u64,
+            command: redact(command).to_string(),
+            response: response.to_string(),
+        };
+        writeln!(self.out, "{}", serde_json::to_string(&entry)?)?;
+        self.out.flush()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Capture {
+    pub header: CaptureHeader,
+    pub entries: Vec<CaptureEntry>,
+}
+
+impl Capture {
+    pub fn load<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        Self::parse(BufReader::new(File::open(path)?))
+    }
+
+    pub fn parse<R: BufRead>(reader: R) -> std::io::Result<Self> {
+        let mut lines = reader.lines().filter(|l| match l {
+            Ok(l) => !l.trim().is_empty(),
+            Err(_) => true,
+        });
+        let header: CaptureHeader = match lines.next() {
+            Some(line) => serde_json::from_str(&line?)?,
+            None => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    "empty capture file",
+                ))
+            }
+        };
+        if header.version > CAPTURE_VERSION {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("capture version {} is not supported", header.version),
+            ));
+        }
+        let entries = lines
+            .map(|line| Ok(serde_json::from_str(&line?)?))
+            .collect::<std::io::Result<Vec<CaptureEntry>>>()?;
+        Ok(Self { header, entries })
+    }
+}
+
+/// Serves recorded responses in place of a socket.
+///
+/// `speed` scales the recorded timing: 1.0 replays in real time, 60.0 runs an
+/// hour-long capture in a minute, and 0.0 (or less) answers without waiting.
+pub struct ReplayTransport {
+    entries: VecDeque<CaptureEntry>,
+    speed: f64,
+    started: Instant,
+}
+
+impl ReplayTransport {
+    pub fn new(capture: Capture, speed: f64) -> Self {
+        Self {
+            entries: capture.entries.into(),
+            speed,
+            started: Instant::now(),
+        }
+    }
+
+    pub fn is_exhausted(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the response recorded for the next occurrence of `command`, waiting
+    /// until its scaled timestamp. Exchanges the caller does not repeat, e.g. a
+    /// one-off `LIST CMD` issued from the UI during recording, are skipped.
+    /// `None` means the capture has run out.
+    pub async fn exchange(&mut self, command: &str) -> Option<String> {
+        let command = redact(command);
+        let index = self.entries.iter().position(|e| e.command == command)?;
+        self.entries.drain(..index);
+        let entry = self.entries.pop_front()?;
+
+        if self.speed > 0.0 {
+            let due = Duration::from_secs_f64(entry.at_ms as f64 / 1000.0 / self.speed);
+            let elapsed = self.started.elapsed();
+            if due > elapsed {
+                tokio::time::sleep(due - elapsed).await;
+            }
+        }
+        Some(entry.response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nut::client::NutClient;
+    use crate::nut::emulator::FakeUpsd;
+
+    #[tokio::test]
+    async fn recorded_session_replays_identically() {
+        let server = FakeUpsd::start().await;
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("session.nutcap");
+
+        let mut config = server.config();
+        config.username = Some("monuser".to_string());
+        let capture = CaptureWriter::create(&path, &server.host(), server.port()).unwrap();
+        let mut client = NutClient::new(config)
+            .with_password(Some("hunter2".to_string()))
+            .with_capture(capture);
+        client.connect().await.unwrap();
+        let live = client.get_ups_data("ups").await.unwrap();
+        let commands = client.list_ups_commands("ups").await.unwrap();
+        drop(client);
+
+        let raw = std::fs::read_to_string(&path).unwrap();
+        assert!(!raw.contains("hunter2"));
+
+        let mut replay = NutClient::replay(Capture::load(&path).unwrap(), 0.0);
+        replay.connect().await.unwrap();
+        let replayed = replay.get_ups_data("ups").await.unwrap();
+        assert_eq!(replayed.status, live.status);
+        assert_eq!(replayed.battery_charge, live.battery_charge);
+        assert_eq!(replayed.extended_vars, live.extended_vars);
+        assert_eq!(replay.list_ups_commands("ups").await.unwrap(), commands);
+
+        // Out of recorded data behaves like a dead connection
+        assert!(replay.get_ups_data("ups").await.is_err());
+        assert!(replay.connect().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn replay_skips_commands_that_are_not_repeated() {
+        let capture = Capture::load(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/outage-low-battery.nutcap"
+        ))
+        .unwrap();
+        let mut transport = ReplayTransport::new(capture, 0.0);
+        let mut statuses = Vec::new();
+        while let Some(response) = transport.exchange("LIST VAR ups").await {
+            statuses.push(crate::nut::parser::parse_list_vars(&response).status);
+        }
+        assert_eq!(statuses.len(), 11);
+        assert_eq!(statuses[2], "OB DISCHRG");
+        assert_eq!(statuses[10], "OL CHRG");
+    }
+
+    #[tokio::test]
+    async fn accelerated_replay_keeps_relative_timing() {
+        let capture = Capture::load(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/outage-low-battery.nutcap"
+        ))
+        .unwrap();
+        // The third reading was recorded 10 s in; at 200x it is due after 50 ms
+        let mut transport = ReplayTransport::new(capture, 200.0);
+        let started = Instant::now();
+        for _ in 0..3 {
+            transport.exchange("LIST VAR ups").await.unwrap();
+        }
+        let elapsed = started.elapsed();
+        assert!(elapsed >= Duration::from_millis(50), "{elapsed:?}");
+        assert!(elapsed < Duration::from_secs(2), "{elapsed:?}");
+    }
+}
@@ -1,4 +1,7 @@
-use super::models::NutConfig;
+use super::capture::{Capture, CaptureWriter, ReplayTransport};
+use super::models::{NutConfig, UpsInfo};
+use super::parser::{parse_var_line, quote, unquote};
+use std::collections::BTreeMap;
 
 use thiserror::Error;
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
@@ -16,20 +19,82 @@ pub enum NutError {
     CommandFailed(String),
 }
 
+/// Result of a tracked instant command, as reported by `GET TRACKING`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TrackingStatus {
+    Pending,
+    Success,
+    /// The driver rejected or failed the command; holds the `ERR` code.
+    Failed(String),
+}
+
 pub struct NutClient {
     config: NutConfig,
+    password: Option<String>,
     stream: Option<BufReader<TcpStream>>,
+    capture: Option<CaptureWriter>,
+    /// Set for clients created with `replay`; takes the place of the socket.
+    replay: Option<ReplayTransport>,
 }
 
 impl NutClient {
     pub fn new(config: NutConfig) -> Self {
         Self {
             config,
+            password: None,
             stream: None,
+            capture: None,
+            replay: None,
+        }
+    }
+
+    /// A client that answers from a recorded session instead of a server.
+    /// See `capture::ReplayTransport` for the meaning of `speed`.
+    pub fn replay(capture: Capture, speed: f64) -> Self {
+        let mut client = Self::new(NutConfig {
+            host: capture.header.host.clone(),
+            port: capture.header.port,
+            username: None,
+            credential_id: None,
+        });
+        client.replay = Some(ReplayTransport::new(capture, speed));
+        client
+    }
+
+    pub fn config(&self) -> &NutConfig {
+        &self.config
+    }
+
+    /// Where replies come from: a live `nut` server or a `replay`.
+    pub fn transport_name(&self) -> &'static str {
+        if self.replay.is_some() {
+            "replay"
+        } else {
+            "nut"
         }
     }
 
+    /// Records every exchange of this client to `capture`.
+    pub fn with_capture(mut self, capture: CaptureWriter) -> Self {
+        self.capture = Some(capture);
+        self
+    }
+
+    /// Supplies the password resolved from `config.credential_id`.
+    pub fn with_password(mut self, password: Option<String>) -> Self {
+        self.password = password;
+        self
+    }
+
     pub async fn connect(&mut self) -> Result<(), NutError> {
+        if let Some(replay) = &self.replay {
+            return if replay.is_exhausted() {
+                Err(NutError::ConnectionFailed)
+            } else {
+                Ok(())
+            };
+        }
+
         let addr = format!("{}:{}", self.config.host, self.config.port);
         let stream = TcpStream::connect(&addr).await?;
         // Wrap the stream in a buffered reader for efficient line-by-line reading
@@ -37,7 +102,7 @@ impl NutClient {
 
         if let Some(username) = &self.config.username {
             self.send_cmd(&format!("USERNAME {username}")).await?;
-            if let Some(password) = &self.config.password {
+            if let Some(password) = self.password.clone() {
                 self.send_cmd(&format!("PASSWORD {password}")).await?;
             }
         }
@@ -57,6 +122,21 @@ impl NutClient {
     /// This function acts intelligently based on the command content.
     /// If the response implies a list (e.g. `BEGIN LIST`), it reads until `END LIST`.
     pub async fn send_cmd(&mut self, cmd: &str) -> Result<String, NutError> {
+        if let Some(replay) = self.replay.as_mut() {
+            return replay.exchange(cmd).await.ok_or(NutError::ConnectionFailed);
+        }
+
+        let response = self.exchange(cmd).await?;
+        if let Some(capture) = self.capture.as_mut() {
+            if let Err(e) = capture.record(cmd, &response) {
+                log::error!("Failed to write NUT capture, recording stopped: {}", e);
+                self.capture = None;
+            }
+        }
+        Ok(response)
+    }
+
+    async fn exchange(&mut self, cmd: &str) -> Result<String, NutError> {
         if self.stream.is_none() {
             return Err(NutError::ConnectionFailed);
         }
@@ -107,23 +187,91 @@ impl NutClient {
         Ok(super::parser::parse_list_vars(&response))
     }
 
+    /// Like `send_cmd`, but turns an `ERR <code>` reply into `NutError::CommandFailed`.
+    async fn query(&mut self, cmd: &str) -> Result<String, NutError> {
+        let response = self.send_cmd(cmd).await?;
+        match response.strip_prefix("ERR ") {
+            Some(code) => Err(NutError::CommandFailed(code.trim().to_string())),
+            None => Ok(response),
+        }
+    }
+
     pub async fn list_ups_names(&mut self) -> Result<Vec<String>, NutError> {
-        let response = self.send_cmd("LIST UPS").await?;
-        let mut names = Vec::new();
+        Ok(self
+            .list_ups()
+            .await?
+            .into_iter()
+            .map(|ups| ups.name)
+            .collect())
+    }
+
+    pub async fn list_ups(&mut self) -> Result<Vec<UpsInfo>, NutError> {
+        let response = self.query("LIST UPS").await?;
+        let mut devices = Vec::new();
         for line in response.lines() {
-            if line.starts_with("UPS ") {
-                // Format: UPS <name> "<description>"
-                let parts: Vec<&str> = line.splitn(3, ' ').collect();
-                if parts.len() >= 2 {
-                    names.push(parts[1].to_string());
-                }
+            // Format: UPS <name> "<description>"
+            if let Some(rest) = line.strip_prefix("UPS ") {
+                let (name, description) = rest.split_once(' ').unwrap_or((rest, ""));
+                devices.push(UpsInfo {
+                    name: name.to_string(),
+                    description: unquote(description),
+                });
             }
         }
-        Ok(names)
+        Ok(devices)
+    }
+
+    /// Raw `LIST VAR` as a sorted map, without mapping into `UpsData`.
+    pub async fn list_vars(
+        &mut self,
+        ups_name: &str,
+    ) -> Result<BTreeMap<String, String>, NutError> {
+        let response = self.query(&format!("LIST VAR {ups_name}")).await?;
+        Ok(response
+            .lines()
+            .filter_map(parse_var_line)
+            .map(|(_, key, value)| (key, value))
+            .collect())
+    }
+
+    /// Writable variables (`LIST RW`) and their current values.
+    pub async fn list_rw_vars(
+        &mut self,
+        ups_name: &str,
+    ) -> Result<BTreeMap<String, String>, NutError> {
+        let response = self.query(&format!("LIST RW {ups_name}")).await?;
+        Ok(response
+            .lines()
+            .filter_map(|line| parse_var_line(&line.replacen("RW ", "VAR ", 1)))
+            .map(|(_, key, value)| (key, value))
+            .collect())
+    }
+
+    pub async fn get_var(&mut self, ups_name: &str, var: &str) -> Result<String, NutError> {
+        let response = self.query(&format!("GET VAR {ups_name} {var}")).await?;
+        parse_var_line(response.trim_end())
+            .map(|(_, _, value)| value)
+            .ok_or(NutError::CommandFailed(response))
+    }
+
+    pub async fn set_var(
+        &mut self,
+        ups_name: &str,
+        var: &str,
+        value: &str,
+    ) -> Result<(), NutError> {
+        let resp = self
+            .query(&format!("SET VAR {ups_name} {var} {}", quote(value)))
+            .await?;
+        if resp.trim() == "OK" {
+            Ok(())
+        } else {
+            Err(NutError::CommandFailed(resp.trim().to_string()))
+        }
     }
 
     pub async fn list_ups_commands(&mut self, ups_name: &str) -> Result<Vec<String>, NutError> {
-        let response = self.send_cmd(&format!("LIST CMD {ups_name}")).await?;
+        let response = self.query(&format!("LIST CMD {ups_name}")).await?;
         let mut cmds = Vec::new();
         for line in response.lines() {
             if line.starts_with("CMD ") {
@@ -138,11 +286,236 @@ impl NutClient {
     }
 
     pub async fn run_instant_cmd(&mut self, ups_name: &str, cmd: &str) -> Result<(), NutError> {
-        let resp = self.send_cmd(&format!("INSTCMD {ups_name} {cmd}")).await?;
+        let resp = self.query(&format!("INSTCMD {ups_name} {cmd}")).await?;
         if resp.trim() == "OK" {
             Ok(())
         } else {
-            Err(NutError::CommandFailed(resp))
+            Err(NutError::CommandFailed(resp.trim().to_string()))
         }
     }
+
+    /// Turns command tracking on or off for this connection. Servers older
+    /// than NUT 2.8 answer `ERR UNKNOWN-COMMAND`.
+    pub async fn set_tracking(&mut self, enabled: bool) -> Result<(), NutError> {
+        let mode = if enabled { "ON" } else { "OFF" };
+        self.query(&format!("SET TRACKING {mode}")).await?;
+        Ok(())
+    }
+
+    /// Like `run_instant_cmd`, but returns the tracking id when tracking is on.
+    pub async fn run_instant_cmd_tracked(
+        &mut self,
+        ups_name: &str,
+        cmd: &str,
+    ) -> Result<Option<String>, NutError> {
+        let resp = self.query(&format!("INSTCMD {ups_name} {cmd}")).await?;
+        let resp = resp.trim();
+        match resp.strip_prefix("OK TRACKING ") {
+            Some(id) => Ok(Some(id.trim().to_string())),
+            None if resp == "OK" => Ok(None),
+            None => Err(NutError::CommandFailed(resp.to_string())),
+        }
+    }
+
+    pub async fn get_tracking(&mut self, id: &str) -> Result<TrackingStatus, NutError> {
+        let resp = self.send_cmd(&format!("GET TRACKING {id}")).await?;
+        match resp.trim() {
+            "PENDING" => Ok(TrackingStatus::Pending),
+            "SUCCESS" => Ok(TrackingStatus::Success),
+            other => match other.strip_prefix("ERR ") {
+                Some(code) => Ok(TrackingStatus::Failed(code.to_string())),
+                None => Err(NutError::CommandFailed(other.to_string())),
+            },
+        }
+    }
+
+    /// Instant command with an argument, e.g. `load.off.delay 30`.
+    pub async fn run_instant_cmd_with_value(
+        &mut self,
+        ups_name: &str,
+        cmd: &str,
+        value: &str,
+    ) -> Result<(), NutError> {
+        let resp = self
+            .query(&format!("INSTCMD {ups_name} {cmd} {}", quote(value)))
+            .await?;
+        if resp.trim() == "OK" {
+            Ok(())
+        } else {
+            Err(NutError::CommandFailed(resp.trim().to_string()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nut::emulator::FakeUpsd;
+    use std::time::Duration;
+
+    async fn connected(server: &FakeUpsd) -> NutClient {
+        let mut client = NutClient::new(server.config());
+        client.connect().await.unwrap();
+        client
+    }
+
+    #[tokio::test]
+    async fn reads_devices_and_variables() {
+        let server = FakeUpsd::start().await;
+        let mut client = connected(&server).await;
+
+        let devices = client.list_ups().await.unwrap();
+        assert_eq!(devices.len(), 1);
+        assert_eq!(devices[0].name, "ups");
+        assert_eq!(devices[0].description, "Emulated UPS");
+
+        let vars = client.list_vars("ups").await.unwrap();
+        assert_eq!(vars["ups.status"], "OL");
+        assert_eq!(
+            client.get_var("ups", "battery.charge").await.unwrap(),
+            "100"
+        );
+
+        let data = client.get_ups_data("ups").await.unwrap();
+        assert_eq!(data.status, "OL");
+        assert_eq!(data.battery_charge, Some(100.0));
+        assert_eq!(data.input_voltage, Some(230.0));
+    }
+
+    #[tokio::test]
+    async fn maps_err_replies_to_command_failed() {
+        let server = FakeUpsd::start().await;
+        let mut client = connected(&server).await;
+
+        let err = client.get_var("ups", "no.such.var").await.unwrap_err();
+        assert!(matches!(err, NutError::CommandFailed(code) if code == "VAR-NOT-SUPPORTED"));
+        let err = client.list_vars("nope").await.unwrap_err();
+        assert!(matches!(err, NutError::CommandFailed(code) if code == "UNKNOWN-UPS"));
+        let err = client.list_ups_commands("nope").await.unwrap_err();
+        assert!(matches!(err, NutError::CommandFailed(code) if code == "UNKNOWN-UPS"));
+        let err = client
+            .run_instant_cmd("ups", "no.such.cmd")
+            .await
+            .unwrap_err();
+        assert!(matches!(err, NutError::CommandFailed(code) if code == "CMD-NOT-SUPPORTED"));
+
+        server.fail_next("LIST", "DATA-STALE");
+        assert!(client.list_vars("ups").await.is_err());
+        // The injected error is one-shot
+        assert!(client.list_vars("ups").await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn writes_need_valid_credentials() {
+        let server = FakeUpsd::start().await;
+        server.require_login("admin", "secret");
+
+        let mut anonymous = connected(&server).await;
+        assert!(anonymous
+            .run_instant_cmd("ups", "beeper.disable")
+            .await
+            .is_err());
+
+        let mut config = server.config();
+        config.username = Some("admin".to_string());
+        let mut client = NutClient::new(config).with_password(Some("secret".to_string()));
+        client.connect().await.unwrap();
+
+        client
+            .run_instant_cmd("ups", "beeper.disable")
+            .await
+            .unwrap();
+        client
+            .run_instant_cmd_with_value("ups", "load.off.delay", "30")
+            .await
+            .unwrap();
+        client
+            .set_var("ups", "input.transfer.low", "165")
+            .await
+            .unwrap();
+        assert!(client.set_var("ups", "ups.status", "OB").await.is_err());
+
+        assert_eq!(
+            server.instcmds(),
+            vec![
+                ("ups".to_string(), "beeper.disable".to_string(), None),
+                (
+                    "ups".to_string(),
+                    "load.off.delay".to_string(),
+                    Some("30".to_string())
+                ),
+            ]
+        );
+        assert_eq!(
+            client.list_rw_vars("ups").await.unwrap()["input.transfer.low"],
+            "165"
+        );
+        assert!(server.received().contains(&"PASSWORD secret".to_string()));
+    }
+
+    #[tokio::test]
+    async fn tracks_instant_commands() {
+        let mut device = crate::nut::emulator::FakeDevice::online();
+        device.commands.insert("outlet.1.load.off".to_string());
+        device
+            .vars
+            .insert("outlet.1.status".to_string(), "on".to_string());
+        let server = FakeUpsd::with_devices([("ups".to_string(), device)]).await;
+        let mut client = connected(&server).await;
+
+        // Untracked until asked for
+        assert_eq!(
+            client
+                .run_instant_cmd_tracked("ups", "beeper.disable")
+                .await
+                .unwrap(),
+            None
+        );
+        client.set_tracking(true).await.unwrap();
+        server.next_tracking_result("ERR INVALID-ARGUMENT");
+        let failed = client
+            .run_instant_cmd_tracked("ups", "beeper.disable")
+            .await
+            .unwrap()
+            .unwrap();
+        let id = client
+            .run_instant_cmd_tracked("ups", "outlet.1.load.off")
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(
+            client.get_tracking(&failed).await.unwrap(),
+            TrackingStatus::Failed("INVALID-ARGUMENT".to_string())
+        );
+        assert_eq!(
+            client.get_tracking(&id).await.unwrap(),
+            TrackingStatus::Success
+        );
+        assert_eq!(
+            client.get_var("ups", "outlet.1.status").await.unwrap(),
+            "off"
+        );
+    }
+
+    #[tokio::test]
+    async fn reports_dropped_connections() {
+        let server = FakeUpsd::start().await;
+        let mut client = connected(&server).await;
+        server.set_reply_delay(Duration::from_millis(200));
+
+        let pending = tokio::spawn(async move { client.get_ups_data("ups").await });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        server.drop_connections();
+        assert!(pending.await.unwrap().is_err());
+    }
+
+    #[tokio::test]
+    async fn fails_without_connect() {
+        let mut client = NutClient::new(FakeUpsd::start().await.config());
+        assert!(matches!(
+            client.list_ups().await,
+            Err(NutError::ConnectionFailed)
+        ));
+    }
 }
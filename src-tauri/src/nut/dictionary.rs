@@ -0,0 +1,610 @@
+//! Units and descriptions for the NUT variable namespace (docs/nut-names.txt
+//! upstream). Indexed names are matched by pattern: `outlet.3.current` looks up
+//! `outlet.n.current` and `input.L2-N.voltage` looks up `input.Ln-N.voltage`.
+
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Unit {
+    #[serde(rename = "%")]
+    Percent,
+    #[serde(rename = "V")]
+    Volt,
+    #[serde(rename = "A")]
+    Ampere,
+    #[serde(rename = "W")]
+    Watt,
+    #[serde(rename = "VA")]
+    VoltAmpere,
+    #[serde(rename = "Hz")]
+    Hertz,
+    #[serde(rename = "s")]
+    Second,
+    #[serde(rename = "°C")]
+    Celsius,
+    #[serde(rename = "Ah")]
+    AmpereHour,
+    #[serde(rename = "°")]
+    Degree,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Kind {
+    Number,
+    Text,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct VariableInfo {
+    /// Pattern with `n` for numeric indexes and `Ln`/`Lm` for phases.
+    pub name: &'static str,
+    pub kind: Kind,
+    pub unit: Option<Unit>,
+    pub description: &'static str,
+}
+
+const fn num(name: &'static str, unit: Unit, description: &'static str) -> VariableInfo {
+    VariableInfo {
+        name,
+        kind: Kind::Number,
+        unit: Some(unit),
+        description,
+    }
+}
+
+const fn count(name: &'static str, description: &'static str) -> VariableInfo {
+    VariableInfo {
+        name,
+        kind: Kind::Number,
+        unit: None,
+        description,
+    }
+}
+
+const fn text(name: &'static str, description: &'static str) -> VariableInfo {
+    VariableInfo {
+        name,
+        kind: Kind::Text,
+        unit: None,
+        description,
+    }
+}
+
+use Unit::*;
+
+static DICTIONARY: &[VariableInfo] = &[
+    // device
+    text("device.model", "Device model"),
+    text("device.mfr", "Device manufacturer"),
+    text("device.serial", "Device serial number"),
+    text("device.type", "Device type (ups, pdu, scd, psu, ats)"),
+    text("device.description", "Device description"),
+    text("device.contact", "Device administrator"),
+    text("device.location", "Device physical location"),
+    text("device.part", "Device part number"),
+    text("device.macaddr", "Physical network address"),
+    num("device.uptime", Second, "Device uptime"),
+    count("device.count", "Number of devices in a daisy chain"),
+    // ups
+    text("ups.status", "UPS status flags"),
+    text("ups.alarm", "UPS alarms"),
+    text("ups.time", "Internal UPS clock time"),
+    text("ups.date", "Internal UPS clock date"),
+    text("ups.model", "UPS model"),
+    text("ups.mfr", "UPS manufacturer"),
+    text("ups.mfr.date", "UPS manufacturing date"),
+    text("ups.serial", "UPS serial number"),
+    text("ups.vendorid", "USB vendor id"),
+    text("ups.productid", "USB product id"),
+    text("ups.firmware", "UPS firmware"),
+    text("ups.firmware.aux", "Auxiliary device firmware"),
+    num("ups.temperature", Celsius, "UPS temperature"),
+    num("ups.load", Percent, "Load on UPS"),
+    num(
+        "ups.load.high",
+        Percent,
+        "Load when UPS switches to overload condition",
+    ),
+    text("ups.id", "UPS system identifier"),
+    num(
+        "ups.delay.start",
+        Second,
+        "Interval to wait before restarting the load",
+    ),
+    num(
+        "ups.delay.reboot",
+        Second,
+        "Interval to wait before rebooting the UPS",
+    ),
+    num(
+        "ups.delay.shutdown",
+        Second,
+        "Interval to wait after shutdown with delay command",
+    ),
+    num(
+        "ups.timer.start",
+        Second,
+        "Time before the load will be started",
+    ),
+    num(
+        "ups.timer.reboot",
+        Second,
+        "Time before the load will be rebooted",
+    ),
+    num(
+        "ups.timer.shutdown",
+        Second,
+        "Time before the load will be shutdown",
+    ),
+    num("ups.test.interval", Second, "Interval between self tests"),
+    text("ups.test.result", "Results of last self test"),
+    text("ups.test.date", "Date of last self test"),
+    text("ups.display.language", "Language to use on front panel"),
+    text("ups.contacts", "UPS external contact sensors"),
+    num("ups.efficiency", Percent, "Efficiency of the UPS"),
+    num("ups.power", VoltAmpere, "Current value of apparent power"),
+    num(
+        "ups.power.nominal",
+        VoltAmpere,
+        "Nominal value of apparent power",
+    ),
+    num("ups.realpower", Watt, "Current value of real power"),
+    num("ups.realpower.nominal", Watt, "Nominal value of real power"),
+    text("ups.beeper.status", "UPS beeper status"),
+    text("ups.type", "UPS type"),
+    text("ups.watchdog.status", "UPS watchdog status"),
+    text("ups.start.auto", "UPS starts when mains is (re)applied"),
+    text("ups.start.battery", "Allow to start UPS from battery"),
+    text("ups.start.reboot", "UPS coldstarts from battery"),
+    text("ups.shutdown", "Enable or disable UPS shutdown ability"),
+    // input
+    num("input.voltage", Volt, "Input voltage"),
+    num(
+        "input.voltage.maximum",
+        Volt,
+        "Maximum incoming voltage seen",
+    ),
+    num(
+        "input.voltage.minimum",
+        Volt,
+        "Minimum incoming voltage seen",
+    ),
+    text("input.voltage.status", "Status relative to the thresholds"),
+    num("input.voltage.low.warning", Volt, "Low warning threshold"),
+    num("input.voltage.low.critical", Volt, "Low critical threshold"),
+    num("input.voltage.high.warning", Volt, "High warning threshold"),
+    num(
+        "input.voltage.high.critical",
+        Volt,
+        "High critical threshold",
+    ),
+    num("input.voltage.nominal", Volt, "Nominal input voltage"),
+    text("input.voltage.extended", "Extended input voltage range"),
+    num(
+        "input.voltage.fault",
+        Volt,
+        "Input voltage at the last power failure",
+    ),
+    num(
+        "input.transfer.delay",
+        Second,
+        "Delay before transfer to mains",
+    ),
+    text(
+        "input.transfer.reason",
+        "Reason for last transfer to battery",
+    ),
+    num("input.transfer.low", Volt, "Low voltage transfer point"),
+    num("input.transfer.high", Volt, "High voltage transfer point"),
+    num(
+        "input.transfer.low.min",
+        Volt,
+        "Smallest settable low voltage transfer point",
+    ),
+    num(
+        "input.transfer.low.max",
+        Volt,
+        "Greatest settable low voltage transfer point",
+    ),
+    num(
+        "input.transfer.high.min",
+        Volt,
+        "Smallest settable high voltage transfer point",
+    ),
+    num(
+        "input.transfer.high.max",
+        Volt,
+        "Greatest settable high voltage transfer point",
+    ),
+    num(
+        "input.transfer.boost.low",
+        Volt,
+        "Low voltage boosting transfer point",
+    ),
+    num(
+        "input.transfer.boost.high",
+        Volt,
+        "High voltage boosting transfer point",
+    ),
+    num(
+        "input.transfer.trim.low",
+        Volt,
+        "Low voltage trimming transfer point",
+    ),
+    num(
+        "input.transfer.trim.high",
+        Volt,
+        "High voltage trimming transfer point",
+    ),
+    text("input.sensitivity", "Input power sensitivity"),
+    text("input.quality", "Input power quality"),
+    num("input.current", Ampere, "Input current"),
+    num("input.current.nominal", Ampere, "Nominal input current"),
+    text("input.current.status", "Status relative to the thresholds"),
+    num("input.frequency", Hertz, "Input line frequency"),
+    num(
+        "input.frequency.nominal",
+        Hertz,
+        "Nominal input line frequency",
+    ),
+    num("input.frequency.low", Hertz, "Input line frequency low"),
+    num("input.frequency.high", Hertz, "Input line frequency high"),
+    text("input.frequency.extended", "Extended input frequency range"),
+    text("input.frequency.status", "Frequency status"),
+    count("input.phases", "Number of input phases"),
+    num("input.load", Percent, "Load on (ePDU) input"),
+    num(
+        "input.realpower",
+        Watt,
+        "Current sum value of all phases real power",
+    ),
+    num(
+        "input.power",
+        VoltAmpere,
+        "Current sum value of all phases apparent power",
+    ),
+    num(
+        "input.phase.shift",
+        Degree,
+        "Voltage dephasing between input sources",
+    ),
+    text("input.source", "The current input power source"),
+    text("input.source.preferred", "The preferred power source"),
+    num("input.Ln-N.voltage", Volt, "Phase to neutral voltage"),
+    num("input.Ln-Lm.voltage", Volt, "Phase to phase voltage"),
+    num("input.Ln.current", Ampere, "Phase current"),
+    num("input.Ln.realpower", Watt, "Phase real power"),
+    num("input.Ln.power", VoltAmpere, "Phase apparent power"),
+    num("input.Ln.load", Percent, "Phase load"),
+    num("input.N.current", Ampere, "Neutral current"),
+    num("input.bypass.voltage", Volt, "Bypass input voltage"),
+    num("input.bypass.frequency", Hertz, "Bypass input frequency"),
+    num("input.bypass.current", Ampere, "Bypass input current"),
+    count("input.bypass.phases", "Number of bypass phases"),
+    // output
+    num("output.voltage", Volt, "Output voltage"),
+    num("output.voltage.nominal", Volt, "Nominal output voltage"),
+    num("output.frequency", Hertz, "Output frequency"),
+    num(
+        "output.frequency.nominal",
+        Hertz,
+        "Nominal output frequency",
+    ),
+    num("output.current", Ampere, "Output current"),
+    num("output.current.nominal", Ampere, "Nominal output current"),
+    num("output.realpower", Watt, "Output real power"),
+    num(
+        "output.realpower.nominal",
+        Watt,
+        "Nominal output real power",
+    ),
+    num("output.power", VoltAmpere, "Output apparent power"),
+    num(
+        "output.power.nominal",
+        VoltAmpere,
+        "Nominal output apparent power",
+    ),
+    count("output.powerfactor", "Power factor"),
+    count("output.phases", "Number of output phases"),
+    num("output.Ln-N.voltage", Volt, "Phase to neutral voltage"),
+    num("output.Ln-Lm.voltage", Volt, "Phase to phase voltage"),
+    num("output.Ln.current", Ampere, "Phase current"),
+    num("output.Ln.realpower", Watt, "Phase real power"),
+    num("output.Ln.power", VoltAmpere, "Phase apparent power"),
+    num("output.Ln.power.percent", Percent, "Phase load"),
+    count("output.Ln.crestfactor", "Phase crest factor"),
+    // battery
+    num("battery.charge", Percent, "Battery charge"),
+    num(
+        "battery.charge.approx",
+        Percent,
+        "Rough approximation of battery charge",
+    ),
+    num(
+        "battery.charge.low",
+        Percent,
+        "Remaining charge to go to low battery",
+    ),
+    num(
+        "battery.charge.restart",
+        Percent,
+        "Minimum charge for the UPS to restart",
+    ),
+    num(
+        "battery.charge.warning",
+        Percent,
+        "Battery level when UPS switches to warning",
+    ),
+    text("battery.charger.status", "Status of the battery charger"),
+    num("battery.voltage", Volt, "Battery voltage"),
+    num(
+        "battery.voltage.cell.max",
+        Volt,
+        "Maximum battery voltage seen in a cell",
+    ),
+    num(
+        "battery.voltage.cell.min",
+        Volt,
+        "Minimum battery voltage seen in a cell",
+    ),
+    num("battery.voltage.nominal", Volt, "Nominal battery voltage"),
+    num(
+        "battery.voltage.low",
+        Volt,
+        "Minimum battery voltage that triggers FSD",
+    ),
+    num("battery.voltage.high", Volt, "Maximum battery voltage"),
+    num("battery.capacity", AmpereHour, "Battery capacity"),
+    num(
+        "battery.capacity.nominal",
+        AmpereHour,
+        "Nominal battery capacity",
+    ),
+    num("battery.current", Ampere, "Battery current"),
+    num("battery.current.total", Ampere, "Total battery current"),
+    text("battery.status", "Health status of the battery"),
+    num("battery.temperature", Celsius, "Battery temperature"),
+    num(
+        "battery.temperature.cell.max",
+        Celsius,
+        "Maximum cell temperature",
+    ),
+    num(
+        "battery.temperature.cell.min",
+        Celsius,
+        "Minimum cell temperature",
+    ),
+    num("battery.runtime", Second, "Battery runtime"),
+    num(
+        "battery.runtime.low",
+        Second,
+        "Remaining runtime to go to low battery",
+    ),
+    num(
+        "battery.runtime.restart",
+        Second,
+        "Minimum runtime for the UPS to restart",
+    ),
+    text("battery.alarm.threshold", "Battery alarm threshold"),
+    text("battery.date", "Battery installation or last change date"),
+    text("battery.date.maintenance", "Battery next maintenance date"),
+    text("battery.mfr.date", "Battery manufacturing date"),
+    count("battery.packs", "Number of battery packs"),
+    count("battery.packs.bad", "Number of bad battery packs"),
+    count("battery.packs.external", "Number of external battery packs"),
+    text("battery.type", "Battery chemistry"),
+    text("battery.protection", "Prevent deep discharge of battery"),
+    text(
+        "battery.energysave",
+        "Switch off when running on battery and no load",
+    ),
+    // ambient, unindexed and per sensor
+    num("ambient.temperature", Celsius, "Ambient temperature"),
+    text("ambient.temperature.alarm", "Temperature alarm"),
+    num(
+        "ambient.temperature.high",
+        Celsius,
+        "Temperature threshold high",
+    ),
+    num(
+        "ambient.temperature.low",
+        Celsius,
+        "Temperature threshold low",
+    ),
+    text(
+        "ambient.temperature.status",
+        "Status relative to the thresholds",
+    ),
+    num("ambient.humidity", Percent, "Ambient relative humidity"),
+    text("ambient.humidity.alarm", "Relative humidity alarm"),
+    num(
+        "ambient.humidity.high",
+        Percent,
+        "Relative humidity threshold high",
+    ),
+    num(
+        "ambient.humidity.low",
+        Percent,
+        "Relative humidity threshold low",
+    ),
+    text(
+        "ambient.humidity.status",
+        "Status relative to the thresholds",
+    ),
+    text("ambient.present", "Ambient sensor presence"),
+    text("ambient.name", "Ambient sensor name"),
+    count("ambient.count", "Number of ambient sensors"),
+    num("ambient.n.temperature", Celsius, "Ambient temperature"),
+    text("ambient.n.temperature.alarm", "Temperature alarm"),
+    num(
+        "ambient.n.temperature.high",
+        Celsius,
+        "Temperature threshold high",
+    ),
+    num(
+        "ambient.n.temperature.low",
+        Celsius,
+        "Temperature threshold low",
+    ),
+    text(
+        "ambient.n.temperature.status",
+        "Status relative to the thresholds",
+    ),
+    num("ambient.n.humidity", Percent, "Ambient relative humidity"),
+    text("ambient.n.humidity.alarm", "Relative humidity alarm"),
+    num(
+        "ambient.n.humidity.high",
+        Percent,
+        "Relative humidity threshold high",
+    ),
+    num(
+        "ambient.n.humidity.low",
+        Percent,
+        "Relative humidity threshold low",
+    ),
+    text(
+        "ambient.n.humidity.status",
+        "Status relative to the thresholds",
+    ),
+    text("ambient.n.present", "Ambient sensor presence"),
+    text("ambient.n.name", "Ambient sensor name"),
+    // outlets, the main outlet and numbered ones
+    text("outlet.id", "Main outlet identifier"),
+    text("outlet.desc", "Main outlet description"),
+    text("outlet.switchable", "Main outlet can be switched"),
+    count("outlet.count", "Number of outlets"),
+    text("outlet.n.id", "Outlet identifier"),
+    text("outlet.n.name", "Outlet name"),
+    text("outlet.n.desc", "Outlet description"),
+    text("outlet.n.switch", "Outlet switch control"),
+    text("outlet.n.status", "Outlet switch status"),
+    text("outlet.n.alarm", "Outlet alarms"),
+    text("outlet.n.switchable", "Outlet can be switched"),
+    num(
+        "outlet.n.autoswitch.charge.low",
+        Percent,
+        "Battery charge that turns the outlet off",
+    ),
+    num(
+        "outlet.n.battery.charge.low",
+        Percent,
+        "Battery charge that turns the outlet off",
+    ),
+    num(
+        "outlet.n.delay.shutdown",
+        Second,
+        "Interval to wait before shutting down this outlet",
+    ),
+    num(
+        "outlet.n.delay.start",
+        Second,
+        "Interval to wait before restarting this outlet",
+    ),
+    num(
+        "outlet.n.timer.shutdown",
+        Second,
+        "Time before the outlet load will be shutdown",
+    ),
+    num(
+        "outlet.n.timer.start",
+        Second,
+        "Time before the outlet load will be started",
+    ),
+    num("outlet.n.current", Ampere, "Current"),
+    num("outlet.n.current.maximum", Ampere, "Maximum seen current"),
+    num("outlet.n.realpower", Watt, "Current value of real power"),
+    num("outlet.n.voltage", Volt, "Voltage"),
+    count("outlet.n.powerfactor", "Power factor"),
+    count("outlet.n.crestfactor", "Crest factor"),
+    num("outlet.n.power", VoltAmpere, "Apparent power"),
+    text("outlet.n.type", "Physical outlet type"),
+    count("outlet.group.count", "Number of outlet groups"),
+    text("outlet.group.n.id", "Outlet group identifier"),
+    text("outlet.group.n.name", "Outlet group name"),
+    text("outlet.group.n.desc", "Outlet group description"),
+    text("outlet.group.n.status", "Outlet group switch status"),
+    num("outlet.group.n.current", Ampere, "Outlet group current"),
+    num("outlet.group.n.realpower", Watt, "Outlet group real power"),
+    num(
+        "outlet.group.n.power",
+        VoltAmpere,
+        "Outlet group apparent power",
+    ),
+    // driver and server
+    text("driver.name", "Driver name"),
+    text("driver.version", "Driver version (NUT release)"),
+    text("driver.version.internal", "Internal driver version"),
+    text(
+        "driver.version.data",
+        "Version of the internal data mapping",
+    ),
+    num("driver.parameter.pollinterval", Second, "Poll interval"),
+    text("driver.parameter.port", "Device port"),
+    text("driver.state", "Driver state"),
+    text("server.info", "Server information"),
+    text("server.version", "Server version"),
+];
+
+/// Rewrites a concrete variable name into its dictionary pattern.
+fn pattern(name: &str) -> String {
+    let is_phase = |s: &str| matches!(s, "L1" | "L2" | "L3");
+    name.split('.')
+        .map(|segment| {
+            if !segment.is_empty() && segment.bytes().all(|b| b.is_ascii_digit()) {
+                return "n".to_string();
+            }
+            match segment.split_once('-') {
+                Some((a, "N")) if is_phase(a) => "Ln-N".to_string(),
+                Some((a, b)) if is_phase(a) && is_phase(b) => "Ln-Lm".to_string(),
+                None if is_phase(segment) => "Ln".to_string(),
+                _ => segment.to_string(),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+/// Dictionary entry for a variable name as reported by the driver.
+pub fn lookup(name: &str) -> Option<&'static VariableInfo> {
+    let pattern = pattern(name);
+    DICTIONARY.iter().find(|info| info.name == pattern)
+}
+
+pub fn all() -> &'static [VariableInfo] {
+    DICTIONARY
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn indexed_names_match_their_patterns() {
+        assert_eq!(lookup("input.L2-N.voltage").unwrap().unit, Some(Volt));
+        assert_eq!(
+            lookup("input.L1-L2.voltage").unwrap().name,
+            "input.Ln-Lm.voltage"
+        );
+        assert_eq!(
+            lookup("output.L3.power.percent").unwrap().unit,
+            Some(Percent)
+        );
+        assert_eq!(
+            lookup("outlet.12.delay.shutdown").unwrap().unit,
+            Some(Second)
+        );
+        assert_eq!(lookup("ambient.2.humidity").unwrap().unit, Some(Percent));
+        assert_eq!(lookup("ups.test.result").unwrap().kind, Kind::Text);
+        assert!(lookup("apcupsd.xfers").is_none());
+    }
+
+    #[test]
+    fn dictionary_has_no_duplicates() {
+        let mut names: Vec<_> = all().iter().map(|v| v.name).collect();
+        names.sort_unstable();
+        let len = names.len();
+        names.dedup();
+        assert_eq!(names.len(), len);
+    }
+}
@@ -0,0 +1,492 @@
+//! In-process stand-in for `upsd`, used by the tests to exercise `NutClient` and
+//! the poll loop without real hardware. It speaks the subset of the NUT network
+//! protocol the client uses and can inject faults: slow replies, dropped
+//! connections, ERR codes and device changes on a timer.
+
+use super::parser::quote;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+
+#[derive(Debug, Clone, Default)]
+pub struct FakeDevice {
+    pub description: String,
+    pub vars: BTreeMap<String, String>,
+    /// Variables that `SET VAR` may change.
+    pub rw: BTreeSet<String>,
+    pub commands: BTreeSet<String>,
+}
+
+impl FakeDevice {
+    /// A line-interactive UPS on mains power with a full battery.
+    pub fn online() -> Self {
+        let vars = [
+            ("battery.charge", "100"),
+            ("battery.runtime", "1800"),
+            ("battery.voltage", "13.5"),
+            ("device.mfr", "Emulated"),
+            ("device.model", "Fake 1500"),
+            ("input.frequency", "50.0"),
+            ("input.transfer.high", "280"),
+            ("input.transfer.low", "170"),
+            ("input.voltage", "230.0"),
+            ("output.voltage", "230.0"),
+            ("ups.load", "25"),
+            ("ups.realpower.nominal", "900"),
+            ("ups.status", "OL"),
+        ];
+        Self {
+            description: "Emulated UPS".to_string(),
+            vars: vars
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            rw: ["input.transfer.high", "input.transfer.low"]
+                .into_iter()
+                .map(str::to_string)
+                .collect(),
+            commands: ["beeper.disable", "load.off.delay", "test.battery.start"]
+                .into_iter()
+                .map(str::to_string)
+                .collect(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct ServerState {
+    devices: BTreeMap<String, FakeDevice>,
+    /// `(username, password)` required for INSTCMD and SET VAR, if any.
+    credentials: Option<(String, String)>,
+    reply_delay: Duration,
+    /// One-shot `ERR` replies keyed by command verb (`LIST`, `INSTCMD`...).
+    injected_errors: HashMap<String, Vec<String>>,
+    refuse_connections: bool,
+    /// Every line received, across all connections.
+    received: Vec<String>,
+    /// `(ups, command, argument)` for each accepted INSTCMD.
+    instcmds: Vec<(String, String, Option<String>)>,
+    /// Result reported by `GET TRACKING` for each tracking id handed out.
+    tracking: Vec<String>,
+    /// Result given to the next tracked commands instead of `SUCCESS`.
+    tracking_results: Vec<String>,
+    connections: usize,
+}
+
+/// A running fake upsd bound to an ephemeral localhost port. Dropping it stops
+/// the listener and closes every connection.
+pub struct FakeUpsd {
+    addr: SocketAddr,
+    state: Arc<Mutex<ServerState>>,
+    kick: Arc<Notify>,
+    listener: JoinHandle<()>,
+}
+
+impl FakeUpsd {
+    /// Serves a single UPS named `ups` in the `FakeDevice::online` state.
+    pub async fn start() -> Self {
+        Self::with_devices([("ups".to_string(), FakeDevice::online())]).await
+    }
+
+    pub async fn with_devices<I: IntoIterator<Item = (String, FakeDevice)>>(devices: I) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("bind fake upsd");
+        let addr = listener.local_addr().unwrap();
+        let state = Arc::new(Mutex::new(ServerState {
+            devices: devices.into_iter().collect(),
+            ..Default::default()
+        }));
+        let kick = Arc::new(Notify::new());
+
+        let accept_state = state.clone();
+        let accept_kick = kick.clone();
+        let listener = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                if accept_state.lock().unwrap().refuse_connections {
+                    drop(stream);
+                    continue;
+                }
+                accept_state.lock().unwrap().connections += 1;
+                tokio::spawn(serve(stream, accept_state.clone(), accept_kick.clone()));
+            }
+        });
+
+        Self {
+            addr,
+            state,
+            kick,
+            listener,
+        }
+    }
+
+    pub fn host(&self) -> String {
+        self.addr.ip().to_string()
+    }
+
+    pub fn port(&self) -> u16 {
+        self.addr.port()
+    }
+
+    /// A `NutConfig` pointing at this server, without credentials.
+    pub fn config(&self) -> super::models::NutConfig {
+        super::models::NutConfig {
+            host: self.host(),
+            port: self.port(),
+            username: None,
+            credential_id: None,
+        }
+    }
+
+    pub fn require_login(&self, username: &str, password: &str) {
+        self.state.lock().unwrap().credentials = Some((username.to_string(), password.to_string()));
+    }
+
+    pub fn set_var(&self, ups: &str, var: &str, value: &str) {
+        let mut state = self.state.lock().unwrap();
+        let device = state.devices.entry(ups.to_string()).or_default();
+        device.vars.insert(var.to_string(), value.to_string());
+    }
+
+    pub fn remove_var(&self, ups: &str, var: &str) {
+        if let Some(device) = self.state.lock().unwrap().devices.get_mut(ups) {
+            device.vars.remove(var);
+        }
+    }
+
+    pub fn var(&self, ups: &str, var: &str) -> Option<String> {
+        let state = self.state.lock().unwrap();
+        state.devices.get(ups)?.vars.get(var).cloned()
+    }
+
+    pub fn set_status(&self, ups: &str, status: &str) {
+        self.set_var(ups, "ups.status", status);
+    }
+
+    /// Applies `changes` to `ups` after `delay`, e.g. a scripted power cut.
+    pub fn schedule(&self, delay: Duration, ups: &str, changes: &[(&str, &str)]) {
+        let state = self.state.clone();
+        let ups = ups.to_string();
+        let changes: Vec<(String, String)> = changes
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        tokio::spawn(async move {
+            tokio::time::sleep(delay).await;
+            let mut state = state.lock().unwrap();
+            let device = state.devices.entry(ups).or_default();
+            device.vars.extend(changes);
+        });
+    }
+
+    /// Delays every reply by `delay`, simulating an overloaded or distant server.
+    pub fn set_reply_delay(&self, delay: Duration) {
+        self.state.lock().unwrap().reply_delay = delay;
+    }
+
+    /// Answers the next command starting with `verb` with `ERR <code>`.
+    pub fn fail_next(&self, verb: &str, code: &str) {
+        self.state
+            .lock()
+            .unwrap()
+            .injected_errors
+            .entry(verb.to_uppercase())
+            .or_default()
+            .push(code.to_string());
+    }
+
+    /// Closes every open client connection.
+    pub fn drop_connections(&self) {
+        self.kick.notify_waiters();
+    }
+
+    /// While set, new connections are accepted and immediately closed.
+    pub fn refuse_connections(&self, refuse: bool) {
+        self.state.lock().unwrap().refuse_connections = refuse;
+    }
+
+    pub fn received(&self) -> Vec<String> {
+        self.state.lock().unwrap().received.clone()
+    }
+
+    pub fn instcmds(&self) -> Vec<(String, String, Option<String>)> {
+        self.state.lock().unwrap().instcmds.clone()
+    }
+
+    /// Makes the next tracked INSTCMD report `result` (e.g. `PENDING` or
+    /// `ERR INVALID-ARGUMENT`) to `GET TRACKING` instead of `SUCCESS`.
+    pub fn next_tracking_result(&self, result: &str) {
+        self.state
+            .lock()
+            .unwrap()
+            .tracking_results
+            .push(result.to_string());
+    }
+
+    /// Number of connections accepted so far.
+    pub fn connections(&self) -> usize {
+        self.state.lock().unwrap().connections
+    }
+}
+
+impl Drop for FakeUpsd {
+    fn drop(&mut self) {
+        self.listener.abort();
+        self.kick.notify_waiters();
+    }
+}
+
+async fn serve(stream: TcpStream, state: Arc<Mutex<ServerState>>, kick: Arc<Notify>) {
+    let mut stream = BufReader::new(stream);
+    let mut session = Session::default();
+    let mut line = String::new();
+    loop {
+        line.clear();
+        let read = tokio::select! {
+            _ = kick.notified() => return,
+            read = stream.read_line(&mut line) => read,
+        };
+        if !matches!(read, Ok(n) if n > 0) {
+            return;
+        }
+
+        let (reply, delay) = {
+            let mut state = state.lock().unwrap();
+            state.received.push(line.trim_end().to_string());
+            (
+                session.handle(&mut state, line.trim_end()),
+                state.reply_delay,
+            )
+        };
+        if !delay.is_zero() {
+            tokio::select! {
+                _ = kick.notified() => return,
+                _ = tokio::time::sleep(delay) => {}
+            }
+        }
+        let Some(reply) = reply else {
+            return;
+        };
+        if stream.write_all(reply.as_bytes()).await.is_err() {
+            return;
+        }
+    }
+}
+
+#[derive(Default)]
+struct Session {
+    username: Option<String>,
+    password: Option<String>,
+    tracking: bool,
+}
+
+impl Session {
+    /// Returns the reply to send, or `None` to close the connection.
+    fn handle(&mut self, state: &mut ServerState, line: &str) -> Option<String> {
+        let args = tokenize(line);
+        let Some(verb) = args.first().map(|v| v.to_uppercase()) else {
+            return Some(err("UNKNOWN-COMMAND"));
+        };
+        if let Some(code) = state
+            .injected_errors
+            .get_mut(&verb)
+            .and_then(|q| (!q.is_empty()).then(|| q.remove(0)))
+        {
+            return Some(err(&code));
+        }
+        let args: Vec<&str> = args.iter().skip(1).map(String::as_str).collect();
+
+        let reply = match (verb.as_str(), args.as_slice()) {
+            ("USERNAME", [name]) => {
+                self.username = Some(name.to_string());
+                ok()
+            }
+            ("PASSWORD", [password]) => {
+                self.password = Some(password.to_string());
+                ok()
+            }
+            ("LOGOUT", _) => return None,
+            ("VER", _) => "Network UPS Tools upsd (emulated)\n".to_string(),
+            ("LIST", ["UPS"]) => {
+                let body: String = state
+                    .devices
+                    .iter()
+                    .map(|(name, d)| format!("UPS {} {}\n", name, quote(&d.description)))
+                    .collect();
+                format!("BEGIN LIST UPS\n{body}END LIST UPS\n")
+            }
+            ("LIST", [kind @ ("VAR" | "RW" | "CMD"), ups]) => {
+                let Some(device) = state.devices.get(*ups) else {
+                    return Some(err("UNKNOWN-UPS"));
+                };
+                let body: String = match *kind {
+                    "VAR" => device
+                        .vars
+                        .iter()
+                        .map(|(k, v)| format!("VAR {ups} {k} {}\n", quote(v)))
+                        .collect(),
+                    "RW" => device
+                        .vars
+                        .iter()
+                        .filter(|(k, _)| device.rw.contains(*k))
+                        .map(|(k, v)| format!("RW {ups} {k} {}\n", quote(v)))
+                        .collect(),
+                    _ => device
+                        .commands
+                        .iter()
+                        .map(|c| format!("CMD {ups} {c}\n"))
+                        .collect(),
+                };
+                format!("BEGIN LIST {kind} {ups}\n{body}END LIST {kind} {ups}\n")
+            }
+            ("GET", ["VAR", ups, var]) => match state.devices.get(*ups) {
+                None => err("UNKNOWN-UPS"),
+                Some(device) => match device.vars.get(*var) {
+                    Some(value) => format!("VAR {ups} {var} {}\n", quote(value)),
+                    None => err("VAR-NOT-SUPPORTED"),
+                },
+            },
+            ("SET", ["VAR", ups, var, value]) => {
+                if !self.authorized(state) {
+                    return Some(err("ACCESS-DENIED"));
+                }
+                match state.devices.get_mut(*ups) {
+                    None => err("UNKNOWN-UPS"),
+                    Some(device) if !device.rw.contains(*var) => err("READONLY"),
+                    Some(device) => {
+                        device.vars.insert(var.to_string(), value.to_string());
+                        ok()
+                    }
+                }
+            }
+            ("SET", ["TRACKING", mode @ ("ON" | "OFF")]) => {
+                self.tracking = *mode == "ON";
+                ok()
+            }
+            ("GET", ["TRACKING", id]) => {
+                match id.parse::<usize>().ok().and_then(|i| state.tracking.get(i)) {
+                    Some(result) => format!("{result}\n"),
+                    None => err("UNKNOWN"),
+                }
+            }
+            ("INSTCMD", [ups, cmd, rest @ ..]) if rest.len() <= 1 => {
+                if !self.authorized(state) {
+                    return Some(err("ACCESS-DENIED"));
+                }
+                match state.devices.get_mut(*ups) {
+                    None => err("UNKNOWN-UPS"),
+                    Some(device) if !device.commands.contains(*cmd) => err("CMD-NOT-SUPPORTED"),
+                    Some(device) => {
+                        // Switched outlets report their new state, as a PDU would
+                        if let Some((outlet, state)) = outlet_switch(cmd) {
+                            device
+                                .vars
+                                .insert(format!("outlet.{outlet}.status"), state.to_string());
+                        }
+                        state.instcmds.push((
+                            ups.to_string(),
+                            cmd.to_string(),
+                            rest.first().map(|v| v.to_string()),
+                        ));
+                        if self.tracking {
+                            let result = if state.tracking_results.is_empty() {
+                                "SUCCESS".to_string()
+                            } else {
+                                state.tracking_results.remove(0)
+                            };
+                            state.tracking.push(result);
+                            format!("OK TRACKING {}\n", state.tracking.len() - 1)
+                        } else {
+                            ok()
+                        }
+                    }
+                }
+            }
+            _ => err("UNKNOWN-COMMAND"),
+        };
+        Some(reply)
+    }
+
+    fn authorized(&self, state: &ServerState) -> bool {
+        match &state.credentials {
+            None => true,
+            Some((user, pass)) => {
+                self.username.as_ref() == Some(user) && self.password.as_ref() == Some(pass)
+            }
+        }
+    }
+}
+
+/// `outlet.2.load.off` -> `(2, "off")`; cycling ends with the outlet on.
+fn outlet_switch(cmd: &str) -> Option<(&str, &str)> {
+    let rest = cmd.strip_prefix("outlet.")?;
+    let (outlet, action) = rest.split_once(".load.")?;
+    match action {
+        "off" => Some((outlet, "off")),
+        "on" | "cycle" => Some((outlet, "on")),
+        _ => None,
+    }
+}
+
+fn ok() -> String {
+    "OK\n".to_string()
+}
+
+fn err(code: &str) -> String {
+    format!("ERR {code}\n")
+}
+
+/// Splits a command line on spaces, honouring double quotes and backslash escapes.
+fn tokenize(line: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut in_quotes = false;
+    let mut has_token = false;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                if let Some(next) = chars.next() {
+                    current.push(next);
+                }
+            }
+            '"' => {
+                in_quotes = !in_quotes;
+                has_token = true;
+            }
+            ' ' if !in_quotes => {
+                if has_token || !current.is_empty() {
+                    args.push(std::mem::take(&mut current));
+                }
+                has_token = false;
+            }
+            c => current.push(c),
+        }
+    }
+    if has_token || !current.is_empty() {
+        args.push(current);
+    }
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokenize_handles_quotes_and_escapes() {
+        assert_eq!(
+            tokenize(r#"SET VAR ups ups.id "Rack \"A\" 2""#),
+            vec!["SET", "VAR", "ups", "ups.id", r#"Rack "A" 2"#]
+        );
+        assert_eq!(
+            tokenize(r#"SET VAR ups x """#),
+            vec!["SET", "VAR", "ups", "x", ""]
+        );
+    }
+}
@@ -1,3 +1,8 @@
+pub mod capture;
 pub mod client;
+pub mod dictionary;
+#[cfg(test)]
+pub mod emulator;
 pub mod models;
 pub mod parser;
+pub mod simulator;
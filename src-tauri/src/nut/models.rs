@@ -5,7 +5,15 @@ pub struct NutConfig {
     pub host: String,
     pub port: u16,
     pub username: Option<String>,
-    pub password: Option<String>,
+    /// Id of the password in the credential store; the secret itself never travels in the config.
+    pub credential_id: Option<String>,
+}
+
+/// One entry of `LIST UPS`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UpsInfo {
+    pub name: String,
+    pub description: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -39,22 +47,672 @@ pub struct UpsData {
     pub output_current: Option<f64>,
     pub battery_current: Option<f64>,
     pub ups_realpower: Option<f64>,
+    /// How `power_watts` was obtained, see `calculate_power`.
+    #[serde(default)]
+    pub power_method: Option<PowerMethod>,
+    /// Our own runtime prediction, next to the `battery_runtime` the UPS reports.
+    /// Set by the monitor, see `crate::reports::runtime`.
+    #[serde(default)]
+    pub runtime_estimate: Option<RuntimeEstimate>,
+    /// Every variable without a field above, as reported. The typed groups
+    /// below are views over it, so nothing a driver reports is lost.
     pub extended_vars: std::collections::HashMap<String, String>,
+
+    // Typed views of the rest of the NUT namespace, filled by `build_details`
+    #[serde(default)]
+    pub input: LineDetails,
+    #[serde(default)]
+    pub output: LineDetails,
+    #[serde(default)]
+    pub transfer: TransferDetails,
+    #[serde(default)]
+    pub battery: BatteryDetails,
+    #[serde(default)]
+    pub device: DeviceDetails,
+    #[serde(default)]
+    pub ambient: Vec<AmbientSensor>,
+    #[serde(default)]
+    pub outlets: Vec<Outlet>,
+    #[serde(default)]
+    pub alarms: Vec<String>,
+}
+
+/// Power factor assumed when converting VA to W and the UPS does not rate both.
+pub const DEFAULT_POWER_FACTOR: f64 = 0.8;
+
+/// Source of `UpsData::power_watts`, best first.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum PowerMethod {
+    /// `ups.realpower`, `output.realpower` or the sum of the phases
+    Measured,
+    /// Apparent power × power factor. Measured VA (`ups.power`, `output.power`)
+    /// ranks above `LoadTimesRealpowerNominal`, `ups.load` × `ups.power.nominal` below it.
+    ApparentTimesPowerFactor,
+    /// `ups.load` × `ups.realpower.nominal`
+    LoadTimesRealpowerNominal,
+    /// `output.voltage` × `output.current` × power factor
+    VoltageTimesCurrent,
+}
+
+/// Runtime predicted from past discharges, in seconds.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct RuntimeEstimate {
+    pub runtime: f64,
+    /// Lower and upper bound of the 95% band
+    pub low: f64,
+    pub high: f64,
+    /// Discharges the model was fitted on
+    pub samples: usize,
+}
+
+/// One phase of a multi-phase input or output (`input.L1-N.voltage`, `output.L2.current`, ...).
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+#[serde(default)]
+pub struct PhaseReading {
+    /// 1-3
+    pub phase: u8,
+    /// Line to neutral, V
+    pub voltage: Option<f64>,
+    /// Line to the next line (L1-L2, L2-L3, L3-L1), V
+    pub line_voltage: Option<f64>,
+    /// A
+    pub current: Option<f64>,
+    /// W
+    pub realpower: Option<f64>,
+    /// VA
+    pub power: Option<f64>,
+    /// Share of the phase's rating, %
+    pub load: Option<f64>,
+}
+
+/// `input.*` or `output.*` beyond the single-phase fields on `UpsData`.
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+#[serde(default)]
+pub struct LineDetails {
+    pub phases: Option<u32>,
+    /// V
+    pub voltage_nominal: Option<f64>,
+    /// Hz
+    pub frequency_nominal: Option<f64>,
+    /// A
+    pub current: Option<f64>,
+    /// A
+    pub current_nominal: Option<f64>,
+    /// W
+    pub realpower: Option<f64>,
+    /// VA
+    pub power: Option<f64>,
+    /// VA
+    pub power_nominal: Option<f64>,
+    pub per_phase: Vec<PhaseReading>,
+    /// Largest deviation of a phase voltage from the phase average, % of the average
+    pub voltage_imbalance: Option<f64>,
+    /// Same for phase currents
+    pub current_imbalance: Option<f64>,
+}
+
+impl LineDetails {
+    /// Number of phases, from `*.phases` or else from what was reported per phase.
+    pub fn phase_count(&self) -> usize {
+        self.phases
+            .map(|n| n as usize)
+            .unwrap_or(self.per_phase.len())
+    }
+
+    /// Sum of a per-phase value, or `None` unless every reported phase has it.
+    pub fn phase_sum(&self, value: impl Fn(&PhaseReading) -> Option<f64>) -> Option<f64> {
+        if self.per_phase.is_empty() {
+            return None;
+        }
+        self.per_phase.iter().map(value).sum()
+    }
+
+    fn update_imbalance(&mut self) {
+        self.voltage_imbalance = imbalance(self.per_phase.iter().filter_map(|p| p.voltage));
+        self.current_imbalance = imbalance(self.per_phase.iter().filter_map(|p| p.current));
+    }
+}
+
+/// Maximum deviation from the mean as a percentage of the mean (the NEMA MG-1
+/// definition), over at least two phases.
+pub fn imbalance(values: impl Iterator<Item = f64>) -> Option<f64> {
+    let values: Vec<f64> = values.collect();
+    if values.len() < 2 {
+        return None;
+    }
+    let mean = values.iter().sum::<f64>() / values.len() as f64;
+    if mean <= 0.0 {
+        return None;
+    }
+    let worst = values.iter().map(|v| (v - mean).abs()).fold(0.0, f64::max);
+    Some(worst / mean * 100.0)
+}
+
+/// Voltage thresholds at which the UPS trims, boosts or transfers to battery, V.
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+#[serde(default)]
+pub struct TransferDetails {
+    pub low: Option<f64>,
+    pub high: Option<f64>,
+    pub boost_low: Option<f64>,
+    pub boost_high: Option<f64>,
+    pub trim_low: Option<f64>,
+    pub trim_high: Option<f64>,
+    pub reason: Option<String>,
+    pub sensitivity: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+#[serde(default)]
+pub struct BatteryDetails {
+    /// %
+    pub charge_low: Option<f64>,
+    /// %
+    pub charge_warning: Option<f64>,
+    /// %
+    pub charge_restart: Option<f64>,
+    /// s
+    pub runtime_low: Option<f64>,
+    /// V
+    pub voltage_nominal: Option<f64>,
+    /// V
+    pub voltage_low: Option<f64>,
+    /// V
+    pub voltage_high: Option<f64>,
+    /// Ah
+    pub capacity: Option<f64>,
+    /// °C
+    pub temperature: Option<f64>,
+    pub status: Option<String>,
+    pub charger_status: Option<String>,
+    pub date: Option<String>,
+    pub mfr_date: Option<String>,
+    pub packs: Option<u32>,
+    pub packs_bad: Option<u32>,
+}
+
+/// `ups.*` and `device.*` values that have no field on `UpsData`.
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+#[serde(default)]
+pub struct DeviceDetails {
+    pub id: Option<String>,
+    pub location: Option<String>,
+    pub contact: Option<String>,
+    pub mfr_date: Option<String>,
+    /// °C
+    pub temperature: Option<f64>,
+    /// %
+    pub efficiency: Option<f64>,
+    /// VA
+    pub power: Option<f64>,
+    /// VA
+    pub power_nominal: Option<f64>,
+    /// s
+    pub uptime: Option<f64>,
+    /// s
+    pub delay_shutdown: Option<f64>,
+    /// s
+    pub delay_start: Option<f64>,
+    /// s, counting down while a shutdown is pending
+    pub timer_shutdown: Option<f64>,
+    /// s
+    pub timer_start: Option<f64>,
+    pub test_result: Option<String>,
+    pub test_date: Option<String>,
+}
+
+/// An ambient sensor; index 0 is the unnumbered `ambient.*` sensor.
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+#[serde(default)]
+pub struct AmbientSensor {
+    pub index: u32,
+    pub name: Option<String>,
+    /// °C
+    pub temperature: Option<f64>,
+    /// %
+    pub humidity: Option<f64>,
+    pub temperature_high: Option<f64>,
+    pub temperature_low: Option<f64>,
+    pub humidity_high: Option<f64>,
+    pub humidity_low: Option<f64>,
+}
+
+/// An outlet; index 0 is the main outlet (`outlet.id`, `outlet.desc`).
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+#[serde(default)]
+pub struct Outlet {
+    pub index: u32,
+    pub id: Option<String>,
+    /// `outlet.n.name`, falling back to `outlet.n.desc`
+    pub name: Option<String>,
+    /// `on` or `off` as the driver reports it
+    pub status: Option<String>,
+    pub switchable: Option<bool>,
+    /// A
+    pub current: Option<f64>,
+    /// W
+    pub realpower: Option<f64>,
+    /// VA
+    pub power: Option<f64>,
+    /// s
+    pub delay_shutdown: Option<f64>,
+    /// s
+    pub delay_start: Option<f64>,
+}
+
+/// Typed access to `extended_vars`.
+struct Vars<'a>(&'a std::collections::HashMap<String, String>);
+
+impl Vars<'_> {
+    fn text(&self, key: &str) -> Option<String> {
+        self.0
+            .get(key)
+            .map(|v| v.trim())
+            .filter(|v| !v.is_empty())
+            .map(str::to_string)
+    }
+
+    fn num(&self, key: &str) -> Option<f64> {
+        self.0.get(key)?.trim().parse().ok()
+    }
+
+    fn count(&self, key: &str) -> Option<u32> {
+        self.num(key).map(|n| n as u32)
+    }
+
+    fn flag(&self, key: &str) -> Option<bool> {
+        match self.0.get(key)?.trim() {
+            "yes" | "1" | "on" | "true" => Some(true),
+            "no" | "0" | "off" | "false" => Some(false),
+            _ => None,
+        }
+    }
+
+    /// Indexes `n` for which some `<prefix>.<n>.*` variable exists.
+    fn indexes(&self, prefix: &str) -> Vec<u32> {
+        let mut found: Vec<u32> = self
+            .0
+            .keys()
+            .filter_map(|k| k.strip_prefix(prefix)?.strip_prefix('.'))
+            .filter_map(|rest| rest.split('.').next()?.parse().ok())
+            .collect();
+        found.sort_unstable();
+        found.dedup();
+        found
+    }
+
+    fn line(&self, prefix: &str) -> LineDetails {
+        let key = |suffix: &str| format!("{prefix}.{suffix}");
+        let per_phase = (1..=3u8)
+            .map(|p| {
+                let next = p % 3 + 1;
+                PhaseReading {
+                    phase: p,
+                    voltage: self.num(&key(&format!("L{p}-N.voltage"))),
+                    line_voltage: self.num(&key(&format!("L{p}-L{next}.voltage"))),
+                    current: self.num(&key(&format!("L{p}.current"))),
+                    realpower: self.num(&key(&format!("L{p}.realpower"))),
+                    power: self.num(&key(&format!("L{p}.power"))),
+                    load: self
+                        .num(&key(&format!("L{p}.power.percent")))
+                        .or_else(|| self.num(&key(&format!("L{p}.load")))),
+                }
+            })
+            .filter(|r| {
+                r != &PhaseReading {
+                    phase: r.phase,
+                    ..Default::default()
+                }
+            })
+            .collect();
+        LineDetails {
+            phases: self.count(&key("phases")),
+            voltage_nominal: self.num(&key("voltage.nominal")),
+            frequency_nominal: self.num(&key("frequency.nominal")),
+            current: self.num(&key("current")),
+            current_nominal: self.num(&key("current.nominal")),
+            realpower: self.num(&key("realpower")),
+            power: self.num(&key("power")),
+            power_nominal: self.num(&key("power.nominal")),
+            per_phase,
+            ..Default::default()
+        }
+    }
+
+    fn ambient(&self) -> Vec<AmbientSensor> {
+        let sensor = |index: u32, prefix: String| {
+            let key = |suffix: &str| format!("{prefix}.{suffix}");
+            AmbientSensor {
+                index,
+                name: self.text(&key("name")),
+                temperature: self.num(&key("temperature")),
+                humidity: self.num(&key("humidity")),
+                temperature_high: self.num(&key("temperature.high")),
+                temperature_low: self.num(&key("temperature.low")),
+                humidity_high: self.num(&key("humidity.high")),
+                humidity_low: self.num(&key("humidity.low")),
+            }
+        };
+        let mut sensors = Vec::new();
+        let main = sensor(0, "ambient".to_string());
+        if main.temperature.is_some() || main.humidity.is_some() {
+            sensors.push(main);
+        }
+        for index in self.indexes("ambient") {
+            sensors.push(sensor(index, format!("ambient.{index}")));
+        }
+        sensors
+    }
+
+    fn outlets(&self) -> Vec<Outlet> {
+        let mut outlets = Vec::new();
+        if self.text("outlet.id").is_some() || self.text("outlet.desc").is_some() {
+            outlets.push(Outlet {
+                index: 0,
+                id: self.text("outlet.id"),
+                name: self.text("outlet.desc"),
+                switchable: self.flag("outlet.switchable"),
+                ..Default::default()
+            });
+        }
+        for index in self.indexes("outlet") {
+            let key = |suffix: &str| format!("outlet.{index}.{suffix}");
+            outlets.push(Outlet {
+                index,
+                id: self.text(&key("id")),
+                name: self.text(&key("name")).or_else(|| self.text(&key("desc"))),
+                status: self.text(&key("status")),
+                switchable: self.flag(&key("switchable")),
+                current: self.num(&key("current")),
+                realpower: self.num(&key("realpower")),
+                power: self.num(&key("power")),
+                delay_shutdown: self.num(&key("delay.shutdown")),
+                delay_start: self.num(&key("delay.start")),
+            });
+        }
+        outlets
+    }
+}
+
+/// Splits `ups.alarm`, which drivers report either as free text or as
+/// `[alarm one] [alarm two]`.
+fn split_alarms(raw: &str) -> Vec<String> {
+    if raw.contains('[') {
+        raw.split(['[', ']'])
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(str::to_string)
+            .collect()
+    } else if raw.trim().is_empty() {
+        Vec::new()
+    } else {
+        vec![raw.trim().to_string()]
+    }
 }
 
 impl UpsData {
+    /// Fills the typed groups from `extended_vars`. Backends call this once all
+    /// variables are in, before `calculate_power`.
+    pub fn build_details(&mut self) {
+        let vars = Vars(&self.extended_vars);
+        self.input = vars.line("input");
+        self.output = vars.line("output");
+        self.transfer = TransferDetails {
+            low: vars.num("input.transfer.low"),
+            high: vars.num("input.transfer.high"),
+            boost_low: vars.num("input.transfer.boost.low"),
+            boost_high: vars.num("input.transfer.boost.high"),
+            trim_low: vars.num("input.transfer.trim.low"),
+            trim_high: vars.num("input.transfer.trim.high"),
+            reason: vars.text("input.transfer.reason"),
+            sensitivity: vars.text("input.sensitivity"),
+        };
+        self.battery = BatteryDetails {
+            charge_low: vars.num("battery.charge.low"),
+            charge_warning: vars.num("battery.charge.warning"),
+            charge_restart: vars.num("battery.charge.restart"),
+            runtime_low: vars.num("battery.runtime.low"),
+            voltage_nominal: vars.num("battery.voltage.nominal"),
+            voltage_low: vars.num("battery.voltage.low"),
+            voltage_high: vars.num("battery.voltage.high"),
+            capacity: vars.num("battery.capacity"),
+            temperature: vars.num("battery.temperature"),
+            status: vars.text("battery.status"),
+            charger_status: vars.text("battery.charger.status"),
+            date: vars.text("battery.date"),
+            mfr_date: vars.text("battery.mfr.date"),
+            packs: vars.count("battery.packs"),
+            packs_bad: vars.count("battery.packs.bad"),
+        };
+        self.device = DeviceDetails {
+            id: vars.text("ups.id"),
+            location: vars.text("device.location"),
+            contact: vars.text("device.contact"),
+            mfr_date: vars.text("ups.mfr.date"),
+            temperature: vars.num("ups.temperature"),
+            efficiency: vars.num("ups.efficiency"),
+            power: vars.num("ups.power"),
+            power_nominal: vars.num("ups.power.nominal"),
+            uptime: vars.num("device.uptime"),
+            delay_shutdown: vars.num("ups.delay.shutdown"),
+            delay_start: vars.num("ups.delay.start"),
+            timer_shutdown: vars.num("ups.timer.shutdown"),
+            timer_start: vars.num("ups.timer.start"),
+            test_result: vars.text("ups.test.result"),
+            test_date: vars.text("ups.test.date"),
+        };
+        // Some keys live in flat fields rather than `extended_vars`
+        self.output.voltage_nominal = self.output.voltage_nominal.or(self.output_voltage_nominal);
+        self.output.frequency_nominal = self
+            .output
+            .frequency_nominal
+            .or(self.output_frequency_nominal);
+        self.output.current = self.output.current.or(self.output_current);
+        self.output.realpower = self.output.realpower.or(self.ups_realpower);
+        // Three-phase units may only report per-phase values
+        for line in [&mut self.input, &mut self.output] {
+            line.realpower = line.realpower.or_else(|| line.phase_sum(|p| p.realpower));
+            line.power = line.power.or_else(|| line.phase_sum(|p| p.power));
+            line.current = line.current.or_else(|| line.phase_sum(|p| p.current));
+            line.update_imbalance();
+        }
+        if self.ups_load.is_none() {
+            self.ups_load = self
+                .output
+                .per_phase
+                .iter()
+                .filter_map(|p| p.load)
+                .reduce(f64::max);
+        }
+        self.ambient = vars.ambient();
+        match self.ambient.iter_mut().find(|s| s.index == 0) {
+            Some(main) => main.temperature = main.temperature.or(self.ambient_temp),
+            None if self.ambient_temp.is_some() => self.ambient.insert(
+                0,
+                AmbientSensor {
+                    temperature: self.ambient_temp,
+                    ..Default::default()
+                },
+            ),
+            None => {}
+        }
+        self.outlets = vars.outlets();
+        self.alarms = vars
+            .text("ups.alarm")
+            .map(|raw| split_alarms(&raw))
+            .unwrap_or_default();
+    }
+
+    /// Fills `power_watts` from the best source available and records which
+    /// one in `power_method`. Run after `build_details`, which gathers the
+    /// per-phase and `output.*` values this looks at.
     pub fn calculate_power(&mut self) {
-        // If we have direct power reporting, use it (parsed elsewhere).
-        // If not, calculate from load % and nominal power.
-        if self.power_watts.is_none() {
-            if let (Some(load), Some(nominal_power)) = (self.ups_load, self.ups_realpower_nominal) {
-                let watts = nominal_power * (load / 100.0);
-                self.power_watts = Some(watts);
-            } else {
-                // Fallback: Estimate from VA if we had it, but we only have Real Power Nominal in the logs provided.
-                // If the user's UPS provides 'ups.power.nominal' (VA), we could use power factor.
-                // For now, if we can't calculate, we leave it None.
+        let from_apparent = |va: f64| {
+            (
+                va * self.power_factor(),
+                PowerMethod::ApparentTimesPowerFactor,
+            )
+        };
+        // A measured value, even in VA, beats one derived from the load percentage
+        let estimate = self
+            .measured_power()
+            .map(|w| (w, PowerMethod::Measured))
+            .or_else(|| self.measured_apparent_power().map(from_apparent))
+            .or_else(|| {
+                self.load_times_nominal()
+                    .map(|w| (w, PowerMethod::LoadTimesRealpowerNominal))
+            })
+            .or_else(|| {
+                let va = self.device.power_nominal? * self.ups_load? / 100.0;
+                Some(from_apparent(va))
+            })
+            .or_else(|| {
+                let va = self.output_voltage? * self.output.current.or(self.output_current)?;
+                Some((va * self.power_factor(), PowerMethod::VoltageTimesCurrent))
+            });
+        self.power_watts = estimate.map(|(w, _)| w);
+        self.power_method = estimate.map(|(_, m)| m);
+    }
+
+    /// Numeric value of a NUT variable, e.g. `input.voltage`, whether it was
+    /// parsed into a field or kept in `extended_vars`. `power.watts` is our own
+    /// `power_watts`.
+    pub fn metric(&self, name: &str) -> Option<f64> {
+        match name {
+            "battery.charge" => self.battery_charge,
+            "battery.runtime" => self.battery_runtime,
+            "battery.voltage" => self.battery_voltage,
+            "battery.current" => self.battery_current,
+            "input.voltage" => self.input_voltage,
+            "input.voltage.fault" => self.input_voltage_fault,
+            "input.frequency" => self.input_frequency,
+            "output.voltage" => self.output_voltage,
+            "output.voltage.nominal" => self.output_voltage_nominal,
+            "output.frequency" => self.output_frequency,
+            "output.frequency.nominal" => self.output_frequency_nominal,
+            "output.current" => self.output_current,
+            "ups.load" => self.ups_load,
+            "ups.realpower" => self.ups_realpower,
+            "ups.realpower.nominal" => self.ups_realpower_nominal,
+            "ambient.temperature" => self.ambient_temp,
+            "power.watts" => self.power_watts,
+            _ => Vars(&self.extended_vars).num(name),
+        }
+    }
+
+    fn measured_power(&self) -> Option<f64> {
+        self.ups_realpower
+            .or(self.output.realpower)
+            .or_else(|| self.output.phase_sum(|p| p.realpower))
+    }
+
+    fn load_times_nominal(&self) -> Option<f64> {
+        let nominal = self.ups_realpower_nominal?;
+        if self.output.phase_count() > 1 {
+            // Multi-phase: each phase is rated at an equal share of the nominal
+            let phases = self.output.phase_count() as f64;
+            if let Some(load) = self.output.phase_sum(|p| p.load) {
+                return Some(nominal / phases * load / 100.0);
             }
         }
+        Some(nominal * self.ups_load? / 100.0)
+    }
+
+    fn measured_apparent_power(&self) -> Option<f64> {
+        self.device
+            .power
+            .or(self.output.power)
+            .or_else(|| self.output.phase_sum(|p| p.power))
+    }
+
+    /// The UPS's own W/VA rating when it reports both, else `DEFAULT_POWER_FACTOR`.
+    fn power_factor(&self) -> f64 {
+        match (self.ups_realpower_nominal, self.device.power_nominal) {
+            (Some(w), Some(va)) if va > 0.0 && w > 0.0 && w <= va => w / va,
+            _ => DEFAULT_POWER_FACTOR,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn three_phase(vars: &[(&str, &str)]) -> UpsData {
+        let mut data = UpsData {
+            ups_realpower_nominal: Some(9000.0),
+            extended_vars: vars
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            ..Default::default()
+        };
+        data.build_details();
+        data.calculate_power();
+        data
+    }
+
+    #[test]
+    fn sums_power_across_phases() {
+        let data = three_phase(&[
+            ("output.phases", "3"),
+            ("output.L1.realpower", "1200"),
+            ("output.L2.realpower", "900"),
+            ("output.L3.realpower", "600"),
+        ]);
+        assert_eq!(data.power_watts, Some(2700.0));
+        assert_eq!(data.output.realpower, Some(2700.0));
+
+        // Without per-phase watts each phase carries a third of the nominal
+        let data = three_phase(&[
+            ("output.L1.power.percent", "40"),
+            ("output.L2.power.percent", "20"),
+            ("output.L3.power.percent", "30"),
+        ]);
+        assert_eq!(data.power_watts, Some(2700.0));
+        assert_eq!(data.ups_load, Some(40.0));
+        assert_eq!(
+            data.power_method,
+            Some(PowerMethod::LoadTimesRealpowerNominal)
+        );
+    }
+
+    #[test]
+    fn prefers_measured_apparent_power_over_the_load_estimate() {
+        let reading = |vars: &[(&str, &str)]| {
+            let mut data = three_phase(vars);
+            data.ups_load = Some(40.0);
+            data.calculate_power();
+            data
+        };
+        let data = reading(&[("ups.power", "5000"), ("ups.power.nominal", "10000")]);
+        // 5000 VA at the unit's 9000 W / 10000 VA rating, not 40 % of 9000 W
+        assert_eq!(data.power_watts, Some(4500.0));
+        assert_eq!(
+            data.power_method,
+            Some(PowerMethod::ApparentTimesPowerFactor)
+        );
+
+        let data = reading(&[("ups.power.nominal", "10000")]);
+        assert_eq!(data.power_watts, Some(3600.0));
+        assert_eq!(
+            data.power_method,
+            Some(PowerMethod::LoadTimesRealpowerNominal)
+        );
+    }
+
+    #[test]
+    fn measures_phase_imbalance() {
+        let data = three_phase(&[
+            ("input.L1-N.voltage", "230"),
+            ("input.L2-N.voltage", "220"),
+            ("input.L3-N.voltage", "240"),
+            ("output.L1.current", "10"),
+        ]);
+        let imbalance = data.input.voltage_imbalance.unwrap();
+        assert!((imbalance - 10.0 / 230.0 * 100.0).abs() < 1e-9);
+        // A single phase has nothing to be unbalanced against
+        assert_eq!(data.output.current_imbalance, None);
     }
 }
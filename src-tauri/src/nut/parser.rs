@@ -1,67 +1,225 @@
 use crate::nut::models::UpsData;
-use std::collections::HashMap;
 
 pub fn parse_list_vars(response: &str) -> UpsData {
+    let vars: Vec<(String, String)> = response
+        .lines()
+        .filter_map(parse_var_line)
+        .map(|(_ups, key, value)| (key, value))
+        .collect();
+    parse_vars(vars.iter().map(|(k, v)| (k.as_str(), v.as_str())))
+}
+
+/// Maps NUT variables (`battery.charge`, `ups.status`, ...) onto `UpsData`.
+pub fn parse_vars<'a>(vars: impl IntoIterator<Item = (&'a str, &'a str)>) -> UpsData {
     let mut data = UpsData::default();
-    let mut vars = HashMap::new();
-
-    for line in response.lines() {
-        if line.starts_with("VAR ups ") {
-            // Format: VAR ups <key> "<value>"
-            // Example: VAR ups battery.charge "100"
-            let parts: Vec<&str> = line.splitn(4, ' ').collect();
-            if parts.len() >= 4 {
-                let key = parts[2];
-                let value_raw = parts[3];
-                // Remove quotes
-                let value = value_raw.trim_matches('"');
-                vars.insert(key, value);
-
-                // Map to struct
-                match key {
-                    "battery.charge" => data.battery_charge = value.parse().ok(),
-                    "battery.runtime" => data.battery_runtime = value.parse().ok(),
-                    "battery.voltage" => data.battery_voltage = value.parse().ok(),
-                    "battery.type" => data.battery_type = Some(value.to_string()),
-                    "input.voltage" => data.input_voltage = value.parse().ok(),
-                    "input.voltage.fault" => data.input_voltage_fault = value.parse().ok(),
-                    "input.frequency" => data.input_frequency = value.parse().ok(),
-                    "output.voltage" => data.output_voltage = value.parse().ok(),
-                    "output.voltage.nominal" => data.output_voltage_nominal = value.parse().ok(),
-                    "output.frequency" => data.output_frequency = value.parse().ok(),
-                    "output.frequency.nominal" => {
-                        data.output_frequency_nominal = value.parse().ok()
-                    }
-                    "ups.load" => data.ups_load = value.parse().ok(),
-                    "ups.status" => data.status = value.to_string(),
-                    "ups.realpower.nominal" => data.ups_realpower_nominal = value.parse().ok(),
-                    "ups.mfr" => data.ups_mfr = Some(value.to_string()),
-                    "ups.model" => data.ups_model = Some(value.to_string()),
-                    "ups.serial" => data.ups_serial = Some(value.to_string()),
-                    "ups.firmware" => data.ups_firmware = Some(value.to_string()),
-                    "ups.type" => data.ups_type = Some(value.to_string()),
-                    "ups.beeper.status" => data.ups_beeper_status = Some(value.to_string()),
-                    "driver.name" => data.driver_name = Some(value.to_string()),
-                    "driver.version" => data.driver_version = Some(value.to_string()),
-
-                    // Phase 8: Standardization
-                    "ambient.temperature" => data.ambient_temp = value.parse().ok(),
-                    "output.current" => data.output_current = value.parse().ok(),
-                    "battery.current" => data.battery_current = value.parse().ok(),
-                    "ups.realpower" => data.ups_realpower = value.parse().ok(),
-
-                    // Extended variables storage for anything else
-                    _ => {
-                        data.extended_vars
-                            .insert(key.to_string(), value.to_string());
-                    }
-                }
+
+    for (key, value) in vars {
+        // Map to struct
+        match key {
+            "battery.charge" => data.battery_charge = value.parse().ok(),
+            "battery.runtime" => data.battery_runtime = value.parse().ok(),
+            "battery.voltage" => data.battery_voltage = value.parse().ok(),
+            "battery.type" => data.battery_type = Some(value.to_string()),
+            "input.voltage" => data.input_voltage = value.parse().ok(),
+            "input.voltage.fault" => data.input_voltage_fault = value.parse().ok(),
+            "input.frequency" => data.input_frequency = value.parse().ok(),
+            "output.voltage" => data.output_voltage = value.parse().ok(),
+            "output.voltage.nominal" => data.output_voltage_nominal = value.parse().ok(),
+            "output.frequency" => data.output_frequency = value.parse().ok(),
+            "output.frequency.nominal" => data.output_frequency_nominal = value.parse().ok(),
+            "ups.load" => data.ups_load = value.parse().ok(),
+            "ups.status" => data.status = value.to_string(),
+            "ups.realpower.nominal" => data.ups_realpower_nominal = value.parse().ok(),
+            "ups.mfr" => data.ups_mfr = Some(value.to_string()),
+            "ups.model" => data.ups_model = Some(value.to_string()),
+            "ups.serial" => data.ups_serial = Some(value.to_string()),
+            "ups.firmware" => data.ups_firmware = Some(value.to_string()),
+            "ups.type" => data.ups_type = Some(value.to_string()),
+            "ups.beeper.status" => data.ups_beeper_status = Some(value.to_string()),
+            "driver.name" => data.driver_name = Some(value.to_string()),
+            "driver.version" => data.driver_version = Some(value.to_string()),
+
+            // Phase 8: Standardization
+            "ambient.temperature" => data.ambient_temp = value.parse().ok(),
+            "output.current" => data.output_current = value.parse().ok(),
+            "battery.current" => data.battery_current = value.parse().ok(),
+            "ups.realpower" => data.ups_realpower = value.parse().ok(),
+
+            // Extended variables storage for anything else
+            _ => {
+                data.extended_vars
+                    .insert(key.to_string(), value.to_string());
             }
         }
     }
 
+    data.build_details();
     // Auto-calculate power if missing
     data.calculate_power();
 
     data
 }
+
+/// Parses one `VAR <ups> <key> "<value>"` line, undoing the protocol's backslash escapes.
+/// Example: VAR myups battery.charge "100"
+pub fn parse_var_line(line: &str) -> Option<(String, String, String)> {
+    let rest = line.strip_prefix("VAR ")?;
+    let (ups, rest) = rest.split_once(' ')?;
+    let (key, value_raw) = rest.split_once(' ')?;
+    Some((ups.to_string(), key.to_string(), unquote(value_raw)))
+}
+
+/// Strips the surrounding quotes from a protocol value and resolves `\"` and `\\`.
+pub fn unquote(raw: &str) -> String {
+    let inner = raw
+        .strip_prefix('"')
+        .and_then(|r| r.strip_suffix('"'))
+        .unwrap_or(raw);
+    let mut out = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            if let Some(escaped) = chars.next() {
+                out.push(escaped);
+            }
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// Quotes a value for SET VAR / INSTCMD arguments.
+pub fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nut::capture::Capture;
+    use crate::nut::client::NutClient;
+    use crate::nut::models::PowerMethod;
+
+    fn fixture(name: &str) -> Capture {
+        let path = format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), name);
+        Capture::load(path).unwrap()
+    }
+
+    #[tokio::test]
+    async fn parses_field_capture_with_named_ups() {
+        let mut client = NutClient::replay(fixture("eaton-5p-named-ups.nutcap"), 0.0);
+        let data = client.get_ups_data("eaton5p").await.unwrap();
+
+        assert_eq!(data.status, "OL CHRG");
+        assert_eq!(data.battery_charge, Some(87.0));
+        assert_eq!(data.battery_runtime, Some(2140.0));
+        assert_eq!(data.input_voltage, Some(228.4));
+        assert_eq!(data.ups_load, Some(31.0));
+        assert_eq!(data.ups_model.as_deref(), Some("5P 1550"));
+        assert_eq!(data.driver_name.as_deref(), Some("usbhid-ups"));
+        assert_eq!(data.extended_vars["ups.id"], r#"Rack "B" \ top"#);
+        assert_eq!(data.extended_vars["outlet.1.status"], "on");
+    }
+
+    #[test]
+    fn picks_the_best_power_source() {
+        let estimate = |vars: &str| {
+            let data = parse_list_vars(vars);
+            (data.power_watts, data.power_method)
+        };
+
+        assert_eq!(
+            estimate(
+                r#"VAR ups ups.realpower "310"
+VAR ups ups.load "50"
+VAR ups ups.realpower.nominal "900""#
+            ),
+            (Some(310.0), Some(PowerMethod::Measured))
+        );
+        assert_eq!(
+            estimate(
+                r#"VAR ups ups.load "50"
+VAR ups ups.realpower.nominal "900""#
+            ),
+            (Some(450.0), Some(PowerMethod::LoadTimesRealpowerNominal))
+        );
+        // Power factor from the UPS's own ratings, 900 W / 1500 VA
+        assert_eq!(
+            estimate(
+                r#"VAR ups ups.power "500"
+VAR ups ups.power.nominal "1500"
+VAR ups ups.realpower.nominal "900""#
+            ),
+            (Some(300.0), Some(PowerMethod::ApparentTimesPowerFactor))
+        );
+        // No W rating at all: VA from the load, default power factor
+        assert_eq!(
+            estimate(
+                r#"VAR ups ups.load "25"
+VAR ups ups.power.nominal "1000""#
+            ),
+            (Some(200.0), Some(PowerMethod::ApparentTimesPowerFactor))
+        );
+        assert_eq!(
+            estimate(
+                r#"VAR ups output.voltage "230"
+VAR ups output.current "2""#
+            ),
+            (Some(368.0), Some(PowerMethod::VoltageTimesCurrent))
+        );
+        assert_eq!(estimate(r#"VAR ups ups.load "25""#), (None, None));
+    }
+
+    #[test]
+    fn builds_typed_details_from_the_wider_namespace() {
+        let data = parse_list_vars(
+            r#"VAR pdu input.phases "3"
+VAR pdu input.L1-N.voltage "229.5"
+VAR pdu input.L2-N.voltage "231.0"
+VAR pdu input.L3-L1.voltage "400.2"
+VAR pdu output.L2.current "4.1"
+VAR pdu output.L2.power.percent "37"
+VAR pdu outlet.id "0"
+VAR pdu outlet.desc "Main Outlet"
+VAR pdu outlet.2.desc "Switch"
+VAR pdu outlet.2.status "off"
+VAR pdu outlet.2.switchable "yes"
+VAR pdu outlet.10.realpower "41"
+VAR pdu ambient.temperature "24.5"
+VAR pdu ambient.1.humidity "41"
+VAR pdu battery.date "2023/04/01"
+VAR pdu ups.alarm "[Replace battery!] [Fan failure]"
+"#,
+        );
+
+        assert_eq!(data.input.phases, Some(3));
+        let phases: Vec<_> = data.input.per_phase.iter().map(|p| p.phase).collect();
+        assert_eq!(phases, [1, 2, 3]);
+        assert_eq!(data.input.per_phase[1].voltage, Some(231.0));
+        assert_eq!(data.input.per_phase[2].line_voltage, Some(400.2));
+        assert_eq!(data.output.per_phase[0].phase, 2);
+        assert_eq!(data.output.per_phase[0].load, Some(37.0));
+
+        let outlets: Vec<_> = data.outlets.iter().map(|o| o.index).collect();
+        assert_eq!(outlets, [0, 2, 10]);
+        assert_eq!(data.outlets[1].name.as_deref(), Some("Switch"));
+        assert_eq!(data.outlets[1].switchable, Some(true));
+        assert_eq!(data.outlets[2].realpower, Some(41.0));
+
+        assert_eq!(data.ambient.len(), 2);
+        assert_eq!(data.ambient[0].temperature, Some(24.5));
+        assert_eq!(data.ambient[1].humidity, Some(41.0));
+        assert_eq!(data.battery.date.as_deref(), Some("2023/04/01"));
+        assert_eq!(data.alarms, ["Replace battery!", "Fan failure"]);
+        // The raw map is still there for anything not modelled
+        assert_eq!(data.extended_vars["outlet.2.status"], "off");
+    }
+
+    #[test]
+    fn quote_roundtrips_through_unquote() {
+        for value in ["plain", r#"with "quotes""#, r"back\slash", ""] {
+            assert_eq!(unquote(&quote(value)), value);
+        }
+    }
+}
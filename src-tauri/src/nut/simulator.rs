@@ -0,0 +1,418 @@
+//! A virtual UPS for demos and operator training. `source::simulator` exposes it
+//! as a `UpsSource`, so the poll loop, history and shutdown protection run
+//! unchanged against a modelled device.
+//!
+//! The model is deliberately simple: a battery of fixed energy drained by the
+//! output load (with a small Peukert-style penalty at high load) and refilled by
+//! a charger that tapers near full, plus an input voltage the operator can cut,
+//! sag or swell through `SimEvent`s.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Handle shared between the simulator source and whoever drives the scenario.
+pub type SharedSimulator = Arc<Mutex<VirtualUps>>;
+
+const NOMINAL_VOLTAGE: f64 = 230.0;
+const TRANSFER_LOW: f64 = 170.0;
+const TRANSFER_HIGH: f64 = 280.0;
+/// Below/above these the UPS corrects the output itself (AVR) instead of transferring.
+const BOOST_BELOW: f64 = 200.0;
+const TRIM_ABOVE: f64 = 255.0;
+const BATTERY_FULL_V: f64 = 27.3;
+const BATTERY_EMPTY_V: f64 = 21.0;
+const LOW_CHARGE: f64 = 20.0;
+const LOW_RUNTIME: f64 = 180.0;
+const QUICK_TEST: Duration = Duration::from_secs(10);
+const DEEP_TEST_END_CHARGE: f64 = 25.0;
+
+/// Instant commands understood by `VirtualUps::instant_command`.
+pub const COMMANDS: &[&str] = &[
+    "beeper.mute",
+    "beeper.off",
+    "beeper.on",
+    "load.off",
+    "load.on",
+    "test.battery.start",
+    "test.battery.start.quick",
+    "test.battery.stop",
+];
+
+/// Scenario changes the UI (or a test) can inject.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum SimEvent {
+    /// Mains fails completely.
+    Outage,
+    /// Mains returns at nominal voltage.
+    Restore,
+    /// Mains drops (or rises) to this voltage and stays there until `Restore`.
+    InputVoltage { volts: f64 },
+    /// Output load in percent of the rated power.
+    SetLoad { percent: f64 },
+    /// Multiplies simulated time, e.g. 10.0 to drain the battery ten times faster.
+    SetSpeed { factor: f64 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BatteryTest {
+    Quick { until: Duration },
+    Deep,
+}
+
+pub struct VirtualUps {
+    rated_watts: f64,
+    /// Usable battery energy at 100 % charge.
+    capacity_wh: f64,
+    charge: f64,
+    load_percent: f64,
+    mains_voltage: f64,
+    output_on: bool,
+    beeper: &'static str,
+    test: Option<BatteryTest>,
+    test_result: String,
+    speed: f64,
+    /// Simulated time since start, advanced by `advance`.
+    clock: Duration,
+    last_tick: Option<Instant>,
+}
+
+impl Default for VirtualUps {
+    /// A 1500 VA / 900 W unit with two 12 V 9 Ah batteries, lightly loaded.
+    fn default() -> Self {
+        Self {
+            rated_watts: 900.0,
+            capacity_wh: 190.0,
+            charge: 100.0,
+            load_percent: 25.0,
+            mains_voltage: NOMINAL_VOLTAGE,
+            output_on: true,
+            beeper: "enabled",
+            test: None,
+            test_result: "No test initiated".to_string(),
+            speed: 1.0,
+            clock: Duration::ZERO,
+            last_tick: None,
+        }
+    }
+}
+
+impl VirtualUps {
+    pub fn shared() -> SharedSimulator {
+        Arc::new(Mutex::new(Self::default()))
+    }
+
+    pub fn apply(&mut self, event: SimEvent, now: Instant) {
+        self.tick(now);
+        match event {
+            SimEvent::Outage => self.mains_voltage = 0.0,
+            SimEvent::Restore => self.mains_voltage = NOMINAL_VOLTAGE,
+            SimEvent::InputVoltage { volts } => self.mains_voltage = volts.clamp(0.0, 400.0),
+            SimEvent::SetLoad { percent } => self.load_percent = percent.clamp(0.0, 150.0),
+            SimEvent::SetSpeed { factor } => self.speed = factor.clamp(0.1, 1000.0),
+        }
+    }
+
+    /// Advances the model to wall-clock `now`, scaled by the simulation speed.
+    pub fn tick(&mut self, now: Instant) {
+        let elapsed = match self.last_tick {
+            Some(last) => now.saturating_duration_since(last),
+            None => Duration::ZERO,
+        };
+        self.last_tick = Some(now);
+        self.advance(elapsed.mul_f64(self.speed));
+    }
+
+    /// Advances the model by `dt` of simulated time.
+    pub fn advance(&mut self, dt: Duration) {
+        // Integrate in steps so a long jump still crosses thresholds in order
+        let mut remaining = dt;
+        while !remaining.is_zero() {
+            let step = remaining.min(Duration::from_secs(1));
+            remaining -= step;
+            self.step(step);
+        }
+    }
+
+    fn step(&mut self, dt: Duration) {
+        self.clock += dt;
+        let hours = dt.as_secs_f64() / 3600.0;
+
+        if self.on_battery() {
+            let load = self.output_watts() / self.rated_watts;
+            let drawn_wh = self.output_watts() * (1.0 + 0.15 * load) * hours;
+            self.charge = (self.charge - drawn_wh / self.capacity_wh * 100.0).max(0.0);
+            if self.charge <= 0.0 {
+                // Battery exhausted: the UPS drops its output
+                self.output_on = false;
+                self.test = None;
+            }
+        } else if self.mains_ok() {
+            // Full recharge in ~4 h, slowing to a trickle over the last 10 %
+            let rate = if self.charge < 90.0 { 25.0 } else { 8.0 };
+            self.charge = (self.charge + rate * hours).min(100.0);
+            if !self.output_on && self.charge > 10.0 && self.test.is_none() {
+                self.output_on = true;
+            }
+        }
+
+        match self.test {
+            Some(BatteryTest::Quick { until }) if self.clock >= until => {
+                self.test = None;
+                self.test_result = if self.charge > LOW_CHARGE {
+                    "Done and passed".to_string()
+                } else {
+                    "Done and warning".to_string()
+                };
+            }
+            Some(BatteryTest::Deep) if self.charge <= DEEP_TEST_END_CHARGE => {
+                self.test = None;
+                self.test_result = "Done and passed".to_string();
+            }
+            _ => {}
+        }
+    }
+
+    fn mains_ok(&self) -> bool {
+        (TRANSFER_LOW..=TRANSFER_HIGH).contains(&self.mains_voltage)
+    }
+
+    fn on_battery(&self) -> bool {
+        self.output_on && self.charge > 0.0 && (!self.mains_ok() || self.test.is_some())
+    }
+
+    /// Small deterministic wobble so charts look alive without a RNG.
+    fn wobble(&self, period_s: f64, amplitude: f64) -> f64 {
+        (self.clock.as_secs_f64() / period_s).sin() * amplitude
+    }
+
+    fn output_watts(&self) -> f64 {
+        if !self.output_on {
+            return 0.0;
+        }
+        ((self.load_percent + self.wobble(7.0, 1.5)).max(0.0) / 100.0) * self.rated_watts
+    }
+
+    fn runtime_secs(&self) -> f64 {
+        let watts = self.output_watts().max(1.0);
+        let load = watts / self.rated_watts;
+        let remaining_wh = self.capacity_wh * self.charge / 100.0;
+        remaining_wh / (watts * (1.0 + 0.15 * load)) * 3600.0
+    }
+
+    fn battery_voltage(&self) -> f64 {
+        // Flat plateau with a knee at low charge, minus sag under discharge
+        let c = self.charge / 100.0;
+        let open_circuit =
+            BATTERY_EMPTY_V + (BATTERY_FULL_V - BATTERY_EMPTY_V) * (1.0 - (1.0 - c).powi(3));
+        if self.on_battery() {
+            let amps = self.output_watts() / open_circuit.max(1.0);
+            open_circuit - amps * 0.025
+        } else {
+            open_circuit
+        }
+    }
+
+    fn input_voltage(&self) -> f64 {
+        if self.mains_voltage <= 0.0 {
+            0.0
+        } else {
+            self.mains_voltage + self.wobble(11.0, 1.2)
+        }
+    }
+
+    fn output_voltage(&self) -> f64 {
+        if !self.output_on {
+            0.0
+        } else if self.on_battery() {
+            NOMINAL_VOLTAGE
+        } else if self.mains_voltage < BOOST_BELOW {
+            self.input_voltage() * 1.13
+        } else if self.mains_voltage > TRIM_ABOVE {
+            self.input_voltage() * 0.88
+        } else {
+            self.input_voltage()
+        }
+    }
+
+    pub fn status(&self) -> String {
+        let mut flags: Vec<&str> = Vec::new();
+        if !self.output_on {
+            flags.push("OFF");
+        }
+        if self.on_battery() {
+            flags.push("OB");
+            flags.push("DISCHRG");
+        } else {
+            flags.push("OL");
+            if self.mains_ok() && self.charge < 100.0 {
+                flags.push("CHRG");
+            }
+            if self.mains_ok() && self.output_on {
+                if self.mains_voltage < BOOST_BELOW {
+                    flags.push("BOOST");
+                } else if self.mains_voltage > TRIM_ABOVE {
+                    flags.push("TRIM");
+                }
+            }
+        }
+        if self.test.is_some() {
+            flags.push("CAL");
+        }
+        if self.charge < LOW_CHARGE || (self.on_battery() && self.runtime_secs() < LOW_RUNTIME) {
+            flags.push("LB");
+        }
+        flags.join(" ")
+    }
+
+    pub fn vars(&self) -> BTreeMap<String, String> {
+        let load = self.output_watts() / self.rated_watts * 100.0;
+        let frequency = if self.mains_voltage > 0.0 {
+            50.0 + self.wobble(13.0, 0.05)
+        } else {
+            0.0
+        };
+        let vars = [
+            ("battery.charge", format!("{:.0}", self.charge)),
+            ("battery.charge.low", format!("{LOW_CHARGE:.0}")),
+            ("battery.runtime", format!("{:.0}", self.runtime_secs())),
+            ("battery.runtime.low", format!("{LOW_RUNTIME:.0}")),
+            ("battery.type", "PbAc".to_string()),
+            ("battery.voltage", format!("{:.1}", self.battery_voltage())),
+            ("battery.voltage.nominal", "24.0".to_string()),
+            ("device.mfr", "WinNUT".to_string()),
+            ("device.model", "Virtual UPS 1500".to_string()),
+            ("device.type", "ups".to_string()),
+            ("driver.name", "simulator".to_string()),
+            ("driver.version", env!("CARGO_PKG_VERSION").to_string()),
+            ("input.frequency", format!("{frequency:.1}")),
+            ("input.transfer.high", format!("{TRANSFER_HIGH:.0}")),
+            ("input.transfer.low", format!("{TRANSFER_LOW:.0}")),
+            ("input.voltage", format!("{:.1}", self.input_voltage())),
+            ("input.voltage.nominal", format!("{NOMINAL_VOLTAGE:.0}")),
+            ("output.frequency", "50.0".to_string()),
+            ("output.frequency.nominal", "50".to_string()),
+            ("output.voltage", format!("{:.1}", self.output_voltage())),
+            ("output.voltage.nominal", format!("{NOMINAL_VOLTAGE:.0}")),
+            ("ups.beeper.status", self.beeper.to_string()),
+            ("ups.load", format!("{load:.0}")),
+            ("ups.mfr", "WinNUT".to_string()),
+            ("ups.model", "Virtual UPS 1500".to_string()),
+            ("ups.realpower", format!("{:.0}", self.output_watts())),
+            ("ups.realpower.nominal", format!("{:.0}", self.rated_watts)),
+            ("ups.status", self.status()),
+            ("ups.test.result", self.test_result.clone()),
+            ("ups.type", "line-interactive".to_string()),
+        ];
+        vars.into_iter().map(|(k, v)| (k.to_string(), v)).collect()
+    }
+
+    /// Runs an instant command. Errors use the NUT `ERR` codes.
+    pub fn instant_command(&mut self, cmd: &str) -> Result<(), &'static str> {
+        match cmd {
+            "beeper.on" => self.beeper = "enabled",
+            "beeper.off" => self.beeper = "disabled",
+            "beeper.mute" => self.beeper = "muted",
+            "load.off" => {
+                self.output_on = false;
+                self.test = None;
+            }
+            "load.on" => self.output_on = true,
+            "test.battery.start" | "test.battery.start.quick" => {
+                if !self.mains_ok() || !self.output_on {
+                    return Err("CMD-NOT-SUPPORTED");
+                }
+                self.test = Some(if cmd == "test.battery.start" {
+                    BatteryTest::Deep
+                } else {
+                    BatteryTest::Quick {
+                        until: self.clock + QUICK_TEST,
+                    }
+                });
+                self.test_result = "In progress".to_string();
+            }
+            "test.battery.stop" => {
+                if self.test.take().is_some() {
+                    self.test_result = "Aborted".to_string();
+                }
+            }
+            _ => return Err("CMD-NOT-SUPPORTED"),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn minutes(m: u64) -> Duration {
+        Duration::from_secs(m * 60)
+    }
+
+    #[test]
+    fn outage_discharges_until_low_battery() {
+        let mut ups = VirtualUps::default();
+        let now = Instant::now();
+        assert_eq!(ups.status(), "OL");
+
+        ups.apply(SimEvent::Outage, now);
+        assert_eq!(ups.status(), "OB DISCHRG");
+        let runtime = ups.runtime_secs();
+
+        ups.advance(minutes(10));
+        let charge = ups.charge;
+        assert!(charge < 85.0 && charge > 70.0, "{charge}");
+        assert!(ups.runtime_secs() < runtime);
+
+        ups.advance(minutes(32));
+        assert!(ups.status().contains("LB"), "{}", ups.status());
+
+        ups.apply(SimEvent::Restore, now);
+        assert!(ups.status().starts_with("OL CHRG"));
+        let low = ups.charge;
+        ups.advance(minutes(30));
+        assert!(ups.charge > low + 10.0);
+    }
+
+    #[test]
+    fn heavy_load_shortens_runtime_more_than_linearly() {
+        let mut ups = VirtualUps::default();
+        let now = Instant::now();
+        ups.apply(SimEvent::SetLoad { percent: 25.0 }, now);
+        let light = ups.runtime_secs();
+        ups.apply(SimEvent::SetLoad { percent: 100.0 }, now);
+        let heavy = ups.runtime_secs();
+        assert!(heavy * 4.0 < light);
+    }
+
+    #[test]
+    fn avr_and_transfer_thresholds() {
+        let mut ups = VirtualUps::default();
+        let now = Instant::now();
+        ups.apply(SimEvent::InputVoltage { volts: 190.0 }, now);
+        assert!(ups.status().contains("BOOST"));
+        ups.apply(SimEvent::InputVoltage { volts: 265.0 }, now);
+        assert!(ups.status().contains("TRIM"));
+        ups.apply(SimEvent::InputVoltage { volts: 150.0 }, now);
+        assert!(ups.status().starts_with("OB"));
+    }
+
+    #[test]
+    fn quick_battery_test_runs_on_battery_then_reports() {
+        let mut ups = VirtualUps::default();
+        ups.instant_command("test.battery.start.quick").unwrap();
+        assert_eq!(ups.status(), "OB DISCHRG CAL");
+        assert_eq!(ups.vars()["ups.test.result"], "In progress");
+
+        ups.advance(Duration::from_secs(11));
+        assert!(ups.status().starts_with("OL"));
+        assert_eq!(ups.vars()["ups.test.result"], "Done and passed");
+
+        // Tests need mains power
+        ups.apply(SimEvent::Outage, Instant::now());
+        assert!(ups.instant_command("test.battery.start").is_err());
+    }
+}
@@ -0,0 +1,373 @@
+//! Battery health. Every stretch of `OB` readings in the history is a discharge;
+//! for each we measure how fast the charge fell, how long the battery would have
+//! lasted at that rate compared with the `battery.runtime` the UPS predicted
+//! when it started, and how far the battery voltage sagged under the load.
+//! The runtime ratio, followed over months, gives the capacity trend. The UPS's
+//! own `RB` flag and the `battery.date` install date are folded into the score
+//! and the replacement forecast.
+
+use crate::db::HistoryEntry;
+use crate::nut::models::UpsData;
+use chrono::{DateTime, Months, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Charge a discharge must use before its runtime is extrapolated; smaller
+/// drops are dominated by the 1% resolution of `battery.charge`.
+pub const MIN_CHARGE_DROP: f64 = 5.0;
+
+/// Capacity, in % of the rated runtime, at which a battery is due for
+/// replacement (IEEE 1188 end of life for VRLA cells).
+pub const END_OF_LIFE_CAPACITY: f64 = 80.0;
+
+/// Typical service life of a VRLA UPS battery.
+pub const SERVICE_LIFE_YEARS: u32 = 4;
+
+/// A reading taken on line this long before the outage still describes the battery at rest.
+const MAX_REST_AGE_SECS: u64 = 15 * 60;
+
+const SECS_PER_MONTH: f64 = 30.44 * 24.0 * 3600.0;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Discharge {
+    /// Unix time the UPS went on battery
+    pub start: u64,
+    /// Unix time power returned, or of the last reading on battery
+    pub end: u64,
+    pub start_charge: Option<f64>,
+    pub end_charge: Option<f64>,
+    /// % of charge used per minute, fitted over the readings on battery
+    pub charge_slope: Option<f64>,
+    /// `battery.runtime` reported when the discharge started, s
+    pub predicted_runtime: Option<f64>,
+    /// Runtime from full charge state at start to empty at the observed rate, s
+    pub delivered_runtime: Option<f64>,
+    /// `delivered_runtime / predicted_runtime`
+    pub runtime_ratio: Option<f64>,
+    /// Battery voltage at rest minus the lowest voltage on battery, V
+    pub voltage_sag: Option<f64>,
+    pub avg_load: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BatteryHealth {
+    /// 0 (replace now) to 100
+    pub score: u32,
+    /// Estimated capacity today, % of what the UPS expects
+    pub capacity_percent: Option<f64>,
+    /// Capacity lost per month, percentage points
+    pub fade_per_month: Option<f64>,
+    /// The UPS reports `RB`
+    pub replace_flag: bool,
+    /// `battery.date` as reported
+    pub battery_date: Option<String>,
+    pub age_years: Option<f64>,
+    /// Unix time the battery should be replaced by
+    pub replace_by: Option<u64>,
+    pub replace_reason: Option<String>,
+    /// Oldest first
+    pub discharges: Vec<Discharge>,
+}
+
+/// Splits `history` (oldest first) into discharges.
+pub fn find_discharges(history: &[HistoryEntry]) -> Vec<Discharge> {
+    let on_battery = |e: &HistoryEntry| e.status.split_whitespace().any(|f| f == "OB");
+    let mut discharges = Vec::new();
+    let mut i = 0;
+    while i < history.len() {
+        if !on_battery(&history[i]) {
+            i += 1;
+            continue;
+        }
+        let first = i;
+        while i < history.len() && on_battery(&history[i]) {
+            i += 1;
+        }
+        let samples = &history[first..i];
+        let rest = first
+            .checked_sub(1)
+            .map(|j| &history[j])
+            .filter(|r| samples[0].timestamp.saturating_sub(r.timestamp) <= MAX_REST_AGE_SECS);
+        // The first reading back on line has the charge when the outage ended
+        let back = history.get(i);
+        discharges.push(analyse(rest, samples, back));
+    }
+    discharges
+}
+
+fn analyse(
+    rest: Option<&HistoryEntry>,
+    samples: &[HistoryEntry],
+    back: Option<&HistoryEntry>,
+) -> Discharge {
+    let first = &samples[0];
+    let last = back.unwrap_or(&samples[samples.len() - 1]);
+    let start = first.timestamp;
+    let end = last.timestamp;
+    let start_charge = rest.and_then(|r| r.battery_charge).or(first.battery_charge);
+    let end_charge = last
+        .battery_charge
+        .or(samples[samples.len() - 1].battery_charge);
+    let predicted_runtime = rest
+        .and_then(|r| r.battery_runtime)
+        .or(first.battery_runtime)
+        .filter(|r| *r > 0.0);
+
+    let points: Vec<(f64, f64)> = samples
+        .iter()
+        .chain(back)
+        .filter_map(|e| Some(((e.timestamp - start) as f64 / 60.0, e.battery_charge?)))
+        .collect();
+    let charge_slope = fit(&points).map(|(slope, _)| -slope);
+
+    let delivered_runtime = match (start_charge, end_charge) {
+        (Some(from), Some(to)) if from - to >= MIN_CHARGE_DROP && end > start => {
+            Some((end - start) as f64 * from / (from - to))
+        }
+        _ => None,
+    };
+    let runtime_ratio = delivered_runtime.zip(predicted_runtime).map(|(d, p)| d / p);
+
+    let min_voltage = samples
+        .iter()
+        .filter_map(|e| e.battery_voltage)
+        .reduce(f64::min);
+    let voltage_sag = rest
+        .and_then(|r| r.battery_voltage)
+        .zip(min_voltage)
+        .map(|(at_rest, low)| (at_rest - low).max(0.0));
+
+    let loads: Vec<f64> = samples.iter().filter_map(|e| e.load_percent).collect();
+    let avg_load = (!loads.is_empty()).then(|| loads.iter().sum::<f64>() / loads.len() as f64);
+
+    Discharge {
+        start,
+        end,
+        start_charge,
+        end_charge,
+        charge_slope,
+        predicted_runtime,
+        delivered_runtime,
+        runtime_ratio,
+        voltage_sag,
+        avg_load,
+    }
+}
+
+/// Least-squares line through `points`: (slope, intercept).
+pub(crate) fn fit(points: &[(f64, f64)]) -> Option<(f64, f64)> {
+    if points.len() < 2 {
+        return None;
+    }
+    let n = points.len() as f64;
+    let mean_x = points.iter().map(|p| p.0).sum::<f64>() / n;
+    let mean_y = points.iter().map(|p| p.1).sum::<f64>() / n;
+    let sxx: f64 = points.iter().map(|p| (p.0 - mean_x).powi(2)).sum();
+    if sxx == 0.0 {
+        return None;
+    }
+    let sxy: f64 = points.iter().map(|p| (p.0 - mean_x) * (p.1 - mean_y)).sum();
+    let slope = sxy / sxx;
+    Some((slope, mean_y - slope * mean_x))
+}
+
+/// Parses the date formats drivers use for `battery.date`: `2023/04/01`,
+/// `2023-04-01` and APC's `04/01/23`.
+pub fn parse_battery_date(text: &str) -> Option<NaiveDate> {
+    ["%Y/%m/%d", "%Y-%m-%d", "%m/%d/%y", "%m/%d/%Y"]
+        .iter()
+        .find_map(|format| NaiveDate::parse_from_str(text.trim(), format).ok())
+}
+
+/// Health of the battery as of `now` (unix time), from `history` (oldest
+/// first) and, when connected, the latest reading.
+pub fn assess(history: &[HistoryEntry], current: Option<&UpsData>, now: u64) -> BatteryHealth {
+    let discharges = find_discharges(history);
+
+    // Capacity trend: runtime ratio against time, in months before now
+    let points: Vec<(f64, f64)> = discharges
+        .iter()
+        .filter_map(|d| {
+            let months = (d.start as f64 - now as f64) / SECS_PER_MONTH;
+            Some((months, d.runtime_ratio? * 100.0))
+        })
+        .collect();
+    let span_days = points
+        .first()
+        .zip(points.last())
+        .map_or(0.0, |(a, b)| (b.0 - a.0) * SECS_PER_MONTH / 86_400.0);
+    let (capacity, fade) = match fit(&points) {
+        // A week apart at least, or the trend is noise
+        Some((slope, at_now)) if span_days >= 7.0 => (Some(at_now), Some(-slope)),
+        _ if !points.is_empty() => {
+            let mean = points.iter().map(|p| p.1).sum::<f64>() / points.len() as f64;
+            (Some(mean), None)
+        }
+        _ => (None, None),
+    };
+    let capacity = capacity.map(|c| c.clamp(0.0, 100.0));
+
+    let replace_flag = match current {
+        Some(data) => data.status.split_whitespace().any(|f| f == "RB"),
+        None => history
+            .last()
+            .is_some_and(|e| e.status.split_whitespace().any(|f| f == "RB")),
+    };
+    let battery_date = current.and_then(|d| d.battery.date.clone().or(d.battery.mfr_date.clone()));
+    let installed = battery_date.as_deref().and_then(parse_battery_date);
+    let now_date = DateTime::<Utc>::from_timestamp(now as i64, 0).map(|t| t.date_naive());
+    let age_years = installed
+        .zip(now_date)
+        .map(|(from, to)| (to - from).num_days().max(0) as f64 / 365.25);
+
+    // Score: capacity, capped by age (100 up to two years, 40 at the end of
+    // the service life) and by the UPS's own verdict
+    let mut score = capacity.unwrap_or(100.0);
+    if let Some(age) = age_years {
+        let worn = ((age - 2.0) / (f64::from(SERVICE_LIFE_YEARS) - 2.0)).clamp(0.0, 1.0);
+        score = score.min(100.0 - 60.0 * worn);
+    }
+    if replace_flag {
+        score = score.min(10.0);
+    }
+
+    // Replacement: the earliest of what the flag, the trend and the age say
+    let mut forecasts: Vec<(u64, String)> = Vec::new();
+    if replace_flag {
+        forecasts.push((
+            now,
+            "UPS reports the battery needs replacing (RB)".to_string(),
+        ));
+    }
+    if let Some(capacity) = capacity {
+        if capacity <= END_OF_LIFE_CAPACITY {
+            forecasts.push((now, format!("Capacity is down to {capacity:.0}%")));
+        } else if let Some(fade) = fade.filter(|f| *f > 0.0) {
+            let months = (capacity - END_OF_LIFE_CAPACITY) / fade;
+            forecasts.push((
+                now + (months * SECS_PER_MONTH) as u64,
+                format!("Capacity trend reaches {END_OF_LIFE_CAPACITY:.0}%"),
+            ));
+        }
+    }
+    if let Some(end_of_life) =
+        installed.and_then(|d| d.checked_add_months(Months::new(SERVICE_LIFE_YEARS * 12)))
+    {
+        let at = end_of_life
+            .and_hms_opt(0, 0, 0)
+            .map_or(now, |t| t.and_utc().timestamp().max(0) as u64);
+        forecasts.push((
+            at,
+            format!("Battery reaches {SERVICE_LIFE_YEARS} years of service"),
+        ));
+    }
+    let replace = forecasts.into_iter().min_by_key(|(at, _)| *at);
+
+    BatteryHealth {
+        score: score.round().clamp(0.0, 100.0) as u32,
+        capacity_percent: capacity,
+        fade_per_month: fade,
+        replace_flag,
+        battery_date,
+        age_years,
+        replace_by: replace.as_ref().map(|(at, _)| *at),
+        replace_reason: replace.map(|(_, reason)| reason),
+        discharges,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(t: u64, status: &str, charge: f64, runtime: f64, voltage: f64) -> HistoryEntry {
+        HistoryEntry {
+            id: None,
+            timestamp: t,
+            input_voltage: None,
+            output_voltage: None,
+            load_percent: Some(40.0),
+            battery_charge: Some(charge),
+            status: status.to_string(),
+            input_l1_voltage: None,
+            input_l2_voltage: None,
+            input_l3_voltage: None,
+            output_l1_load: None,
+            output_l2_load: None,
+            output_l3_load: None,
+            battery_runtime: Some(runtime),
+            battery_voltage: Some(voltage),
+        }
+    }
+
+    /// An outage at `t` that takes 20% of charge in `minutes`, with 2400 s predicted.
+    fn outage(t: u64, minutes: u64) -> Vec<HistoryEntry> {
+        let step = minutes * 60 / 4;
+        vec![
+            entry(t - 60, "OL", 100.0, 2400.0, 27.3),
+            entry(t, "OB DISCHRG", 100.0, 2350.0, 25.1),
+            entry(t + step, "OB DISCHRG", 95.0, 1900.0, 24.8),
+            entry(t + 2 * step, "OB DISCHRG", 90.0, 1700.0, 24.6),
+            entry(t + 3 * step, "OB DISCHRG", 85.0, 1500.0, 24.5),
+            entry(t + 4 * step, "OL CHRG", 80.0, 1400.0, 26.0),
+        ]
+    }
+
+    #[test]
+    fn measures_a_discharge() {
+        let discharges = find_discharges(&outage(1_000_000, 8));
+        assert_eq!(discharges.len(), 1);
+        let d = &discharges[0];
+        assert_eq!((d.start, d.end), (1_000_000, 1_000_480));
+        assert_eq!(d.predicted_runtime, Some(2400.0));
+        // 20% in 8 minutes: 2.5%/min, 40 minutes from 100%
+        assert!((d.charge_slope.unwrap() - 2.5).abs() < 1e-9);
+        assert_eq!(d.delivered_runtime, Some(2400.0));
+        assert_eq!(d.runtime_ratio, Some(1.0));
+        assert!((d.voltage_sag.unwrap() - 2.8).abs() < 1e-9);
+        assert_eq!(d.avg_load, Some(40.0));
+    }
+
+    #[test]
+    fn forecasts_replacement_from_fade_age_and_flag() {
+        let day = 86_400;
+        let now = 1_700_000_000;
+        // Runtime fell from 100% to 90% of the prediction over three months
+        let mut history = outage(now - 90 * day, 8);
+        history.extend(outage(now - 45 * day, 7));
+        history.extend(outage(now - day, 8 * 90 / 100));
+        let mut last = history.pop().unwrap();
+        last.battery_charge = Some(80.0);
+        history.push(last);
+
+        let health = assess(&history, None, now);
+        assert_eq!(health.discharges.len(), 3);
+        let capacity = health.capacity_percent.unwrap();
+        assert!((85.0..95.0).contains(&capacity), "{capacity}");
+        let fade = health.fade_per_month.unwrap();
+        assert!(fade > 2.0 && fade < 5.0, "{fade}");
+        let months_left = (health.replace_by.unwrap() - now) as f64 / SECS_PER_MONTH;
+        assert!((1.0..6.0).contains(&months_left), "{months_left}");
+        assert_eq!(health.score, capacity.round() as u32);
+
+        // An old battery with the RB flag up is due now, whatever the trend says
+        let data = UpsData {
+            status: "OL RB".to_string(),
+            battery: crate::nut::models::BatteryDetails {
+                date: Some("10/17/19".to_string()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let health = assess(&history, Some(&data), now);
+        assert!(health.replace_flag);
+        assert_eq!(health.score, 10);
+        // Its four years of service ran out last month
+        assert!(health.replace_by.unwrap() < now);
+        assert!(health.replace_reason.unwrap().contains("years"));
+        assert!(health.age_years.unwrap() > 4.0);
+        assert_eq!(
+            parse_battery_date("2023/04/01"),
+            NaiveDate::from_ymd_opt(2023, 4, 1)
+        );
+    }
+}
@@ -0,0 +1,303 @@
+//! Electricity cost and carbon. Each hourly energy total from `NutDB::get_energy`
+//! is priced with the configured `TariffRate` at its local time, then hours are
+//! summed into calendar days, ISO weeks (starting Monday) or months.
+
+use crate::db::{EnergyBucket, EnergyPeriod, NutDB};
+use crate::settings::{TariffConfig, TariffRate, TariffTier};
+use chrono::{DateTime, Datelike, Local, NaiveDate, TimeZone, Timelike, Weekday};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ReportPeriod {
+    Day,
+    Week,
+    Month,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct CostRow {
+    /// Unix time the day, week or month starts at
+    pub start: u64,
+    /// `2024-05-17`, `2024-W20` or `2024-05`
+    pub label: String,
+    pub kwh: f64,
+    pub cost: f64,
+    pub kg_co2: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CostReport {
+    pub period: ReportPeriod,
+    pub currency: String,
+    pub rows: Vec<CostRow>,
+    /// Sum of `rows`; its `start` is that of the first row
+    pub total: CostRow,
+}
+
+/// Report over the last `time_range_hours`, widened back to the start of the
+/// first day, week or month so that no row covers only part of its period.
+pub fn build(
+    db: &NutDB,
+    tariff: &TariffConfig,
+    period: ReportPeriod,
+    time_range_hours: u64,
+) -> rusqlite::Result<CostReport> {
+    let now = Local::now();
+    let since = now - chrono::Duration::hours(time_range_hours as i64);
+    let first = period_start(since.date_naive(), period);
+    // Tiers count from the start of the month, which may be earlier still
+    let fetch_from = local_midnight(&Local, first.with_day(1).unwrap_or(first));
+    let hours_back = (now.timestamp() as u64).saturating_sub(fetch_from) / 3600 + 1;
+    let hours = db.get_energy(EnergyPeriod::Hour, hours_back)?;
+    Ok(summarize(
+        &hours,
+        tariff,
+        period,
+        &Local,
+        local_midnight(&Local, first),
+    ))
+}
+
+/// Prices `hours` (hourly totals, oldest first) and groups those from `since` on.
+pub fn summarize<Tz: TimeZone>(
+    hours: &[EnergyBucket],
+    tariff: &TariffConfig,
+    period: ReportPeriod,
+    tz: &Tz,
+    since: u64,
+) -> CostReport {
+    let costs = price_hours(hours, &tariff.rate, tz);
+    let mut rows: Vec<CostRow> = Vec::new();
+    let mut current: Option<NaiveDate> = None;
+    for (hour, cost) in hours.iter().zip(costs) {
+        if hour.start < since {
+            continue;
+        }
+        let start = period_start(local_time(tz, hour.start).date_naive(), period);
+        if current != Some(start) {
+            current = Some(start);
+            rows.push(CostRow {
+                start: local_midnight(tz, start),
+                label: label(start, period),
+                ..Default::default()
+            });
+        }
+        if let Some(row) = rows.last_mut() {
+            row.kwh += hour.kwh;
+            row.cost += cost;
+            row.kg_co2 += hour.kwh * tariff.carbon_kg_per_kwh;
+        }
+    }
+
+    let mut total = CostRow {
+        start: rows.first().map_or(since, |r| r.start),
+        label: "Total".to_string(),
+        ..Default::default()
+    };
+    for row in &rows {
+        total.kwh += row.kwh;
+        total.cost += row.cost;
+        total.kg_co2 += row.kg_co2;
+    }
+    CostReport {
+        period,
+        currency: tariff.currency.clone(),
+        rows,
+        total,
+    }
+}
+
+/// Cost of each hour in `hours`, in order.
+pub fn price_hours<Tz: TimeZone>(hours: &[EnergyBucket], rate: &TariffRate, tz: &Tz) -> Vec<f64> {
+    let mut month = None;
+    let mut used_this_month = 0.0;
+    hours
+        .iter()
+        .map(|hour| match rate {
+            TariffRate::Flat { price_per_kwh } => hour.kwh * price_per_kwh,
+            TariffRate::TimeOfUse {
+                default_price,
+                periods,
+            } => {
+                let time = local_time(tz, hour.start);
+                let weekend = matches!(time.weekday(), Weekday::Sat | Weekday::Sun);
+                let h = time.hour();
+                let price = periods
+                    .iter()
+                    .find(|p| {
+                        let in_hours = if p.start_hour < p.end_hour {
+                            (p.start_hour..p.end_hour).contains(&h)
+                        } else {
+                            h >= p.start_hour || h < p.end_hour
+                        };
+                        in_hours && !(p.weekdays_only && weekend)
+                    })
+                    .map_or(*default_price, |p| p.price_per_kwh);
+                hour.kwh * price
+            }
+            TariffRate::Tiered { tiers } => {
+                let time = local_time(tz, hour.start);
+                let this_month = Some((time.year(), time.month()));
+                if month != this_month {
+                    month = this_month;
+                    used_this_month = 0.0;
+                }
+                let cost = tiered_cost(tiers, used_this_month, hour.kwh);
+                used_this_month += hour.kwh;
+                cost
+            }
+        })
+        .collect()
+}
+
+/// Cost of `kwh` when `used` kWh have already been billed this month.
+fn tiered_cost(tiers: &[TariffTier], used: f64, kwh: f64) -> f64 {
+    let end = used + kwh;
+    let mut lower = 0.0;
+    let mut cost = 0.0;
+    for tier in tiers {
+        let upper = tier.up_to_kwh.unwrap_or(f64::INFINITY);
+        let (from, to) = (used.max(lower), end.min(upper));
+        if to > from {
+            cost += (to - from) * tier.price_per_kwh;
+        }
+        lower = upper;
+    }
+    cost
+}
+
+pub(crate) fn local_time<Tz: TimeZone>(tz: &Tz, unix: u64) -> DateTime<Tz> {
+    tz.timestamp_opt(unix as i64, 0)
+        .earliest()
+        .unwrap_or_else(|| tz.timestamp_opt(0, 0).unwrap())
+}
+
+pub(crate) fn local_midnight<Tz: TimeZone>(tz: &Tz, date: NaiveDate) -> u64 {
+    let midnight = date.and_hms_opt(0, 0, 0).unwrap_or_default();
+    tz.from_local_datetime(&midnight)
+        .earliest()
+        .map_or(0, |t| t.timestamp().max(0) as u64)
+}
+
+fn period_start(date: NaiveDate, period: ReportPeriod) -> NaiveDate {
+    match period {
+        ReportPeriod::Day => date,
+        ReportPeriod::Week => {
+            date - chrono::Days::new(u64::from(date.weekday().num_days_from_monday()))
+        }
+        ReportPeriod::Month => date.with_day(1).unwrap_or(date),
+    }
+}
+
+fn label(start: NaiveDate, period: ReportPeriod) -> String {
+    match period {
+        ReportPeriod::Day => start.format("%Y-%m-%d").to_string(),
+        ReportPeriod::Week => {
+            let week = start.iso_week();
+            format!("{}-W{:02}", week.year(), week.week())
+        }
+        ReportPeriod::Month => start.format("%Y-%m").to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::TariffPeriod;
+    use chrono::Utc;
+
+    /// Hourly buckets of `kwh` each, starting at the given UTC date and hour.
+    fn hours(y: i32, m: u32, d: u32, h: u32, count: u64, kwh: f64) -> Vec<EnergyBucket> {
+        let first = Utc.with_ymd_and_hms(y, m, d, h, 0, 0).unwrap().timestamp() as u64;
+        (0..count)
+            .map(|i| EnergyBucket {
+                start: first + i * 3600,
+                kwh,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn prices_time_of_use_and_groups_by_week() {
+        let tariff = TariffConfig {
+            currency: "EUR".to_string(),
+            carbon_kg_per_kwh: 0.5,
+            rate: TariffRate::TimeOfUse {
+                default_price: 0.30,
+                periods: vec![TariffPeriod {
+                    start_hour: 22,
+                    end_hour: 6,
+                    price_per_kwh: 0.10,
+                    weekdays_only: true,
+                }],
+            },
+        };
+        // Friday 2024-05-17 12:00 to Monday 2024-05-20 12:00, 1 kWh an hour
+        let energy = hours(2024, 5, 17, 12, 72, 1.0);
+
+        let days = summarize(&energy, &tariff, ReportPeriod::Day, &Utc, 0);
+        let labels: Vec<_> = days.rows.iter().map(|r| r.label.as_str()).collect();
+        assert_eq!(
+            labels,
+            ["2024-05-17", "2024-05-18", "2024-05-19", "2024-05-20"]
+        );
+        // Friday: 10 day hours, then 22-24 cheap
+        assert!((days.rows[0].cost - (10.0 * 0.30 + 2.0 * 0.10)).abs() < 1e-9);
+        // The weekend pays the default price all night
+        assert!((days.rows[1].cost - 24.0 * 0.30).abs() < 1e-9);
+        // Monday 00-06 is cheap again
+        assert!((days.rows[3].cost - (6.0 * 0.10 + 6.0 * 0.30)).abs() < 1e-9);
+        assert_eq!(days.total.kwh, 72.0);
+        assert!((days.total.kg_co2 - 36.0).abs() < 1e-9);
+
+        let weeks = summarize(&energy, &tariff, ReportPeriod::Week, &Utc, 0);
+        let labels: Vec<_> = weeks.rows.iter().map(|r| r.label.as_str()).collect();
+        assert_eq!(labels, ["2024-W20", "2024-W21"]);
+        assert_eq!(weeks.rows[0].kwh, 60.0);
+        assert_eq!(
+            weeks.rows[1].start,
+            Utc.with_ymd_and_hms(2024, 5, 20, 0, 0, 0)
+                .unwrap()
+                .timestamp() as u64
+        );
+    }
+
+    #[test]
+    fn tiers_restart_every_month() {
+        let tariff = TariffConfig {
+            rate: TariffRate::Tiered {
+                tiers: vec![
+                    TariffTier {
+                        up_to_kwh: Some(10.0),
+                        price_per_kwh: 0.10,
+                    },
+                    TariffTier {
+                        up_to_kwh: None,
+                        price_per_kwh: 0.20,
+                    },
+                ],
+            },
+            ..Default::default()
+        };
+        // 4 kWh an hour over the last 4 hours of April and the first 4 of May
+        let energy = hours(2024, 4, 30, 20, 8, 4.0);
+        let costs = price_hours(&energy, &tariff.rate, &Utc);
+        // 4 + 4 + 2 kWh in the first tier, then the second; May starts over
+        let expected = [0.4, 0.4, 0.6, 0.8, 0.4, 0.4, 0.6, 0.8];
+        for (cost, expected) in costs.iter().zip(expected) {
+            assert!((cost - expected).abs() < 1e-9, "{costs:?}");
+        }
+
+        // Hours before `since` still count towards the tiers but are not reported
+        let may = Utc
+            .with_ymd_and_hms(2024, 5, 1, 2, 0, 0)
+            .unwrap()
+            .timestamp() as u64;
+        let report = summarize(&energy, &tariff, ReportPeriod::Month, &Utc, may);
+        assert_eq!(report.rows.len(), 1);
+        assert_eq!(report.rows[0].label, "2024-05");
+        assert_eq!(report.rows[0].kwh, 8.0);
+        assert!((report.rows[0].cost - 1.4).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,6 @@
+//! Reports computed from what `NutDB` has recorded.
+
+pub mod battery;
+pub mod cost;
+pub mod quality;
+pub mod runtime;
@@ -0,0 +1,487 @@
+//! Power quality over a time window, from two sources. The history, which
+//! records every status change and every fast voltage move, gives the events:
+//! sags and swells, TRIM/BOOST episodes and transfers to battery. The
+//! `quality` rollup, filled from every reading by
+//! `crate::monitor::quality::QualityMeter`, gives the time spent at each input
+//! voltage and frequency and in each operating mode. Sags and swells follow
+//! IEEE 1159: below 90% and above 110% of the nominal voltage.
+
+use super::cost::{local_midnight, local_time};
+use crate::db::{HistoryEntry, QualityBucket};
+use crate::nut::models::UpsData;
+use chrono::TimeZone;
+use serde::{Deserialize, Serialize};
+
+pub const METRIC_VOLTAGE: &str = "voltage";
+pub const METRIC_FREQUENCY: &str = "frequency";
+pub const METRIC_MODE: &str = "mode";
+
+/// Width of a voltage bin, V
+pub const VOLTAGE_BIN: f64 = 2.0;
+/// Frequency bins are tenths of a hertz.
+const FREQUENCY_SCALE: f64 = 10.0;
+
+pub const MODE_LINE: i64 = 0;
+pub const MODE_TRIM: i64 = 1;
+pub const MODE_BOOST: i64 = 2;
+pub const MODE_BATTERY: i64 = 3;
+
+const SAG_BELOW: f64 = 0.9;
+const SWELL_ABOVE: f64 = 1.1;
+/// Below this fraction of nominal the supply is interrupted rather than sagging.
+const INTERRUPTION_BELOW: f64 = 0.1;
+/// Frequency deviation tolerated, fraction of nominal
+const FREQUENCY_TOLERANCE: f64 = 0.01;
+/// Readings further apart than this do not belong to the same event.
+const MAX_GAP_SECS: u64 = 300;
+
+const NOMINAL_VOLTAGES: [f64; 10] = [
+    100.0, 110.0, 115.0, 120.0, 127.0, 200.0, 208.0, 220.0, 230.0, 240.0,
+];
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct VoltageEvents {
+    pub count: u32,
+    pub total_secs: u64,
+    pub longest_secs: u64,
+    /// Lowest voltage of the sags, or highest of the swells
+    pub extreme: Option<f64>,
+    /// Events that went past the transfer threshold
+    pub beyond_transfer: u32,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct ModeActivity {
+    /// Times the UPS entered the mode
+    pub events: u32,
+    pub secs: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct FrequencyStats {
+    pub nominal: f64,
+    pub min: f64,
+    pub max: f64,
+    pub max_deviation: f64,
+    /// Time more than 1% off nominal
+    pub secs_outside: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct DayCount {
+    /// Unix time of local midnight
+    pub start: u64,
+    /// `2024-05-17`
+    pub label: String,
+    pub transfers: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct HistogramBin {
+    /// Input voltage, from inclusive to exclusive
+    pub from: f64,
+    pub to: f64,
+    pub secs: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PowerQualityReport {
+    pub nominal_voltage: f64,
+    pub sag_below: f64,
+    pub swell_above: f64,
+    pub transfer_low: Option<f64>,
+    pub transfer_high: Option<f64>,
+    pub sags: VoltageEvents,
+    pub swells: VoltageEvents,
+    pub frequency: Option<FrequencyStats>,
+    pub trim: ModeActivity,
+    pub boost: ModeActivity,
+    pub on_battery_secs: f64,
+    /// Time covered by the rollup
+    pub observed_secs: f64,
+    /// Transfers to battery
+    pub transfers: u32,
+    /// Every day of the window, oldest first
+    pub transfers_per_day: Vec<DayCount>,
+    pub histogram: Vec<HistogramBin>,
+}
+
+/// Rollup bins a reading falls in: (metric, bin).
+pub fn bins(data: &UpsData) -> Vec<(&'static str, i64)> {
+    let mut bins = Vec::new();
+    if let Some(volts) = data.input_voltage.filter(|v| v.is_finite() && *v >= 0.0) {
+        bins.push((
+            METRIC_VOLTAGE,
+            ((volts / VOLTAGE_BIN).floor() * VOLTAGE_BIN) as i64,
+        ));
+    }
+    if let Some(hz) = data.input_frequency.filter(|f| f.is_finite() && *f > 0.0) {
+        bins.push((METRIC_FREQUENCY, (hz * FREQUENCY_SCALE).round() as i64));
+    }
+    if !data.status.is_empty() {
+        bins.push((METRIC_MODE, mode(&data.status)));
+    }
+    bins
+}
+
+fn has_flag(status: &str, flag: &str) -> bool {
+    status.split_whitespace().any(|f| f == flag)
+}
+
+fn mode(status: &str) -> i64 {
+    if has_flag(status, "OB") {
+        MODE_BATTERY
+    } else if has_flag(status, "TRIM") {
+        MODE_TRIM
+    } else if has_flag(status, "BOOST") {
+        MODE_BOOST
+    } else {
+        MODE_LINE
+    }
+}
+
+/// Report on `history` (oldest first) and the rollup `buckets` of the window
+/// `since..until`. Nominal values and transfer thresholds come from the
+/// latest reading when there is one.
+pub fn build<Tz: TimeZone>(
+    history: &[HistoryEntry],
+    buckets: &[QualityBucket],
+    current: Option<&UpsData>,
+    tz: &Tz,
+    since: u64,
+    until: u64,
+) -> PowerQualityReport {
+    let of = |metric: &'static str| {
+        buckets
+            .iter()
+            .filter(move |b| b.metric == metric && b.secs > 0.0)
+    };
+    let histogram: Vec<HistogramBin> = of(METRIC_VOLTAGE)
+        .map(|b| HistogramBin {
+            from: b.bin as f64,
+            to: b.bin as f64 + VOLTAGE_BIN,
+            secs: b.secs,
+        })
+        .collect();
+
+    let nominal_voltage = current
+        .and_then(|d| d.input.voltage_nominal.or(d.output_voltage_nominal))
+        .filter(|v| *v > 0.0)
+        .unwrap_or_else(|| guess_nominal_voltage(history, &histogram));
+    let transfer_low = current.and_then(|d| d.transfer.low);
+    let transfer_high = current.and_then(|d| d.transfer.high);
+    let sag_below = nominal_voltage * SAG_BELOW;
+    let swell_above = nominal_voltage * SWELL_ABOVE;
+
+    let sags = voltage_events(
+        history,
+        |v| v >= nominal_voltage * INTERRUPTION_BELOW && v < sag_below,
+        f64::min,
+        |v| transfer_low.is_some_and(|t| v < t),
+    );
+    let swells = voltage_events(
+        history,
+        |v| v > swell_above,
+        f64::max,
+        |v| transfer_high.is_some_and(|t| v > t),
+    );
+
+    let mode_secs =
+        |m: i64| -> f64 { of(METRIC_MODE).filter(|b| b.bin == m).map(|b| b.secs).sum() };
+    let trim = ModeActivity {
+        events: rising_edges(history, "TRIM").len() as u32,
+        secs: mode_secs(MODE_TRIM),
+    };
+    let boost = ModeActivity {
+        events: rising_edges(history, "BOOST").len() as u32,
+        secs: mode_secs(MODE_BOOST),
+    };
+
+    let transfer_times = rising_edges(history, "OB");
+    let mut transfers_per_day = Vec::new();
+    let mut day = local_time(tz, since).date_naive();
+    let last_day = local_time(tz, until).date_naive();
+    while day <= last_day {
+        let start = local_midnight(tz, day);
+        let transfers = transfer_times
+            .iter()
+            .filter(|t| local_time(tz, **t).date_naive() == day)
+            .count() as u32;
+        transfers_per_day.push(DayCount {
+            start,
+            label: day.format("%Y-%m-%d").to_string(),
+            transfers,
+        });
+        let Some(next) = day.succ_opt() else { break };
+        day = next;
+    }
+
+    PowerQualityReport {
+        nominal_voltage,
+        sag_below,
+        swell_above,
+        transfer_low,
+        transfer_high,
+        sags,
+        swells,
+        frequency: frequency_stats(buckets, current),
+        trim,
+        boost,
+        on_battery_secs: mode_secs(MODE_BATTERY),
+        observed_secs: of(METRIC_MODE).map(|b| b.secs).sum(),
+        transfers: transfer_times.len() as u32,
+        transfers_per_day,
+        histogram,
+    }
+}
+
+/// Standard voltage closest to the typical reading, for UPS units that do not report one.
+fn guess_nominal_voltage(history: &[HistoryEntry], histogram: &[HistogramBin]) -> f64 {
+    let time: f64 = histogram.iter().map(|b| b.secs).sum();
+    let typical = if time > 0.0 {
+        histogram
+            .iter()
+            .map(|b| (b.from + VOLTAGE_BIN / 2.0) * b.secs)
+            .sum::<f64>()
+            / time
+    } else {
+        let readings: Vec<f64> = history
+            .iter()
+            .filter_map(|e| e.input_voltage)
+            .filter(|v| *v > 50.0)
+            .collect();
+        if readings.is_empty() {
+            return 230.0;
+        }
+        readings.iter().sum::<f64>() / readings.len() as f64
+    };
+    NOMINAL_VOLTAGES
+        .into_iter()
+        .min_by(|a, b| (a - typical).abs().total_cmp(&(b - typical).abs()))
+        .unwrap_or(230.0)
+}
+
+/// Stretches of readings for which `inside` holds.
+fn voltage_events(
+    history: &[HistoryEntry],
+    inside: impl Fn(f64) -> bool,
+    worse: fn(f64, f64) -> f64,
+    beyond_transfer: impl Fn(f64) -> bool,
+) -> VoltageEvents {
+    let mut stats = VoltageEvents::default();
+    let mut close = |start: u64, end: u64, extreme: f64| {
+        let secs = end - start;
+        stats.count += 1;
+        stats.total_secs += secs;
+        stats.longest_secs = stats.longest_secs.max(secs);
+        stats.extreme = Some(stats.extreme.map_or(extreme, |x| worse(x, extreme)));
+        if beyond_transfer(extreme) {
+            stats.beyond_transfer += 1;
+        }
+    };
+
+    // (start, last reading, extreme voltage) of the event in progress
+    let mut open: Option<(u64, u64, f64)> = None;
+    for entry in history {
+        let Some(volts) = entry.input_voltage else {
+            continue;
+        };
+        let now_inside = inside(volts);
+        if let Some((start, last, extreme)) = open {
+            let gap = entry.timestamp.saturating_sub(last) > MAX_GAP_SECS;
+            if gap || !now_inside {
+                // The event lasted until this reading, unless we lost track of it
+                close(start, if gap { last } else { entry.timestamp }, extreme);
+                open = None;
+            }
+        }
+        if now_inside {
+            open = Some(match open {
+                Some((start, _, extreme)) => (start, entry.timestamp, worse(extreme, volts)),
+                None => (entry.timestamp, entry.timestamp, volts),
+            });
+        }
+    }
+    if let Some((start, last, extreme)) = open {
+        close(start, last, extreme);
+    }
+    stats
+}
+
+/// Times at which `flag` appeared in the status.
+fn rising_edges(history: &[HistoryEntry], flag: &str) -> Vec<u64> {
+    history
+        .windows(2)
+        .filter(|w| !has_flag(&w[0].status, flag) && has_flag(&w[1].status, flag))
+        .map(|w| w[1].timestamp)
+        .collect()
+}
+
+fn frequency_stats(buckets: &[QualityBucket], current: Option<&UpsData>) -> Option<FrequencyStats> {
+    let bins: Vec<(f64, f64)> = buckets
+        .iter()
+        .filter(|b| b.metric == METRIC_FREQUENCY && b.secs > 0.0)
+        .map(|b| (b.bin as f64 / FREQUENCY_SCALE, b.secs))
+        .collect();
+    let time: f64 = bins.iter().map(|b| b.1).sum();
+    if time <= 0.0 {
+        return None;
+    }
+    let mean = bins.iter().map(|(hz, secs)| hz * secs).sum::<f64>() / time;
+    let nominal = current
+        .and_then(|d| d.input.frequency_nominal.or(d.output_frequency_nominal))
+        .filter(|f| *f > 0.0)
+        .unwrap_or(if (mean - 60.0).abs() < (mean - 50.0).abs() {
+            60.0
+        } else {
+            50.0
+        });
+    let min = bins.iter().map(|b| b.0).fold(f64::MAX, f64::min);
+    let max = bins.iter().map(|b| b.0).fold(f64::MIN, f64::max);
+    // Half a bin of slack, so rounding alone never counts as a deviation
+    let tolerance = nominal * FREQUENCY_TOLERANCE + 0.5 / FREQUENCY_SCALE;
+    Some(FrequencyStats {
+        nominal,
+        min,
+        max,
+        max_deviation: (min - nominal).abs().max((max - nominal).abs()),
+        secs_outside: bins
+            .iter()
+            .filter(|(hz, _)| (hz - nominal).abs() > tolerance)
+            .map(|b| b.1)
+            .sum(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn entry(timestamp: u64, status: &str, volts: f64) -> HistoryEntry {
+        HistoryEntry {
+            id: None,
+            timestamp,
+            input_voltage: Some(volts),
+            output_voltage: Some(230.0),
+            load_percent: None,
+            battery_charge: None,
+            status: status.to_string(),
+            input_l1_voltage: None,
+            input_l2_voltage: None,
+            input_l3_voltage: None,
+            output_l1_load: None,
+            output_l2_load: None,
+            output_l3_load: None,
+            battery_runtime: None,
+            battery_voltage: None,
+        }
+    }
+
+    fn bucket(metric: &str, bin: i64, secs: f64) -> QualityBucket {
+        QualityBucket {
+            metric: metric.to_string(),
+            bin,
+            secs,
+        }
+    }
+
+    #[test]
+    fn finds_events_and_transfers() {
+        // 2024-05-17 00:00 UTC
+        let day = 1_715_904_000;
+        let history = vec![
+            entry(day + 100, "OL", 230.0),
+            // A 30 s sag to 200 V, handled by BOOST
+            entry(day + 200, "OL BOOST", 204.0),
+            entry(day + 215, "OL BOOST", 200.0),
+            entry(day + 230, "OL", 229.0),
+            // A swell, then a sag deep enough to transfer the next day
+            entry(day + 3600, "OL TRIM", 256.0),
+            entry(day + 3610, "OL", 231.0),
+            entry(day + 86_400 + 50, "OL", 190.0),
+            entry(day + 86_400 + 60, "OB DISCHRG", 150.0),
+            entry(day + 86_400 + 120, "OL", 230.0),
+            // A sag whose end was not seen lasts until its last reading
+            entry(day + 86_400 + 7000, "OL", 202.0),
+            entry(day + 86_400 + 9000, "OB", 0.0),
+        ];
+        let data = UpsData {
+            input: crate::nut::models::LineDetails {
+                voltage_nominal: Some(230.0),
+                ..Default::default()
+            },
+            transfer: crate::nut::models::TransferDetails {
+                low: Some(170.0),
+                high: Some(264.0),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let report = build(&history, &[], Some(&data), &Utc, day, day + 86_400 + 9000);
+        assert_eq!(report.sag_below, 207.0);
+        assert_eq!(report.sags.count, 3);
+        assert_eq!(report.sags.longest_secs, 70);
+        assert_eq!(report.sags.total_secs, 30 + 70);
+        assert_eq!(report.sags.extreme, Some(150.0));
+        assert_eq!(report.sags.beyond_transfer, 1);
+        assert_eq!(report.swells.count, 1);
+        assert_eq!(report.swells.extreme, Some(256.0));
+        assert_eq!(report.swells.beyond_transfer, 0);
+        assert_eq!((report.boost.events, report.trim.events), (1, 1));
+
+        assert_eq!(report.transfers, 2);
+        let per_day: Vec<(&str, u32)> = report
+            .transfers_per_day
+            .iter()
+            .map(|d| (d.label.as_str(), d.transfers))
+            .collect();
+        assert_eq!(per_day, [("2024-05-17", 0), ("2024-05-18", 2)]);
+    }
+
+    #[test]
+    fn reads_time_from_the_rollup() {
+        let buckets = [
+            bucket(METRIC_FREQUENCY, 499, 3000.0),
+            bucket(METRIC_FREQUENCY, 500, 500.0),
+            bucket(METRIC_FREQUENCY, 507, 100.0),
+            bucket(METRIC_MODE, MODE_LINE, 3000.0),
+            bucket(METRIC_MODE, MODE_TRIM, 500.0),
+            bucket(METRIC_MODE, MODE_BATTERY, 100.0),
+            bucket(METRIC_VOLTAGE, 118, 3500.0),
+            bucket(METRIC_VOLTAGE, 120, 100.0),
+        ];
+        let report = build(&[], &buckets, None, &Utc, 0, 3600);
+        // No nominal reported: the closest standard voltage to the readings
+        assert_eq!(report.nominal_voltage, 120.0);
+        assert_eq!(report.observed_secs, 3600.0);
+        assert_eq!(report.trim.secs, 500.0);
+        assert_eq!(report.on_battery_secs, 100.0);
+        assert_eq!(report.histogram.len(), 2);
+        assert_eq!(
+            (report.histogram[0].from, report.histogram[0].to),
+            (118.0, 120.0)
+        );
+
+        let frequency = report.frequency.unwrap();
+        assert_eq!(frequency.nominal, 50.0);
+        assert!((frequency.max_deviation - 0.7).abs() < 1e-9);
+        assert_eq!(frequency.secs_outside, 100.0);
+
+        let data = UpsData {
+            input_voltage: Some(229.3),
+            input_frequency: Some(49.96),
+            status: "OL TRIM".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            bins(&data),
+            [
+                (METRIC_VOLTAGE, 228),
+                (METRIC_FREQUENCY, 500),
+                (METRIC_MODE, MODE_TRIM)
+            ]
+        );
+    }
+}
@@ -0,0 +1,167 @@
+//! Runtime prediction. The `battery.runtime` of many UPS units is far off, so
+//! we fit our own from the discharges in the history: each one tells how long
+//! a full battery lasts at the load it carried. A power law
+//! `runtime = a · load^-k` through those points, fitted in log space, gives
+//! the full-charge runtime at the present load, scaled by the charge left.
+//! The scatter of the discharges around the curve gives the 95% band.
+
+use super::battery::{fit, Discharge};
+use crate::nut::models::{RuntimeEstimate, UpsData};
+use serde::{Deserialize, Serialize};
+
+/// Discharges needed before the model is trusted.
+pub const MIN_DISCHARGES: usize = 2;
+
+/// Exponent used while the discharges were all at about the same load; lead-acid
+/// batteries deliver less energy the faster they are drained.
+pub const DEFAULT_EXPONENT: f64 = 1.2;
+
+/// Highest to lowest load the discharges must span before the exponent is fitted.
+const MIN_LOAD_SPAN: f64 = 1.5;
+
+/// Smallest spread of ln(runtime) assumed, however well the discharges line up.
+const MIN_SIGMA: f64 = 0.05;
+
+/// Two-sided 95% quantile of the normal distribution
+const Z_95: f64 = 1.96;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RuntimeModel {
+    /// Full-charge runtime at 100% load, s
+    pub coefficient: f64,
+    pub exponent: f64,
+    /// Standard deviation of ln(runtime) around the curve
+    pub sigma: f64,
+    pub samples: usize,
+}
+
+impl RuntimeModel {
+    /// Fits the discharges that ran long enough to measure; `None` if too few did.
+    pub fn fit(discharges: &[Discharge]) -> Option<Self> {
+        let points: Vec<(f64, f64)> = discharges
+            .iter()
+            .filter_map(|d| {
+                let load = d.avg_load.filter(|l| *l >= 1.0)?;
+                let from = d.start_charge.filter(|c| *c > 0.0)?;
+                // Runtime from 100%, discharging at the same rate
+                let full = d.delivered_runtime.filter(|r| *r > 0.0)? * 100.0 / from;
+                Some(((load / 100.0).ln(), full.ln()))
+            })
+            .collect();
+        if points.len() < MIN_DISCHARGES {
+            return None;
+        }
+
+        let (min, max) = points.iter().fold((f64::MAX, f64::MIN), |(lo, hi), p| {
+            (lo.min(p.0), hi.max(p.0))
+        });
+        let fitted = (max - min >= MIN_LOAD_SPAN.ln())
+            .then(|| fit(&points))
+            .flatten()
+            .map(|(slope, _)| (-slope).clamp(0.5, 2.0));
+        let exponent = fitted.unwrap_or(DEFAULT_EXPONENT);
+        let params = if fitted.is_some() { 2 } else { 1 };
+
+        let n = points.len() as f64;
+        let ln_a = points.iter().map(|(x, y)| y + exponent * x).sum::<f64>() / n;
+        let residuals: f64 = points
+            .iter()
+            .map(|(x, y)| (y - (ln_a - exponent * x)).powi(2))
+            .sum();
+        let sigma = if points.len() > params {
+            (residuals / (points.len() - params) as f64).sqrt()
+        } else {
+            0.0
+        };
+
+        Some(Self {
+            coefficient: ln_a.exp(),
+            exponent,
+            sigma: sigma.max(MIN_SIGMA),
+            samples: points.len(),
+        })
+    }
+
+    /// Runtime left at `load` and `charge` (both %).
+    pub fn predict(&self, load: f64, charge: f64) -> RuntimeEstimate {
+        let full = self.coefficient * (load.max(1.0) / 100.0).powf(-self.exponent);
+        let runtime = full * charge.clamp(0.0, 100.0) / 100.0;
+        let spread = Z_95 * self.sigma * (1.0 + 1.0 / self.samples as f64).sqrt();
+        RuntimeEstimate {
+            runtime,
+            low: runtime * (-spread).exp(),
+            high: runtime * spread.exp(),
+            samples: self.samples,
+        }
+    }
+
+    /// Prediction for a reading, if it reports both load and charge.
+    pub fn estimate(&self, data: &UpsData) -> Option<RuntimeEstimate> {
+        Some(self.predict(data.ups_load?, data.battery_charge?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn discharge(load: f64, start_charge: f64, delivered: f64) -> Discharge {
+        Discharge {
+            start: 0,
+            end: 600,
+            start_charge: Some(start_charge),
+            end_charge: Some(start_charge - 20.0),
+            charge_slope: None,
+            predicted_runtime: None,
+            delivered_runtime: Some(delivered),
+            runtime_ratio: None,
+            voltage_sag: None,
+            avg_load: Some(load),
+        }
+    }
+
+    #[test]
+    fn fits_the_load_curve() {
+        // 600 s at full load, exponent 1.3
+        let runtime = |load: f64| 600.0 * (load / 100.0_f64).powf(-1.3);
+        let discharges = [
+            discharge(20.0, 100.0, runtime(20.0)),
+            // Started at 80%: delivered 80% of the full runtime
+            discharge(40.0, 80.0, runtime(40.0) * 0.8),
+            discharge(80.0, 100.0, runtime(80.0)),
+        ];
+        let model = RuntimeModel::fit(&discharges).unwrap();
+        assert!((model.exponent - 1.3).abs() < 1e-9);
+        assert!((model.coefficient - 600.0).abs() < 1e-6);
+        assert_eq!(model.sigma, MIN_SIGMA);
+
+        let estimate = model.predict(50.0, 50.0);
+        assert!((estimate.runtime - runtime(50.0) / 2.0).abs() < 1e-6);
+        assert!(estimate.low < estimate.runtime && estimate.runtime < estimate.high);
+        assert!(estimate.low > estimate.runtime * 0.85);
+        assert_eq!(estimate.samples, 3);
+    }
+
+    #[test]
+    fn scatter_widens_the_band() {
+        assert!(RuntimeModel::fit(&[discharge(40.0, 100.0, 1200.0)]).is_none());
+
+        // All at about the same load: the exponent cannot be told, so it is assumed
+        let discharges = [
+            discharge(40.0, 100.0, 1000.0),
+            discharge(42.0, 100.0, 1400.0),
+            discharge(38.0, 100.0, 1200.0),
+        ];
+        let model = RuntimeModel::fit(&discharges).unwrap();
+        assert_eq!(model.exponent, DEFAULT_EXPONENT);
+        assert!(model.sigma > 0.1, "{}", model.sigma);
+
+        let estimate = model.predict(40.0, 100.0);
+        assert!((1000.0..1400.0).contains(&estimate.runtime));
+        assert!(
+            estimate.low < 900.0 && estimate.high > 1500.0,
+            "{estimate:?}"
+        );
+        assert!(model.estimate(&UpsData::default()).is_none());
+    }
+}
@@ -0,0 +1,1079 @@
+use crate::nut::models::NutConfig;
+use crate::source::snmp::{AuthProtocol, PrivProtocol, SnmpVersion};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// Bump this whenever the on-disk layout changes and add a step to `migrate`.
+pub const SETTINGS_VERSION: u32 = 1;
+
+const SETTINGS_FILE: &str = "settings.json";
+
+#[derive(Error, Debug)]
+pub enum SettingsError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Malformed settings file: {0}")]
+    Parse(#[from] serde_json::Error),
+    #[error("Settings version {0} is newer than this build supports")]
+    UnsupportedVersion(u32),
+    #[error("Invalid settings: {0}")]
+    Invalid(String),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ShutdownConfig {
+    pub enabled: bool,
+    pub battery_threshold: u32,
+    pub runtime_threshold: u32,
+    pub stop_type: String,
+    #[serde(rename = "delaySeconds")]
+    pub timer_sec: u64,
+    /// Which runtime `runtime_threshold` is compared with.
+    #[serde(default)]
+    pub runtime_source: RuntimeSource,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum RuntimeSource {
+    /// `battery.runtime` as reported by the UPS
+    #[default]
+    Reported,
+    /// The lower bound of our prediction from past discharges, see
+    /// `crate::reports::runtime`, while on battery; the reported value on mains
+    /// or until enough discharges are on record.
+    Predicted,
+}
+
+impl Default for ShutdownConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            battery_threshold: 30,
+            runtime_threshold: 120,
+            stop_type: "Shutdown".to_string(),
+            timer_sec: 15,
+            runtime_source: RuntimeSource::Reported,
+        }
+    }
+}
+
+/// Load shedding policy: outlets to switch off or commands to run while on
+/// battery. Evaluated by `crate::monitor::shedding`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct LoadSheddingConfig {
+    pub enabled: bool,
+    /// Journal what would be done without sending any command.
+    pub dry_run: bool,
+    /// Undo the shed actions once mains power has been back for `restore_delay_sec`.
+    pub restore: bool,
+    pub restore_delay_sec: u64,
+    /// Pause between two commands of a sequence, so loads do not all step at once.
+    pub step_delay_ms: u64,
+    pub rules: Vec<SheddingRule>,
+}
+
+impl Default for LoadSheddingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            dry_run: false,
+            restore: true,
+            restore_delay_sec: 60,
+            step_delay_ms: 2000,
+            rules: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SheddingRule {
+    pub name: String,
+    pub trigger: SheddingTrigger,
+    /// Run in order, each at most once per outage.
+    pub actions: Vec<SheddingAction>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(
+    tag = "type",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum SheddingTrigger {
+    OnBatteryFor { minutes: u32 },
+    ChargeBelow { percent: u32 },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(
+    tag = "type",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum SheddingAction {
+    /// `outlet.n.load.off`, undone with `outlet.n.load.on`
+    OutletOff { outlet: u32 },
+    /// Any instant command, optionally undone with another
+    Command {
+        command: String,
+        #[serde(default)]
+        restore_command: Option<String>,
+    },
+}
+
+/// Electricity price and grid carbon intensity, used by `crate::reports::cost`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct TariffConfig {
+    /// Shown next to costs, e.g. `EUR`
+    pub currency: String,
+    /// kg of CO2 emitted per kWh drawn from the grid
+    pub carbon_kg_per_kwh: f64,
+    pub rate: TariffRate,
+}
+
+impl Default for TariffConfig {
+    fn default() -> Self {
+        Self {
+            currency: "USD".to_string(),
+            carbon_kg_per_kwh: 0.4,
+            rate: TariffRate::Flat {
+                price_per_kwh: 0.15,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(
+    tag = "type",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum TariffRate {
+    Flat {
+        price_per_kwh: f64,
+    },
+    /// Price by local time of day; hours outside every period cost `default_price`.
+    TimeOfUse {
+        default_price: f64,
+        periods: Vec<TariffPeriod>,
+    },
+    /// Block pricing on the kWh used so far in the calendar month.
+    Tiered {
+        tiers: Vec<TariffTier>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TariffPeriod {
+    /// First hour of the period, 0-23
+    pub start_hour: u32,
+    /// Hour the period ends at, 1-24; before `start_hour` for periods that span midnight
+    pub end_hour: u32,
+    pub price_per_kwh: f64,
+    /// Saturdays and Sundays pay `default_price`.
+    #[serde(default)]
+    pub weekdays_only: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TariffTier {
+    /// Monthly kWh this tier ends at; `None` for the last tier
+    pub up_to_kwh: Option<f64>,
+    pub price_per_kwh: f64,
+}
+
+/// Scheduled battery self-tests, run by `crate::monitor::selftest`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SelfTestConfig {
+    pub enabled: bool,
+    pub kind: SelfTestKind,
+    pub schedule: SelfTestSchedule,
+    /// Local hour the test is due at, 0-23
+    pub hour: u32,
+    /// A test that is due is held back while the local time is in this window.
+    pub quiet_hours: Option<QuietHours>,
+    /// Below this charge (%) the test is skipped rather than run.
+    pub min_charge: u32,
+    /// Give up waiting for `ups.test.result` after this long.
+    pub timeout_min: u32,
+}
+
+impl Default for SelfTestConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            kind: SelfTestKind::Quick,
+            schedule: SelfTestSchedule::Monthly { day: 1 },
+            hour: 10,
+            quiet_hours: None,
+            min_charge: 90,
+            timeout_min: 30,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SelfTestKind {
+    /// `test.battery.start.quick`: a few seconds on battery
+    Quick,
+    /// `test.battery.start.deep`: runs the battery down to its low level
+    Deep,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(
+    tag = "type",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum SelfTestSchedule {
+    /// Every week; 0 is Monday
+    Weekly { weekday: u32 },
+    /// Every month on this day, 1-28
+    Monthly { day: u32 },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QuietHours {
+    /// First quiet hour, 0-23
+    pub start_hour: u32,
+    /// Hour quiet ends at, 0-23; before `start_hour` for windows that span midnight
+    pub end_hour: u32,
+}
+
+impl QuietHours {
+    pub fn contains(&self, hour: u32) -> bool {
+        if self.start_hour <= self.end_hour {
+            (self.start_hour..self.end_hour).contains(&hour)
+        } else {
+            hour >= self.start_hour || hour < self.end_hour
+        }
+    }
+}
+
+/// User-defined alerts, evaluated by `crate::monitor::alerts`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct AlertsConfig {
+    pub rules: Vec<AlertRule>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AlertRule {
+    /// Unique; alerts are journalled and notified under this name.
+    pub name: String,
+    pub condition: AlertCondition,
+    pub severity: AlertSeverity,
+    /// The condition must hold this long before the alert fires.
+    #[serde(default)]
+    pub for_sec: u64,
+    /// How far the value must come back past the threshold before the alert resolves.
+    #[serde(default)]
+    pub hysteresis: f64,
+    /// Least time between two firings of the rule.
+    #[serde(default)]
+    pub cooldown_sec: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(
+    tag = "type",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum AlertCondition {
+    /// A numeric NUT variable (`ups.load`, `ups.temperature`, ...) above `value`
+    Above {
+        metric: String,
+        value: f64,
+    },
+    Below {
+        metric: String,
+        value: f64,
+    },
+    /// A flag in `ups.status`, e.g. `OB`, `RB` or `OVER`
+    Flag {
+        flag: String,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum AlertSeverity {
+    Info,
+    Warning,
+    Critical,
+}
+
+/// Where notifications go besides the app window, see `crate::notify`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct NotificationsConfig {
+    /// Program run for every notification, like upsmon's NOTIFYCMD.
+    pub command: Option<String>,
+    pub desktop: DesktopNotifications,
+    pub email: EmailNotifications,
+    pub webhooks: Vec<Webhook>,
+}
+
+/// What `crate::notify::Notification` is about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum NotificationKind {
+    PowerLost,
+    PowerRestored,
+    LowBattery,
+    ReplaceBattery,
+    ConnectionLost,
+    ConnectionRestored,
+    ShutdownScheduled,
+    ShutdownCancelled,
+    /// A user-defined alert fired or resolved
+    Alert,
+}
+
+impl NotificationKind {
+    pub const ALL: [NotificationKind; 9] = [
+        NotificationKind::PowerLost,
+        NotificationKind::PowerRestored,
+        NotificationKind::LowBattery,
+        NotificationKind::ReplaceBattery,
+        NotificationKind::ConnectionLost,
+        NotificationKind::ConnectionRestored,
+        NotificationKind::ShutdownScheduled,
+        NotificationKind::ShutdownCancelled,
+        NotificationKind::Alert,
+    ];
+}
+
+/// Native OS notifications from the desktop app.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct DesktopNotifications {
+    pub enabled: bool,
+    /// Kinds of notification shown; the rest are dropped.
+    pub events: Vec<NotificationKind>,
+    /// Least time between two notifications of the same kind and title.
+    pub min_interval_sec: u64,
+}
+
+impl Default for DesktopNotifications {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            events: NotificationKind::ALL.to_vec(),
+            min_interval_sec: 60,
+        }
+    }
+}
+
+/// How the SMTP connection is secured.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SmtpSecurity {
+    /// Plain connection upgraded with STARTTLS, usually port 587
+    #[default]
+    StartTls,
+    /// TLS from the first byte, usually port 465
+    Tls,
+    /// No encryption at all; for a relay on the local network
+    None,
+}
+
+/// Mail to the people on call, see `crate::notify::email`. `{title}`,
+/// `{body}`, `{severity}`, `{event}` and `{time}` in the templates are
+/// replaced with the notification's parts.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct EmailNotifications {
+    pub enabled: bool,
+    pub host: String,
+    pub port: u16,
+    pub security: SmtpSecurity,
+    /// Sent without authentication when unset.
+    pub username: Option<String>,
+    /// Id of the SMTP password in the credential store, see `crate::credentials`.
+    pub credential_id: Option<String>,
+    pub from: String,
+    pub to: Vec<String>,
+    /// Kinds of notification mailed; the rest are dropped.
+    pub events: Vec<NotificationKind>,
+    pub subject: String,
+    pub body: String,
+    /// How long power must stay back before an outage counts as over and its
+    /// summary is sent. Events in between are collected instead of mailed.
+    pub settle_sec: u64,
+}
+
+impl Default for EmailNotifications {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            host: String::new(),
+            port: 587,
+            security: SmtpSecurity::StartTls,
+            username: None,
+            credential_id: None,
+            from: String::new(),
+            to: Vec::new(),
+            events: NotificationKind::ALL.to_vec(),
+            subject: "[UPS] {title}".to_string(),
+            body: "{body}\n\nSeverity: {severity}\nTime: {time}".to_string(),
+            settle_sec: 300,
+        }
+    }
+}
+
+/// What a webhook's requests look like, see `crate::notify::webhook`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum WebhookFormat {
+    /// The notification as JSON, signed with HMAC-SHA256 when a secret is set
+    #[default]
+    Json,
+    Slack,
+    Teams,
+    Discord,
+    /// Publish to an ntfy topic, e.g. `https://ntfy.sh/my-ups`
+    Ntfy,
+    /// Gotify's message endpoint, e.g. `https://gotify.example.com/message`
+    Gotify,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Webhook {
+    pub name: String,
+    pub url: String,
+    pub format: WebhookFormat,
+    /// Id in the credential store of the signing key (`json`), access token
+    /// (`ntfy`) or app token (`gotify`).
+    pub credential_id: Option<String>,
+    /// Kinds of notification sent; the rest are dropped.
+    pub events: Vec<NotificationKind>,
+}
+
+impl Default for Webhook {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            url: String::new(),
+            format: WebhookFormat::Json,
+            credential_id: None,
+            events: NotificationKind::ALL.to_vec(),
+        }
+    }
+}
+
+/// Telegram bot that answers commands and pushes notifications, see `crate::telegram`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct TelegramSettings {
+    pub enabled: bool,
+    /// Id of the bot token in the credential store, see `crate::credentials`.
+    pub credential_id: Option<String>,
+    /// Chats the bot talks to; messages from any other chat are ignored.
+    pub allowed_chats: Vec<i64>,
+    /// Kinds of notification pushed to the allowed chats.
+    pub events: Vec<NotificationKind>,
+    /// Bot API server, for a self-hosted one.
+    pub api_url: String,
+}
+
+impl Default for TelegramSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            credential_id: None,
+            allowed_chats: Vec::new(),
+            events: NotificationKind::ALL.to_vec(),
+            api_url: "https://api.telegram.org".to_string(),
+        }
+    }
+}
+
+/// Protocol spoken by the monitored device, see `crate::source`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Backend {
+    #[default]
+    Nut,
+    /// apcupsd Network Information Server
+    Apcupsd,
+    /// SNMP agent exposing the UPS-MIB
+    Snmp,
+    /// The built-in virtual UPS, for demos and training
+    Simulator,
+}
+
+/// SNMP options; used when `backend` is `Snmp`. For v3 the connection's
+/// username is the USM user and its stored password the passphrase.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SnmpSettings {
+    pub version: SnmpVersion,
+    pub community: String,
+    pub auth_protocol: AuthProtocol,
+    pub priv_protocol: PrivProtocol,
+}
+
+impl Default for SnmpSettings {
+    fn default() -> Self {
+        Self {
+            version: SnmpVersion::V2c,
+            community: "public".to_string(),
+            auth_protocol: AuthProtocol::None,
+            priv_protocol: PrivProtocol::None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ConnectionSettings {
+    pub backend: Backend,
+    pub host: String,
+    pub port: u16,
+    pub username: Option<String>,
+    /// Id of the NUT password in the credential store, see `crate::credentials`.
+    pub credential_id: Option<String>,
+    pub ups_name: String,
+    pub snmp: SnmpSettings,
+}
+
+impl Default for ConnectionSettings {
+    fn default() -> Self {
+        Self {
+            backend: Backend::Nut,
+            host: String::new(),
+            port: 3493,
+            username: None,
+            credential_id: None,
+            ups_name: "ups".to_string(),
+            snmp: SnmpSettings::default(),
+        }
+    }
+}
+
+impl ConnectionSettings {
+    pub fn nut_config(&self) -> NutConfig {
+        NutConfig {
+            host: self.host.clone(),
+            port: self.port,
+            username: self.username.clone(),
+            credential_id: self.credential_id.clone(),
+        }
+    }
+
+    /// A connection is only attempted at launch once a host has been configured
+    /// or the virtual UPS is selected.
+    pub fn is_configured(&self) -> bool {
+        self.backend == Backend::Simulator || !self.host.trim().is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct AppSettings {
+    pub version: u32,
+    pub connection: ConnectionSettings,
+    pub poll_interval_ms: u64,
+    pub shutdown: ShutdownConfig,
+    pub load_shedding: LoadSheddingConfig,
+    pub tariff: TariffConfig,
+    pub self_test: SelfTestConfig,
+    pub alerts: AlertsConfig,
+    pub notifications: NotificationsConfig,
+    pub telegram: TelegramSettings,
+}
+
+impl Default for AppSettings {
+    fn default() -> Self {
+        Self {
+            version: SETTINGS_VERSION,
+            connection: ConnectionSettings::default(),
+            poll_interval_ms: 1000,
+            shutdown: ShutdownConfig::default(),
+            load_shedding: LoadSheddingConfig::default(),
+            tariff: TariffConfig::default(),
+            self_test: SelfTestConfig::default(),
+            alerts: AlertsConfig::default(),
+            notifications: NotificationsConfig::default(),
+            telegram: TelegramSettings::default(),
+        }
+    }
+}
+
+impl AppSettings {
+    pub fn validate(&self) -> Result<(), SettingsError> {
+        let invalid = |msg: &str| Err(SettingsError::Invalid(msg.to_string()));
+
+        if self.connection.is_configured() {
+            if self.connection.port == 0 {
+                return invalid("port must be between 1 and 65535");
+            }
+            if self.connection.ups_name.trim().is_empty() {
+                return invalid("UPS name must not be empty");
+            }
+            let snmp = &self.connection.snmp;
+            if self.connection.backend == Backend::Snmp {
+                match snmp.version {
+                    SnmpVersion::V2c if snmp.community.is_empty() => {
+                        return invalid("SNMP community must not be empty");
+                    }
+                    SnmpVersion::V3
+                        if self
+                            .connection
+                            .username
+                            .as_deref()
+                            .unwrap_or("")
+                            .trim()
+                            .is_empty() =>
+                    {
+                        return invalid("SNMPv3 needs a user name");
+                    }
+                    SnmpVersion::V3
+                        if snmp.priv_protocol != PrivProtocol::None
+                            && snmp.auth_protocol == AuthProtocol::None =>
+                    {
+                        return invalid("SNMPv3 privacy requires an authentication protocol");
+                    }
+                    _ => {}
+                }
+            }
+        }
+        if !(250..=60_000).contains(&self.poll_interval_ms) {
+            return invalid("poll interval must be between 250 ms and 60 s");
+        }
+        if self.shutdown.battery_threshold > 100 {
+            return invalid("battery threshold must be a percentage (0-100)");
+        }
+        if !matches!(
+            self.shutdown.stop_type.as_str(),
+            "Shutdown" | "Hibernate" | "Sleep"
+        ) {
+            return invalid("stop type must be Shutdown, Hibernate or Sleep");
+        }
+        if self.shutdown.timer_sec > 3600 {
+            return invalid("shutdown delay must not exceed one hour");
+        }
+        for rule in &self.load_shedding.rules {
+            if rule.name.trim().is_empty() {
+                return invalid("load shedding rules need a name");
+            }
+            if rule.actions.is_empty() {
+                return invalid("load shedding rules need at least one action");
+            }
+            if matches!(rule.trigger, SheddingTrigger::ChargeBelow { percent } if percent > 100) {
+                return invalid("load shedding charge must be a percentage (0-100)");
+            }
+            let blank_command = rule.actions.iter().any(|a| {
+                matches!(a, SheddingAction::Command { command, .. } if command.trim().is_empty())
+            });
+            if blank_command {
+                return invalid("load shedding commands must not be empty");
+            }
+        }
+        let price = |p: f64| p.is_finite() && p >= 0.0;
+        if !price(self.tariff.carbon_kg_per_kwh) {
+            return invalid("carbon intensity must not be negative");
+        }
+        match &self.tariff.rate {
+            TariffRate::Flat { price_per_kwh } if !price(*price_per_kwh) => {
+                return invalid("electricity prices must not be negative");
+            }
+            TariffRate::TimeOfUse {
+                default_price,
+                periods,
+            } => {
+                if !price(*default_price) || periods.iter().any(|p| !price(p.price_per_kwh)) {
+                    return invalid("electricity prices must not be negative");
+                }
+                if periods
+                    .iter()
+                    .any(|p| p.start_hour > 23 || p.end_hour > 24 || p.start_hour == p.end_hour)
+                {
+                    return invalid(
+                        "tariff periods need a start hour (0-23) and a different end hour (1-24)",
+                    );
+                }
+            }
+            TariffRate::Tiered { tiers } => {
+                if tiers.is_empty() || tiers.last().is_some_and(|t| t.up_to_kwh.is_some()) {
+                    return invalid("the last tariff tier must have no upper limit");
+                }
+                if tiers.iter().any(|t| !price(t.price_per_kwh)) {
+                    return invalid("electricity prices must not be negative");
+                }
+                let limits: Vec<f64> = tiers.iter().filter_map(|t| t.up_to_kwh).collect();
+                if limits.len() != tiers.len() - 1 || limits.windows(2).any(|w| w[0] >= w[1]) {
+                    return invalid("tariff tier limits must increase");
+                }
+            }
+            _ => {}
+        }
+        let test = &self.self_test;
+        match test.schedule {
+            SelfTestSchedule::Weekly { weekday } if weekday > 6 => {
+                return invalid("self-test weekday must be 0 (Monday) to 6 (Sunday)");
+            }
+            SelfTestSchedule::Monthly { day } if !(1..=28).contains(&day) => {
+                return invalid("self-test day of month must be between 1 and 28");
+            }
+            _ => {}
+        }
+        if test.hour > 23 {
+            return invalid("self-test hour must be between 0 and 23");
+        }
+        if let Some(quiet) = &test.quiet_hours {
+            if quiet.start_hour > 23 || quiet.end_hour > 23 || quiet.start_hour == quiet.end_hour {
+                return invalid("quiet hours need different start and end hours (0-23)");
+            }
+            if quiet.contains(test.hour) {
+                return invalid("self-tests must not be scheduled within the quiet hours");
+            }
+        }
+        if test.min_charge > 100 {
+            return invalid("self-test minimum charge must be a percentage (0-100)");
+        }
+        if !(1..=24 * 60).contains(&test.timeout_min) {
+            return invalid("self-test timeout must be between 1 minute and 24 hours");
+        }
+        for (i, rule) in self.alerts.rules.iter().enumerate() {
+            if rule.name.trim().is_empty() {
+                return invalid("alert rules need a name");
+            }
+            if self.alerts.rules[..i].iter().any(|r| r.name == rule.name) {
+                return invalid("alert rule names must be unique");
+            }
+            match &rule.condition {
+                AlertCondition::Above { metric, value }
+                | AlertCondition::Below { metric, value } => {
+                    if metric.trim().is_empty() {
+                        return invalid("alert rules need a metric");
+                    }
+                    if !value.is_finite() {
+                        return invalid("alert thresholds must be numbers");
+                    }
+                }
+                AlertCondition::Flag { flag } if flag.trim().is_empty() => {
+                    return invalid("alert rules need a status flag");
+                }
+                AlertCondition::Flag { .. } => {}
+            }
+            if !rule.hysteresis.is_finite() || rule.hysteresis < 0.0 {
+                return invalid("alert hysteresis must not be negative");
+            }
+            if rule.for_sec > 24 * 3600 || rule.cooldown_sec > 24 * 3600 {
+                return invalid("alert delays must not exceed 24 hours");
+            }
+        }
+        if self
+            .notifications
+            .command
+            .as_deref()
+            .is_some_and(|c| c.trim().is_empty())
+        {
+            return invalid("notification command must not be empty");
+        }
+        if self.notifications.desktop.min_interval_sec > 24 * 3600 {
+            return invalid("notification interval must not exceed 24 hours");
+        }
+        let email = &self.notifications.email;
+        if email.enabled {
+            if email.host.trim().is_empty() || email.port == 0 {
+                return invalid("email notifications need an SMTP server");
+            }
+            if email.from.parse::<lettre::message::Mailbox>().is_err() {
+                return invalid("email sender is not a valid address");
+            }
+            if email.to.is_empty()
+                || email
+                    .to
+                    .iter()
+                    .any(|to| to.parse::<lettre::message::Mailbox>().is_err())
+            {
+                return invalid("email recipients must be valid addresses");
+            }
+            if email.settle_sec > 24 * 3600 {
+                return invalid("email settle time must not exceed 24 hours");
+            }
+        }
+        for (i, webhook) in self.notifications.webhooks.iter().enumerate() {
+            if webhook.name.trim().is_empty() {
+                return invalid("webhooks need a name");
+            }
+            if self.notifications.webhooks[..i]
+                .iter()
+                .any(|w| w.name == webhook.name)
+            {
+                return invalid("webhook names must be unique");
+            }
+            if !reqwest::Url::parse(&webhook.url)
+                .is_ok_and(|url| matches!(url.scheme(), "http" | "https"))
+            {
+                return invalid("webhook URLs must start with http:// or https://");
+            }
+        }
+        let telegram = &self.telegram;
+        if telegram.enabled {
+            if telegram.allowed_chats.is_empty() {
+                return invalid("the Telegram bot needs at least one allowed chat");
+            }
+            if !reqwest::Url::parse(&telegram.api_url)
+                .is_ok_and(|url| matches!(url.scheme(), "http" | "https"))
+            {
+                return invalid("the Telegram API URL must start with http:// or https://");
+            }
+        }
+        Ok(())
+    }
+
+    /// Upgrades settings written by an older build in place.
+    /// Version 0 is a file that predates the `version` field.
+    fn migrate(&mut self) -> Result<(), SettingsError> {
+        if self.version > SETTINGS_VERSION {
+            return Err(SettingsError::UnsupportedVersion(self.version));
+        }
+        self.version = SETTINGS_VERSION;
+        Ok(())
+    }
+}
+
+/// Owns the settings file in the app config dir and the last successfully loaded copy.
+pub struct SettingsStore {
+    path: PathBuf,
+    settings: AppSettings,
+    /// Why the file on disk could not be used, until new settings replace it.
+    load_error: Option<String>,
+}
+
+impl SettingsStore {
+    /// Loads the settings file. A missing file means defaults. A file that cannot be
+    /// read, parsed or validated also leaves defaults in memory so the app can start,
+    /// but is reported by `load_error` and kept until the next `update`.
+    pub fn open<P: AsRef<Path>>(config_dir: P) -> Self {
+        let path = config_dir.as_ref().join(SETTINGS_FILE);
+        let (settings, load_error) = match Self::read(&path) {
+            Ok(settings) => (settings, None),
+            Err(e) => {
+                log::error!("Failed to load settings from {}: {}", path.display(), e);
+                (AppSettings::default(), Some(e.to_string()))
+            }
+        };
+        Self {
+            path,
+            settings,
+            load_error,
+        }
+    }
+
+    fn read(path: &Path) -> Result<AppSettings, SettingsError> {
+        if !path.exists() {
+            return Ok(AppSettings::default());
+        }
+        let raw = std::fs::read_to_string(path)?;
+        let value: serde_json::Value = serde_json::from_str(&raw)?;
+        let mut settings: AppSettings = serde_json::from_value(value.clone())?;
+        if value.get("version").is_none() {
+            settings.version = 0;
+        }
+        settings.migrate()?;
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn get(&self) -> &AppSettings {
+        &self.settings
+    }
+
+    /// Set when the file could not be loaded and `get` returns defaults instead.
+    pub fn load_error(&self) -> Option<&str> {
+        self.load_error.as_deref()
+    }
+
+    /// Validates and persists new settings. The in-memory copy is only replaced
+    /// once the file has been written. A file that failed to load is moved to
+    /// `settings.json.bak` rather than overwritten.
+    pub fn update(&mut self, mut settings: AppSettings) -> Result<(), SettingsError> {
+        settings.migrate()?;
+        settings.validate()?;
+
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        if self.load_error.is_some() && self.path.exists() {
+            let backup = self.path.with_extension("json.bak");
+            std::fs::rename(&self.path, &backup)?;
+            log::warn!("Moved the unreadable settings to {}", backup.display());
+        }
+        // Write to a sibling file first so a crash mid-write cannot truncate the settings
+        let tmp = self.path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_string_pretty(&settings)?)?;
+        std::fs::rename(&tmp, &self.path)?;
+
+        self.settings = settings;
+        self.load_error = None;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn configured() -> AppSettings {
+        let mut settings = AppSettings::default();
+        settings.connection.host = "nas.local".to_string();
+        settings
+    }
+
+    fn json(settings: &AppSettings) -> serde_json::Value {
+        serde_json::to_value(settings).unwrap()
+    }
+
+    #[test]
+    fn validates_connection_and_poll_interval() {
+        assert!(AppSettings::default().validate().is_ok());
+        assert!(configured().validate().is_ok());
+
+        let mut settings = configured();
+        settings.connection.port = 0;
+        assert!(matches!(
+            settings.validate(),
+            Err(SettingsError::Invalid(_))
+        ));
+
+        let mut settings = configured();
+        settings.connection.ups_name = " ".to_string();
+        assert!(matches!(
+            settings.validate(),
+            Err(SettingsError::Invalid(_))
+        ));
+
+        for interval in [100, 120_000] {
+            let mut settings = configured();
+            settings.poll_interval_ms = interval;
+            assert!(matches!(
+                settings.validate(),
+                Err(SettingsError::Invalid(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn migrates_unversioned_files_and_rejects_newer_ones() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(SETTINGS_FILE);
+
+        std::fs::write(&path, r#"{"connection":{"host":"nas.local"}}"#).unwrap();
+        let store = SettingsStore::open(dir.path());
+        assert_eq!(store.get().version, SETTINGS_VERSION);
+        assert_eq!(store.get().connection.host, "nas.local");
+        assert_eq!(store.get().connection.port, 3493);
+
+        let newer = SETTINGS_VERSION + 1;
+        std::fs::write(
+            &path,
+            format!(r#"{{"version":{newer},"pollIntervalMs":500}}"#),
+        )
+        .unwrap();
+        assert!(matches!(
+            SettingsStore::read(&path),
+            Err(SettingsError::UnsupportedVersion(v)) if v == newer
+        ));
+        // Opening still succeeds, on defaults, but says why
+        let store = SettingsStore::open(dir.path());
+        assert_eq!(store.get().poll_interval_ms, 1000);
+        assert!(store.load_error().unwrap().contains("newer"));
+    }
+
+    #[test]
+    fn falls_back_to_defaults_on_a_corrupt_or_invalid_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(SETTINGS_FILE);
+        let defaults = json(&AppSettings::default());
+
+        std::fs::write(&path, "{\"connection\": {\"host\": ").unwrap();
+        let store = SettingsStore::open(dir.path());
+        assert_eq!(json(store.get()), defaults);
+        assert!(store.load_error().unwrap().starts_with("Malformed"));
+
+        let invalid = r#"{"version":1,"pollIntervalMs":5}"#;
+        std::fs::write(&path, invalid).unwrap();
+        let mut store = SettingsStore::open(dir.path());
+        assert_eq!(json(store.get()), defaults);
+        assert!(store.load_error().unwrap().starts_with("Invalid"));
+
+        // Saving keeps the file that failed to load next to the new one
+        store.update(configured()).unwrap();
+        assert_eq!(store.load_error(), None);
+        let backup = dir.path().join("settings.json.bak");
+        assert_eq!(std::fs::read_to_string(backup).unwrap(), invalid);
+        let store = SettingsStore::open(dir.path());
+        assert_eq!(store.load_error(), None);
+        assert_eq!(store.get().connection.host, "nas.local");
+    }
+
+    #[test]
+    fn writes_through_a_temporary_file_and_reloads() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_dir = dir.path().join("config");
+        let mut store = SettingsStore::open(&config_dir);
+        assert_eq!(json(store.get()), json(&AppSettings::default()));
+
+        // Left over by a crash during an earlier write
+        std::fs::create_dir_all(&config_dir).unwrap();
+        std::fs::write(config_dir.join("settings.json.tmp"), "garbage").unwrap();
+
+        let mut settings = configured();
+        settings.poll_interval_ms = 2500;
+        store.update(settings.clone()).unwrap();
+        assert_eq!(json(store.get()), json(&settings));
+        assert!(!config_dir.join("settings.json.tmp").exists());
+        assert_eq!(
+            json(SettingsStore::open(&config_dir).get()),
+            json(&settings)
+        );
+    }
+
+    #[test]
+    fn keeps_the_previous_settings_when_an_update_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = SettingsStore::open(dir.path());
+        store.update(configured()).unwrap();
+        let saved = std::fs::read_to_string(store.path()).unwrap();
+
+        let mut invalid = configured();
+        invalid.poll_interval_ms = 0;
+        assert!(store.update(invalid).is_err());
+        assert_eq!(std::fs::read_to_string(store.path()).unwrap(), saved);
+
+        // A file stands where the config dir should be, so the write itself fails
+        let blocked = dir.path().join("blocked");
+        std::fs::write(&blocked, "").unwrap();
+        let mut store = SettingsStore::open(&blocked);
+        assert!(matches!(
+            store.update(configured()),
+            Err(SettingsError::Io(_))
+        ));
+        assert_eq!(json(store.get()), json(&AppSettings::default()));
+    }
+}
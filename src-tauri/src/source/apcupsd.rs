@@ -0,0 +1,388 @@
+//! apcupsd Network Information Server (NIS) client.
+//!
+//! The protocol is a request/response exchange of length-prefixed records: the
+//! client sends a big-endian `u16` length followed by `status` or `events`, and
+//! the server answers with one record per line (`KEY      : value`), ending with
+//! a zero-length record. apcupsd exposes no control commands over NIS.
+
+use super::{SourceError, SourceMetadata, UpsSource};
+use crate::nut::models::UpsData;
+use async_trait::async_trait;
+use std::collections::BTreeMap;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+pub const DEFAULT_PORT: u16 = 3551;
+
+pub struct NisClient {
+    host: String,
+    port: u16,
+    stream: Option<TcpStream>,
+}
+
+impl NisClient {
+    pub fn new(host: String, port: u16) -> Self {
+        Self {
+            host,
+            port,
+            stream: None,
+        }
+    }
+
+    /// Sends one request and collects the records of the reply.
+    async fn request(&mut self, command: &str) -> Result<Vec<String>, SourceError> {
+        let stream = self.stream.as_mut().ok_or(SourceError::NotConnected)?;
+        let result = exchange(stream, command).await;
+        if result.is_err() {
+            // A half-read reply leaves the stream out of sync; force a reconnect
+            self.stream = None;
+        }
+        result
+    }
+
+    /// Raw `status` report, keyed by the apcupsd field name.
+    pub async fn status(&mut self) -> Result<BTreeMap<String, String>, SourceError> {
+        let records = self.request("status").await?;
+        Ok(records
+            .iter()
+            .filter_map(|line| {
+                let (key, value) = line.split_once(':')?;
+                Some((key.trim().to_string(), value.trim().to_string()))
+            })
+            .collect())
+    }
+
+    /// The apcupsd event log, oldest first.
+    pub async fn events(&mut self) -> Result<Vec<String>, SourceError> {
+        Ok(self
+            .request("events")
+            .await?
+            .into_iter()
+            .map(|line| line.trim_end().to_string())
+            .filter(|line| !line.is_empty())
+            .collect())
+    }
+}
+
+async fn exchange(stream: &mut TcpStream, command: &str) -> Result<Vec<String>, SourceError> {
+    let len = u16::try_from(command.len())
+        .map_err(|_| SourceError::Protocol("request too long".to_string()))?;
+    stream.write_all(&len.to_be_bytes()).await?;
+    stream.write_all(command.as_bytes()).await?;
+    stream.flush().await?;
+
+    let mut records = Vec::new();
+    loop {
+        let mut len = [0u8; 2];
+        stream.read_exact(&mut len).await?;
+        let len = u16::from_be_bytes(len) as usize;
+        if len == 0 {
+            return Ok(records);
+        }
+        let mut record = vec![0u8; len];
+        stream.read_exact(&mut record).await?;
+        records.push(String::from_utf8_lossy(&record).into_owned());
+    }
+}
+
+#[async_trait]
+impl UpsSource for NisClient {
+    async fn connect(&mut self) -> Result<(), SourceError> {
+        let stream = TcpStream::connect((self.host.as_str(), self.port)).await?;
+        self.stream = Some(stream);
+        Ok(())
+    }
+
+    async fn disconnect(&mut self) -> Result<(), SourceError> {
+        if let Some(mut stream) = self.stream.take() {
+            let _ = stream.shutdown().await;
+        }
+        Ok(())
+    }
+
+    /// apcupsd serves a single UPS per NIS port, so `ups_name` is ignored.
+    async fn fetch_data(&mut self, _ups_name: &str) -> Result<UpsData, SourceError> {
+        let status = self.status().await?;
+        map_status(&status)
+    }
+
+    async fn list_commands(&mut self, _ups_name: &str) -> Result<Vec<String>, SourceError> {
+        Ok(Vec::new())
+    }
+
+    async fn run_command(&mut self, _ups_name: &str, command: &str) -> Result<(), SourceError> {
+        Err(SourceError::Unsupported(format!(
+            "apcupsd does not accept commands over NIS ({command})"
+        )))
+    }
+
+    fn metadata(&self) -> SourceMetadata {
+        SourceMetadata {
+            backend: "apcupsd",
+            endpoint: format!("{}:{}", self.host, self.port),
+            supports_commands: false,
+        }
+    }
+}
+
+/// Leading number of a value such as `45.2 Minutes` or `230.0 Volts`.
+fn number(value: &str) -> Option<f64> {
+    value.split_whitespace().next()?.parse().ok()
+}
+
+/// Translates apcupsd `STATUS` words into NUT status flags, which the dashboard,
+/// tray and shutdown logic already understand.
+pub fn map_status_flags(status: &str) -> String {
+    let mut flags: Vec<&str> = Vec::new();
+    for word in status.split_whitespace() {
+        match word {
+            "ONLINE" => flags.push("OL"),
+            "ONBATT" => {
+                flags.push("OB");
+                flags.push("DISCHRG");
+            }
+            "LOWBATT" => flags.push("LB"),
+            "REPLACEBATT" => flags.push("RB"),
+            "CAL" => flags.push("CAL"),
+            "TRIM" => flags.push("TRIM"),
+            "BOOST" => flags.push("BOOST"),
+            "OVERLOAD" => flags.push("OVER"),
+            "SHUTTING" => flags.push("FSD"),
+            "NOBATT" => flags.push("NOBATT"),
+            _ => {}
+        }
+    }
+    flags.join(" ")
+}
+
+/// Maps an apcupsd `status` report into `UpsData`. Fields without a dedicated
+/// slot land in `extended_vars` under their NUT name where one exists, otherwise
+/// as `apcupsd.<field>`.
+pub fn map_status(status: &BTreeMap<String, String>) -> Result<UpsData, SourceError> {
+    let raw_status = status.get("STATUS").map(String::as_str).unwrap_or("");
+    if raw_status.contains("COMMLOST") {
+        // Same meaning as NUT's ERR DATA-STALE: the daemon is up but cannot see the UPS
+        return Err(SourceError::CommandFailed("COMMLOST".to_string()));
+    }
+
+    let mut data = UpsData {
+        status: map_status_flags(raw_status),
+        ..Default::default()
+    };
+
+    for (key, value) in status {
+        let num = number(value);
+        match key.as_str() {
+            "BCHARGE" => data.battery_charge = num,
+            // apcupsd reports minutes, UpsData uses seconds like NUT
+            "TIMELEFT" => data.battery_runtime = num.map(|m| (m * 60.0).round()),
+            "BATTV" => data.battery_voltage = num,
+            "LINEV" => data.input_voltage = num,
+            "LINEFREQ" => data.input_frequency = num,
+            "OUTPUTV" => data.output_voltage = num,
+            "NOMOUTV" => data.output_voltage_nominal = num,
+            "LOADPCT" => data.ups_load = num,
+            "NOMPOWER" => data.ups_realpower_nominal = num,
+            "OUTCURNT" => data.output_current = num,
+            "AMBTEMP" => data.ambient_temp = num,
+            "MODEL" => data.ups_model = Some(value.clone()),
+            "SERIALNO" => data.ups_serial = Some(value.clone()),
+            "FIRMWARE" => data.ups_firmware = Some(value.clone()),
+            "DRIVER" => data.driver_name = Some(value.clone()),
+            "VERSION" => data.driver_version = Some(value.clone()),
+            "STATUS" => {}
+            _ => {
+                let (name, value) = match key.as_str() {
+                    "LOTRANS" => ("input.transfer.low", num.map(|n| n.to_string())),
+                    "HITRANS" => ("input.transfer.high", num.map(|n| n.to_string())),
+                    "NOMINV" => ("input.voltage.nominal", num.map(|n| n.to_string())),
+                    "NOMBATTV" => ("battery.voltage.nominal", num.map(|n| n.to_string())),
+                    "MBATTCHG" => ("battery.charge.low", num.map(|n| n.to_string())),
+                    "MINTIMEL" => ("battery.runtime.low", num.map(|n| (n * 60.0).to_string())),
+                    "BATTDATE" => ("battery.date", Some(value.clone())),
+                    "MANDATE" => ("ups.mfr.date", Some(value.clone())),
+                    "SELFTEST" => ("ups.test.result", Some(value.clone())),
+                    "SENSE" => ("input.sensitivity", Some(value.clone())),
+                    "UPSNAME" => ("ups.id", Some(value.clone())),
+                    "HUMIDITY" => ("ambient.humidity", num.map(|n| n.to_string())),
+                    // The UPS's own temperature, not the room's
+                    "ITEMP" => ("ups.temperature", num.map(|n| n.to_string())),
+                    _ => {
+                        data.extended_vars
+                            .insert(format!("apcupsd.{}", key.to_lowercase()), value.clone());
+                        continue;
+                    }
+                };
+                if let Some(value) = value {
+                    data.extended_vars.insert(name.to_string(), value);
+                }
+            }
+        }
+    }
+
+    if data.ups_mfr.is_none() && data.ups_model.is_some() {
+        data.ups_mfr = Some("APC".to_string());
+    }
+    data.build_details();
+    data.calculate_power();
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use tokio::net::TcpListener;
+
+    const STATUS: &str = "\
+APC      : 001,036,0879
+DATE     : 2025-10-12 10:15:02 +0200
+HOSTNAME : nas
+VERSION  : 3.14.14 (31 May 2016) debian
+UPSNAME  : rack-a
+CABLE    : USB Cable
+DRIVER   : USB UPS Driver
+UPSMODE  : Stand Alone
+MODEL    : Back-UPS RS 900G
+STATUS   : ONLINE
+LINEV    : 232.0 Volts
+LOADPCT  : 18.0 Percent
+BCHARGE  : 100.0 Percent
+TIMELEFT : 45.2 Minutes
+MBATTCHG : 10 Percent
+MINTIMEL : 5 Minutes
+SENSE    : Medium
+LOTRANS  : 176.0 Volts
+HITRANS  : 282.0 Volts
+BATTV    : 27.1 Volts
+ITEMP    : 29.2 C Internal
+LINEFREQ : 50.0 Hz
+NOMINV   : 230 Volts
+NOMBATTV : 24.0 Volts
+NOMPOWER : 540 Watts
+FIRMWARE : 879.L4 .I USB FW:L4
+SERIALNO : 3B1234X56789
+BATTDATE : 2023-04-18
+SELFTEST : NO
+XFERS    : 3
+END APC  : 2025-10-12 10:15:40 +0200
+";
+
+    /// Minimal apcupsd NIS server: answers `status` with whatever is in `report`.
+    struct NisStandIn {
+        port: u16,
+        report: Arc<Mutex<String>>,
+    }
+
+    impl NisStandIn {
+        async fn start(report: &str) -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let port = listener.local_addr().unwrap().port();
+            let report = Arc::new(Mutex::new(report.to_string()));
+            let shared = report.clone();
+            tokio::spawn(async move {
+                while let Ok((mut stream, _)) = listener.accept().await {
+                    let report = shared.clone();
+                    tokio::spawn(async move {
+                        loop {
+                            let mut len = [0u8; 2];
+                            if stream.read_exact(&mut len).await.is_err() {
+                                return;
+                            }
+                            let mut cmd = vec![0u8; u16::from_be_bytes(len) as usize];
+                            if stream.read_exact(&mut cmd).await.is_err() {
+                                return;
+                            }
+                            let body = match cmd.as_slice() {
+                                b"status" => report.lock().unwrap().clone(),
+                                b"events" => "2025-10-12 09:01:12 +0200  Power failure.\n\
+                                              2025-10-12 09:01:40 +0200  Power is back. UPS running on mains.\n"
+                                    .to_string(),
+                                _ => "Invalid command\n".to_string(),
+                            };
+                            let mut reply = Vec::new();
+                            for line in body.lines() {
+                                let line = format!("{line}\n");
+                                reply.extend((line.len() as u16).to_be_bytes());
+                                reply.extend(line.as_bytes());
+                            }
+                            reply.extend([0, 0]);
+                            if stream.write_all(&reply).await.is_err() {
+                                return;
+                            }
+                        }
+                    });
+                }
+            });
+            Self { port, report }
+        }
+
+        fn set(&self, key: &str, value: &str) {
+            let mut report = self.report.lock().unwrap();
+            *report = report
+                .lines()
+                .map(|line| match line.split_once(':') {
+                    Some((k, _)) if k.trim() == key => format!("{k}: {value}\n"),
+                    _ => format!("{line}\n"),
+                })
+                .collect();
+        }
+    }
+
+    #[tokio::test]
+    async fn maps_status_report_into_ups_data() {
+        let server = NisStandIn::start(STATUS).await;
+        let mut client = NisClient::new("127.0.0.1".to_string(), server.port);
+        client.connect().await.unwrap();
+
+        let data = client.fetch_data("ignored").await.unwrap();
+        assert_eq!(data.status, "OL");
+        assert_eq!(data.battery_charge, Some(100.0));
+        assert_eq!(data.battery_runtime, Some(2712.0));
+        assert_eq!(data.input_voltage, Some(232.0));
+        assert_eq!(data.ups_load, Some(18.0));
+        assert_eq!(data.battery_voltage, Some(27.1));
+        assert_eq!(data.ups_realpower_nominal, Some(540.0));
+        assert_eq!(data.ups_model.as_deref(), Some("Back-UPS RS 900G"));
+        assert_eq!(data.ups_mfr.as_deref(), Some("APC"));
+        assert_eq!(data.extended_vars["input.transfer.low"], "176");
+        assert_eq!(data.extended_vars["battery.runtime.low"], "300");
+        assert_eq!(data.extended_vars["battery.date"], "2023-04-18");
+        assert_eq!(data.extended_vars["apcupsd.xfers"], "3");
+        assert_eq!(data.device.temperature, Some(29.2));
+        assert_eq!(data.ambient_temp, None);
+
+        // Several requests on one connection
+        server.set("STATUS", "ONBATT LOWBATT");
+        server.set("BCHARGE", "8.0 Percent");
+        let data = client.fetch_data("ignored").await.unwrap();
+        assert_eq!(data.status, "OB DISCHRG LB");
+        assert_eq!(data.battery_charge, Some(8.0));
+
+        let events = client.events().await.unwrap();
+        assert_eq!(events.len(), 2);
+        assert!(events[0].ends_with("Power failure."));
+    }
+
+    #[tokio::test]
+    async fn lost_ups_communication_is_an_error() {
+        let server = NisStandIn::start(STATUS).await;
+        server.set("STATUS", "COMMLOST");
+        let mut client = NisClient::new("127.0.0.1".to_string(), server.port);
+        client.connect().await.unwrap();
+        assert!(client.fetch_data("ups").await.is_err());
+        assert!(client
+            .run_command("ups", "test.battery.start")
+            .await
+            .is_err());
+        assert!(client.list_commands("ups").await.unwrap().is_empty());
+    }
+
+    #[test]
+    fn status_words_map_to_nut_flags() {
+        assert_eq!(map_status_flags("ONLINE TRIM"), "OL TRIM");
+        assert_eq!(map_status_flags("CAL ONBATT"), "CAL OB DISCHRG");
+        assert_eq!(map_status_flags("ONLINE REPLACEBATT"), "OL RB");
+        assert_eq!(map_status_flags("SHUTTING DOWN"), "FSD");
+    }
+}
@@ -0,0 +1,159 @@
+//! Backend-agnostic access to a UPS. The poll loop and the Tauri commands only
+//! see `dyn UpsSource`; each protocol lives in its own submodule and is picked
+//! from the connection settings by `open`.
+
+pub mod apcupsd;
+mod nut;
+mod simulator;
+pub mod snmp;
+
+use crate::nut::client::NutClient;
+use crate::nut::models::UpsData;
+use crate::nut::simulator::SharedSimulator;
+use crate::settings::{Backend, ConnectionSettings};
+use async_trait::async_trait;
+use serde::Serialize;
+use std::sync::Arc;
+use thiserror::Error;
+use tokio::sync::Mutex;
+
+#[derive(Error, Debug)]
+pub enum SourceError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Not connected")]
+    NotConnected,
+    #[error("Authentication failed")]
+    AuthFailed,
+    #[error("Command failed: {0}")]
+    CommandFailed(String),
+    #[error("Unexpected reply: {0}")]
+    Protocol(String),
+    #[error("Not supported by this backend: {0}")]
+    Unsupported(String),
+}
+
+/// Describes where readings come from, for the UI and logs.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SourceMetadata {
+    /// Short backend id, e.g. `nut` or `simulator`.
+    pub backend: &'static str,
+    /// Human-readable address, e.g. `192.168.1.10:3493`.
+    pub endpoint: String,
+    pub supports_commands: bool,
+}
+
+/// What is known about an instant command once `run_tracked_command` returns.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase", tag = "state", content = "detail")]
+pub enum CommandOutcome {
+    /// Accepted by the server; the backend cannot tell whether the device acted on it.
+    Sent,
+    /// The device confirmed the command.
+    Succeeded,
+    /// The device reported a failure, e.g. `INVALID-ARGUMENT`.
+    Failed(String),
+    /// Still unconfirmed when we stopped waiting.
+    Pending,
+}
+
+impl std::fmt::Display for CommandOutcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CommandOutcome::Sent => write!(f, "sent"),
+            CommandOutcome::Succeeded => write!(f, "succeeded"),
+            CommandOutcome::Failed(code) => write!(f, "failed: {code}"),
+            CommandOutcome::Pending => write!(f, "pending"),
+        }
+    }
+}
+
+#[async_trait]
+pub trait UpsSource: Send {
+    /// (Re)opens the connection. Called again by the poll loop's watchdog after a failure.
+    async fn connect(&mut self) -> Result<(), SourceError>;
+
+    async fn disconnect(&mut self) -> Result<(), SourceError>;
+
+    async fn fetch_data(&mut self, ups_name: &str) -> Result<UpsData, SourceError>;
+
+    /// Instant commands the device accepts. Backends without commands return an empty list.
+    async fn list_commands(&mut self, ups_name: &str) -> Result<Vec<String>, SourceError>;
+
+    async fn run_command(&mut self, ups_name: &str, command: &str) -> Result<(), SourceError>;
+
+    /// Runs a command and waits briefly for the device to confirm it, where the
+    /// backend can track commands. Others report `CommandOutcome::Sent`.
+    async fn run_tracked_command(
+        &mut self,
+        ups_name: &str,
+        command: &str,
+    ) -> Result<CommandOutcome, SourceError> {
+        self.run_command(ups_name, command).await?;
+        Ok(CommandOutcome::Sent)
+    }
+
+    fn metadata(&self) -> SourceMetadata;
+}
+
+/// The active source shared by the poll loop and the commands.
+pub type SharedSource = Arc<Mutex<Option<Box<dyn UpsSource>>>>;
+
+/// Builds the source described by the connection settings. Nothing is connected yet.
+pub fn open(
+    conn: &ConnectionSettings,
+    password: Option<String>,
+    simulator: &SharedSimulator,
+) -> Box<dyn UpsSource> {
+    match conn.backend {
+        Backend::Nut => Box::new(NutClient::new(conn.nut_config()).with_password(password)),
+        Backend::Apcupsd => Box::new(apcupsd::NisClient::new(conn.host.clone(), conn.port)),
+        Backend::Snmp => Box::new(
+            snmp::SnmpClient::new(conn.host.clone(), conn.port, conn.snmp.clone())
+                .with_credentials(conn.username.clone(), password),
+        ),
+        Backend::Simulator => Box::new(simulator.clone()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nut::emulator::FakeUpsd;
+    use crate::nut::simulator::VirtualUps;
+
+    #[tokio::test]
+    async fn opens_the_backend_named_in_the_settings() {
+        let server = FakeUpsd::start().await;
+        let simulator = VirtualUps::shared();
+        let mut conn = ConnectionSettings {
+            host: server.host(),
+            port: server.port(),
+            ..Default::default()
+        };
+
+        let mut source = open(&conn, None, &simulator);
+        assert_eq!(source.metadata().backend, "nut");
+        assert_eq!(
+            source.metadata().endpoint,
+            format!("127.0.0.1:{}", server.port())
+        );
+        source.connect().await.unwrap();
+        assert_eq!(source.fetch_data("ups").await.unwrap().status, "OL");
+        assert!(matches!(
+            source.run_command("ups", "no.such.cmd").await,
+            Err(SourceError::CommandFailed(_))
+        ));
+
+        conn.backend = Backend::Simulator;
+        let mut source = open(&conn, None, &simulator);
+        assert_eq!(source.metadata().backend, "simulator");
+        source.connect().await.unwrap();
+        assert!(source
+            .list_commands("ups")
+            .await
+            .unwrap()
+            .contains(&"test.battery.start".to_string()));
+    }
+}
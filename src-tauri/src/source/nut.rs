@@ -0,0 +1,76 @@
+use super::{CommandOutcome, SourceError, SourceMetadata, UpsSource};
+use crate::nut::client::{NutClient, NutError, TrackingStatus};
+use crate::nut::models::UpsData;
+use async_trait::async_trait;
+use std::time::Duration;
+
+/// How long `run_tracked_command` polls `GET TRACKING` before reporting `Pending`.
+const TRACKING_TIMEOUT: Duration = Duration::from_secs(5);
+const TRACKING_POLL: Duration = Duration::from_millis(200);
+
+impl From<NutError> for SourceError {
+    fn from(e: NutError) -> Self {
+        match e {
+            NutError::Io(e) => SourceError::Io(e),
+            NutError::ConnectionFailed => SourceError::NotConnected,
+            NutError::AuthFailed => SourceError::AuthFailed,
+            NutError::CommandFailed(msg) => SourceError::CommandFailed(msg),
+        }
+    }
+}
+
+#[async_trait]
+impl UpsSource for NutClient {
+    async fn connect(&mut self) -> Result<(), SourceError> {
+        Ok(NutClient::connect(self).await?)
+    }
+
+    async fn disconnect(&mut self) -> Result<(), SourceError> {
+        Ok(NutClient::disconnect(self).await?)
+    }
+
+    async fn fetch_data(&mut self, ups_name: &str) -> Result<UpsData, SourceError> {
+        Ok(self.get_ups_data(ups_name).await?)
+    }
+
+    async fn list_commands(&mut self, ups_name: &str) -> Result<Vec<String>, SourceError> {
+        Ok(self.list_ups_commands(ups_name).await?)
+    }
+
+    async fn run_command(&mut self, ups_name: &str, command: &str) -> Result<(), SourceError> {
+        Ok(self.run_instant_cmd(ups_name, command).await?)
+    }
+
+    async fn run_tracked_command(
+        &mut self,
+        ups_name: &str,
+        command: &str,
+    ) -> Result<CommandOutcome, SourceError> {
+        // Tracking is per connection; servers without it still run the command
+        if let Err(e) = self.set_tracking(true).await {
+            log::debug!("Command tracking unavailable: {}", e);
+        }
+        let Some(id) = self.run_instant_cmd_tracked(ups_name, command).await? else {
+            return Ok(CommandOutcome::Sent);
+        };
+        let deadline = tokio::time::Instant::now() + TRACKING_TIMEOUT;
+        loop {
+            match self.get_tracking(&id).await? {
+                TrackingStatus::Success => return Ok(CommandOutcome::Succeeded),
+                TrackingStatus::Failed(code) => return Ok(CommandOutcome::Failed(code)),
+                TrackingStatus::Pending if tokio::time::Instant::now() >= deadline => {
+                    return Ok(CommandOutcome::Pending)
+                }
+                TrackingStatus::Pending => tokio::time::sleep(TRACKING_POLL).await,
+            }
+        }
+    }
+
+    fn metadata(&self) -> SourceMetadata {
+        SourceMetadata {
+            backend: self.transport_name(),
+            endpoint: format!("{}:{}", self.config().host, self.config().port),
+            supports_commands: true,
+        }
+    }
+}
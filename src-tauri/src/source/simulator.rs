@@ -0,0 +1,89 @@
+//! The built-in virtual UPS as a source, for demos and operator training. Any
+//! UPS name is accepted so existing settings keep working.
+
+use super::{SourceError, SourceMetadata, UpsSource};
+use crate::nut::models::UpsData;
+use crate::nut::parser::parse_vars;
+use crate::nut::simulator::{SharedSimulator, VirtualUps, COMMANDS};
+use async_trait::async_trait;
+use std::sync::MutexGuard;
+use std::time::Instant;
+
+/// Locks the model and brings it up to the current time.
+fn current(simulator: &SharedSimulator) -> MutexGuard<'_, VirtualUps> {
+    let mut ups = simulator.lock().unwrap_or_else(|e| e.into_inner());
+    ups.tick(Instant::now());
+    ups
+}
+
+#[async_trait]
+impl UpsSource for SharedSimulator {
+    async fn connect(&mut self) -> Result<(), SourceError> {
+        Ok(())
+    }
+
+    async fn disconnect(&mut self) -> Result<(), SourceError> {
+        Ok(())
+    }
+
+    async fn fetch_data(&mut self, _ups_name: &str) -> Result<UpsData, SourceError> {
+        let vars = current(self).vars();
+        Ok(parse_vars(
+            vars.iter().map(|(k, v)| (k.as_str(), v.as_str())),
+        ))
+    }
+
+    async fn list_commands(&mut self, _ups_name: &str) -> Result<Vec<String>, SourceError> {
+        Ok(COMMANDS.iter().map(|c| c.to_string()).collect())
+    }
+
+    async fn run_command(&mut self, _ups_name: &str, command: &str) -> Result<(), SourceError> {
+        current(self)
+            .instant_command(command)
+            .map_err(|code| SourceError::CommandFailed(code.to_string()))
+    }
+
+    fn metadata(&self) -> SourceMetadata {
+        SourceMetadata {
+            backend: "simulator",
+            endpoint: "simulator".to_string(),
+            supports_commands: true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nut::simulator::SimEvent;
+
+    #[tokio::test]
+    async fn reads_and_drives_the_virtual_ups() {
+        let simulator = VirtualUps::shared();
+        let mut source: Box<dyn UpsSource> = Box::new(simulator.clone());
+        source.connect().await.unwrap();
+
+        let data = source.fetch_data("myups").await.unwrap();
+        assert_eq!(data.status, "OL");
+        assert_eq!(data.battery_charge, Some(100.0));
+
+        source.run_command("myups", "beeper.off").await.unwrap();
+        let data = source.fetch_data("myups").await.unwrap();
+        assert_eq!(data.ups_beeper_status.as_deref(), Some("disabled"));
+        assert!(matches!(
+            source.run_command("myups", "shutdown.return").await,
+            Err(SourceError::CommandFailed(code)) if code == "CMD-NOT-SUPPORTED"
+        ));
+
+        simulator
+            .lock()
+            .unwrap()
+            .apply(SimEvent::Outage, Instant::now());
+        assert!(source
+            .fetch_data("myups")
+            .await
+            .unwrap()
+            .status
+            .starts_with("OB"));
+    }
+}
@@ -0,0 +1,224 @@
+//! Local SNMP agent simulator for tests: answers GET, GETNEXT and GETBULK from
+//! an in-memory MIB over v2c and, when given a USM user, over v3 with engine
+//! discovery and the usual `usmStats` reports.
+
+use super::ber::{Oid, Reader, Value};
+use super::message::{
+    self, EngineInfo, Pdu, GET, GET_BULK, GET_NEXT, REPORT, VERSION_2C, VERSION_3,
+};
+use super::usm::UsmUser;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::net::UdpSocket;
+
+const ENGINE_ID: &[u8] = b"\x80\x00\x1f\x88\x04ups-agent";
+
+pub struct SnmpAgent {
+    port: u16,
+    mib: Arc<Mutex<BTreeMap<Oid, Value>>>,
+}
+
+struct State {
+    community: Vec<u8>,
+    user: Option<UsmUser>,
+    mib: Arc<Mutex<BTreeMap<Oid, Value>>>,
+    started: Instant,
+    salt: u64,
+}
+
+impl SnmpAgent {
+    pub async fn start(community: &str, mut user: Option<UsmUser>) -> Self {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let port = socket.local_addr().unwrap().port();
+        let mib = Arc::new(Mutex::new(BTreeMap::new()));
+        if let Some(user) = user.as_mut() {
+            user.localize(ENGINE_ID);
+        }
+        let mut state = State {
+            community: community.as_bytes().to_vec(),
+            user,
+            mib: mib.clone(),
+            started: Instant::now(),
+            salt: 0,
+        };
+        tokio::spawn(async move {
+            let mut buf = vec![0u8; 65535];
+            while let Ok((len, peer)) = socket.recv_from(&mut buf).await {
+                if let Some(reply) = state.handle(&buf[..len]) {
+                    let _ = socket.send_to(&reply, peer).await;
+                }
+            }
+        });
+        Self { port, mib }
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    pub fn set(&self, oid: &str, value: Value) {
+        self.mib.lock().unwrap().insert(Oid::parse(oid), value);
+    }
+
+    /// A healthy single-phase UPS on mains, as a generic network card reports it.
+    pub fn load_ups_mib(&self) {
+        let text = |s: &str| Value::OctetString(s.as_bytes().to_vec());
+        let ups = "1.3.6.1.2.1.33.1";
+        for (oid, value) in [
+            (
+                "1.3.6.1.2.1.1.2.0",
+                Value::Oid(Oid::parse("1.3.6.1.4.1.8072.3.2.10")),
+            ),
+            ("1.1.0", text("Generic")),
+            ("1.2.0", text("UPS 900")),
+            ("1.3.0", text("2.1")),
+            ("1.4.0", text("agent 1.0")),
+            ("1.5.0", text("rack-b")),
+            ("2.1.0", Value::Integer(2)),
+            ("2.2.0", Value::Integer(0)),
+            ("2.3.0", Value::Integer(40)),
+            ("2.4.0", Value::Integer(100)),
+            ("2.5.0", Value::Integer(272)),
+            ("2.7.0", Value::Integer(29)),
+            ("3.2.0", Value::Integer(1)),
+            ("3.3.1.2.1", Value::Integer(500)),
+            ("3.3.1.3.1", Value::Integer(231)),
+            ("4.1.0", Value::Integer(3)),
+            ("4.2.0", Value::Integer(500)),
+            ("4.3.0", Value::Integer(1)),
+            ("4.4.1.2.1", Value::Integer(230)),
+            ("4.4.1.3.1", Value::Integer(9)),
+            ("4.4.1.4.1", Value::Integer(198)),
+            ("4.4.1.5.1", Value::Integer(22)),
+            ("6.1.0", Value::Gauge32(0)),
+            ("9.3.0", Value::Integer(230)),
+            ("9.6.0", Value::Integer(900)),
+            ("9.7.0", Value::Integer(2)),
+            ("9.9.0", Value::Integer(170)),
+            ("9.10.0", Value::Integer(280)),
+        ] {
+            let oid = if oid.starts_with("1.3.6") {
+                oid.to_string()
+            } else {
+                format!("{ups}.{oid}")
+            };
+            self.set(&oid, value);
+        }
+    }
+}
+
+impl State {
+    fn handle(&mut self, bytes: &[u8]) -> Option<Vec<u8>> {
+        match message::peek_version(bytes).ok()? {
+            VERSION_2C => {
+                let (community, pdu) = message::decode_community(bytes).ok()?;
+                // Real agents drop requests with an unknown community silently
+                if community != self.community {
+                    return None;
+                }
+                Some(message::encode_community(&community, &self.answer(&pdu)))
+            }
+            VERSION_3 => self.handle_usm(bytes),
+            _ => None,
+        }
+    }
+
+    fn engine(&self) -> EngineInfo {
+        EngineInfo {
+            id: ENGINE_ID.to_vec(),
+            boots: 1,
+            time: self.started.elapsed().as_secs() as u32,
+        }
+    }
+
+    fn report(&self, msg_id: i32, counter: u32) -> Option<Vec<u8>> {
+        let pdu = Pdu {
+            kind: REPORT,
+            request_id: msg_id,
+            error_status: 0,
+            error_index: 0,
+            varbinds: vec![(
+                Oid::parse("1.3.6.1.6.3.15.1.1").child(&[counter, 0]),
+                Value::Counter32(1),
+            )],
+        };
+        message::encode_v3(None, msg_id, &self.engine(), &pdu, false, 0).ok()
+    }
+
+    fn handle_usm(&mut self, bytes: &[u8]) -> Option<Vec<u8>> {
+        let (msg_id, engine_id, user_name) = peek_header(bytes)?;
+        if engine_id.is_empty() {
+            return self.report(msg_id, 4);
+        }
+        let user = self.user.as_ref()?;
+        if user.name.as_bytes() != user_name {
+            return self.report(msg_id, 3);
+        }
+        let request = match message::decode_v3(bytes, Some(user)) {
+            Ok(request) => request,
+            Err(_) => return self.report(msg_id, 5),
+        };
+        let response = self.answer(&request.pdu);
+        self.salt += 1;
+        let user = self.user.as_ref()?;
+        message::encode_v3(
+            Some(user),
+            msg_id,
+            &self.engine(),
+            &response,
+            false,
+            self.salt,
+        )
+        .ok()
+    }
+
+    fn answer(&self, pdu: &Pdu) -> Pdu {
+        let mib = self.mib.lock().unwrap();
+        let next = |oid: &Oid| {
+            mib.range(oid.clone()..)
+                .find(|(candidate, _)| *candidate > oid)
+                .map(|(oid, value)| (oid.clone(), value.clone()))
+        };
+        let mut varbinds = Vec::new();
+        for (oid, _) in &pdu.varbinds {
+            match pdu.kind {
+                GET => varbinds.push((
+                    oid.clone(),
+                    mib.get(oid).cloned().unwrap_or(Value::NoSuchObject),
+                )),
+                GET_NEXT => varbinds.push(next(oid).unwrap_or((oid.clone(), Value::EndOfMibView))),
+                GET_BULK => {
+                    let mut cursor = oid.clone();
+                    for _ in 0..pdu.error_index.max(1) {
+                        match next(&cursor) {
+                            Some((oid, value)) => {
+                                cursor = oid.clone();
+                                varbinds.push((oid, value));
+                            }
+                            None => {
+                                varbinds.push((cursor.clone(), Value::EndOfMibView));
+                                break;
+                            }
+                        }
+                    }
+                }
+                _ => return pdu.response(4, Vec::new()),
+            }
+        }
+        pdu.response(0, varbinds)
+    }
+}
+
+/// Message id, engine id and user name of a v3 message, read without checking it.
+fn peek_header(bytes: &[u8]) -> Option<(i32, Vec<u8>, Vec<u8>)> {
+    let mut msg = Reader::new(bytes).sequence().ok()?;
+    msg.integer().ok()?;
+    let msg_id = msg.sequence().ok()?.integer().ok()? as i32;
+    let mut security = msg.constructed(0x04).ok()?.sequence().ok()?;
+    let engine_id = security.octets().ok()?.to_vec();
+    security.integer().ok()?;
+    security.integer().ok()?;
+    let user = security.octets().ok()?.to_vec();
+    Some((msg_id, engine_id, user))
+}
@@ -0,0 +1,412 @@
+//! The subset of ASN.1 BER that SNMP uses: definite lengths, primitive
+//! INTEGER/OCTET STRING/NULL/OBJECT IDENTIFIER, SEQUENCE and the SMIv2
+//! application types.
+
+use crate::source::SourceError;
+use std::fmt;
+
+pub const INTEGER: u8 = 0x02;
+pub const OCTET_STRING: u8 = 0x04;
+pub const NULL: u8 = 0x05;
+pub const OBJECT_IDENTIFIER: u8 = 0x06;
+pub const SEQUENCE: u8 = 0x30;
+pub const IP_ADDRESS: u8 = 0x40;
+pub const COUNTER32: u8 = 0x41;
+pub const GAUGE32: u8 = 0x42;
+pub const TIMETICKS: u8 = 0x43;
+pub const OPAQUE: u8 = 0x44;
+pub const COUNTER64: u8 = 0x46;
+pub const NO_SUCH_OBJECT: u8 = 0x80;
+pub const NO_SUCH_INSTANCE: u8 = 0x81;
+pub const END_OF_MIB_VIEW: u8 = 0x82;
+
+fn malformed(what: &str) -> SourceError {
+    SourceError::Protocol(format!("malformed SNMP message: {what}"))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Oid(pub Vec<u32>);
+
+impl Oid {
+    /// Parses dotted notation; panics on malformed input, so only use it on constants.
+    pub fn parse(dotted: &str) -> Self {
+        Self(
+            dotted
+                .trim_start_matches('.')
+                .split('.')
+                .map(|arc| arc.parse().expect("invalid OID constant"))
+                .collect(),
+        )
+    }
+
+    pub fn child(&self, arcs: &[u32]) -> Self {
+        let mut oid = self.0.clone();
+        oid.extend_from_slice(arcs);
+        Self(oid)
+    }
+
+    pub fn starts_with(&self, prefix: &Oid) -> bool {
+        self.0.starts_with(&prefix.0)
+    }
+
+    /// Arcs after `prefix`, e.g. the row index of a table column.
+    pub fn suffix(&self, prefix: &Oid) -> Option<&[u32]> {
+        self.0.strip_prefix(prefix.0.as_slice())
+    }
+}
+
+impl fmt::Display for Oid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let arcs: Vec<String> = self.0.iter().map(u32::to_string).collect();
+        f.write_str(&arcs.join("."))
+    }
+}
+
+/// A variable binding value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Integer(i64),
+    OctetString(Vec<u8>),
+    Null,
+    Oid(Oid),
+    IpAddress([u8; 4]),
+    Counter32(u32),
+    Gauge32(u32),
+    TimeTicks(u32),
+    Opaque(Vec<u8>),
+    Counter64(u64),
+    NoSuchObject,
+    NoSuchInstance,
+    EndOfMibView,
+}
+
+impl Value {
+    /// Numeric view of any integer-like type.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Integer(n) => Some(*n as f64),
+            Value::Counter32(n) | Value::Gauge32(n) | Value::TimeTicks(n) => Some(*n as f64),
+            Value::Counter64(n) => Some(*n as f64),
+            _ => None,
+        }
+    }
+
+    pub fn as_string(&self) -> Option<String> {
+        match self {
+            Value::OctetString(bytes) => Some(
+                String::from_utf8_lossy(bytes)
+                    .trim_end_matches('\0')
+                    .trim()
+                    .to_string(),
+            ),
+            _ => None,
+        }
+    }
+
+    /// True for the v2 exception values an agent returns instead of an error.
+    pub fn is_exception(&self) -> bool {
+        matches!(
+            self,
+            Value::NoSuchObject | Value::NoSuchInstance | Value::EndOfMibView
+        )
+    }
+
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            Value::Integer(n) => write_integer(out, *n),
+            Value::OctetString(bytes) => write_tlv(out, OCTET_STRING, bytes),
+            Value::Null => write_tlv(out, NULL, &[]),
+            Value::Oid(oid) => write_oid(out, oid),
+            Value::IpAddress(addr) => write_tlv(out, IP_ADDRESS, addr),
+            Value::Counter32(n) => write_unsigned(out, COUNTER32, *n as u64),
+            Value::Gauge32(n) => write_unsigned(out, GAUGE32, *n as u64),
+            Value::TimeTicks(n) => write_unsigned(out, TIMETICKS, *n as u64),
+            Value::Opaque(bytes) => write_tlv(out, OPAQUE, bytes),
+            Value::Counter64(n) => write_unsigned(out, COUNTER64, *n),
+            Value::NoSuchObject => write_tlv(out, NO_SUCH_OBJECT, &[]),
+            Value::NoSuchInstance => write_tlv(out, NO_SUCH_INSTANCE, &[]),
+            Value::EndOfMibView => write_tlv(out, END_OF_MIB_VIEW, &[]),
+        }
+    }
+}
+
+fn write_length(out: &mut Vec<u8>, len: usize) {
+    if len < 0x80 {
+        out.push(len as u8);
+    } else {
+        let bytes = len.to_be_bytes();
+        let skip = bytes.iter().take_while(|b| **b == 0).count();
+        out.push(0x80 | (bytes.len() - skip) as u8);
+        out.extend_from_slice(&bytes[skip..]);
+    }
+}
+
+pub fn write_tlv(out: &mut Vec<u8>, tag: u8, content: &[u8]) {
+    out.push(tag);
+    write_length(out, content.len());
+    out.extend_from_slice(content);
+}
+
+/// Writes a constructed value whose content is produced by `build`.
+pub fn write_constructed(out: &mut Vec<u8>, tag: u8, build: impl FnOnce(&mut Vec<u8>)) {
+    let mut content = Vec::new();
+    build(&mut content);
+    write_tlv(out, tag, &content);
+}
+
+pub fn write_integer(out: &mut Vec<u8>, n: i64) {
+    let bytes = n.to_be_bytes();
+    // Drop redundant leading bytes while keeping the sign bit intact
+    let mut start = 0;
+    while start < 7 {
+        let (b, next) = (bytes[start], bytes[start + 1]);
+        if (b == 0x00 && next & 0x80 == 0) || (b == 0xff && next & 0x80 != 0) {
+            start += 1;
+        } else {
+            break;
+        }
+    }
+    write_tlv(out, INTEGER, &bytes[start..]);
+}
+
+fn write_unsigned(out: &mut Vec<u8>, tag: u8, n: u64) {
+    let bytes = n.to_be_bytes();
+    let skip = bytes.iter().take_while(|b| **b == 0).count().min(7);
+    let mut content = Vec::with_capacity(9);
+    if bytes[skip] & 0x80 != 0 {
+        content.push(0);
+    }
+    content.extend_from_slice(&bytes[skip..]);
+    write_tlv(out, tag, &content);
+}
+
+pub fn write_octets(out: &mut Vec<u8>, bytes: &[u8]) {
+    write_tlv(out, OCTET_STRING, bytes);
+}
+
+pub fn write_oid(out: &mut Vec<u8>, oid: &Oid) {
+    let arcs = &oid.0;
+    let mut content = Vec::new();
+    let first = match arcs.len() {
+        0 => 0,
+        1 => arcs[0] * 40,
+        _ => arcs[0] * 40 + arcs[1],
+    };
+    let mut push_arc = |mut arc: u32| {
+        let mut chunk = vec![(arc & 0x7f) as u8];
+        arc >>= 7;
+        while arc > 0 {
+            chunk.push(0x80 | (arc & 0x7f) as u8);
+            arc >>= 7;
+        }
+        content.extend(chunk.iter().rev());
+    };
+    push_arc(first);
+    for arc in arcs.iter().skip(2) {
+        push_arc(*arc);
+    }
+    write_tlv(out, OBJECT_IDENTIFIER, &content);
+}
+
+/// Cursor over a BER buffer. Offsets are kept so callers can patch fields in
+/// place, which the USM needs for the message digest.
+pub struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(buf: &'a [u8]) -> Self {
+        Self { buf, pos: 0 }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pos >= self.buf.len()
+    }
+
+    /// Reads one TLV and returns its tag, content and the content's offset.
+    pub fn read_tlv(&mut self) -> Result<(u8, &'a [u8], usize), SourceError> {
+        let tag = *self
+            .buf
+            .get(self.pos)
+            .ok_or_else(|| malformed("truncated"))?;
+        let mut pos = self.pos + 1;
+        let first = *self.buf.get(pos).ok_or_else(|| malformed("truncated"))?;
+        pos += 1;
+        let len = if first < 0x80 {
+            first as usize
+        } else {
+            let count = (first & 0x7f) as usize;
+            if count == 0 || count > 4 {
+                return Err(malformed("unsupported length"));
+            }
+            let bytes = self
+                .buf
+                .get(pos..pos + count)
+                .ok_or_else(|| malformed("truncated"))?;
+            pos += count;
+            bytes.iter().fold(0usize, |acc, b| (acc << 8) | *b as usize)
+        };
+        let content = self
+            .buf
+            .get(pos..pos + len)
+            .ok_or_else(|| malformed("truncated"))?;
+        self.pos = pos + len;
+        Ok((tag, content, pos))
+    }
+
+    fn expect(&mut self, tag: u8) -> Result<(&'a [u8], usize), SourceError> {
+        let (found, content, offset) = self.read_tlv()?;
+        if found != tag {
+            return Err(malformed(&format!(
+                "expected tag {tag:#04x}, found {found:#04x}"
+            )));
+        }
+        Ok((content, offset))
+    }
+
+    /// Enters a constructed value, returning a reader over its content.
+    pub fn constructed(&mut self, tag: u8) -> Result<Reader<'a>, SourceError> {
+        let (content, offset) = self.expect(tag)?;
+        // Keep absolute offsets by reading from the original buffer
+        Ok(Reader {
+            buf: &self.buf[..offset + content.len()],
+            pos: offset,
+        })
+    }
+
+    pub fn sequence(&mut self) -> Result<Reader<'a>, SourceError> {
+        self.constructed(SEQUENCE)
+    }
+
+    pub fn integer(&mut self) -> Result<i64, SourceError> {
+        let (content, _) = self.expect(INTEGER)?;
+        decode_signed(content)
+    }
+
+    pub fn octets(&mut self) -> Result<&'a [u8], SourceError> {
+        Ok(self.expect(OCTET_STRING)?.0)
+    }
+
+    /// Like `octets`, also returning the content's offset in the buffer.
+    pub fn octets_at(&mut self) -> Result<(&'a [u8], usize), SourceError> {
+        self.expect(OCTET_STRING)
+    }
+
+    pub fn oid(&mut self) -> Result<Oid, SourceError> {
+        let (content, _) = self.expect(OBJECT_IDENTIFIER)?;
+        decode_oid(content)
+    }
+
+    pub fn value(&mut self) -> Result<Value, SourceError> {
+        let (tag, content, _) = self.read_tlv()?;
+        Ok(match tag {
+            INTEGER => Value::Integer(decode_signed(content)?),
+            OCTET_STRING => Value::OctetString(content.to_vec()),
+            NULL => Value::Null,
+            OBJECT_IDENTIFIER => Value::Oid(decode_oid(content)?),
+            IP_ADDRESS => Value::IpAddress(
+                content
+                    .try_into()
+                    .map_err(|_| malformed("IpAddress length"))?,
+            ),
+            COUNTER32 => Value::Counter32(decode_unsigned(content)? as u32),
+            GAUGE32 => Value::Gauge32(decode_unsigned(content)? as u32),
+            TIMETICKS => Value::TimeTicks(decode_unsigned(content)? as u32),
+            OPAQUE => Value::Opaque(content.to_vec()),
+            COUNTER64 => Value::Counter64(decode_unsigned(content)?),
+            NO_SUCH_OBJECT => Value::NoSuchObject,
+            NO_SUCH_INSTANCE => Value::NoSuchInstance,
+            END_OF_MIB_VIEW => Value::EndOfMibView,
+            other => return Err(malformed(&format!("unknown value type {other:#04x}"))),
+        })
+    }
+}
+
+fn decode_signed(content: &[u8]) -> Result<i64, SourceError> {
+    if content.is_empty() || content.len() > 8 {
+        return Err(malformed("integer length"));
+    }
+    let init = if content[0] & 0x80 != 0 { -1i64 } else { 0 };
+    Ok(content.iter().fold(init, |acc, b| (acc << 8) | *b as i64))
+}
+
+fn decode_unsigned(content: &[u8]) -> Result<u64, SourceError> {
+    let content = match content {
+        [0, rest @ ..] if !rest.is_empty() => rest,
+        other => other,
+    };
+    if content.is_empty() || content.len() > 8 {
+        return Err(malformed("unsigned length"));
+    }
+    Ok(content.iter().fold(0u64, |acc, b| (acc << 8) | *b as u64))
+}
+
+fn decode_oid(content: &[u8]) -> Result<Oid, SourceError> {
+    let mut arcs = Vec::new();
+    let mut arc: u64 = 0;
+    for (i, b) in content.iter().enumerate() {
+        arc = (arc << 7) | (b & 0x7f) as u64;
+        if arc > u32::MAX as u64 {
+            return Err(malformed("OID arc too large"));
+        }
+        if b & 0x80 == 0 {
+            if arcs.is_empty() {
+                let first = (arc / 40).min(2);
+                arcs.push(first as u32);
+                arcs.push((arc - first * 40) as u32);
+            } else {
+                arcs.push(arc as u32);
+            }
+            arc = 0;
+        } else if i == content.len() - 1 {
+            return Err(malformed("truncated OID"));
+        }
+    }
+    Ok(Oid(arcs))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roundtrip(value: Value) -> Value {
+        let mut out = Vec::new();
+        value.encode(&mut out);
+        let mut reader = Reader::new(&out);
+        let decoded = reader.value().unwrap();
+        assert!(reader.is_empty());
+        decoded
+    }
+
+    #[test]
+    fn values_roundtrip() {
+        for value in [
+            Value::Integer(0),
+            Value::Integer(127),
+            Value::Integer(128),
+            Value::Integer(-129),
+            Value::Integer(i32::MAX as i64),
+            Value::Gauge32(u32::MAX),
+            Value::TimeTicks(271_200),
+            Value::Counter64(u64::MAX),
+            Value::OctetString(vec![b'x'; 300]),
+            Value::Oid(Oid::parse("1.3.6.1.2.1.33.1.2.4.0")),
+            Value::Oid(Oid::parse("1.3.6.1.4.1.318.1.1.1.2.2.1.0")),
+            Value::NoSuchInstance,
+        ] {
+            assert_eq!(roundtrip(value.clone()), value);
+        }
+    }
+
+    #[test]
+    fn encodes_known_vectors() {
+        let mut out = Vec::new();
+        write_integer(&mut out, 128);
+        assert_eq!(out, [0x02, 0x02, 0x00, 0x80]);
+
+        out.clear();
+        write_oid(&mut out, &Oid::parse("1.3.6.1.4.1.318"));
+        assert_eq!(out, [0x06, 0x07, 0x2b, 0x06, 0x01, 0x04, 0x01, 0x82, 0x3e]);
+    }
+}
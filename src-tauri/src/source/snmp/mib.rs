@@ -0,0 +1,555 @@
+//! UPS-MIB (RFC 1628) objects plus the APC PowerNet and Eaton XUPS
+//! extensions, and their mapping into `UpsData`.
+
+use super::ber::{Oid, Value};
+use crate::nut::models::UpsData;
+use std::collections::BTreeMap;
+
+pub const SYS_OBJECT_ID: &str = "1.3.6.1.2.1.1.2.0";
+
+const UPS_MIB: &str = "1.3.6.1.2.1.33.1";
+const APC_UPS: &str = "1.3.6.1.4.1.318.1.1.1";
+const EATON_XUPS: &str = "1.3.6.1.4.1.534.1";
+
+/// Input and output tables are read for at most this many lines (phases).
+pub const MAX_LINES: u32 = 3;
+
+/// Readings are requested at most this many per GET to stay clear of `tooBig`.
+pub const GET_CHUNK: usize = 24;
+
+/// Enterprise MIBs the backend knows, picked from `sysObjectID`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Vendor {
+    Apc,
+    Eaton,
+}
+
+impl Vendor {
+    pub fn detect(sys_object_id: &Oid) -> Option<Self> {
+        if sys_object_id.starts_with(&Oid::parse("1.3.6.1.4.1.318")) {
+            Some(Vendor::Apc)
+        } else if sys_object_id.starts_with(&Oid::parse("1.3.6.1.4.1.534")) {
+            Some(Vendor::Eaton)
+        } else {
+            None
+        }
+    }
+
+    /// Scalars read on top of the UPS-MIB.
+    pub fn oids(self) -> Vec<Oid> {
+        let (base, arcs): (&str, &[&[u32]]) = match self {
+            Vendor::Apc => (APC_UPS, APC_SCALARS),
+            Vendor::Eaton => (EATON_XUPS, XUPS_SCALARS),
+        };
+        let base = Oid::parse(base);
+        arcs.iter().map(|arcs| base.child(arcs)).collect()
+    }
+}
+
+const APC_SCALARS: &[&[u32]] = &[
+    &[1, 1, 1, 0], // upsBasicIdentModel
+    &[1, 2, 1, 0], // upsAdvIdentFirmwareRevision
+    &[1, 2, 3, 0], // upsAdvIdentSerialNumber
+    &[2, 1, 1, 0], // upsBasicBatteryStatus
+    &[2, 1, 3, 0], // upsBasicBatteryLastReplaceDate
+    &[2, 2, 1, 0], // upsAdvBatteryCapacity
+    &[2, 2, 2, 0], // upsAdvBatteryTemperature
+    &[2, 2, 3, 0], // upsAdvBatteryRunTimeRemaining
+    &[2, 2, 4, 0], // upsAdvBatteryReplaceIndicator
+    &[2, 2, 8, 0], // upsAdvBatteryActualVoltage
+    &[3, 2, 1, 0], // upsAdvInputLineVoltage
+    &[3, 2, 4, 0], // upsAdvInputFrequency
+    &[4, 1, 1, 0], // upsBasicOutputStatus
+    &[4, 2, 1, 0], // upsAdvOutputVoltage
+    &[4, 2, 2, 0], // upsAdvOutputFrequency
+    &[4, 2, 3, 0], // upsAdvOutputLoad
+    &[4, 2, 4, 0], // upsAdvOutputCurrent
+];
+
+const XUPS_SCALARS: &[&[u32]] = &[
+    &[1, 1, 0], // xupsIdentManufacturer
+    &[1, 2, 0], // xupsIdentModel
+    &[1, 3, 0], // xupsIdentSoftwareVersion
+    &[2, 1, 0], // xupsBatTimeRemaining
+    &[2, 2, 0], // xupsBatVoltage
+    &[2, 3, 0], // xupsBatCurrent
+    &[2, 4, 0], // xupsBatCapacity
+    &[2, 5, 0], // xupsBatteryAbmStatus
+    &[3, 1, 0], // xupsInputFrequency
+    &[4, 1, 0], // xupsOutputLoad
+    &[4, 2, 0], // xupsOutputFrequency
+    &[6, 1, 0], // xupsEnvAmbientTemp
+];
+
+/// UPS-MIB scalars, relative to `upsObjects`.
+const UPS_SCALARS: &[&[u32]] = &[
+    &[1, 1, 0],  // upsIdentManufacturer
+    &[1, 2, 0],  // upsIdentModel
+    &[1, 3, 0],  // upsIdentUPSSoftwareVersion
+    &[1, 4, 0],  // upsIdentAgentSoftwareVersion
+    &[1, 5, 0],  // upsIdentName
+    &[2, 1, 0],  // upsBatteryStatus
+    &[2, 2, 0],  // upsSecondsOnBattery
+    &[2, 3, 0],  // upsEstimatedMinutesRemaining
+    &[2, 4, 0],  // upsEstimatedChargeRemaining
+    &[2, 5, 0],  // upsBatteryVoltage
+    &[2, 6, 0],  // upsBatteryCurrent
+    &[2, 7, 0],  // upsBatteryTemperature
+    &[3, 1, 0],  // upsInputLineBads
+    &[3, 2, 0],  // upsInputNumLines
+    &[4, 1, 0],  // upsOutputSource
+    &[4, 2, 0],  // upsOutputFrequency
+    &[4, 3, 0],  // upsOutputNumLines
+    &[6, 1, 0],  // upsAlarmsPresent
+    &[9, 1, 0],  // upsConfigInputVoltage
+    &[9, 2, 0],  // upsConfigInputFreq
+    &[9, 3, 0],  // upsConfigOutputVoltage
+    &[9, 4, 0],  // upsConfigOutputFreq
+    &[9, 5, 0],  // upsConfigOutputVA
+    &[9, 6, 0],  // upsConfigOutputPower
+    &[9, 7, 0],  // upsConfigLowBattTime
+    &[9, 9, 0],  // upsConfigLowVoltageTransferPoint
+    &[9, 10, 0], // upsConfigHighVoltageTransferPoint
+];
+
+pub fn ups_oid(arcs: &[u32]) -> Oid {
+    Oid::parse(UPS_MIB).child(arcs)
+}
+
+pub fn scalar_oids() -> Vec<Oid> {
+    UPS_SCALARS.iter().map(|arcs| ups_oid(arcs)).collect()
+}
+
+/// `upsInputTable` and `upsOutputTable` columns for lines `1..=lines`.
+pub fn line_oids(input_lines: u32, output_lines: u32) -> Vec<Oid> {
+    let mut oids = Vec::new();
+    for line in 1..=input_lines.min(MAX_LINES) {
+        for column in 2..=5 {
+            oids.push(ups_oid(&[3, 3, 1, column, line]));
+        }
+    }
+    for line in 1..=output_lines.min(MAX_LINES) {
+        for column in 2..=5 {
+            oids.push(ups_oid(&[4, 4, 1, column, line]));
+        }
+    }
+    oids
+}
+
+/// `upsAlarmDescr` column; each row holds the OID of a well-known alarm.
+pub fn alarm_descr_column() -> Oid {
+    ups_oid(&[6, 2, 1, 2])
+}
+
+/// Names of the well-known alarms under `upsWellKnownAlarms`, by last arc.
+fn alarm_name(oid: &Oid) -> Option<&'static str> {
+    let arc = oid.suffix(&ups_oid(&[6, 3]))?;
+    Some(match arc {
+        [1] => "BatteryBad",
+        [2] => "OnBattery",
+        [3] => "LowBattery",
+        [4] => "DepletedBattery",
+        [5] => "TempBad",
+        [6] => "InputBad",
+        [7] => "OutputBad",
+        [8] => "OutputOverload",
+        [9] => "OnBypass",
+        [10] => "BypassBad",
+        [11] => "OutputOffAsRequested",
+        [12] => "UpsOffAsRequested",
+        [13] => "ChargerFailed",
+        [14] => "UpsOutputOff",
+        [15] => "UpsSystemOff",
+        [16] => "FanFailure",
+        [17] => "FuseFailure",
+        [18] => "GeneralFault",
+        [19] => "DiagnosticTestFailed",
+        [20] => "CommunicationsLost",
+        [21] => "AwaitingPower",
+        [22] => "ShutdownPending",
+        [23] => "ShutdownImminent",
+        [24] => "TestInProgress",
+        _ => return None,
+    })
+}
+
+/// Everything read in one poll, keyed by OID. Exception values are dropped on insert.
+#[derive(Debug, Default)]
+pub struct Readings {
+    values: BTreeMap<Oid, Value>,
+    pub alarms: Vec<Oid>,
+    pub vendor: Option<Vendor>,
+}
+
+impl Readings {
+    pub fn new(vendor: Option<Vendor>) -> Self {
+        Self {
+            vendor,
+            ..Default::default()
+        }
+    }
+
+    pub fn insert(&mut self, oid: Oid, value: Value) {
+        if !value.is_exception() {
+            self.values.insert(oid, value);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    fn num(&self, oid: &Oid) -> Option<f64> {
+        self.values.get(oid).and_then(Value::as_f64)
+    }
+
+    fn text(&self, oid: &Oid) -> Option<String> {
+        self.values
+            .get(oid)
+            .and_then(Value::as_string)
+            .filter(|s| !s.is_empty())
+    }
+
+    fn ups(&self, arcs: &[u32]) -> Option<f64> {
+        self.num(&ups_oid(arcs))
+    }
+
+    fn ups_text(&self, arcs: &[u32]) -> Option<String> {
+        self.text(&ups_oid(arcs))
+    }
+
+    fn apc(&self, arcs: &[u32]) -> Option<f64> {
+        self.num(&Oid::parse(APC_UPS).child(arcs))
+    }
+
+    fn apc_text(&self, arcs: &[u32]) -> Option<String> {
+        self.text(&Oid::parse(APC_UPS).child(arcs))
+    }
+
+    fn xups(&self, arcs: &[u32]) -> Option<f64> {
+        self.num(&Oid::parse(EATON_XUPS).child(arcs))
+    }
+
+    fn xups_text(&self, arcs: &[u32]) -> Option<String> {
+        self.text(&Oid::parse(EATON_XUPS).child(arcs))
+    }
+
+    /// Number of input and output lines to read, 1 when the agent does not say.
+    pub fn line_counts(&self) -> (u32, u32) {
+        let lines = |arcs: &[u32]| self.ups(arcs).map(|n| n.max(1.0) as u32).unwrap_or(1);
+        (lines(&[3, 2, 0]), lines(&[4, 3, 0]))
+    }
+
+    pub fn alarms_present(&self) -> bool {
+        self.ups(&[6, 1, 0]).unwrap_or(0.0) > 0.0
+    }
+}
+
+fn tenths(value: Option<f64>) -> Option<f64> {
+    value.map(|v| v / 10.0)
+}
+
+fn set_var(data: &mut UpsData, name: &str, value: Option<f64>) {
+    if let Some(value) = value {
+        data.extended_vars
+            .insert(name.to_string(), value.to_string());
+    }
+}
+
+/// Maps one poll into `UpsData`. UPS-MIB values come first; vendor objects
+/// fill whatever the standard MIB left empty. Values without a dedicated slot
+/// land in `extended_vars` under their NUT name, or as `snmp.<object>`.
+pub fn map_readings(readings: &Readings) -> UpsData {
+    let r = readings;
+    let mut data = UpsData {
+        ups_mfr: r.ups_text(&[1, 1, 0]),
+        ups_model: r.ups_text(&[1, 2, 0]),
+        ups_firmware: r.ups_text(&[1, 3, 0]),
+        battery_charge: r.ups(&[2, 4, 0]),
+        // Minutes in the MIB, seconds like NUT in UpsData
+        battery_runtime: r.ups(&[2, 3, 0]).map(|m| m * 60.0),
+        battery_voltage: tenths(r.ups(&[2, 5, 0])),
+        battery_current: tenths(r.ups(&[2, 6, 0])),
+        output_frequency: tenths(r.ups(&[4, 2, 0])),
+        output_voltage_nominal: r.ups(&[9, 3, 0]),
+        output_frequency_nominal: tenths(r.ups(&[9, 4, 0])),
+        ups_realpower_nominal: r.ups(&[9, 6, 0]),
+        ..Default::default()
+    };
+    if let Some(agent) = r.ups_text(&[1, 4, 0]) {
+        data.extended_vars
+            .insert("ups.firmware.aux".to_string(), agent);
+    }
+    if let Some(name) = r.ups_text(&[1, 5, 0]) {
+        data.extended_vars.insert("ups.id".to_string(), name);
+    }
+    set_var(&mut data, "battery.temperature", r.ups(&[2, 7, 0]));
+    set_var(&mut data, "snmp.upsSecondsOnBattery", r.ups(&[2, 2, 0]));
+    set_var(&mut data, "snmp.upsInputLineBads", r.ups(&[3, 1, 0]));
+    set_var(&mut data, "input.voltage.nominal", r.ups(&[9, 1, 0]));
+    set_var(
+        &mut data,
+        "input.frequency.nominal",
+        tenths(r.ups(&[9, 2, 0])),
+    );
+    set_var(&mut data, "ups.power.nominal", r.ups(&[9, 5, 0]));
+    set_var(
+        &mut data,
+        "battery.runtime.low",
+        r.ups(&[9, 7, 0]).map(|m| m * 60.0),
+    );
+    set_var(&mut data, "input.transfer.low", r.ups(&[9, 9, 0]));
+    set_var(&mut data, "input.transfer.high", r.ups(&[9, 10, 0]));
+
+    map_lines(r, &mut data);
+    if let Some(vendor) = r.vendor {
+        map_vendor(r, vendor, &mut data);
+    }
+    if let Some(alarms) = alarm_text(r) {
+        data.extended_vars.insert("ups.alarm".to_string(), alarms);
+    }
+    data.status = status_flags(r);
+    data.build_details();
+    data.calculate_power();
+    data
+}
+
+/// Line 1 fills the single-phase fields; multi-line devices also get the
+/// per-phase NUT variables, with power summed and load taken from the busiest line.
+fn map_lines(r: &Readings, data: &mut UpsData) {
+    let (input_lines, output_lines) = r.line_counts();
+    let (input_lines, output_lines) = (input_lines.min(MAX_LINES), output_lines.min(MAX_LINES));
+
+    for line in 1..=input_lines {
+        let col = |c: u32| r.ups(&[3, 3, 1, c, line]);
+        if line == 1 {
+            data.input_frequency = tenths(col(2));
+            data.input_voltage = col(3);
+            set_var(data, "input.current", tenths(col(4)));
+            set_var(data, "input.realpower", col(5));
+        }
+        if input_lines > 1 {
+            set_var(data, &format!("input.L{line}-N.voltage"), col(3));
+            set_var(data, &format!("input.L{line}.current"), tenths(col(4)));
+            set_var(data, &format!("input.L{line}.realpower"), col(5));
+        }
+    }
+
+    let mut power_total: Option<f64> = None;
+    let mut load_max: Option<f64> = None;
+    for line in 1..=output_lines {
+        let col = |c: u32| r.ups(&[4, 4, 1, c, line]);
+        if line == 1 {
+            data.output_voltage = col(2);
+            data.output_current = tenths(col(3));
+        }
+        if let Some(power) = col(4) {
+            power_total = Some(power_total.unwrap_or(0.0) + power);
+        }
+        if let Some(load) = col(5) {
+            load_max = Some(load_max.map_or(load, |max: f64| max.max(load)));
+        }
+        if output_lines > 1 {
+            set_var(data, &format!("output.L{line}-N.voltage"), col(2));
+            set_var(data, &format!("output.L{line}.current"), tenths(col(3)));
+            set_var(data, &format!("output.L{line}.realpower"), col(4));
+            set_var(data, &format!("output.L{line}.power.percent"), col(5));
+        }
+    }
+    data.ups_realpower = power_total;
+    data.ups_load = load_max;
+}
+
+fn map_vendor(r: &Readings, vendor: Vendor, data: &mut UpsData) {
+    fn fill<T>(slot: &mut Option<T>, value: Option<T>) {
+        if slot.is_none() {
+            *slot = value;
+        }
+    }
+    match vendor {
+        Vendor::Apc => {
+            fill(&mut data.ups_mfr, Some("APC".to_string()));
+            fill(&mut data.ups_model, r.apc_text(&[1, 1, 1, 0]));
+            fill(&mut data.ups_firmware, r.apc_text(&[1, 2, 1, 0]));
+            fill(&mut data.ups_serial, r.apc_text(&[1, 2, 3, 0]));
+            fill(&mut data.battery_charge, r.apc(&[2, 2, 1, 0]));
+            // TimeTicks are hundredths of a second
+            fill(
+                &mut data.battery_runtime,
+                r.apc(&[2, 2, 3, 0]).map(|t| (t / 100.0).round()),
+            );
+            fill(&mut data.battery_voltage, r.apc(&[2, 2, 8, 0]));
+            fill(&mut data.input_voltage, r.apc(&[3, 2, 1, 0]));
+            fill(&mut data.input_frequency, r.apc(&[3, 2, 4, 0]));
+            fill(&mut data.output_voltage, r.apc(&[4, 2, 1, 0]));
+            fill(&mut data.output_frequency, r.apc(&[4, 2, 2, 0]));
+            fill(&mut data.ups_load, r.apc(&[4, 2, 3, 0]));
+            fill(&mut data.output_current, r.apc(&[4, 2, 4, 0]));
+            if !data.extended_vars.contains_key("battery.temperature") {
+                set_var(data, "battery.temperature", r.apc(&[2, 2, 2, 0]));
+            }
+            if let Some(date) = r.apc_text(&[2, 1, 3, 0]) {
+                data.extended_vars.insert("battery.date".to_string(), date);
+            }
+        }
+        Vendor::Eaton => {
+            fill(&mut data.ups_mfr, r.xups_text(&[1, 1, 0]));
+            fill(&mut data.ups_model, r.xups_text(&[1, 2, 0]));
+            fill(&mut data.ups_firmware, r.xups_text(&[1, 3, 0]));
+            fill(&mut data.battery_runtime, r.xups(&[2, 1, 0]));
+            fill(&mut data.battery_voltage, r.xups(&[2, 2, 0]));
+            fill(&mut data.battery_current, r.xups(&[2, 3, 0]));
+            fill(&mut data.battery_charge, r.xups(&[2, 4, 0]));
+            fill(&mut data.input_frequency, tenths(r.xups(&[3, 1, 0])));
+            fill(&mut data.ups_load, r.xups(&[4, 1, 0]));
+            fill(&mut data.output_frequency, tenths(r.xups(&[4, 2, 0])));
+            fill(&mut data.ambient_temp, r.xups(&[6, 1, 0]));
+        }
+    }
+}
+
+/// Builds NUT status flags from `upsOutputSource`, `upsBatteryStatus`, the
+/// alarm table and, where present, vendor status objects.
+pub fn status_flags(r: &Readings) -> String {
+    let mut flags: Vec<&str> = Vec::new();
+    let push = |flag: &'static str, flags: &mut Vec<&str>| {
+        if !flags.contains(&flag) {
+            flags.push(flag);
+        }
+    };
+
+    let source = r.ups(&[4, 1, 0]).map(|n| n as i64);
+    match source {
+        Some(3) => push("OL", &mut flags),
+        Some(4) => {
+            push("OL", &mut flags);
+            push("BYPASS", &mut flags);
+        }
+        Some(5) => {
+            push("OB", &mut flags);
+            push("DISCHRG", &mut flags);
+        }
+        Some(6) => {
+            push("OL", &mut flags);
+            push("BOOST", &mut flags);
+        }
+        Some(7) => {
+            push("OL", &mut flags);
+            push("TRIM", &mut flags);
+        }
+        Some(2) => push("OFF", &mut flags),
+        _ => {}
+    }
+
+    if source.is_none() && r.vendor == Some(Vendor::Apc) {
+        match r.apc(&[4, 1, 1, 0]).map(|n| n as i64) {
+            Some(2) => push("OL", &mut flags),
+            Some(3) => {
+                push("OB", &mut flags);
+                push("DISCHRG", &mut flags);
+            }
+            Some(4) => {
+                push("OL", &mut flags);
+                push("BOOST", &mut flags);
+            }
+            Some(6) | Some(9) | Some(10) => {
+                push("OL", &mut flags);
+                push("BYPASS", &mut flags);
+            }
+            Some(12) => {
+                push("OL", &mut flags);
+                push("TRIM", &mut flags);
+            }
+            Some(5) | Some(7) | Some(11) => push("OFF", &mut flags),
+            _ => {}
+        }
+    }
+
+    if let Some(Vendor::Eaton) = r.vendor {
+        match r.xups(&[2, 5, 0]).map(|n| n as i64) {
+            Some(1) if flags.contains(&"OL") => push("CHRG", &mut flags),
+            Some(2) => push("DISCHRG", &mut flags),
+            _ => {}
+        }
+    }
+
+    match r.ups(&[2, 1, 0]).map(|n| n as i64) {
+        Some(3) | Some(4) => push("LB", &mut flags),
+        _ => {}
+    }
+    if r.vendor == Some(Vendor::Apc) {
+        if r.apc(&[2, 1, 1, 0]) == Some(3.0) {
+            push("LB", &mut flags);
+        }
+        if r.apc(&[2, 2, 4, 0]) == Some(2.0) {
+            push("RB", &mut flags);
+        }
+    }
+
+    for alarm in r.alarms.iter().filter_map(alarm_name) {
+        match alarm {
+            "BatteryBad" => push("RB", &mut flags),
+            "LowBattery" | "DepletedBattery" => push("LB", &mut flags),
+            "OutputOverload" => push("OVER", &mut flags),
+            "OnBypass" => push("BYPASS", &mut flags),
+            "OutputOffAsRequested" | "UpsOffAsRequested" | "UpsOutputOff" | "UpsSystemOff" => {
+                push("OFF", &mut flags)
+            }
+            "ShutdownImminent" => push("FSD", &mut flags),
+            _ => {}
+        }
+    }
+    flags.join(" ")
+}
+
+/// `ups.alarm` text for the alarm table, like NUT's snmp-ups driver reports it.
+pub fn alarm_text(r: &Readings) -> Option<String> {
+    let names: Vec<&str> = r.alarms.iter().filter_map(alarm_name).collect();
+    (!names.is_empty()).then(|| names.join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn readings(values: &[(&[u32], Value)]) -> Readings {
+        let mut r = Readings::default();
+        for (arcs, value) in values {
+            r.insert(ups_oid(arcs), value.clone());
+        }
+        r
+    }
+
+    #[test]
+    fn output_source_and_alarms_become_nut_flags() {
+        let mut r = readings(&[(&[4, 1, 0], Value::Integer(7))]);
+        assert_eq!(status_flags(&r), "OL TRIM");
+
+        r.insert(ups_oid(&[4, 1, 0]), Value::Integer(5));
+        r.insert(ups_oid(&[2, 1, 0]), Value::Integer(3));
+        r.alarms = vec![ups_oid(&[6, 3, 2]), ups_oid(&[6, 3, 1])];
+        assert_eq!(status_flags(&r), "OB DISCHRG LB RB");
+        assert_eq!(alarm_text(&r).as_deref(), Some("OnBattery BatteryBad"));
+    }
+
+    #[test]
+    fn three_phase_output_sums_power_and_keeps_phases() {
+        let mut values: Vec<(&[u32], Value)> = vec![
+            (&[4, 1, 0], Value::Integer(3)),
+            (&[4, 3, 0], Value::Integer(3)),
+        ];
+        let rows: [[u32; 5]; 3] = [[4, 4, 1, 4, 1], [4, 4, 1, 4, 2], [4, 4, 1, 4, 3]];
+        let loads: [[u32; 5]; 3] = [[4, 4, 1, 5, 1], [4, 4, 1, 5, 2], [4, 4, 1, 5, 3]];
+        for (i, arcs) in rows.iter().enumerate() {
+            values.push((arcs, Value::Integer(1000 + i as i64 * 100)));
+        }
+        for (i, arcs) in loads.iter().enumerate() {
+            values.push((arcs, Value::Integer(30 + i as i64 * 10)));
+        }
+        let data = map_readings(&readings(&values));
+        assert_eq!(data.ups_realpower, Some(3300.0));
+        assert_eq!(data.ups_load, Some(50.0));
+        assert_eq!(data.extended_vars["output.L2.realpower"], "1100");
+        assert_eq!(data.extended_vars["output.L3.power.percent"], "50");
+    }
+}
@@ -0,0 +1,629 @@
+//! SNMP agent client reading the UPS-MIB (RFC 1628), with the APC PowerNet and
+//! Eaton XUPS enterprise MIBs filling in what the standard MIB leaves out.
+//!
+//! Supports SNMPv2c communities and SNMPv3 USM users (noAuthNoPriv, authNoPriv
+//! and authPriv with MD5/SHA and DES/AES). The backend is read-only: network
+//! cards that accept UPS-MIB control objects usually need a separate write
+//! community, which this client does not use.
+
+mod ber;
+mod message;
+mod mib;
+mod usm;
+
+#[cfg(test)]
+mod agent;
+
+pub use ber::{Oid, Value};
+pub use usm::{AuthProtocol, PrivProtocol};
+
+use super::{SourceError, SourceMetadata, UpsSource};
+use crate::nut::models::UpsData;
+use crate::settings::SnmpSettings;
+use async_trait::async_trait;
+use message::{EngineInfo, Pdu, FLAG_AUTH, GET, REPORT, RESPONSE};
+use mib::{Readings, Vendor};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::net::UdpSocket;
+use usm::UsmUser;
+
+pub const DEFAULT_PORT: u16 = 161;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_millis(750);
+const DEFAULT_RETRIES: u32 = 1;
+const MAX_DATAGRAM: usize = 65535;
+const BULK_REPETITIONS: i64 = 16;
+const MAX_ALARM_ROWS: usize = 64;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SnmpVersion {
+    #[default]
+    V2c,
+    V3,
+}
+
+/// `usmStats` counters an agent reports instead of answering (RFC 3414 5).
+const USM_STATS: &str = "1.3.6.1.6.3.15.1.1";
+
+pub struct SnmpClient {
+    host: String,
+    port: u16,
+    settings: SnmpSettings,
+    username: String,
+    password: Option<String>,
+    timeout: Duration,
+    retries: u32,
+    socket: Option<UdpSocket>,
+    user: Option<UsmUser>,
+    engine: EngineInfo,
+    engine_synced: Instant,
+    vendor: Option<Vendor>,
+    next_id: i32,
+    salt: u64,
+}
+
+impl SnmpClient {
+    pub fn new(host: String, port: u16, settings: SnmpSettings) -> Self {
+        Self {
+            host,
+            port,
+            settings,
+            username: String::new(),
+            password: None,
+            timeout: DEFAULT_TIMEOUT,
+            retries: DEFAULT_RETRIES,
+            socket: None,
+            user: None,
+            engine: EngineInfo::default(),
+            engine_synced: Instant::now(),
+            vendor: None,
+            next_id: std::process::id() as i32 & 0x7fff,
+            // Privacy salts only need to be unique per key, not secret
+            salt: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_nanos() as u64)
+                .unwrap_or(0),
+        }
+    }
+
+    /// SNMPv3 user name and passphrase; the passphrase derives both the
+    /// authentication and the privacy key.
+    pub fn with_credentials(mut self, username: Option<String>, password: Option<String>) -> Self {
+        self.username = username.unwrap_or_default();
+        self.password = password;
+        self.user = None;
+        self
+    }
+
+    /// How long to wait for each reply and how many times to resend a request.
+    pub fn with_timeout(mut self, timeout: Duration, retries: u32) -> Self {
+        self.timeout = timeout;
+        self.retries = retries;
+        self
+    }
+
+    fn next_id(&mut self) -> i32 {
+        self.next_id = self.next_id.wrapping_add(1) & 0x7fff_ffff;
+        self.next_id
+    }
+
+    /// Sends `bytes` until `accept` recognises a reply, resending after each timeout.
+    async fn send_and_wait<T>(
+        socket: &UdpSocket,
+        bytes: &[u8],
+        timeout: Duration,
+        retries: u32,
+        mut accept: impl FnMut(&[u8]) -> Option<T>,
+    ) -> Result<T, SourceError> {
+        let mut buf = vec![0u8; MAX_DATAGRAM];
+        for _ in 0..=retries {
+            socket.send(bytes).await?;
+            let deadline = tokio::time::Instant::now() + timeout;
+            while let Ok(received) = tokio::time::timeout_at(deadline, socket.recv(&mut buf)).await
+            {
+                // Stale replies to earlier attempts and foreign datagrams are skipped
+                if let Some(reply) = accept(&buf[..received?]) {
+                    return Ok(reply);
+                }
+            }
+        }
+        Err(SourceError::Io(std::io::Error::new(
+            std::io::ErrorKind::TimedOut,
+            "no reply from SNMP agent",
+        )))
+    }
+
+    async fn request(&mut self, pdu: Pdu) -> Result<Pdu, SourceError> {
+        match self.settings.version {
+            SnmpVersion::V2c => self.request_community(pdu).await,
+            SnmpVersion::V3 => self.request_usm(pdu).await,
+        }
+    }
+
+    async fn request_community(&mut self, pdu: Pdu) -> Result<Pdu, SourceError> {
+        let socket = self.socket.as_ref().ok_or(SourceError::NotConnected)?;
+        let community = self.settings.community.as_bytes();
+        let bytes = message::encode_community(community, &pdu);
+        Self::send_and_wait(socket, &bytes, self.timeout, self.retries, |reply| {
+            message::decode_community(reply)
+                .ok()
+                .filter(|(c, reply)| {
+                    c == community && reply.kind == RESPONSE && reply.request_id == pdu.request_id
+                })
+                .map(|(_, reply)| reply)
+        })
+        .await
+    }
+
+    /// Current view of the agent's clock, extrapolated from the last sync.
+    fn engine_now(&self) -> EngineInfo {
+        EngineInfo {
+            time: self
+                .engine
+                .time
+                .saturating_add(self.engine_synced.elapsed().as_secs() as u32),
+            ..self.engine.clone()
+        }
+    }
+
+    fn sync_engine(&mut self, engine: EngineInfo) {
+        if engine.id != self.engine.id {
+            if let Some(user) = self.user.as_mut() {
+                user.localize(&engine.id);
+            }
+        }
+        self.engine = engine;
+        self.engine_synced = Instant::now();
+    }
+
+    /// Learns the agent's engine id, boots and time (RFC 3414 4).
+    async fn discover(&mut self) -> Result<(), SourceError> {
+        let msg_id = self.next_id();
+        let socket = self.socket.as_ref().ok_or(SourceError::NotConnected)?;
+        let probe = Pdu::request(GET, msg_id, &[]);
+        let bytes = message::encode_v3(None, msg_id, &EngineInfo::default(), &probe, true, 0)?;
+        let reply = Self::send_and_wait(socket, &bytes, self.timeout, self.retries, |reply| {
+            message::decode_v3(reply, None)
+                .ok()
+                .filter(|m| m.msg_id == msg_id)
+        })
+        .await?;
+        if reply.engine.id.is_empty() {
+            return Err(SourceError::Protocol(
+                "agent did not disclose its engine id".to_string(),
+            ));
+        }
+        self.sync_engine(reply.engine);
+        Ok(())
+    }
+
+    async fn request_usm(&mut self, pdu: Pdu) -> Result<Pdu, SourceError> {
+        // One retry after the agent corrects our engine id or clock
+        for attempt in 0..2 {
+            let socket = self.socket.as_ref().ok_or(SourceError::NotConnected)?;
+            let user = self.user.as_ref().ok_or(SourceError::NotConnected)?;
+            self.next_id = self.next_id.wrapping_add(1) & 0x7fff_ffff;
+            let msg_id = self.next_id;
+            self.salt = self.salt.wrapping_add(1);
+            let engine = self.engine_now();
+            let expects_auth = user.flags() & FLAG_AUTH != 0;
+            let bytes = message::encode_v3(Some(user), msg_id, &engine, &pdu, true, self.salt)?;
+            let reply = Self::send_and_wait(socket, &bytes, self.timeout, self.retries, |reply| {
+                message::decode_v3(reply, Some(user))
+                    .ok()
+                    .filter(|m| m.msg_id == msg_id)
+            })
+            .await?;
+
+            if reply.pdu.kind == REPORT {
+                let counter =
+                    reply.pdu.varbinds.first().and_then(|(oid, _)| {
+                        oid.suffix(&Oid::parse(USM_STATS)).map(<[u32]>::to_vec)
+                    });
+                match counter.as_deref() {
+                    // notInTimeWindows, unknownEngineIDs
+                    Some([2, 0]) | Some([4, 0]) if attempt == 0 => {
+                        self.sync_engine(reply.engine);
+                        continue;
+                    }
+                    // unknownUserNames, wrongDigests, decryptionErrors
+                    Some([3, 0]) | Some([5, 0]) | Some([6, 0]) => {
+                        return Err(SourceError::AuthFailed)
+                    }
+                    Some([1, 0]) => {
+                        return Err(SourceError::Protocol(
+                            "agent does not support the configured security level".to_string(),
+                        ))
+                    }
+                    _ => {
+                        return Err(SourceError::Protocol(format!(
+                            "agent sent report {}",
+                            reply
+                                .pdu
+                                .varbinds
+                                .first()
+                                .map(|(oid, _)| oid.to_string())
+                                .unwrap_or_default()
+                        )))
+                    }
+                }
+            }
+            if expects_auth && reply.flags & FLAG_AUTH == 0 {
+                return Err(SourceError::Protocol(
+                    "unauthenticated response to an authenticated request".to_string(),
+                ));
+            }
+            if reply.pdu.kind != RESPONSE || reply.pdu.request_id != pdu.request_id {
+                return Err(SourceError::Protocol("mismatched request id".to_string()));
+            }
+            return Ok(reply.pdu);
+        }
+        Err(SourceError::Protocol(
+            "agent keeps rejecting the engine time".to_string(),
+        ))
+    }
+
+    /// GET in chunks; objects the agent lacks come back as exception values.
+    pub async fn get(&mut self, oids: &[Oid]) -> Result<Vec<(Oid, Value)>, SourceError> {
+        let mut values = Vec::with_capacity(oids.len());
+        for chunk in oids.chunks(mib::GET_CHUNK) {
+            let id = self.next_id();
+            let reply = self.request(Pdu::request(GET, id, chunk)).await?;
+            if reply.error_status != 0 {
+                return Err(SourceError::CommandFailed(format!(
+                    "SNMP {}",
+                    message::error_name(reply.error_status)
+                )));
+            }
+            values.extend(reply.varbinds);
+        }
+        Ok(values)
+    }
+
+    /// Walks the subtree under `root` with GETBULK, returning at most `limit` rows.
+    pub async fn walk(
+        &mut self,
+        root: &Oid,
+        limit: usize,
+    ) -> Result<Vec<(Oid, Value)>, SourceError> {
+        let mut rows = Vec::new();
+        let mut cursor = root.clone();
+        while rows.len() < limit {
+            let id = self.next_id();
+            let reply = self
+                .request(Pdu::get_bulk(id, &cursor, BULK_REPETITIONS))
+                .await?;
+            if reply.error_status != 0 {
+                return Err(SourceError::CommandFailed(format!(
+                    "SNMP {}",
+                    message::error_name(reply.error_status)
+                )));
+            }
+            let mut advanced = false;
+            for (oid, value) in reply.varbinds {
+                if !oid.starts_with(root) || value == Value::EndOfMibView || oid <= cursor {
+                    return Ok(rows);
+                }
+                cursor = oid.clone();
+                rows.push((oid, value));
+                advanced = true;
+            }
+            if !advanced {
+                break;
+            }
+        }
+        rows.truncate(limit);
+        Ok(rows)
+    }
+}
+
+#[async_trait]
+impl UpsSource for SnmpClient {
+    async fn connect(&mut self) -> Result<(), SourceError> {
+        self.socket = None;
+        let addr = tokio::net::lookup_host((self.host.as_str(), self.port))
+            .await?
+            .next()
+            .ok_or_else(|| {
+                SourceError::Io(std::io::Error::new(
+                    std::io::ErrorKind::NotFound,
+                    format!("cannot resolve {}", self.host),
+                ))
+            })?;
+        let socket = UdpSocket::bind(if addr.is_ipv4() {
+            "0.0.0.0:0"
+        } else {
+            "[::]:0"
+        })
+        .await?;
+        socket.connect(addr).await?;
+        self.socket = Some(socket);
+
+        let result = async {
+            if self.settings.version == SnmpVersion::V3 {
+                if self.user.is_none() {
+                    self.user = Some(UsmUser::new(
+                        &self.username,
+                        self.settings.auth_protocol,
+                        self.settings.priv_protocol,
+                        self.password.as_deref().unwrap_or(""),
+                    )?);
+                    self.engine = EngineInfo::default();
+                }
+                self.discover().await?;
+            }
+            // UDP has no handshake: a first GET proves the agent answers us
+            let sys_object_id = Oid::parse(mib::SYS_OBJECT_ID);
+            let reply = self.get(std::slice::from_ref(&sys_object_id)).await?;
+            self.vendor = match reply.first() {
+                Some((_, Value::Oid(id))) => Vendor::detect(id),
+                _ => None,
+            };
+            Ok(())
+        }
+        .await;
+        if result.is_err() {
+            self.socket = None;
+        }
+        result
+    }
+
+    async fn disconnect(&mut self) -> Result<(), SourceError> {
+        self.socket = None;
+        Ok(())
+    }
+
+    /// An agent describes a single UPS, so `ups_name` is ignored.
+    async fn fetch_data(&mut self, _ups_name: &str) -> Result<UpsData, SourceError> {
+        let mut readings = Readings::new(self.vendor);
+        for (oid, value) in self.get(&mib::scalar_oids()).await? {
+            readings.insert(oid, value);
+        }
+        let (input_lines, output_lines) = readings.line_counts();
+        for (oid, value) in self.get(&mib::line_oids(input_lines, output_lines)).await? {
+            readings.insert(oid, value);
+        }
+        if readings.alarms_present() {
+            readings.alarms = self
+                .walk(&mib::alarm_descr_column(), MAX_ALARM_ROWS)
+                .await?
+                .into_iter()
+                .filter_map(|(_, value)| match value {
+                    Value::Oid(alarm) => Some(alarm),
+                    _ => None,
+                })
+                .collect();
+        }
+        if let Some(vendor) = self.vendor {
+            for (oid, value) in self.get(&vendor.oids()).await? {
+                readings.insert(oid, value);
+            }
+        }
+        if readings.is_empty() {
+            return Err(SourceError::Unsupported(
+                "agent exposes neither the UPS-MIB nor a known vendor MIB".to_string(),
+            ));
+        }
+        Ok(mib::map_readings(&readings))
+    }
+
+    async fn list_commands(&mut self, _ups_name: &str) -> Result<Vec<String>, SourceError> {
+        Ok(Vec::new())
+    }
+
+    async fn run_command(&mut self, _ups_name: &str, command: &str) -> Result<(), SourceError> {
+        Err(SourceError::Unsupported(format!(
+            "the SNMP backend is read-only ({command})"
+        )))
+    }
+
+    fn metadata(&self) -> SourceMetadata {
+        SourceMetadata {
+            backend: "snmp",
+            endpoint: format!("{}:{}", self.host, self.port),
+            supports_commands: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::agent::SnmpAgent;
+    use super::*;
+
+    fn settings(version: SnmpVersion, auth: AuthProtocol, privacy: PrivProtocol) -> SnmpSettings {
+        SnmpSettings {
+            version,
+            community: "public".to_string(),
+            auth_protocol: auth,
+            priv_protocol: privacy,
+        }
+    }
+
+    fn client(agent: &SnmpAgent, settings: SnmpSettings) -> SnmpClient {
+        SnmpClient::new("127.0.0.1".to_string(), agent.port(), settings)
+            .with_timeout(Duration::from_millis(300), 1)
+    }
+
+    #[tokio::test]
+    async fn reads_the_ups_mib_over_v2c() {
+        let agent = SnmpAgent::start("public", None).await;
+        agent.load_ups_mib();
+        let mut snmp = client(
+            &agent,
+            settings(SnmpVersion::V2c, AuthProtocol::None, PrivProtocol::None),
+        );
+        snmp.connect().await.unwrap();
+
+        let data = snmp.fetch_data("ignored").await.unwrap();
+        assert_eq!(data.status, "OL");
+        assert_eq!(data.ups_mfr.as_deref(), Some("Generic"));
+        assert_eq!(data.battery_charge, Some(100.0));
+        assert_eq!(data.battery_runtime, Some(2400.0));
+        assert_eq!(data.battery_voltage, Some(27.2));
+        assert_eq!(data.input_voltage, Some(231.0));
+        assert_eq!(data.input_frequency, Some(50.0));
+        assert_eq!(data.ups_load, Some(22.0));
+        assert_eq!(data.ups_realpower, Some(198.0));
+        assert_eq!(data.ups_realpower_nominal, Some(900.0));
+        assert_eq!(data.extended_vars["input.transfer.low"], "170");
+        assert_eq!(data.extended_vars["battery.runtime.low"], "120");
+
+        // Outage with a bad battery reported through the alarm table
+        agent.set("1.3.6.1.2.1.33.1.4.1.0", Value::Integer(5));
+        agent.set("1.3.6.1.2.1.33.1.6.1.0", Value::Integer(2));
+        agent.set(
+            "1.3.6.1.2.1.33.1.6.2.1.2.1",
+            Value::Oid(Oid::parse("1.3.6.1.2.1.33.1.6.3.2")),
+        );
+        agent.set(
+            "1.3.6.1.2.1.33.1.6.2.1.2.2",
+            Value::Oid(Oid::parse("1.3.6.1.2.1.33.1.6.3.1")),
+        );
+        let data = snmp.fetch_data("ignored").await.unwrap();
+        assert_eq!(data.status, "OB DISCHRG RB");
+        assert_eq!(data.extended_vars["ups.alarm"], "OnBattery BatteryBad");
+
+        assert!(snmp.list_commands("ups").await.unwrap().is_empty());
+        assert!(matches!(
+            snmp.run_command("ups", "test.battery.start").await,
+            Err(SourceError::Unsupported(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn wrong_community_times_out() {
+        let agent = SnmpAgent::start("secret", None).await;
+        agent.load_ups_mib();
+        let mut snmp = client(
+            &agent,
+            settings(SnmpVersion::V2c, AuthProtocol::None, PrivProtocol::None),
+        );
+        assert!(matches!(snmp.connect().await, Err(SourceError::Io(_))));
+        assert!(matches!(
+            snmp.fetch_data("ups").await,
+            Err(SourceError::NotConnected)
+        ));
+    }
+
+    #[tokio::test]
+    async fn reads_over_v3_at_every_security_level() {
+        for (auth, privacy) in [
+            (AuthProtocol::None, PrivProtocol::None),
+            (AuthProtocol::Md5, PrivProtocol::Des),
+            (AuthProtocol::Sha, PrivProtocol::Aes),
+        ] {
+            let user = UsmUser::new("monitor", auth, privacy, "battery-staple").unwrap();
+            let agent = SnmpAgent::start("public", Some(user)).await;
+            agent.load_ups_mib();
+            let mut snmp = client(&agent, settings(SnmpVersion::V3, auth, privacy))
+                .with_credentials(
+                    Some("monitor".to_string()),
+                    Some("battery-staple".to_string()),
+                );
+            snmp.connect().await.unwrap();
+            let data = snmp.fetch_data("ups").await.unwrap();
+            assert_eq!(data.battery_charge, Some(100.0), "{auth:?}/{privacy:?}");
+            assert_eq!(data.status, "OL");
+        }
+    }
+
+    #[tokio::test]
+    async fn v3_wrong_passphrase_is_an_auth_failure() {
+        let user = UsmUser::new(
+            "monitor",
+            AuthProtocol::Sha,
+            PrivProtocol::Aes,
+            "battery-staple",
+        )
+        .unwrap();
+        let agent = SnmpAgent::start("public", Some(user)).await;
+        agent.load_ups_mib();
+        let mut snmp = client(
+            &agent,
+            settings(SnmpVersion::V3, AuthProtocol::Sha, PrivProtocol::Aes),
+        )
+        .with_credentials(
+            Some("monitor".to_string()),
+            Some("wrong-staple".to_string()),
+        );
+        assert!(matches!(snmp.connect().await, Err(SourceError::AuthFailed)));
+
+        let mut snmp = client(
+            &agent,
+            settings(SnmpVersion::V3, AuthProtocol::Sha, PrivProtocol::Aes),
+        )
+        .with_credentials(
+            Some("intruder".to_string()),
+            Some("battery-staple".to_string()),
+        );
+        assert!(matches!(snmp.connect().await, Err(SourceError::AuthFailed)));
+    }
+
+    #[tokio::test]
+    async fn apc_powernet_fills_in_without_the_ups_mib() {
+        let agent = SnmpAgent::start("public", None).await;
+        agent.set(
+            mib::SYS_OBJECT_ID,
+            Value::Oid(Oid::parse("1.3.6.1.4.1.318.1.3.27")),
+        );
+        let apc = |arcs: &str| format!("1.3.6.1.4.1.318.1.1.1.{arcs}");
+        agent.set(
+            &apc("1.1.1.0"),
+            Value::OctetString(b"Smart-UPS 1500".to_vec()),
+        );
+        agent.set(
+            &apc("1.2.3.0"),
+            Value::OctetString(b"AS1234567890".to_vec()),
+        );
+        agent.set(&apc("2.1.3.0"), Value::OctetString(b"04/18/23".to_vec()));
+        agent.set(&apc("2.2.1.0"), Value::Gauge32(64));
+        agent.set(&apc("2.2.3.0"), Value::TimeTicks(180_000));
+        agent.set(&apc("2.2.4.0"), Value::Integer(2));
+        agent.set(&apc("3.2.1.0"), Value::Gauge32(0));
+        agent.set(&apc("4.1.1.0"), Value::Integer(3));
+        agent.set(&apc("4.2.3.0"), Value::Gauge32(35));
+
+        let mut snmp = client(
+            &agent,
+            settings(SnmpVersion::V2c, AuthProtocol::None, PrivProtocol::None),
+        );
+        snmp.connect().await.unwrap();
+        let data = snmp.fetch_data("ups").await.unwrap();
+        assert_eq!(data.status, "OB DISCHRG RB");
+        assert_eq!(data.ups_mfr.as_deref(), Some("APC"));
+        assert_eq!(data.ups_model.as_deref(), Some("Smart-UPS 1500"));
+        assert_eq!(data.ups_serial.as_deref(), Some("AS1234567890"));
+        assert_eq!(data.battery_charge, Some(64.0));
+        assert_eq!(data.battery_runtime, Some(1800.0));
+        assert_eq!(data.ups_load, Some(35.0));
+        assert_eq!(data.extended_vars["battery.date"], "04/18/23");
+    }
+
+    #[tokio::test]
+    async fn eaton_xups_adds_what_the_ups_mib_lacks() {
+        let agent = SnmpAgent::start("public", None).await;
+        agent.load_ups_mib();
+        agent.set(
+            mib::SYS_OBJECT_ID,
+            Value::Oid(Oid::parse("1.3.6.1.4.1.534.1")),
+        );
+        agent.set("1.3.6.1.4.1.534.1.6.1.0", Value::Integer(24));
+        agent.set("1.3.6.1.4.1.534.1.2.5.0", Value::Integer(1));
+        agent.set("1.3.6.1.2.1.33.1.2.4.0", Value::Integer(80));
+        // The UPS-MIB value wins over the vendor one
+        agent.set("1.3.6.1.4.1.534.1.2.4.0", Value::Integer(79));
+
+        let mut snmp = client(
+            &agent,
+            settings(SnmpVersion::V2c, AuthProtocol::None, PrivProtocol::None),
+        );
+        snmp.connect().await.unwrap();
+        let data = snmp.fetch_data("ups").await.unwrap();
+        assert_eq!(data.ambient_temp, Some(24.0));
+        assert_eq!(data.battery_charge, Some(80.0));
+        assert_eq!(data.status, "OL CHRG");
+    }
+}
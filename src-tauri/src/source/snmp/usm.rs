@@ -0,0 +1,277 @@
+//! User-based Security Model keys and transforms: password to key conversion
+//! and localization (RFC 3414 A.2), HMAC-MD5-96 / HMAC-SHA-96 authentication,
+//! CBC-DES (RFC 3414 8.1) and CFB128-AES-128 (RFC 3826) privacy.
+
+use super::message::{EngineInfo, FLAG_AUTH, FLAG_PRIV};
+use crate::source::SourceError;
+use aes::cipher::{AsyncStreamCipher, BlockDecryptMut, BlockEncryptMut, KeyIvInit};
+use hmac::{Hmac, Mac};
+use md5::Md5;
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+
+/// Length of the truncated HMAC carried in `msgAuthenticationParameters`.
+pub const AUTH_PARAMS_LEN: usize = 12;
+
+/// RFC 3414 requires passphrases of at least 8 characters.
+const MIN_PASSPHRASE_LEN: usize = 8;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum AuthProtocol {
+    #[default]
+    None,
+    Md5,
+    Sha,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PrivProtocol {
+    #[default]
+    None,
+    Des,
+    Aes,
+}
+
+/// Stretches a passphrase into a key by hashing 1 MiB of it repeated.
+fn password_to_key<D: Digest>(password: &[u8]) -> Vec<u8> {
+    let mut hasher = D::new();
+    let mut chunk = [0u8; 64];
+    let mut index = 0;
+    for _ in 0..(1 << 20) / 64 {
+        for byte in chunk.iter_mut() {
+            *byte = password[index % password.len()];
+            index += 1;
+        }
+        hasher.update(chunk);
+    }
+    hasher.finalize().to_vec()
+}
+
+/// Binds a key to one authoritative engine: H(Ku || engineID || Ku).
+fn localize<D: Digest>(key: &[u8], engine_id: &[u8]) -> Vec<u8> {
+    let mut hasher = D::new();
+    hasher.update(key);
+    hasher.update(engine_id);
+    hasher.update(key);
+    hasher.finalize().to_vec()
+}
+
+/// A USM user with its master keys. Keys are derived once at construction (the
+/// 1 MiB hash is slow in debug builds) and localized per engine.
+pub struct UsmUser {
+    pub name: String,
+    auth: AuthProtocol,
+    privacy: PrivProtocol,
+    auth_master: Vec<u8>,
+    priv_master: Vec<u8>,
+    auth_key: Vec<u8>,
+    priv_key: Vec<u8>,
+}
+
+impl UsmUser {
+    /// Creates a user whose authentication and privacy keys both come from `passphrase`.
+    pub fn new(
+        name: &str,
+        auth: AuthProtocol,
+        privacy: PrivProtocol,
+        passphrase: &str,
+    ) -> Result<Self, SourceError> {
+        if privacy != PrivProtocol::None && auth == AuthProtocol::None {
+            return Err(SourceError::Protocol(
+                "SNMPv3 privacy requires an authentication protocol".to_string(),
+            ));
+        }
+        if auth != AuthProtocol::None && passphrase.chars().count() < MIN_PASSPHRASE_LEN {
+            return Err(SourceError::Protocol(format!(
+                "SNMPv3 passphrases must be at least {MIN_PASSPHRASE_LEN} characters"
+            )));
+        }
+        let master = match auth {
+            AuthProtocol::None => Vec::new(),
+            AuthProtocol::Md5 => password_to_key::<Md5>(passphrase.as_bytes()),
+            AuthProtocol::Sha => password_to_key::<Sha1>(passphrase.as_bytes()),
+        };
+        Ok(Self {
+            name: name.to_string(),
+            auth,
+            privacy,
+            auth_master: master.clone(),
+            priv_master: master,
+            auth_key: Vec::new(),
+            priv_key: Vec::new(),
+        })
+    }
+
+    pub fn flags(&self) -> u8 {
+        match (self.auth, self.privacy) {
+            (AuthProtocol::None, _) => 0,
+            (_, PrivProtocol::None) => FLAG_AUTH,
+            _ => FLAG_AUTH | FLAG_PRIV,
+        }
+    }
+
+    /// Derives the keys used with the engine identified by `engine_id`.
+    pub fn localize(&mut self, engine_id: &[u8]) {
+        let derive = |master: &[u8]| match self.auth {
+            AuthProtocol::None => Vec::new(),
+            AuthProtocol::Md5 => localize::<Md5>(master, engine_id),
+            AuthProtocol::Sha => localize::<Sha1>(master, engine_id),
+        };
+        self.auth_key = derive(&self.auth_master);
+        self.priv_key = derive(&self.priv_master);
+    }
+
+    fn require_keys(&self) -> Result<(), SourceError> {
+        if self.auth_key.is_empty() {
+            return Err(SourceError::Protocol(
+                "SNMPv3 keys have not been localized".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Truncated HMAC of a whole message whose auth parameters are zeroed.
+    pub fn sign(&self, message: &[u8]) -> Result<Vec<u8>, SourceError> {
+        self.require_keys()?;
+        let digest = match self.auth {
+            AuthProtocol::None => return Ok(Vec::new()),
+            AuthProtocol::Md5 => {
+                let mut mac = Hmac::<Md5>::new_from_slice(&self.auth_key)
+                    .expect("HMAC accepts any key length");
+                mac.update(message);
+                mac.finalize().into_bytes().to_vec()
+            }
+            AuthProtocol::Sha => {
+                let mut mac = Hmac::<Sha1>::new_from_slice(&self.auth_key)
+                    .expect("HMAC accepts any key length");
+                mac.update(message);
+                mac.finalize().into_bytes().to_vec()
+            }
+        };
+        Ok(digest[..AUTH_PARAMS_LEN].to_vec())
+    }
+
+    /// Encrypts a scoped PDU, returning the ciphertext and `msgPrivacyParameters`.
+    pub fn encrypt(
+        &self,
+        engine: &EngineInfo,
+        salt: u64,
+        mut plaintext: Vec<u8>,
+    ) -> Result<(Vec<u8>, Vec<u8>), SourceError> {
+        self.require_keys()?;
+        match self.privacy {
+            PrivProtocol::None => Ok((plaintext, Vec::new())),
+            PrivProtocol::Des => {
+                // The salt is engineBoots followed by a local counter
+                let mut params = engine.boots.to_be_bytes().to_vec();
+                params.extend_from_slice(&(salt as u32).to_be_bytes());
+                let iv = self.des_iv(&params);
+                let padded = plaintext.len().div_ceil(8) * 8;
+                plaintext.resize(padded, 0);
+                cbc::Encryptor::<des::Des>::new_from_slices(&self.priv_key[..8], &iv)
+                    .map_err(|_| SourceError::Protocol("invalid DES key".to_string()))?
+                    .encrypt_padded_mut::<aes::cipher::block_padding::NoPadding>(
+                        &mut plaintext,
+                        padded,
+                    )
+                    .map_err(|_| SourceError::Protocol("DES encryption failed".to_string()))?;
+                Ok((plaintext, params))
+            }
+            PrivProtocol::Aes => {
+                let params = salt.to_be_bytes().to_vec();
+                let iv = aes_iv(engine, &params);
+                cfb_mode::Encryptor::<aes::Aes128>::new_from_slices(&self.priv_key[..16], &iv)
+                    .map_err(|_| SourceError::Protocol("invalid AES key".to_string()))?
+                    .encrypt(&mut plaintext);
+                Ok((plaintext, params))
+            }
+        }
+    }
+
+    pub fn decrypt(
+        &self,
+        engine: &EngineInfo,
+        params: &[u8],
+        ciphertext: &[u8],
+    ) -> Result<Vec<u8>, SourceError> {
+        self.require_keys()?;
+        let mut buf = ciphertext.to_vec();
+        let failed = || SourceError::Protocol("SNMPv3 decryption failed".to_string());
+        match self.privacy {
+            PrivProtocol::None => {}
+            PrivProtocol::Des => {
+                if params.len() != 8 || !buf.len().is_multiple_of(8) {
+                    return Err(failed());
+                }
+                let iv = self.des_iv(params);
+                cbc::Decryptor::<des::Des>::new_from_slices(&self.priv_key[..8], &iv)
+                    .map_err(|_| failed())?
+                    .decrypt_padded_mut::<aes::cipher::block_padding::NoPadding>(&mut buf)
+                    .map_err(|_| failed())?;
+            }
+            PrivProtocol::Aes => {
+                if params.len() != 8 {
+                    return Err(failed());
+                }
+                let iv = aes_iv(engine, params);
+                cfb_mode::Decryptor::<aes::Aes128>::new_from_slices(&self.priv_key[..16], &iv)
+                    .map_err(|_| failed())?
+                    .decrypt(&mut buf);
+            }
+        }
+        Ok(buf)
+    }
+
+    /// The second half of the DES privacy key is the pre-IV, XORed with the salt.
+    fn des_iv(&self, salt: &[u8]) -> Vec<u8> {
+        self.priv_key[8..16]
+            .iter()
+            .zip(salt)
+            .map(|(a, b)| a ^ b)
+            .collect()
+    }
+}
+
+fn aes_iv(engine: &EngineInfo, salt: &[u8]) -> Vec<u8> {
+    let mut iv = engine.boots.to_be_bytes().to_vec();
+    iv.extend_from_slice(&engine.time.to_be_bytes());
+    iv.extend_from_slice(salt);
+    iv
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{b:02x}")).collect()
+    }
+
+    #[test]
+    fn derives_the_rfc_3414_sample_keys() {
+        let engine_id = [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2];
+
+        let md5 = password_to_key::<Md5>(b"maplesyrup");
+        assert_eq!(hex(&md5), "9faf3283884e92834ebc9847d8edd963");
+        assert_eq!(
+            hex(&localize::<Md5>(&md5, &engine_id)),
+            "526f5eed9fcce26f8964c2930787d82b"
+        );
+
+        let sha = password_to_key::<Sha1>(b"maplesyrup");
+        assert_eq!(hex(&sha), "9fb5cc0381497b3793528939ff788d5d79145211");
+        assert_eq!(
+            hex(&localize::<Sha1>(&sha, &engine_id)),
+            "6695febc9288e36282235fc7151f128497b38f3f"
+        );
+    }
+
+    #[test]
+    fn rejects_weak_or_inconsistent_settings() {
+        assert!(UsmUser::new("u", AuthProtocol::Sha, PrivProtocol::Aes, "short").is_err());
+        assert!(UsmUser::new("u", AuthProtocol::None, PrivProtocol::Des, "long enough").is_err());
+        assert!(UsmUser::new("u", AuthProtocol::None, PrivProtocol::None, "").is_ok());
+    }
+}
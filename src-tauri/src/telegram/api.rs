@@ -0,0 +1,167 @@
+//! The few Bot API methods the bot uses, over long polling.
+
+use reqwest::Url;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::time::Duration;
+use thiserror::Error;
+
+/// How long `getUpdates` waits on the server for something to happen.
+pub const POLL_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Error, Debug)]
+pub enum ApiError {
+    #[error("HTTP error: {0}")]
+    Http(reqwest::Error),
+    #[error("Bot API error: {0}")]
+    Api(String),
+}
+
+impl From<reqwest::Error> for ApiError {
+    /// Drops the request URL, which carries the bot token, so errors can be logged.
+    fn from(e: reqwest::Error) -> Self {
+        ApiError::Http(e.without_url())
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Update {
+    pub update_id: i64,
+    pub message: Option<Message>,
+    pub callback_query: Option<CallbackQuery>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Message {
+    pub message_id: i64,
+    pub chat: Chat,
+    pub text: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Chat {
+    pub id: i64,
+}
+
+/// A press on an inline keyboard button.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CallbackQuery {
+    pub id: String,
+    /// The message carrying the keyboard
+    pub message: Option<Message>,
+    pub data: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Button {
+    pub text: String,
+    pub callback_data: String,
+}
+
+#[derive(Deserialize)]
+struct Reply<T> {
+    ok: bool,
+    result: Option<T>,
+    description: Option<String>,
+}
+
+#[derive(Clone)]
+pub struct Api {
+    client: reqwest::Client,
+    /// `<server>/bot<token>/`
+    base: Url,
+}
+
+impl Api {
+    pub fn new(server: &str, token: &str) -> Result<Self, ApiError> {
+        // `./` keeps the token's colon from reading as a URL scheme
+        let base = Url::parse(&format!("{}/", server.trim_end_matches('/')))
+            .and_then(|url| url.join(&format!("./bot{token}/")))
+            .map_err(|e| ApiError::Api(format!("Invalid API URL: {e}")))?;
+        let client = reqwest::Client::builder()
+            .timeout(POLL_TIMEOUT + Duration::from_secs(10))
+            .build()?;
+        Ok(Self { client, base })
+    }
+
+    async fn call<T: DeserializeOwned>(&self, method: &str, params: Value) -> Result<T, ApiError> {
+        let url = self
+            .base
+            .join(method)
+            .map_err(|e| ApiError::Api(e.to_string()))?;
+        let reply: Reply<T> = self
+            .client
+            .post(url)
+            .json(&params)
+            .send()
+            .await?
+            .json()
+            .await?;
+        match reply {
+            Reply {
+                ok: true,
+                result: Some(result),
+                ..
+            } => Ok(result),
+            Reply { description, .. } => Err(ApiError::Api(
+                description.unwrap_or_else(|| format!("{method} failed")),
+            )),
+        }
+    }
+
+    /// Updates from `offset` on, waiting up to `POLL_TIMEOUT` for the first.
+    pub async fn get_updates(&self, offset: i64) -> Result<Vec<Update>, ApiError> {
+        self.call(
+            "getUpdates",
+            json!({
+                "offset": offset,
+                "timeout": POLL_TIMEOUT.as_secs(),
+                "allowed_updates": ["message", "callback_query"],
+            }),
+        )
+        .await
+    }
+
+    /// Sends plain text, with a row of inline buttons if any are given.
+    pub async fn send_message(
+        &self,
+        chat: i64,
+        text: &str,
+        buttons: Vec<Button>,
+    ) -> Result<Message, ApiError> {
+        let mut params = json!({ "chat_id": chat, "text": text });
+        if !buttons.is_empty() {
+            params["reply_markup"] = json!({ "inline_keyboard": [buttons] });
+        }
+        self.call("sendMessage", params).await
+    }
+
+    /// Stops the button's spinner; `text` shows as a short toast.
+    pub async fn answer_callback_query(&self, id: &str, text: &str) -> Result<bool, ApiError> {
+        self.call(
+            "answerCallbackQuery",
+            json!({ "callback_query_id": id, "text": text }),
+        )
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn errors_do_not_reveal_the_token() {
+        // A port nothing listens on
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let server = format!("http://{}", listener.local_addr().unwrap());
+        drop(listener);
+
+        let api = Api::new(&server, "123:secret").unwrap();
+        let error = api.get_updates(0).await.unwrap_err();
+        assert!(matches!(error, ApiError::Http(_)));
+        assert!(!error.to_string().contains("123:secret"), "{error}");
+        assert!(!format!("{error:?}").contains("123:secret"), "{error:?}");
+    }
+}
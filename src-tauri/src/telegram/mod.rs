@@ -0,0 +1,660 @@
+//! Telegram bot for whoever is on call. It answers `/status`, `/history <range>`,
+//! `/abort` and `/cmd <instcmd>` from the allowed chats only, working on the
+//! same source, database and shutdown tracker as the poll loop, and pushes
+//! notifications to those chats through `TelegramChannel`. Instant commands
+//! run only once confirmed with the button under the bot's reply.
+
+pub mod api;
+
+use crate::monitor::shutdown;
+use crate::monitor::Monitor;
+use crate::notify::{Channel, Notification};
+use crate::settings::{NotificationKind, TelegramSettings};
+use api::{Api, Button, CallbackQuery, Message, Update};
+use async_trait::async_trait;
+use chacha20poly1305::aead::{rand_core::RngCore, OsRng};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// How long an instant command waits for its confirmation.
+pub const CONFIRM_WINDOW: Duration = Duration::from_secs(60);
+/// Pause after a failed `getUpdates`, e.g. while offline.
+const RETRY_DELAY: Duration = Duration::from_secs(10);
+/// Longest range `/history` accepts.
+const MAX_HISTORY_HOURS: u64 = 24 * 365;
+/// Journal category for commands run from a chat.
+pub const JOURNAL_CATEGORY: &str = "command";
+
+const HELP: &str = "/status - current readings\n\
+/history 24h - summary of the last 24 hours (or 6h, 7d, 30d...)\n\
+/abort - cancel a pending shutdown\n\
+/cmd - list the UPS's instant commands\n\
+/cmd <command> - run one, after confirmation";
+
+/// An instant command waiting for its confirmation button.
+struct PendingCommand {
+    chat: i64,
+    command: String,
+    at: Instant,
+}
+
+#[derive(Clone)]
+pub struct Bot {
+    api: Api,
+    allowed_chats: Vec<i64>,
+}
+
+impl Bot {
+    pub fn new(api: Api, allowed_chats: Vec<i64>) -> Self {
+        Self { api, allowed_chats }
+    }
+
+    /// Answers commands about `ups_name` until the task is aborted.
+    pub async fn run(self, monitor: Monitor, ups_name: String) {
+        let mut session = Session {
+            bot: self,
+            monitor,
+            ups_name,
+            pending: HashMap::new(),
+        };
+        let mut offset = 0;
+        loop {
+            match session.bot.api.get_updates(offset).await {
+                Ok(updates) => {
+                    for update in updates {
+                        offset = update.update_id + 1;
+                        session.handle(update).await;
+                    }
+                }
+                Err(e) => {
+                    log::warn!("Telegram getUpdates failed: {}", e);
+                    tokio::time::sleep(RETRY_DELAY).await;
+                }
+            }
+        }
+    }
+}
+
+struct Session {
+    bot: Bot,
+    monitor: Monitor,
+    ups_name: String,
+    /// Instant commands awaiting confirmation, by token
+    pending: HashMap<String, PendingCommand>,
+}
+
+impl Session {
+    async fn handle(&mut self, update: Update) {
+        if let Some(message) = update.message {
+            self.on_message(message).await;
+        } else if let Some(query) = update.callback_query {
+            self.on_button(query).await;
+        }
+    }
+
+    fn allowed(&self, chat: i64) -> bool {
+        let allowed = self.bot.allowed_chats.contains(&chat);
+        if !allowed {
+            // Logged so the id can be copied into the allow-list
+            log::warn!("Ignoring Telegram chat {}, it is not allowed", chat);
+        }
+        allowed
+    }
+
+    async fn reply(&self, chat: i64, text: &str, buttons: Vec<Button>) {
+        if let Err(e) = self.bot.api.send_message(chat, text, buttons).await {
+            log::error!("Failed to answer Telegram chat {}: {}", chat, e);
+        }
+    }
+
+    async fn on_message(&mut self, message: Message) {
+        let chat = message.chat.id;
+        let Some(text) = message.text else {
+            return;
+        };
+        if !self.allowed(chat) {
+            return;
+        }
+        let mut words = text.split_whitespace();
+        // In groups commands come as `/status@SomeBot`
+        let command = words
+            .next()
+            .and_then(|c| c.split('@').next())
+            .unwrap_or_default();
+        let argument = words.next();
+        let answer = match command {
+            "/status" => self.status().await,
+            "/history" => self.history(argument.unwrap_or("24h")).await,
+            "/abort" => self.abort().await,
+            "/cmd" => match argument {
+                None => self.commands().await,
+                Some(name) => return self.prepare(chat, name).await,
+            },
+            "/start" | "/help" => HELP.to_string(),
+            _ => format!("Unknown command.\n\n{HELP}"),
+        };
+        self.reply(chat, &answer, Vec::new()).await;
+    }
+
+    async fn status(&self) -> String {
+        let mut source = self.monitor.source.lock().await;
+        let Some(source) = source.as_mut() else {
+            return "Not connected to a UPS.".to_string();
+        };
+        let data = match source.fetch_data(&self.ups_name).await {
+            Ok(data) => data,
+            Err(e) => return format!("Reading {} failed: {e}", self.ups_name),
+        };
+        let mut lines = vec![format!("{}: {}", self.ups_name, data.status)];
+        let mut battery = Vec::new();
+        if let Some(charge) = data.battery_charge {
+            battery.push(format!("Battery {charge:.0}%"));
+        }
+        if let Some(runtime) = data.battery_runtime {
+            battery.push(format!("{:.0} min left", runtime / 60.0));
+        }
+        let mut power = Vec::new();
+        if let Some(load) = data.ups_load {
+            power.push(format!("Load {load:.0}%"));
+        }
+        if let Some(v) = data.input_voltage {
+            power.push(format!("In {v:.0} V"));
+        }
+        if let Some(v) = data.output_voltage {
+            power.push(format!("Out {v:.0} V"));
+        }
+        lines.extend([battery, power].map(|parts| parts.join(", ")));
+        let tracker = self.monitor.shutdown.lock().await;
+        if tracker.pending {
+            lines.push(format!(
+                "{} in {} s, /abort to cancel",
+                tracker.action_type, tracker.countdown_remaining
+            ));
+        }
+        lines.retain(|l| !l.is_empty());
+        lines.join("\n")
+    }
+
+    async fn history(&self, range: &str) -> String {
+        let Some(hours) = parse_range(range) else {
+            return "Give the range as hours or days, e.g. /history 6h or /history 7d".to_string();
+        };
+        let db = self.monitor.db.lock().await;
+        let Some(db) = db.as_ref() else {
+            return "History is not available.".to_string();
+        };
+        match db.get_history_stats(hours) {
+            Ok(stats) if stats.data_points == 0 => format!("No readings in the last {range}."),
+            Ok(stats) => format!(
+                "Last {range}, {} readings\n\
+                 Input {:.0}-{:.0} V, avg {:.0} V\n\
+                 Output {:.0}-{:.0} V, avg {:.0} V\n\
+                 Load max {:.0}%, avg {:.0}%\n\
+                 Battery min {:.0}%, avg {:.0}%\n\
+                 Readings on battery: {}",
+                stats.data_points,
+                stats.min_input_voltage,
+                stats.max_input_voltage,
+                stats.avg_input_voltage,
+                stats.min_output_voltage,
+                stats.max_output_voltage,
+                stats.avg_output_voltage,
+                stats.max_load,
+                stats.avg_load,
+                stats.min_battery,
+                stats.avg_battery,
+                stats.outages,
+            ),
+            Err(e) => format!("Reading the history failed: {e}"),
+        }
+    }
+
+    async fn abort(&self) -> String {
+        {
+            let mut tracker = self.monitor.shutdown.lock().await;
+            if !tracker.pending {
+                return "No shutdown is pending.".to_string();
+            }
+            log::info!("Shutdown aborted from Telegram");
+            tracker.cancel();
+        }
+        if self.monitor.stops_system() {
+            if let Err(e) = shutdown::abort_system_stop().await {
+                return format!("Countdown cancelled, but the system stop could not be: {e}");
+            }
+        }
+        "Shutdown aborted. It is scheduled again if the UPS still meets the shutdown conditions."
+            .to_string()
+    }
+
+    async fn commands(&self) -> String {
+        let mut source = self.monitor.source.lock().await;
+        let Some(source) = source.as_mut() else {
+            return "Not connected to a UPS.".to_string();
+        };
+        match source.list_commands(&self.ups_name).await {
+            Ok(commands) if commands.is_empty() => {
+                "The UPS offers no instant commands.".to_string()
+            }
+            Ok(commands) => format!("Instant commands:\n{}", commands.join("\n")),
+            Err(e) => format!("Listing commands failed: {e}"),
+        }
+    }
+
+    /// Checks `command` against the device and asks for confirmation.
+    async fn prepare(&mut self, chat: i64, command: &str) {
+        let offered = {
+            let mut source = self.monitor.source.lock().await;
+            match source.as_mut() {
+                Some(source) => source.list_commands(&self.ups_name).await,
+                None => Ok(Vec::new()),
+            }
+        };
+        match offered {
+            Ok(commands) if commands.iter().any(|c| c == command) => {}
+            Ok(_) => {
+                let text = format!(
+                    "{} does not offer {command}. /cmd lists what it does.",
+                    self.ups_name
+                );
+                return self.reply(chat, &text, Vec::new()).await;
+            }
+            Err(e) => {
+                return self
+                    .reply(chat, &format!("Listing commands failed: {e}"), Vec::new())
+                    .await
+            }
+        }
+
+        let now = Instant::now();
+        self.pending
+            .retain(|_, p| now.saturating_duration_since(p.at) < CONFIRM_WINDOW);
+        let token = format!("{:016x}", OsRng.next_u64());
+        self.pending.insert(
+            token.clone(),
+            PendingCommand {
+                chat,
+                command: command.to_string(),
+                at: now,
+            },
+        );
+        let text = format!(
+            "Run {command} on {}? Confirm within {} s.",
+            self.ups_name,
+            CONFIRM_WINDOW.as_secs()
+        );
+        let buttons = vec![
+            Button {
+                text: format!("Run {command}"),
+                callback_data: format!("run:{token}"),
+            },
+            Button {
+                text: "Cancel".to_string(),
+                callback_data: format!("cancel:{token}"),
+            },
+        ];
+        self.reply(chat, &text, buttons).await;
+    }
+
+    async fn on_button(&mut self, query: CallbackQuery) {
+        let Some(chat) = query.message.as_ref().map(|m| m.chat.id) else {
+            return;
+        };
+        if !self.allowed(chat) {
+            return;
+        }
+        let data = query.data.unwrap_or_default();
+        let (action, token) = data.split_once(':').unwrap_or_default();
+        // Only the chat that asked can confirm, and only in time
+        let pending = self
+            .pending
+            .remove(token)
+            .filter(|p| p.chat == chat && p.at.elapsed() < CONFIRM_WINDOW);
+        let (toast, answer) = match (action, pending) {
+            (_, None) => ("Expired", None),
+            ("run", Some(pending)) => ("Running", Some(self.execute(&pending.command).await)),
+            (_, Some(pending)) => ("Cancelled", Some(format!("{} not run.", pending.command))),
+        };
+        if let Err(e) = self.bot.api.answer_callback_query(&query.id, toast).await {
+            log::error!("Failed to answer Telegram button: {}", e);
+        }
+        if let Some(answer) = answer {
+            self.reply(chat, &answer, Vec::new()).await;
+        }
+    }
+
+    /// Runs a confirmed command and journals the outcome.
+    async fn execute(&self, command: &str) -> String {
+        let result = {
+            let mut source = self.monitor.source.lock().await;
+            match source.as_mut() {
+                Some(source) => source
+                    .run_tracked_command(&self.ups_name, command)
+                    .await
+                    .map_err(|e| e.to_string()),
+                None => Err("not connected".to_string()),
+            }
+        };
+        let detail = match &result {
+            Ok(outcome) => outcome.to_string(),
+            Err(e) => format!("error: {e}"),
+        };
+        let summary = format!("{command} ({}) from Telegram", self.ups_name);
+        log::info!("{}: {}", summary, detail);
+        if let Some(db) = self.monitor.db.lock().await.as_ref() {
+            if let Err(e) = db.add_journal_entry(JOURNAL_CATEGORY, &summary, Some(&detail)) {
+                log::error!("Failed to write journal: {}", e);
+            }
+        }
+        format!("{command}: {detail}")
+    }
+}
+
+/// The bot and its notification channel, if it is enabled and has a token.
+pub fn from_settings(
+    config: &TelegramSettings,
+    token: Option<String>,
+) -> Option<(Bot, TelegramChannel)> {
+    if !config.enabled {
+        return None;
+    }
+    let Some(token) = token else {
+        log::error!("Telegram bot disabled: no bot token stored");
+        return None;
+    };
+    match Api::new(&config.api_url, &token) {
+        Ok(api) => Some((
+            Bot::new(api.clone(), config.allowed_chats.clone()),
+            TelegramChannel::new(api, config.allowed_chats.clone(), config.events.clone()),
+        )),
+        Err(e) => {
+            log::error!("Telegram bot disabled: {}", e);
+            None
+        }
+    }
+}
+
+/// Hours in a range like `12h` or `7d`.
+fn parse_range(range: &str) -> Option<u64> {
+    let hours = if let Some(count) = range.strip_suffix('h') {
+        count.parse().ok()?
+    } else {
+        let days: u64 = range.strip_suffix('d')?.parse().ok()?;
+        days.checked_mul(24)?
+    };
+    (1..=MAX_HISTORY_HOURS).contains(&hours).then_some(hours)
+}
+
+/// Pushes notifications to the bot's allowed chats.
+pub struct TelegramChannel {
+    api: Api,
+    chats: Vec<i64>,
+    events: Vec<NotificationKind>,
+}
+
+impl TelegramChannel {
+    pub fn new(api: Api, chats: Vec<i64>, events: Vec<NotificationKind>) -> Self {
+        Self { api, chats, events }
+    }
+}
+
+#[async_trait]
+impl Channel for TelegramChannel {
+    fn name(&self) -> &str {
+        "telegram"
+    }
+
+    async fn send(&self, notification: &Notification) -> Result<(), String> {
+        if !self.events.contains(&notification.kind) {
+            return Ok(());
+        }
+        let text = format!("{}\n{}", notification.title, notification.body);
+        for chat in &self.chats {
+            self.api
+                .send_message(*chat, text.trim(), Vec::new())
+                .await
+                .map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::NutDB;
+    use crate::monitor::history::HistoryRecorder;
+    use crate::monitor::shutdown::ShutdownTracker;
+    use crate::nut::client::NutClient;
+    use crate::nut::emulator::FakeUpsd;
+    use crate::source::UpsSource;
+    use serde_json::{json, Value};
+    use std::collections::VecDeque;
+    use std::sync::{Arc, Mutex};
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn parses_history_ranges() {
+        assert_eq!(parse_range("24h"), Some(24));
+        assert_eq!(parse_range("7d"), Some(168));
+        assert_eq!(parse_range("0h"), None);
+        assert_eq!(parse_range("2y"), None);
+        assert_eq!(parse_range("400d"), None);
+        assert_eq!(parse_range(""), None);
+        // Multi-byte units are rejected, not split inside a character
+        assert_eq!(parse_range("7д"), None);
+        assert_eq!(parse_range("😀"), None);
+    }
+
+    /// What the mock Bot API hands out and what it was sent.
+    #[derive(Default)]
+    struct BotApi {
+        updates: VecDeque<Value>,
+        next_update: i64,
+        /// `(method, params)` of every call but `getUpdates`
+        calls: Vec<(String, Value)>,
+    }
+
+    impl BotApi {
+        fn message(&mut self, chat: i64, text: &str) {
+            self.next_update += 1;
+            self.updates.push_back(json!({
+                "update_id": self.next_update,
+                "message": { "message_id": self.next_update, "chat": { "id": chat }, "text": text },
+            }));
+        }
+
+        fn button(&mut self, chat: i64, data: &str) {
+            self.next_update += 1;
+            self.updates.push_back(json!({
+                "update_id": self.next_update,
+                "callback_query": {
+                    "id": format!("cb{}", self.next_update),
+                    "message": { "message_id": 1, "chat": { "id": chat } },
+                    "data": data,
+                },
+            }));
+        }
+
+        fn texts(&self) -> Vec<String> {
+            self.calls
+                .iter()
+                .filter(|(method, _)| method == "sendMessage")
+                .map(|(_, params)| params["text"].as_str().unwrap().to_string())
+                .collect()
+        }
+    }
+
+    /// Serves the Bot API methods the bot uses, one request per connection.
+    async fn mock_bot_api(listener: TcpListener, state: Arc<Mutex<BotApi>>) {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut line = String::new();
+            reader.read_line(&mut line).await.unwrap();
+            let path = line.split_whitespace().nth(1).unwrap().to_string();
+            let mut length = 0;
+            loop {
+                line.clear();
+                reader.read_line(&mut line).await.unwrap();
+                match line.trim_end().split_once(": ") {
+                    Some((name, value)) if name.eq_ignore_ascii_case("content-length") => {
+                        length = value.parse().unwrap();
+                    }
+                    Some(_) => {}
+                    None => break,
+                }
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).await.unwrap();
+            let params: Value = serde_json::from_slice(&body).unwrap();
+
+            assert!(path.starts_with("/bot123:secret/"), "{path}");
+            let method = path.rsplit('/').next().unwrap().to_string();
+            let result = match method.as_str() {
+                "getUpdates" => {
+                    let next = state.lock().unwrap().updates.pop_front();
+                    if next.is_none() {
+                        tokio::time::sleep(Duration::from_millis(20)).await;
+                    }
+                    json!(next.into_iter().collect::<Vec<_>>())
+                }
+                "sendMessage" => json!({ "message_id": 99, "chat": { "id": params["chat_id"] } }),
+                _ => json!(true),
+            };
+            if method != "getUpdates" {
+                state.lock().unwrap().calls.push((method, params));
+            }
+            let body = json!({ "ok": true, "result": result }).to_string();
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            );
+            reader
+                .into_inner()
+                .write_all(response.as_bytes())
+                .await
+                .unwrap();
+        }
+    }
+
+    async fn replies(state: &Arc<Mutex<BotApi>>, count: usize) -> Vec<String> {
+        for _ in 0..250 {
+            let texts = state.lock().unwrap().texts();
+            if texts.len() >= count {
+                return texts;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        panic!(
+            "expected {count} replies, got {:?}",
+            state.lock().unwrap().texts()
+        );
+    }
+
+    #[tokio::test]
+    async fn answers_allowed_chats_and_confirms_instant_commands() {
+        let upsd = FakeUpsd::start().await;
+        let mut client = NutClient::new(upsd.config());
+        client.connect().await.unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let db = NutDB::new(dir.path());
+        db.init().unwrap();
+        let data = client.fetch_data("ups").await.unwrap();
+        HistoryRecorder::default().record(&db, &data, Instant::now());
+
+        let monitor = Monitor::new(
+            Arc::new(tokio::sync::Mutex::new(Some(
+                Box::new(client) as Box<dyn UpsSource>
+            ))),
+            Arc::new(tokio::sync::Mutex::new(Some(db))),
+            Arc::new(tokio::sync::Mutex::new(ShutdownTracker::default())),
+        )
+        .without_system_stop();
+        {
+            let mut tracker = monitor.shutdown.lock().await;
+            tracker.pending = true;
+            tracker.countdown_remaining = 42;
+            tracker.action_type = "Shutdown".to_string();
+        }
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = format!("http://{}", listener.local_addr().unwrap());
+        let state = Arc::new(Mutex::new(BotApi::default()));
+        let api_task = tokio::spawn(mock_bot_api(listener, state.clone()));
+        let bot = Bot::new(Api::new(&server, "123:secret").unwrap(), vec![42]);
+        let bot_task = tokio::spawn(bot.run(monitor.clone(), "ups".to_string()));
+
+        {
+            let mut api = state.lock().unwrap();
+            api.message(7, "/status");
+            api.message(42, "/status@UpsBot");
+            api.message(42, "/history 24h");
+            api.message(42, "/abort");
+            api.message(42, "/cmd beeper.disable");
+            api.message(42, "/cmd load.off");
+        }
+        let texts = replies(&state, 5).await;
+        assert_eq!(
+            texts[0],
+            "ups: OL\nBattery 100%, 30 min left\nLoad 25%, In 230 V, Out 230 V\nShutdown in 42 s, /abort to cancel"
+        );
+        assert!(
+            texts[1].starts_with("Last 24h, 1 readings\nInput 230-230 V"),
+            "{}",
+            texts[1]
+        );
+        assert!(texts[2].starts_with("Shutdown aborted."));
+        assert!(!monitor.shutdown.lock().await.pending);
+        assert_eq!(texts[3], "Run beeper.disable on ups? Confirm within 60 s.");
+        assert_eq!(
+            texts[4],
+            "ups does not offer load.off. /cmd lists what it does."
+        );
+        // Nothing runs before the confirmation, and chat 7 got no answer
+        assert!(upsd.instcmds().is_empty());
+        let prompt = state.lock().unwrap().calls[3].1.clone();
+        assert_eq!(prompt["chat_id"], 42);
+        let run = prompt["reply_markup"]["inline_keyboard"][0][0]["callback_data"]
+            .as_str()
+            .unwrap()
+            .to_string();
+
+        {
+            let mut api = state.lock().unwrap();
+            // Another chat cannot confirm it
+            api.button(43, &run);
+            api.button(42, &run);
+            // A token works once
+            api.button(42, &run);
+        }
+        let texts = replies(&state, 6).await;
+        assert_eq!(texts[5], "beeper.disable: succeeded");
+        assert_eq!(upsd.instcmds().len(), 1);
+        assert_eq!(upsd.instcmds()[0].1, "beeper.disable");
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let toasts: Vec<Value> = state
+            .lock()
+            .unwrap()
+            .calls
+            .iter()
+            .filter(|(method, _)| method == "answerCallbackQuery")
+            .map(|(_, params)| params["text"].clone())
+            .collect();
+        assert_eq!(toasts, [json!("Running"), json!("Expired")]);
+        let journal = monitor
+            .db
+            .lock()
+            .await
+            .as_ref()
+            .unwrap()
+            .get_journal(1)
+            .unwrap();
+        assert_eq!(journal[0].category, JOURNAL_CATEGORY);
+        assert_eq!(journal[0].message, "beeper.disable (ups) from Telegram");
+
+        bot_task.abort();
+        api_task.abort();
+    }
+}
@@ -9,63 +9,74 @@ import { useUpsData } from './hooks/useUpsData';
 import { useNotifications } from './hooks/useNotifications';
 import { Toaster, toast } from 'sonner';
 import { AlertTriangle } from 'lucide-react';
+import { AppSettings, NutConfig } from './types/ups';
 
 export default function App() {
   useUpsData();
   useNotifications();
-  const { config, setConnected, setSupportedCommands, shutdownConfig } = useUpsStore();
+  const { config, setConfig, setConnected, setSupportedCommands, shutdownConfig, setShutdownConfig } = useUpsStore();
   const { countdown } = useShutdownMonitor();
   const [view, setView] = useState<'dashboard' | 'history'>('dashboard');
 
-  // Auto-connect and start polling on mount if config exists
-  // Re-run if config or shutdownConfig changes?
-  // BEWARE: calling start_background_polling multiple times spawns multiple threads if not careful.
-  // Ideally, backend should be robust.
-  // For now, we only run if we think we are disconnected or init.
-  // But React.useEffect runs on dep change.
-  // We'll stick to original logic: run once on mount/config change.
-  // Warning: If shutdownConfig changes, this effect runs again. user might get double polling.
-  // We need to implement disconnect() before connect() if we want to be safe, but disconnect is async.
-
+  // The backend persists settings and resumes monitoring on its own at launch.
+  // On mount we only mirror its state into the store, and hand over a config
+  // that was saved by an older version in localStorage.
   useEffect(() => {
-    const autoConnect = async () => {
-      if (config && config.host && config.host.length > 0) {
-        try {
-          console.log("Auto-connecting to:", config.host);
-          await invoke('connect_nut', { config });
+    const syncWithBackend = async () => {
+      try {
+        let settings = await invoke<AppSettings>('get_settings');
 
-          // Pass shutdownConfig to backend
-          await invoke('start_background_polling', {
-            upsName: config.ups_name || 'ups',
-            intervalMs: 1000,
-            shutdownConfig: shutdownConfig // Pass the full config object
+        if (!settings.connection.host && config && config.host) {
+          console.log("Migrating saved connection to backend:", config.host);
+          // Older versions kept the password in localStorage; hand it to the keyring once
+          const legacyPassword = (config as NutConfig & { password?: string }).password;
+          settings = await invoke<AppSettings>('update_settings', {
+            settings: {
+              ...settings,
+              connection: {
+                host: config.host,
+                port: config.port,
+                username: config.username || null,
+                credentialId: null,
+                upsName: config.ups_name || 'ups',
+              },
+              shutdown: shutdownConfig,
+            },
+            password: legacyPassword || null,
           });
+        }
+
+        const conn = settings.connection;
+        if (!conn.host && conn.backend !== 'simulator') return;
 
-          try {
-            const cmds = await invoke<string[]>("list_ups_commands", { upsName: config.ups_name || "ups" });
-            setSupportedCommands(cmds);
-          } catch (e) {
-            console.warn("Command fetch failed:", e);
-          }
+        setConfig({
+          host: conn.host,
+          port: conn.port,
+          username: conn.username || undefined,
+          credential_id: conn.credentialId,
+          ups_name: conn.upsName,
+          backend: conn.backend ?? 'nut',
+          snmp: conn.snmp,
+        });
+        setShutdownConfig(settings.shutdown);
 
-          setConnected(true);
-        } catch (err) {
-          console.error("Auto-connect failed:", err);
-          // Don't toast here to avoid spam on startup
+        try {
+          const cmds = await invoke<string[]>("list_ups_commands", { upsName: conn.upsName });
+          setSupportedCommands(cmds);
+        } catch (e) {
+          console.warn("Command fetch failed:", e);
         }
+
+        setConnected(true);
+      } catch (err) {
+        console.error("Backend settings sync failed:", err);
+        // e.g. a settings file that failed to load, which must not go unnoticed
+        toast.error(`Settings sync failed: ${err}`, { duration: Infinity });
       }
     };
 
-    // Simple debounce or check?
-    // useUpsStore initializes config from localStorage.
-    autoConnect();
-
-    // Cleanup? invoke('disconnect_nut')?
-    // Effect cleanup runs on unmount or before re-run.
-    return () => {
-      // We usually don't want to disconnect on hot reload, but for production it's fine.
-    };
-  }, [config, shutdownConfig]); // Dependencies
+    syncWithBackend();
+  }, []);
 
   // Theme Management
   const { theme } = useUpsStore();
@@ -1,14 +1,16 @@
 import { Progress } from "@/components/ui/progress";
 import { Battery, BatteryCharging, BatteryWarning } from "lucide-react";
+import { RuntimeEstimate } from "../../types/ups";
 
 interface BatteryGaugeProps {
   percentage: number | undefined;
   status: string;
   voltage: number | undefined;
   runtime: number | undefined;
+  estimate?: RuntimeEstimate | null;
 }
 
-export function BatteryGauge({ percentage = 0, status, voltage, runtime }: BatteryGaugeProps) {
+export function BatteryGauge({ percentage = 0, status, voltage, runtime, estimate }: BatteryGaugeProps) {
   const isCharging = status.includes("OL") && percentage < 100;
   const isOnBattery = status.includes("OB");
 
@@ -25,6 +27,7 @@ export function BatteryGauge({ percentage = 0, status, voltage, runtime }: Batte
 
   const formatRuntime = (seconds: number | undefined) => {
     if (seconds === undefined || seconds <= 0) return "--:--:--";
+    seconds = Math.round(seconds);
     const h = Math.floor(seconds / 3600);
     const m = Math.floor((seconds % 3600) / 60);
     const s = seconds % 60;
@@ -66,6 +69,14 @@ export function BatteryGauge({ percentage = 0, status, voltage, runtime }: Batte
           <div className="flex items-baseline gap-1">
             <span className="text-sm font-bold text-foreground font-mono tracking-tight">{formatRuntime(runtime)}</span>
           </div>
+          {estimate && (
+            <span
+              className="text-[9px] text-muted-foreground font-mono"
+              title={`Predicted from ${estimate.samples} past discharges; 95% between ${formatRuntime(estimate.low)} and ${formatRuntime(estimate.high)}`}
+            >
+              Predicted {formatRuntime(estimate.runtime)}
+            </span>
+          )}
         </div>
       </div>
     </div>
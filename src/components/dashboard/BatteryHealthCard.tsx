@@ -0,0 +1,64 @@
+import { useEffect, useState } from 'react';
+import { invoke } from '@tauri-apps/api/core';
+import { HeartPulse } from 'lucide-react';
+import type { BatteryHealth } from '../../types/ups';
+
+interface BatteryHealthCardProps {
+  upsName: string;
+  /** Refetch when this changes, e.g. the UPS status flags */
+  status: string;
+}
+
+const REFRESH_MS = 15 * 60 * 1000;
+
+export function BatteryHealthCard({ upsName, status }: BatteryHealthCardProps) {
+  const [health, setHealth] = useState<BatteryHealth | null>(null);
+
+  useEffect(() => {
+    const load = () =>
+      invoke<BatteryHealth>('get_battery_health', { upsName })
+        .then(setHealth)
+        .catch(() => setHealth(null));
+    load();
+    const timer = setInterval(load, REFRESH_MS);
+    return () => clearInterval(timer);
+  }, [upsName, status]);
+
+  if (!health) return null;
+
+  const color = health.score >= 80 ? 'text-emerald-500' : health.score >= 50 ? 'text-orange-500' : 'text-destructive';
+  const last = health.discharges[health.discharges.length - 1];
+  const rows: [string, string][] = [
+    ['Capacity', health.capacity_percent != null ? `${health.capacity_percent.toFixed(0)}%` : '--'],
+    ['Fade', health.fade_per_month != null ? `${health.fade_per_month.toFixed(1)}%/mo` : '--'],
+    ['Installed', health.battery_date ? `${health.battery_date}${health.age_years != null ? ` (${health.age_years.toFixed(1)}y)` : ''}` : '--'],
+    ['Discharges', `${health.discharges.length}`],
+    ['Last Sag', last?.voltage_sag != null ? `${last.voltage_sag.toFixed(1)}V` : '--'],
+    ['Replace By', health.replace_by ? new Date(health.replace_by * 1000).toLocaleDateString() : '--'],
+  ];
+
+  return (
+    <div className="space-y-1">
+      <h3 className="text-[10px] font-bold tracking-[0.2em] text-muted-foreground uppercase mb-2 flex items-center justify-between">
+        Battery Health
+        <HeartPulse className="h-3 w-3 opacity-40" />
+      </h3>
+      <div className="flex items-baseline gap-1 px-1" title={health.replace_reason ?? undefined}>
+        <span className={`text-2xl font-mono font-bold ${color}`}>{health.score}</span>
+        <span className="text-[10px] text-muted-foreground">/ 100</span>
+        {health.replace_flag && (
+          <span className="ml-auto text-[9px] px-1.5 rounded-full font-bold uppercase bg-destructive/20 text-destructive">Replace</span>
+        )}
+      </div>
+      <dl className="space-y-1 px-1">
+        {rows.map(([label, value]) => (
+          <div key={label} className="flex justify-between gap-2 border-b border-border/10 pb-1">
+            <dt className="text-[10px] text-muted-foreground">{label}</dt>
+            <dd className="text-[10px] font-medium">{value}</dd>
+          </div>
+        ))}
+      </dl>
+      {health.replace_reason && <p className="text-[9px] text-muted-foreground/70 px-1">{health.replace_reason}</p>}
+    </div>
+  );
+}
@@ -9,15 +9,22 @@ import {
   Info,
   Settings,
   Database,
+  Zap,
+  Power,
 } from 'lucide-react';
 import { StatusCard } from './StatusCard';
 import { LoadChart } from './LoadChart';
 import { BatteryGauge } from './BatteryGauge';
+import { BatteryHealthCard } from './BatteryHealthCard';
 import { PowerStatus } from './PowerStatus';
+import { SimulationPanel } from './SimulationPanel';
 import { useUpsStore } from '../../store/upsStore';
 import { useShutdownMonitor } from '../../hooks/useShutdownMonitor';
 import { toast } from 'sonner';
 import { invoke } from '@tauri-apps/api/core';
+import { useEffect, useState } from 'react';
+import { POWER_METHOD_LABELS } from '../../types/ups';
+import type { CommandOutcome, OutletAction, OutletSwitch, VariableInfo } from '../../types/ups';
 
 export function DashboardView() {
   const { data, history, config, shutdownConfig, events, supportedCommands } = useUpsStore();
@@ -25,6 +32,38 @@ export function DashboardView() {
 
   const status = data?.status || "UNKNOWN";
 
+  const switchOutlet = async (index: number, action: OutletAction) => {
+    try {
+      const pending = await invoke<OutletSwitch>("prepare_outlet_switch", {
+        upsName: config?.ups_name || "ups",
+        index,
+        action,
+      });
+      const confirmed = confirm(`Switch "${pending.name}" ${action.toUpperCase()}? (${pending.command})`);
+      if (!confirmed) return;
+      const outcome = await invoke<CommandOutcome>("confirm_outlet_switch", { token: pending.token });
+      if (outcome.state === 'failed') {
+        toast.error(`${pending.name}: ${outcome.detail}`);
+      } else if (outcome.state === 'pending') {
+        toast.warning(`${pending.name}: sent, not yet confirmed by the UPS`);
+      } else {
+        toast.success(`${pending.name} switched ${action}`);
+      }
+    } catch (e) {
+      toast.error(`${e}`);
+    }
+  };
+
+  // Units and descriptions for the extended variables, refetched when the set of names changes
+  const [variableInfo, setVariableInfo] = useState<Record<string, VariableInfo>>({});
+  const extendedKeys = Object.keys(data?.extended_vars ?? {}).sort().join(',');
+  useEffect(() => {
+    if (!extendedKeys) return;
+    invoke<Record<string, VariableInfo>>("describe_variables", { names: extendedKeys.split(',') })
+      .then(setVariableInfo)
+      .catch(() => setVariableInfo({}));
+  }, [extendedKeys]);
+
 
   return (
     <>
@@ -35,10 +74,17 @@ export function DashboardView() {
           status={status}
           voltage={data?.battery_voltage}
           runtime={data?.battery_runtime}
+          estimate={data?.runtime_estimate}
         />
         <div className="flex flex-col gap-6">
           <PowerStatus status={status} />
 
+          {config?.backend === 'simulator' && <SimulationPanel />}
+
+          <div className="pt-4 border-t border-border/20">
+            <BatteryHealthCard upsName={config?.ups_name || "ups"} status={status} />
+          </div>
+
           <div className="pt-4 border-t border-border/20">
             <h3 className="text-[10px] font-bold tracking-[0.2em] text-muted-foreground uppercase mb-3 flex items-center justify-between">
               Event Log
@@ -92,7 +138,13 @@ export function DashboardView() {
             unit="Hz"
             icon={Activity}
           />
-          <StatusCard title="Power" value={data?.power_watts ? Math.round(data.power_watts) : "--"} unit="W" icon={Server} />
+          <StatusCard
+            title="Power"
+            value={data?.power_watts ? Math.round(data.power_watts) : "--"}
+            unit="W"
+            icon={Server}
+            subValue={data?.power_method ? POWER_METHOD_LABELS[data.power_method] : undefined}
+          />
         </div>
 
         <div className="flex-1 flex flex-col min-h-0 bg-muted/5 rounded-lg p-2 border border-border/5">
@@ -216,6 +268,83 @@ export function DashboardView() {
 
         <div className="h-px bg-border/20 my-1" />
 
+        {/* Phases (multi-phase units only) */}
+        {((data?.input?.per_phase.length ?? 0) > 1 || (data?.output?.per_phase.length ?? 0) > 1) && (
+          <div className="space-y-1">
+            <h4 className="text-[10px] font-bold text-muted-foreground mb-1 uppercase flex items-center gap-2">
+              <Zap className="h-3 w-3 text-primary/40" />
+              Phases
+            </h4>
+            <div className="grid grid-cols-3 gap-2">
+              {[1, 2, 3].map((phase) => {
+                const input = data?.input?.per_phase.find((p) => p.phase === phase);
+                const output = data?.output?.per_phase.find((p) => p.phase === phase);
+                if (!input && !output) return null;
+                return (
+                  <div key={phase} className="bg-muted/10 p-2 rounded border border-border/20 flex flex-col items-center">
+                    <span className="text-[9px] text-muted-foreground uppercase font-bold text-center w-full border-b border-border/10 mb-1 pb-1">L{phase}</span>
+                    <span className="text-[10px] font-bold text-foreground">{input?.voltage != null ? `${input.voltage}V` : "--"}</span>
+                    <span className="text-[10px] text-muted-foreground">{output?.current != null ? `${output.current}A` : "--"}</span>
+                    <span className="text-[10px] text-muted-foreground">{output?.load != null ? `${output.load}%` : "--"}</span>
+                  </div>
+                );
+              })}
+            </div>
+            <dl className="space-y-1 px-1 pt-1">
+              <div className="flex justify-between gap-2 border-b border-border/10 pb-1"><dt className="text-[10px] text-muted-foreground">Voltage Imbalance</dt><dd className="text-[10px] font-medium">{data?.input?.voltage_imbalance != null ? `${data.input.voltage_imbalance.toFixed(1)}%` : "--"}</dd></div>
+              <div className="flex justify-between gap-2 border-b border-border/10 pb-1"><dt className="text-[10px] text-muted-foreground">Current Imbalance</dt><dd className="text-[10px] font-medium">{data?.output?.current_imbalance != null ? `${data.output.current_imbalance.toFixed(1)}%` : "--"}</dd></div>
+            </dl>
+            <div className="h-px bg-border/20 my-1" />
+          </div>
+        )}
+
+        {/* Outlets (numbered outlet groups) */}
+        {data?.outlets && data.outlets.some((o) => o.index > 0) && (
+          <div className="space-y-1">
+            <h4 className="text-[10px] font-bold text-muted-foreground mb-1 uppercase flex items-center gap-2">
+              <Power className="h-3 w-3 text-primary/40" />
+              Outlets
+            </h4>
+            <div className="space-y-1 px-1">
+              {data.outlets.filter((o) => o.index > 0).map((outlet) => {
+                const isOn = outlet.status === 'on';
+                const canSwitch = outlet.switchable !== false;
+                return (
+                  <div key={outlet.index} className="flex items-center justify-between gap-2 border-b border-border/10 pb-1">
+                    <div className="flex flex-col min-w-0">
+                      <span className="text-[10px] font-medium truncate">{outlet.name || `Outlet ${outlet.index}`}</span>
+                      <span className="text-[9px] text-muted-foreground">
+                        {[
+                          outlet.current != null ? `${outlet.current}A` : null,
+                          outlet.realpower != null ? `${outlet.realpower}W` : null,
+                          outlet.delay_shutdown != null ? `off delay ${outlet.delay_shutdown}s` : null,
+                        ].filter(Boolean).join(' · ') || '--'}
+                      </span>
+                    </div>
+                    <div className="flex items-center gap-1.5 shrink-0">
+                      <span className={`text-[9px] px-1.5 rounded-full font-bold uppercase ${isOn ? 'bg-primary/20 text-primary' : 'bg-muted text-muted-foreground'}`}>
+                        {outlet.status || '?'}
+                      </span>
+                      {canSwitch && (
+                        <button
+                          onClick={() => switchOutlet(outlet.index, isOn ? 'off' : 'on')}
+                          className={`px-1.5 py-0.5 rounded border text-[9px] font-bold uppercase transition-all ${isOn
+                            ? 'bg-destructive/10 border-destructive/30 text-destructive hover:bg-destructive/20'
+                            : 'border-border hover:bg-primary/10 hover:border-primary/40 text-foreground/70 hover:text-primary'
+                            }`}
+                        >
+                          {isOn ? 'Off' : 'On'}
+                        </button>
+                      )}
+                    </div>
+                  </div>
+                );
+              })}
+            </div>
+            <div className="h-px bg-border/20 my-1" />
+          </div>
+        )}
+
         {/* 5. Extended Info (Detailed) */}
         {data?.extended_vars && Object.keys(data.extended_vars).length > 0 && (
           <div className="space-y-1">
@@ -238,10 +367,12 @@ export function DashboardView() {
                     <h5 className="text-[9px] font-black text-primary/60 uppercase tracking-widest border-b border-white/5 pb-0.5 mb-1">{prefix}</h5>
                     {vars.map(([key, value]) => (
                       <div key={key} className="flex items-center justify-between gap-2 group hover:bg-white/5 rounded px-1 -mx-1 transition-colors">
-                        <dt className="text-[9px] text-muted-foreground font-mono truncate opacity-70 group-hover:opacity-100 transition-opacity" title={key}>
+                        <dt className="text-[9px] text-muted-foreground font-mono truncate opacity-70 group-hover:opacity-100 transition-opacity" title={variableInfo[key]?.description ?? key}>
                           {key.split('.').slice(1).join('.')}
                         </dt>
-                        <dd className="text-[10px] font-bold text-foreground text-right truncate" title={value}>{value}</dd>
+                        <dd className="text-[10px] font-bold text-foreground text-right truncate" title={value}>
+                          {value}{variableInfo[key]?.unit && <span className="ml-0.5 font-normal text-muted-foreground">{variableInfo[key].unit}</span>}
+                        </dd>
                       </div>
                     ))}
                   </div>
@@ -0,0 +1,77 @@
+import { useState } from "react";
+import { invoke } from "@tauri-apps/api/core";
+import { toast } from "sonner";
+import { FlaskConical } from "lucide-react";
+import { SimEvent } from "../../types/ups";
+
+const SPEEDS = [1, 10, 60];
+
+// Drives the backend's virtual UPS (see src-tauri/src/nut/simulator.rs)
+export function SimulationPanel() {
+  const [load, setLoad] = useState(25);
+  const [speed, setSpeed] = useState(1);
+
+  const send = (event: SimEvent) =>
+    invoke("simulate_power_event", { event }).catch((e) => toast.error(`Simulation error: ${e}`));
+
+  const buttonClass =
+    "px-2 py-1.5 rounded-md border text-[9px] font-bold uppercase tracking-tighter transition-all bg-zinc-900/5 dark:bg-white/5 border-border hover:bg-primary/10 hover:border-primary/40 text-foreground/70 hover:text-primary";
+
+  return (
+    <div className="pt-4 border-t border-border/20">
+      <h3 className="text-[10px] font-bold tracking-[0.2em] text-muted-foreground uppercase mb-3 flex items-center justify-between">
+        Simulation
+        <FlaskConical className="h-3 w-3 opacity-40" />
+      </h3>
+      <div className="grid grid-cols-2 gap-1.5">
+        <button className={`${buttonClass} !text-destructive !border-destructive/30`} onClick={() => send({ type: "outage" })}>
+          Cut Power
+        </button>
+        <button className={buttonClass} onClick={() => send({ type: "restore" })}>
+          Restore
+        </button>
+        <button className={buttonClass} onClick={() => send({ type: "inputVoltage", volts: 190 })}>
+          Sag (Boost)
+        </button>
+        <button className={buttonClass} onClick={() => send({ type: "inputVoltage", volts: 265 })}>
+          Swell (Trim)
+        </button>
+      </div>
+
+      <div className="mt-3 space-y-1">
+        <div className="flex justify-between text-[9px] font-bold uppercase tracking-tighter text-muted-foreground">
+          <span>Load</span>
+          <span>{load}%</span>
+        </div>
+        <input
+          type="range"
+          min={0}
+          max={110}
+          value={load}
+          onChange={(e) => setLoad(parseInt(e.target.value))}
+          onMouseUp={() => send({ type: "setLoad", percent: load })}
+          onKeyUp={() => send({ type: "setLoad", percent: load })}
+          className="w-full accent-primary"
+        />
+      </div>
+
+      <div className="mt-2 flex items-center justify-between">
+        <span className="text-[9px] font-bold uppercase tracking-tighter text-muted-foreground">Speed</span>
+        <div className="flex gap-1">
+          {SPEEDS.map((factor) => (
+            <button
+              key={factor}
+              className={`${buttonClass} ${speed === factor ? '!border-primary !text-primary' : ''}`}
+              onClick={() => {
+                setSpeed(factor);
+                send({ type: "setSpeed", factor });
+              }}
+            >
+              {factor}x
+            </button>
+          ))}
+        </div>
+      </div>
+    </div>
+  );
+}
@@ -10,9 +10,11 @@ import {
   ResponsiveContainer,
   Brush,
 } from "recharts";
-import { HistoryStats } from "../../types/ups";
+import { toast } from "sonner";
+import { CostReport, EnergyBucket, EnergyPeriod, HistoryStats, ReportPeriod } from "../../types/ups";
 
-import { RefreshCw, Activity, Zap, TrendingUp, AlertTriangle, Battery } from "lucide-react";
+import { PowerQualityPanel } from "./PowerQualityPanel";
+import { RefreshCw, Activity, Zap, TrendingUp, AlertTriangle, Battery, Gauge, Download } from "lucide-react";
 
 interface HistoryEntry {
   timestamp: number;
@@ -21,11 +23,30 @@ interface HistoryEntry {
   load_percent: number | null;
   battery_charge: number | null;
   status: string;
+  input_l1_voltage: number | null;
+  input_l2_voltage: number | null;
+  input_l3_voltage: number | null;
+  output_l1_load: number | null;
+  output_l2_load: number | null;
+  output_l3_load: number | null;
+  battery_runtime?: number | null;
+  battery_voltage?: number | null;
 }
 
+const PHASE_COLORS = ["#f43f5e", "#eab308", "#8b5cf6"];
+
+// Energy bucket size that suits each time range
+const energyPeriod = (range: string): EnergyPeriod =>
+  range === "1y" ? "month" : range === "7d" || range === "30d" ? "day" : "hour";
+
+const reportPeriod = (range: string): ReportPeriod =>
+  range === "1y" ? "month" : range === "30d" ? "week" : "day";
+
 export function HistoryCharts() {
   const [data, setData] = useState<HistoryEntry[]>([]);
   const [stats, setStats] = useState<HistoryStats | null>(null);
+  const [energy, setEnergy] = useState<EnergyBucket[]>([]);
+  const [cost, setCost] = useState<CostReport | null>(null);
   const [range, setRange] = useState<string>("24h");
   const [loading, setLoading] = useState(false);
 
@@ -49,6 +70,18 @@ export function HistoryCharts() {
         timeRange: range,
       });
       setStats(statsResult);
+
+      const energyResult = await invoke<EnergyBucket[]>("get_energy", {
+        period: energyPeriod(range),
+        timeRange: range,
+      });
+      setEnergy(energyResult);
+
+      const costResult = await invoke<CostReport>("get_cost_report", {
+        period: reportPeriod(range),
+        timeRange: range,
+      });
+      setCost(costResult);
     } catch (err) {
       console.error("Failed to fetch history:", err);
     } finally {
@@ -60,6 +93,20 @@ export function HistoryCharts() {
     fetchData();
   }, [range]);
 
+  const exportData = async (format: "json" | "csv") => {
+    try {
+      const files = await invoke<string[]>("export_data", { timeRange: range, format });
+      toast.success(`Exported ${files.length} file(s)`, { description: files.join("\n") });
+    } catch (err) {
+      toast.error("Export failed", { description: String(err) });
+    }
+  };
+
+  // Per-phase series are only drawn when the unit reported them
+  const multiPhase = data.some((d) => d.input_l2_voltage != null || d.output_l2_load != null);
+  const totalKwh = energy.reduce((sum, b) => sum + b.kwh, 0);
+  const peakKwh = energy.reduce((max, b) => Math.max(max, b.kwh), 0);
+
   return (
     <div className="space-y-4 animate-in fade-in slide-in-from-bottom-4 duration-500 h-full flex flex-col">
       {/* Header Controls */}
@@ -85,6 +132,18 @@ export function HistoryCharts() {
             </button>
           ))}
           <div className="w-px h-3 bg-border/20 mx-0.5" />
+          {(["csv", "json"] as const).map((format) => (
+            <button
+              key={format}
+              onClick={() => exportData(format)}
+              className="flex items-center gap-1 px-1.5 py-0.5 rounded text-[10px] font-bold uppercase text-muted-foreground hover:text-primary hover:bg-white/5 transition-colors"
+              title={`Export ${range} of history, journal and energy costs as ${format.toUpperCase()}`}
+            >
+              <Download className="h-3 w-3" />
+              {format}
+            </button>
+          ))}
+          <div className="w-px h-3 bg-border/20 mx-0.5" />
           <button
             onClick={fetchData}
             disabled={loading}
@@ -98,7 +157,7 @@ export function HistoryCharts() {
 
       {/* Stats Grid */}
       {stats && (
-        <div className="grid grid-cols-4 gap-3 shrink-0 mb-2">
+        <div className="grid grid-cols-5 gap-3 shrink-0 mb-2">
           {/* Voltage Stats */}
           <div className="bg-muted/5 border border-border/5 rounded p-2 flex flex-col justify-between">
             <div className="flex items-center gap-1.5 mb-1">
@@ -145,6 +204,30 @@ export function HistoryCharts() {
             </div>
           </div>
 
+          {/* Energy Stats */}
+          <div className="bg-muted/5 border border-border/5 rounded p-2 flex flex-col justify-between">
+            <div className="flex items-center gap-1.5 mb-1">
+              <Gauge className="h-3 w-3 text-yellow-400" />
+              <span className="text-[9px] font-bold uppercase text-muted-foreground tracking-wider">Energy</span>
+            </div>
+            <div className="flex items-baseline gap-1">
+              <span className="text-lg font-mono font-bold text-foreground">{totalKwh.toFixed(2)}</span>
+              <span className="text-[10px] text-muted-foreground">kWh</span>
+            </div>
+            <div className="flex items-center justify-between mt-1 text-[9px] text-muted-foreground/70 font-mono">
+              {cost ? (
+                <>
+                  <span title={`Peak ${energyPeriod(range)}: ${peakKwh.toFixed(2)} kWh`}>
+                    {cost.total.cost.toFixed(2)} {cost.currency}
+                  </span>
+                  <span>{cost.total.kg_co2.toFixed(1)} kg CO2</span>
+                </>
+              ) : (
+                <span>Peak {energyPeriod(range)}: {peakKwh.toFixed(2)}</span>
+              )}
+            </div>
+          </div>
+
           {/* Outage Stats */}
           <div className="bg-muted/5 border border-border/5 rounded p-2 flex flex-col justify-between">
             <div className="flex items-center gap-1.5 mb-1">
@@ -162,6 +245,8 @@ export function HistoryCharts() {
         </div>
       )}
 
+      <PowerQualityPanel range={range} />
+
       <div className="flex-1 grid grid-rows-2 gap-4 min-h-0">
         {/* Voltage Chart Container */}
         <div className="flex flex-col min-h-0 bg-muted/5 rounded-lg p-3 border border-border/5 hover:border-border/10 transition-colors">
@@ -177,6 +262,12 @@ export function HistoryCharts() {
                 <div className="w-2 h-0.5 bg-green-500 rounded-full"></div>
                 <span className="text-[9px] font-medium text-muted-foreground">Output</span>
               </div>
+              {multiPhase && [1, 2, 3].map((phase) => (
+                <div key={phase} className="flex items-center gap-1.5">
+                  <div className="w-2 h-0.5 rounded-full" style={{ backgroundColor: PHASE_COLORS[phase - 1] }}></div>
+                  <span className="text-[9px] font-medium text-muted-foreground">L{phase}</span>
+                </div>
+              ))}
             </div>
           </div>
 
@@ -243,6 +334,19 @@ export function HistoryCharts() {
                   activeDot={{ r: 4, strokeWidth: 0 }}
                   isAnimationActive={false}
                 />
+                {multiPhase && [1, 2, 3].map((phase) => (
+                  <Area
+                    key={phase}
+                    type="monotone"
+                    dataKey={`input_l${phase}_voltage`}
+                    name={`L${phase}-N`}
+                    stroke={PHASE_COLORS[phase - 1]}
+                    strokeWidth={1}
+                    fill="none"
+                    connectNulls
+                    isAnimationActive={false}
+                  />
+                ))}
               </AreaChart>
             </ResponsiveContainer>
           </div>
@@ -327,6 +431,20 @@ export function HistoryCharts() {
                   activeDot={{ r: 4, strokeWidth: 0 }}
                   isAnimationActive={false}
                 />
+                {multiPhase && [1, 2, 3].map((phase) => (
+                  <Area
+                    key={phase}
+                    type="monotone"
+                    dataKey={`output_l${phase}_load`}
+                    name={`L${phase} load`}
+                    stroke={PHASE_COLORS[phase - 1]}
+                    strokeWidth={1}
+                    strokeDasharray="3 3"
+                    fill="none"
+                    connectNulls
+                    isAnimationActive={false}
+                  />
+                ))}
                 <Brush
                   dataKey="timeStr"
                   height={20}
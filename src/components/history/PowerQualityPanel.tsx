@@ -0,0 +1,92 @@
+import { useEffect, useState } from "react";
+import { invoke } from "@tauri-apps/api/core";
+import { BarChart, Bar, XAxis, YAxis, Tooltip, ResponsiveContainer, ReferenceLine } from "recharts";
+import { Waves } from "lucide-react";
+import { useUpsStore } from "../../store/upsStore";
+import { PowerQualityReport, VoltageEvents } from "../../types/ups";
+
+const duration = (secs: number) =>
+  secs >= 3600 ? `${(secs / 3600).toFixed(1)}h` : secs >= 60 ? `${Math.round(secs / 60)}m` : `${Math.round(secs)}s`;
+
+function Events({ label, events, unit }: { label: string; events: VoltageEvents; unit: string }) {
+  return (
+    <div className="flex flex-col">
+      <span className="text-[9px] font-bold uppercase text-muted-foreground tracking-wider">{label}</span>
+      <span className={`text-sm font-mono font-bold ${events.count > 0 ? "text-amber-400" : "text-foreground"}`}>
+        {events.count}
+      </span>
+      <span className="text-[9px] text-muted-foreground/70 font-mono">
+        {events.count > 0
+          ? `${duration(events.total_secs)}${events.extreme != null ? ` · ${events.extreme.toFixed(0)}${unit}` : ""}`
+          : "-"}
+        {events.beyond_transfer > 0 && ` · ${events.beyond_transfer} xfer`}
+      </span>
+    </div>
+  );
+}
+
+export function PowerQualityPanel({ range }: { range: string }) {
+  const { config } = useUpsStore();
+  const [report, setReport] = useState<PowerQualityReport | null>(null);
+
+  useEffect(() => {
+    invoke<PowerQualityReport>("get_power_quality", { upsName: config?.ups_name || "ups", timeRange: range })
+      .then(setReport)
+      .catch((err) => console.error("Failed to fetch power quality:", err));
+  }, [range, config?.ups_name]);
+
+  if (!report) return null;
+  const histogram = report.histogram.map((b) => ({ volts: b.from, minutes: b.secs / 60 }));
+  const transferDays = report.transfers_per_day.filter((d) => d.transfers > 0);
+
+  return (
+    <div className="grid grid-cols-12 gap-3 shrink-0 bg-muted/5 border border-border/5 rounded p-2">
+      <div className="col-span-6 grid grid-cols-4 gap-2">
+        <div className="col-span-4 flex items-center gap-1.5">
+          <Waves className="h-3 w-3 text-cyan-400" />
+          <span className="text-[9px] font-bold uppercase text-muted-foreground tracking-wider">
+            Power Quality · {report.nominal_voltage.toFixed(0)} V nominal
+          </span>
+        </div>
+        <Events label="Sags" events={report.sags} unit="V" />
+        <Events label="Swells" events={report.swells} unit="V" />
+        <div className="flex flex-col">
+          <span className="text-[9px] font-bold uppercase text-muted-foreground tracking-wider">Trim / Boost</span>
+          <span className="text-sm font-mono font-bold text-foreground">
+            {report.trim.events} / {report.boost.events}
+          </span>
+          <span className="text-[9px] text-muted-foreground/70 font-mono">
+            {duration(report.trim.secs)} / {duration(report.boost.secs)}
+          </span>
+        </div>
+        <div className="flex flex-col" title={transferDays.map((d) => `${d.label}: ${d.transfers}`).join("\n")}>
+          <span className="text-[9px] font-bold uppercase text-muted-foreground tracking-wider">Transfers</span>
+          <span className={`text-sm font-mono font-bold ${report.transfers > 0 ? "text-red-500" : "text-foreground"}`}>
+            {report.transfers}
+          </span>
+          <span className="text-[9px] text-muted-foreground/70 font-mono">
+            {report.frequency
+              ? `${report.frequency.min.toFixed(1)}-${report.frequency.max.toFixed(1)} Hz`
+              : `${transferDays.length} day(s)`}
+          </span>
+        </div>
+      </div>
+      <div className="col-span-6 h-20">
+        <ResponsiveContainer width="100%" height="100%">
+          <BarChart data={histogram} margin={{ top: 2, right: 2, left: -30, bottom: 0 }}>
+            <XAxis dataKey="volts" fontSize={9} tickLine={false} axisLine={false} tick={{ fill: "#64748b" }} />
+            <YAxis fontSize={9} tickLine={false} axisLine={false} tick={{ fill: "#64748b" }} />
+            <Tooltip
+              formatter={(value: number) => [`${value.toFixed(0)} min`, "Time"]}
+              labelFormatter={(volts) => `${volts} V`}
+              contentStyle={{ backgroundColor: "rgba(15, 23, 42, 0.9)", border: "1px solid rgba(148, 163, 184, 0.1)", borderRadius: "6px", fontSize: "11px" }}
+            />
+            <ReferenceLine x={Math.floor(report.sag_below / 2) * 2} stroke="#f59e0b" strokeDasharray="3 3" />
+            <ReferenceLine x={Math.floor(report.swell_above / 2) * 2} stroke="#f59e0b" strokeDasharray="3 3" />
+            <Bar dataKey="minutes" fill="#06b6d4" isAnimationActive={false} />
+          </BarChart>
+        </ResponsiveContainer>
+      </div>
+    </div>
+  );
+}
@@ -0,0 +1,167 @@
+import { Input } from '@/components/ui/input';
+import { Label } from '@/components/ui/label';
+import { Button } from '@/components/ui/button';
+import { Trash2 } from 'lucide-react';
+import { AlertCondition, AlertRule, AlertsConfig } from '../../types/ups';
+
+// Common NUT variables offered for metric rules; any numeric variable works
+const METRICS = [
+  'input.voltage',
+  'input.frequency',
+  'output.voltage',
+  'ups.load',
+  'ups.temperature',
+  'battery.charge',
+  'battery.runtime',
+  'battery.temperature',
+  'ambient.temperature',
+  'power.watts',
+];
+
+const fieldClass = 'h-7 text-[11px] bg-muted/20 border-border/50';
+const selectClass = 'h-7 bg-muted/20 border border-border/50 rounded-md px-1 text-[10px] font-bold';
+const labelClass = 'text-[10px] font-bold uppercase text-muted-foreground';
+
+const number = (text: string) => {
+  const n = parseFloat(text);
+  return Number.isFinite(n) ? n : 0;
+};
+
+interface Props {
+  value: AlertsConfig;
+  onChange: (value: AlertsConfig) => void;
+}
+
+export function AlertSettings({ value, onChange }: Props) {
+  const updateRule = (index: number, patch: Partial<AlertRule>) =>
+    onChange({ rules: value.rules.map((r, i) => (i === index ? { ...r, ...patch } : r)) });
+
+  const setConditionType = (index: number, rule: AlertRule, type: AlertCondition['type']) => {
+    const metric = rule.condition.type === 'flag' ? 'input.voltage' : rule.condition.metric;
+    const threshold = rule.condition.type === 'flag' ? 0 : rule.condition.value;
+    updateRule(index, { condition: type === 'flag' ? { type, flag: 'OB' } : { type, metric, value: threshold } });
+  };
+
+  return (
+    <div className="grid gap-3 pt-4 border-t border-border/20">
+      <Label className="text-[11px] font-bold uppercase tracking-wider">Alerts</Label>
+      <datalist id="alert-metrics">
+        {METRICS.map((m) => (
+          <option key={m} value={m} />
+        ))}
+      </datalist>
+
+      {value.rules.map((rule, index) => (
+        <div key={index} className="grid gap-1.5">
+          <div className="grid grid-cols-12 gap-2 items-center">
+            <Input
+              value={rule.name}
+              placeholder="Rule name"
+              onChange={(e) => updateRule(index, { name: e.target.value })}
+              className={`col-span-3 ${fieldClass}`}
+            />
+            <select
+              value={rule.condition.type}
+              onChange={(e) => setConditionType(index, rule, e.target.value as AlertCondition['type'])}
+              className={`col-span-2 ${selectClass}`}
+            >
+              <option value="above">Above</option>
+              <option value="below">Below</option>
+              <option value="flag">Status flag</option>
+            </select>
+            {rule.condition.type === 'flag' ? (
+              <Input
+                value={rule.condition.flag}
+                placeholder="OB"
+                onChange={(e) => updateRule(index, { condition: { type: 'flag', flag: e.target.value.trim().toUpperCase() } })}
+                className={`col-span-4 ${fieldClass} font-mono`}
+              />
+            ) : (
+              <>
+                <Input
+                  value={rule.condition.metric}
+                  list="alert-metrics"
+                  onChange={(e) =>
+                    rule.condition.type !== 'flag' &&
+                    updateRule(index, { condition: { ...rule.condition, metric: e.target.value.trim() } })
+                  }
+                  className={`col-span-3 ${fieldClass} font-mono`}
+                />
+                <Input
+                  defaultValue={rule.condition.value}
+                  onBlur={(e) =>
+                    rule.condition.type !== 'flag' &&
+                    updateRule(index, { condition: { ...rule.condition, value: number(e.target.value) } })
+                  }
+                  className={`col-span-1 ${fieldClass}`}
+                />
+              </>
+            )}
+            <select
+              value={rule.severity}
+              onChange={(e) => updateRule(index, { severity: e.target.value as AlertRule['severity'] })}
+              className={`col-span-2 ${selectClass}`}
+            >
+              <option value="info">Info</option>
+              <option value="warning">Warning</option>
+              <option value="critical">Critical</option>
+            </select>
+            <button
+              onClick={() => onChange({ rules: value.rules.filter((_, i) => i !== index) })}
+              className="col-span-1 flex justify-center text-muted-foreground hover:text-destructive"
+              title="Remove rule"
+            >
+              <Trash2 className="h-3.5 w-3.5" />
+            </button>
+          </div>
+          <div className="grid grid-cols-12 gap-2 items-center">
+            <Label className={`col-span-2 ${labelClass}`}>For (s)</Label>
+            <Input
+              value={rule.forSec}
+              onChange={(e) => updateRule(index, { forSec: parseInt(e.target.value) || 0 })}
+              className={`col-span-1 ${fieldClass}`}
+            />
+            <Label className={`col-span-2 ${labelClass}`} title="How far back past the threshold before the alert resolves">
+              Hysteresis
+            </Label>
+            <Input
+              defaultValue={rule.hysteresis}
+              disabled={rule.condition.type === 'flag'}
+              onBlur={(e) => updateRule(index, { hysteresis: Math.max(0, number(e.target.value)) })}
+              className={`col-span-1 ${fieldClass}`}
+            />
+            <Label className={`col-span-2 ${labelClass}`}>Cooldown (s)</Label>
+            <Input
+              value={rule.cooldownSec}
+              onChange={(e) => updateRule(index, { cooldownSec: parseInt(e.target.value) || 0 })}
+              className={`col-span-1 ${fieldClass}`}
+            />
+          </div>
+        </div>
+      ))}
+
+      <Button
+        variant="outline"
+        size="sm"
+        onClick={() =>
+          onChange({
+            rules: [
+              ...value.rules,
+              {
+                name: `Alert ${value.rules.length + 1}`,
+                condition: { type: 'above', metric: 'ups.load', value: 80 },
+                severity: 'warning',
+                forSec: 10,
+                hysteresis: 5,
+                cooldownSec: 300,
+              },
+            ],
+          })
+        }
+        className="h-7 text-[10px] font-bold uppercase tracking-widest border-dashed"
+      >
+        Add Alert
+      </Button>
+    </div>
+  );
+}
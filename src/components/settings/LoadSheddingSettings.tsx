@@ -0,0 +1,137 @@
+import { Input } from '@/components/ui/input';
+import { Label } from '@/components/ui/label';
+import { Button } from '@/components/ui/button';
+import { Trash2 } from 'lucide-react';
+import { LoadSheddingConfig, SheddingAction, SheddingRule } from '../../types/ups';
+
+// Actions are edited as a comma-separated list: a number is an outlet to switch off,
+// anything else an instant command, optionally followed by "/restore.command".
+const formatActions = (actions: SheddingAction[]) =>
+  actions
+    .map((a) => (a.type === 'outletOff' ? `${a.outlet}` : a.restoreCommand ? `${a.command}/${a.restoreCommand}` : a.command))
+    .join(', ');
+
+const parseActions = (text: string): SheddingAction[] =>
+  text
+    .split(',')
+    .map((t) => t.trim())
+    .filter(Boolean)
+    .map((t): SheddingAction => {
+      if (/^\d+$/.test(t)) return { type: 'outletOff', outlet: parseInt(t) };
+      const [command, restoreCommand] = t.split('/').map((p) => p.trim());
+      return { type: 'command', command, restoreCommand: restoreCommand || null };
+    });
+
+interface Props {
+  value: LoadSheddingConfig;
+  onChange: (value: LoadSheddingConfig) => void;
+}
+
+export function LoadSheddingSettings({ value, onChange }: Props) {
+  const update = (patch: Partial<LoadSheddingConfig>) => onChange({ ...value, ...patch });
+  const updateRule = (index: number, patch: Partial<SheddingRule>) =>
+    update({ rules: value.rules.map((r, i) => (i === index ? { ...r, ...patch } : r)) });
+
+  return (
+    <div className="grid gap-3 pt-4 border-t border-border/20">
+      <div className="flex items-center gap-4">
+        <div className="flex items-center gap-2">
+          <input
+            type="checkbox"
+            id="shedding-enabled"
+            checked={value.enabled}
+            onChange={(e) => update({ enabled: e.target.checked })}
+            className="h-3.5 w-3.5 rounded border-border bg-muted checked:bg-primary"
+          />
+          <Label htmlFor="shedding-enabled" className="text-[11px] font-bold uppercase tracking-wider cursor-pointer">Load Shedding</Label>
+        </div>
+        <div className="flex items-center gap-2">
+          <input
+            type="checkbox"
+            id="shedding-dry-run"
+            checked={value.dryRun}
+            onChange={(e) => update({ dryRun: e.target.checked })}
+            className="h-3.5 w-3.5 rounded border-border bg-muted checked:bg-primary"
+          />
+          <Label htmlFor="shedding-dry-run" className="text-[10px] font-bold uppercase text-muted-foreground cursor-pointer">Dry Run</Label>
+        </div>
+        <div className="flex items-center gap-2">
+          <input
+            type="checkbox"
+            id="shedding-restore"
+            checked={value.restore}
+            onChange={(e) => update({ restore: e.target.checked })}
+            className="h-3.5 w-3.5 rounded border-border bg-muted checked:bg-primary"
+          />
+          <Label htmlFor="shedding-restore" className="text-[10px] font-bold uppercase text-muted-foreground cursor-pointer">Restore After</Label>
+          <Input
+            value={value.restoreDelaySec}
+            onChange={(e) => update({ restoreDelaySec: parseInt(e.target.value) || 0 })}
+            className="h-7 w-14 text-[11px] bg-muted/20 border-border/50"
+          />
+          <span className="text-[10px] text-muted-foreground font-bold">SEC</span>
+        </div>
+      </div>
+
+      {value.rules.map((rule, index) => (
+        <div key={index} className="grid grid-cols-12 gap-2 items-center">
+          <Input
+            value={rule.name}
+            placeholder="Rule name"
+            onChange={(e) => updateRule(index, { name: e.target.value })}
+            className="col-span-3 h-7 text-[11px] bg-muted/20 border-border/50"
+          />
+          <select
+            value={rule.trigger.type}
+            onChange={(e) =>
+              updateRule(index, {
+                trigger: e.target.value === 'chargeBelow' ? { type: 'chargeBelow', percent: 50 } : { type: 'onBatteryFor', minutes: 5 },
+              })
+            }
+            className="col-span-3 h-7 bg-muted/20 border border-border/50 rounded-md px-1 text-[10px] font-bold"
+          >
+            <option value="onBatteryFor">On battery (min)</option>
+            <option value="chargeBelow">Charge below (%)</option>
+          </select>
+          <Input
+            value={rule.trigger.type === 'chargeBelow' ? rule.trigger.percent : rule.trigger.minutes}
+            onChange={(e) => {
+              const n = parseInt(e.target.value) || 0;
+              updateRule(index, {
+                trigger: rule.trigger.type === 'chargeBelow' ? { type: 'chargeBelow', percent: n } : { type: 'onBatteryFor', minutes: n },
+              });
+            }}
+            className="col-span-1 h-7 text-[11px] bg-muted/20 border-border/50"
+          />
+          <Input
+            defaultValue={formatActions(rule.actions)}
+            placeholder="2, 3, beeper.disable"
+            title="Outlet numbers to switch off, or instant commands (command/restore.command)"
+            onBlur={(e) => updateRule(index, { actions: parseActions(e.target.value) })}
+            className="col-span-4 h-7 text-[11px] bg-muted/20 border-border/50 font-mono"
+          />
+          <button
+            onClick={() => update({ rules: value.rules.filter((_, i) => i !== index) })}
+            className="col-span-1 flex justify-center text-muted-foreground hover:text-destructive"
+            title="Remove rule"
+          >
+            <Trash2 className="h-3.5 w-3.5" />
+          </button>
+        </div>
+      ))}
+
+      <Button
+        variant="outline"
+        size="sm"
+        onClick={() =>
+          update({
+            rules: [...value.rules, { name: `Rule ${value.rules.length + 1}`, trigger: { type: 'onBatteryFor', minutes: 5 }, actions: [] }],
+          })
+        }
+        className="h-7 text-[10px] font-bold uppercase tracking-widest border-dashed"
+      >
+        Add Rule
+      </Button>
+    </div>
+  );
+}
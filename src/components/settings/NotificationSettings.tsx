@@ -0,0 +1,216 @@
+import { Input } from '@/components/ui/input';
+import { Label } from '@/components/ui/label';
+import { EmailNotifications, NOTIFICATION_KINDS, NotificationKind, NotificationsConfig, SmtpSecurity } from '../../types/ups';
+
+const fieldClass = 'h-7 text-[11px] bg-muted/20 border-border/50';
+const labelClass = 'text-[10px] font-bold uppercase text-muted-foreground';
+const selectClass = 'h-7 bg-muted/20 border border-border/50 rounded-md px-1 text-[10px] font-bold';
+
+const DEFAULT_PORTS: Record<SmtpSecurity, number> = { startTls: 587, tls: 465, none: 25 };
+
+const toggle = (events: NotificationKind[], kind: NotificationKind, on: boolean) =>
+  on ? [...events, kind] : events.filter((k) => k !== kind);
+
+interface Props {
+  value: NotificationsConfig;
+  onChange: (value: NotificationsConfig) => void;
+  // Only holds a newly typed SMTP password; the saved one stays in the OS keyring
+  smtpPassword: string;
+  onSmtpPasswordChange: (password: string) => void;
+}
+
+export function NotificationSettings({ value, onChange, smtpPassword, onSmtpPasswordChange }: Props) {
+  const desktop = value.desktop;
+  const updateDesktop = (patch: Partial<NotificationsConfig['desktop']>) =>
+    onChange({ ...value, desktop: { ...desktop, ...patch } });
+  const toggleEvent = (kind: NotificationKind, on: boolean) =>
+    updateDesktop({ events: toggle(desktop.events, kind, on) });
+  const email = value.email;
+  const updateEmail = (patch: Partial<EmailNotifications>) => onChange({ ...value, email: { ...email, ...patch } });
+
+  return (
+    <div className="grid gap-3 pt-4 border-t border-border/20">
+      <div className="flex items-center gap-4">
+        <div className="flex items-center gap-2">
+          <input
+            type="checkbox"
+            id="desktop-notifications"
+            checked={desktop.enabled}
+            onChange={(e) => updateDesktop({ enabled: e.target.checked })}
+            className="h-3.5 w-3.5 rounded border-border bg-muted checked:bg-primary"
+          />
+          <Label htmlFor="desktop-notifications" className="text-[11px] font-bold uppercase tracking-wider cursor-pointer">
+            Desktop Notifications
+          </Label>
+        </div>
+        <div className="flex items-center gap-2" title="Least time between two notifications of the same kind">
+          <span className={labelClass}>At most every</span>
+          <Input
+            value={desktop.minIntervalSec}
+            onChange={(e) => updateDesktop({ minIntervalSec: parseInt(e.target.value) || 0 })}
+            className={`w-14 ${fieldClass}`}
+          />
+          <span className="text-[10px] text-muted-foreground font-bold">SEC</span>
+        </div>
+      </div>
+
+      <div className="grid grid-cols-3 gap-x-4 gap-y-1.5">
+        {NOTIFICATION_KINDS.map(({ kind, label }) => (
+          <div key={kind} className="flex items-center gap-2">
+            <input
+              type="checkbox"
+              id={`notify-${kind}`}
+              checked={desktop.events.includes(kind)}
+              disabled={!desktop.enabled}
+              onChange={(e) => toggleEvent(kind, e.target.checked)}
+              className="h-3.5 w-3.5 rounded border-border bg-muted checked:bg-primary"
+            />
+            <Label htmlFor={`notify-${kind}`} className={`${labelClass} cursor-pointer`}>{label}</Label>
+          </div>
+        ))}
+      </div>
+
+      <div className="flex items-center gap-4 pt-2">
+        <div className="flex items-center gap-2">
+          <input
+            type="checkbox"
+            id="email-notifications"
+            checked={email.enabled}
+            onChange={(e) => updateEmail({ enabled: e.target.checked })}
+            className="h-3.5 w-3.5 rounded border-border bg-muted checked:bg-primary"
+          />
+          <Label htmlFor="email-notifications" className="text-[11px] font-bold uppercase tracking-wider cursor-pointer">
+            Email
+          </Label>
+        </div>
+        <div className="flex items-center gap-2" title="How long power must stay back before the outage summary is sent">
+          <span className={labelClass}>Summary after</span>
+          <Input
+            value={email.settleSec}
+            disabled={!email.enabled}
+            onChange={(e) => updateEmail({ settleSec: parseInt(e.target.value) || 0 })}
+            className={`w-14 ${fieldClass}`}
+          />
+          <span className="text-[10px] text-muted-foreground font-bold">SEC</span>
+        </div>
+      </div>
+
+      {email.enabled && (
+        <>
+          <div className="grid grid-cols-12 gap-2 items-center">
+            <Label className={`col-span-3 ${labelClass}`}>SMTP Server</Label>
+            <Input
+              value={email.host}
+              placeholder="smtp.example.com"
+              onChange={(e) => updateEmail({ host: e.target.value.trim() })}
+              className={`col-span-4 ${fieldClass} font-mono`}
+            />
+            <Input
+              value={email.port}
+              onChange={(e) => updateEmail({ port: parseInt(e.target.value) || 0 })}
+              className={`col-span-2 ${fieldClass}`}
+            />
+            <select
+              value={email.security}
+              onChange={(e) => {
+                const security = e.target.value as SmtpSecurity;
+                updateEmail({ security, port: DEFAULT_PORTS[security] });
+              }}
+              className={`col-span-3 ${selectClass}`}
+            >
+              <option value="startTls">STARTTLS</option>
+              <option value="tls">TLS</option>
+              <option value="none">None</option>
+            </select>
+          </div>
+          <div className="grid grid-cols-12 gap-2 items-center">
+            <Label className={`col-span-3 ${labelClass}`}>Login</Label>
+            <Input
+              value={email.username ?? ''}
+              placeholder="User (optional)"
+              onChange={(e) => updateEmail({ username: e.target.value || null })}
+              className={`col-span-4 ${fieldClass}`}
+            />
+            <Input
+              type="password"
+              value={smtpPassword}
+              disabled={!email.username}
+              placeholder={email.credentialId ? 'Stored securely' : 'Password'}
+              onChange={(e) => onSmtpPasswordChange(e.target.value)}
+              className={`col-span-5 ${fieldClass}`}
+            />
+          </div>
+          <div className="grid grid-cols-12 gap-2 items-center">
+            <Label className={`col-span-3 ${labelClass}`}>From / To</Label>
+            <Input
+              value={email.from}
+              placeholder="UPS <ups@example.com>"
+              onChange={(e) => updateEmail({ from: e.target.value })}
+              className={`col-span-4 ${fieldClass}`}
+            />
+            <Input
+              defaultValue={email.to.join(', ')}
+              placeholder="oncall@example.com, ..."
+              onBlur={(e) =>
+                updateEmail({
+                  to: e.target.value
+                    .split(',')
+                    .map((t) => t.trim())
+                    .filter(Boolean),
+                })
+              }
+              className={`col-span-5 ${fieldClass}`}
+            />
+          </div>
+          <div className="grid grid-cols-12 gap-2 items-center">
+            <Label className={`col-span-3 ${labelClass}`} title="{title}, {body}, {severity}, {event} and {time} are filled in">
+              Subject
+            </Label>
+            <Input
+              value={email.subject}
+              onChange={(e) => updateEmail({ subject: e.target.value })}
+              className={`col-span-9 ${fieldClass} font-mono`}
+            />
+          </div>
+          <div className="grid grid-cols-12 gap-2 items-start">
+            <Label className={`col-span-3 pt-1.5 ${labelClass}`} title="{title}, {body}, {severity}, {event} and {time} are filled in">
+              Body
+            </Label>
+            <textarea
+              value={email.body}
+              rows={3}
+              onChange={(e) => updateEmail({ body: e.target.value })}
+              className="col-span-9 rounded-md px-2 py-1 text-[11px] bg-muted/20 border border-border/50 font-mono"
+            />
+          </div>
+          <div className="grid grid-cols-3 gap-x-4 gap-y-1.5">
+            {NOTIFICATION_KINDS.map(({ kind, label }) => (
+              <div key={kind} className="flex items-center gap-2">
+                <input
+                  type="checkbox"
+                  id={`email-${kind}`}
+                  checked={email.events.includes(kind)}
+                  onChange={(e) => updateEmail({ events: toggle(email.events, kind, e.target.checked) })}
+                  className="h-3.5 w-3.5 rounded border-border bg-muted checked:bg-primary"
+                />
+                <Label htmlFor={`email-${kind}`} className={`${labelClass} cursor-pointer`}>{label}</Label>
+              </div>
+            ))}
+          </div>
+        </>
+      )}
+
+      <div className="grid grid-cols-12 gap-2 items-center">
+        <Label className={`col-span-3 ${labelClass}`} title="Run for every notification with the message as its argument">
+          Notify Command
+        </Label>
+        <Input
+          value={value.command ?? ''}
+          placeholder="/usr/local/bin/ups-notify"
+          onChange={(e) => onChange({ ...value, command: e.target.value || null })}
+          className={`col-span-9 ${fieldClass} font-mono`}
+        />
+      </div>
+    </div>
+  );
+}
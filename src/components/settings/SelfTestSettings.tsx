@@ -0,0 +1,126 @@
+import { Input } from '@/components/ui/input';
+import { Label } from '@/components/ui/label';
+import { SelfTestConfig } from '../../types/ups';
+
+const WEEKDAYS = ['Monday', 'Tuesday', 'Wednesday', 'Thursday', 'Friday', 'Saturday', 'Sunday'];
+const fieldClass = 'h-7 text-[11px] bg-muted/20 border-border/50';
+const selectClass = 'h-7 bg-muted/20 border border-border/50 rounded-md px-1 text-[10px] font-bold';
+const labelClass = 'text-[10px] font-bold uppercase text-muted-foreground';
+
+const hour = (text: string) => Math.min(23, Math.max(0, parseInt(text) || 0));
+
+interface Props {
+  value: SelfTestConfig;
+  onChange: (value: SelfTestConfig) => void;
+}
+
+export function SelfTestSettings({ value, onChange }: Props) {
+  const update = (patch: Partial<SelfTestConfig>) => onChange({ ...value, ...patch });
+  const schedule = value.schedule;
+
+  return (
+    <div className="grid gap-3 pt-4 border-t border-border/20">
+      <div className="flex items-center gap-4">
+        <div className="flex items-center gap-2">
+          <input
+            type="checkbox"
+            id="selftest-enabled"
+            checked={value.enabled}
+            onChange={(e) => update({ enabled: e.target.checked })}
+            className="h-3.5 w-3.5 rounded border-border bg-muted checked:bg-primary"
+          />
+          <Label htmlFor="selftest-enabled" className="text-[11px] font-bold uppercase tracking-wider cursor-pointer">Battery Self-Test</Label>
+        </div>
+        <select
+          value={value.kind}
+          onChange={(e) => update({ kind: e.target.value as SelfTestConfig['kind'] })}
+          className={selectClass}
+        >
+          <option value="quick">Quick</option>
+          <option value="deep">Deep</option>
+        </select>
+      </div>
+
+      <div className="grid grid-cols-12 gap-2 items-center">
+        <select
+          value={schedule.type}
+          onChange={(e) =>
+            update({ schedule: e.target.value === 'weekly' ? { type: 'weekly', weekday: 0 } : { type: 'monthly', day: 1 } })
+          }
+          className={`col-span-3 ${selectClass}`}
+        >
+          <option value="weekly">Weekly</option>
+          <option value="monthly">Monthly</option>
+        </select>
+        {schedule.type === 'weekly' ? (
+          <select
+            value={schedule.weekday}
+            onChange={(e) => update({ schedule: { type: 'weekly', weekday: parseInt(e.target.value) } })}
+            className={`col-span-3 ${selectClass}`}
+          >
+            {WEEKDAYS.map((day, i) => (
+              <option key={day} value={i}>{day}</option>
+            ))}
+          </select>
+        ) : (
+          <div className="col-span-3 flex items-center gap-2">
+            <span className={labelClass}>Day</span>
+            <Input
+              value={schedule.day}
+              onChange={(e) =>
+                update({ schedule: { type: 'monthly', day: Math.min(28, Math.max(1, parseInt(e.target.value) || 1)) } })
+              }
+              className={fieldClass}
+            />
+          </div>
+        )}
+        <Label className={`col-span-2 ${labelClass}`}>At Hour</Label>
+        <Input value={value.hour} onChange={(e) => update({ hour: hour(e.target.value) })} className={`col-span-2 ${fieldClass}`} />
+      </div>
+
+      <div className="grid grid-cols-12 gap-2 items-center">
+        <div className="col-span-3 flex items-center gap-2">
+          <input
+            type="checkbox"
+            id="selftest-quiet"
+            checked={value.quietHours != null}
+            onChange={(e) => update({ quietHours: e.target.checked ? { startHour: 22, endHour: 7 } : null })}
+            className="h-3.5 w-3.5 rounded border-border bg-muted checked:bg-primary"
+          />
+          <Label htmlFor="selftest-quiet" className={`${labelClass} cursor-pointer`}>Quiet Hours</Label>
+        </div>
+        {value.quietHours && (
+          <>
+            <Input
+              value={value.quietHours.startHour}
+              placeholder="From"
+              onChange={(e) => update({ quietHours: { ...value.quietHours!, startHour: hour(e.target.value) } })}
+              className={`col-span-2 ${fieldClass}`}
+            />
+            <Input
+              value={value.quietHours.endHour}
+              placeholder="To"
+              onChange={(e) => update({ quietHours: { ...value.quietHours!, endHour: hour(e.target.value) } })}
+              className={`col-span-2 ${fieldClass}`}
+            />
+          </>
+        )}
+      </div>
+
+      <div className="grid grid-cols-12 gap-2 items-center">
+        <Label className={`col-span-3 ${labelClass}`}>Min Charge %</Label>
+        <Input
+          value={value.minCharge}
+          onChange={(e) => update({ minCharge: Math.min(100, parseInt(e.target.value) || 0) })}
+          className={`col-span-2 ${fieldClass}`}
+        />
+        <Label className={`col-span-3 ${labelClass}`}>Timeout (min)</Label>
+        <Input
+          value={value.timeoutMin}
+          onChange={(e) => update({ timeoutMin: parseInt(e.target.value) || 1 })}
+          className={`col-span-2 ${fieldClass}`}
+        />
+      </div>
+    </div>
+  );
+}
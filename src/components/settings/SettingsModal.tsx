@@ -6,7 +6,14 @@ import { Input } from '@/components/ui/input';
 import { Label } from '@/components/ui/label';
 import { useUpsStore } from '@/store/upsStore';
 import { Settings, Server, Shield, LayoutGrid, HardDrive } from 'lucide-react';
-import { ShutdownType } from "../../types/ups";
+import { AlertsConfig, AppSettings, DEFAULT_ALERTS, DEFAULT_LOAD_SHEDDING, DEFAULT_NOTIFICATIONS, DEFAULT_SELF_TEST, DEFAULT_SNMP_SETTINGS, DEFAULT_TARIFF, DEFAULT_TELEGRAM, LoadSheddingConfig, NotificationsConfig, RuntimeSource, SelfTestConfig, ShutdownType, SnmpSettings, TariffConfig, TelegramSettings as Telegram, UpsBackend } from "../../types/ups";
+import { AlertSettings } from './AlertSettings';
+import { LoadSheddingSettings } from './LoadSheddingSettings';
+import { NotificationSettings } from './NotificationSettings';
+import { WebhookSettings } from './WebhookSettings';
+import { TelegramSettings } from './TelegramSettings';
+import { SelfTestSettings } from './SelfTestSettings';
+import { TariffSettings } from './TariffSettings';
 import { toast } from 'sonner';
 import { useUpdater } from '../../hooks/useUpdater';
 import { Download, RefreshCw, RotateCw } from 'lucide-react';
@@ -32,10 +39,34 @@ export function SettingsModal() {
     isEnabled().then(setAutostartProxy).catch(console.error);
   }, []);
 
+  // Settings only edited here live in the backend, so reload them when the modal opens
+  const [loadShedding, setLoadShedding] = useState<LoadSheddingConfig>(DEFAULT_LOAD_SHEDDING);
+  const [tariff, setTariff] = useState<TariffConfig>(DEFAULT_TARIFF);
+  const [selfTest, setSelfTest] = useState<SelfTestConfig>(DEFAULT_SELF_TEST);
+  const [alerts, setAlerts] = useState<AlertsConfig>(DEFAULT_ALERTS);
+  const [notifications, setNotifications] = useState<NotificationsConfig>(DEFAULT_NOTIFICATIONS);
+  const [smtpPassword, setSmtpPassword] = useState("");
+  const [webhookSecrets, setWebhookSecrets] = useState<Record<string, string>>({});
+  const [telegram, setTelegram] = useState<Telegram>(DEFAULT_TELEGRAM);
+  const [telegramToken, setTelegramToken] = useState("");
+
   // Sync autostart status when modal opens
   useEffect(() => {
     if (open) {
       isEnabled().then(setAutostartProxy).catch(console.error);
+      invoke<AppSettings>('get_settings')
+        .then((s) => {
+          setLoadShedding(s.loadShedding ?? DEFAULT_LOAD_SHEDDING);
+          setTariff(s.tariff ?? DEFAULT_TARIFF);
+          setSelfTest(s.selfTest ?? DEFAULT_SELF_TEST);
+          setAlerts(s.alerts ?? DEFAULT_ALERTS);
+          setNotifications({ ...DEFAULT_NOTIFICATIONS, ...s.notifications });
+          setSmtpPassword("");
+          setWebhookSecrets({});
+          setTelegram({ ...DEFAULT_TELEGRAM, ...s.telegram });
+          setTelegramToken("");
+        })
+        .catch(console.error);
     }
   }, [open]);
 
@@ -63,8 +94,11 @@ export function SettingsModal() {
   const [host, setHost] = useState(config?.host || "");
   const [port, setPort] = useState(config?.port?.toString() || "3493");
   const [username, setUsername] = useState(config?.username || "");
-  const [password, setPassword] = useState(config?.password || "");
+  // Only holds a newly typed password; the saved one stays in the OS keyring
+  const [password, setPassword] = useState("");
   const [upsName, setUpsName] = useState(config?.ups_name || "ups");
+  const [backend, setBackend] = useState<UpsBackend>(config?.backend ?? 'nut');
+  const [snmp, setSnmp] = useState<SnmpSettings>(config?.snmp ?? DEFAULT_SNMP_SETTINGS);
   const [ratedPowerInput, setRatedPowerInput] = useState(ratedPower?.toString() || "");
   const [fullLoadRuntimeInput, setFullLoadRuntimeInput] = useState(fullLoadRuntime?.toString() || "");
 
@@ -80,6 +114,7 @@ export function SettingsModal() {
   const [runtimeThreshold, setRuntimeThreshold] = useState(shutdownConfig.runtimeThreshold.toString());
   const [stopType, setStopType] = useState<ShutdownType>(shutdownConfig.stopType);
   const [delaySeconds, setDelaySeconds] = useState(shutdownConfig.delaySeconds.toString());
+  const [runtimeSource, setRuntimeSource] = useState<RuntimeSource>(shutdownConfig.runtimeSource ?? 'reported');
 
   // Test Countdown State
   const [testCountdown, setTestCountdown] = useState<number | null>(null);
@@ -89,8 +124,10 @@ export function SettingsModal() {
       setHost(config.host);
       setPort(config.port.toString());
       setUsername(config.username || "");
-      setPassword(config.password || "");
+      setPassword("");
       setUpsName(config.ups_name || "ups");
+      setBackend(config.backend ?? 'nut');
+      setSnmp(config.snmp ?? DEFAULT_SNMP_SETTINGS);
     }
   }, [config]);
 
@@ -135,25 +172,68 @@ export function SettingsModal() {
     }
   }, [testCountdown, stopType]);
 
-  const handleSave = async () => {
-    const newConfig = {
+  const buildSettings = (): AppSettings => ({
+    version: 1,
+    connection: {
+      backend,
       host,
       port: parseInt(port) || 3493,
-      username,
-      password,
-      ups_name: upsName
-    };
-    setConfig(newConfig);
-    setRatedPower(parseInt(ratedPowerInput) || 0);
-    setFullLoadRuntime(parseInt(fullLoadRuntimeInput) || 0);
-
-    setShutdownConfig({
+      username: username || null,
+      credentialId: config?.credential_id ?? null,
+      upsName,
+      snmp,
+    },
+    pollIntervalMs: 1000,
+    shutdown: {
       enabled: shutdownEnabled,
       batteryThreshold: parseInt(batteryThreshold) || 30,
       runtimeThreshold: parseInt(runtimeThreshold) || 120,
       stopType,
       delaySeconds: parseInt(delaySeconds) || 15,
-    });
+      runtimeSource,
+    },
+    loadShedding,
+    tariff,
+    selfTest,
+    alerts,
+    notifications,
+    telegram,
+  });
+
+  const handleSave = async () => {
+    const settings = buildSettings();
+    setRatedPower(parseInt(ratedPowerInput) || 0);
+    setFullLoadRuntime(parseInt(fullLoadRuntimeInput) || 0);
+
+    try {
+      // Backend owns the settings file and restarts monitoring with the new values
+      const saved = await invoke<AppSettings>("update_settings", {
+        settings,
+        password: password || null,
+        smtpPassword: smtpPassword || null,
+        webhookSecrets,
+        telegramToken: telegramToken || null,
+      });
+      setNotifications({ ...DEFAULT_NOTIFICATIONS, ...saved.notifications });
+      setSmtpPassword("");
+      setWebhookSecrets({});
+      setTelegram({ ...DEFAULT_TELEGRAM, ...saved.telegram });
+      setTelegramToken("");
+      setConfig({
+        host,
+        port: saved.connection.port,
+        username,
+        credential_id: saved.connection.credentialId,
+        ups_name: upsName,
+        backend,
+        snmp
+      });
+    } catch (e) {
+      toast.error(`Failed to apply settings: ${e}`);
+      return;
+    }
+
+    setShutdownConfig(settings.shutdown);
 
     toast.success("Settings saved successfully");
     setOpen(false);
@@ -161,16 +241,31 @@ export function SettingsModal() {
 
   const handleConnect = async () => {
     try {
+      const settings = buildSettings();
+      const saved = await invoke<AppSettings>("update_settings", {
+        settings,
+        password: password || null,
+        smtpPassword: smtpPassword || null,
+        webhookSecrets,
+        telegramToken: telegramToken || null,
+      });
+      setNotifications({ ...DEFAULT_NOTIFICATIONS, ...saved.notifications });
+      setSmtpPassword("");
+      setWebhookSecrets({});
+      setTelegram({ ...DEFAULT_TELEGRAM, ...saved.telegram });
+      setTelegramToken("");
+
       const newConfig = {
         host,
-        port: parseInt(port) || 3493,
+        port: saved.connection.port,
         username,
-        password,
-        ups_name: upsName
+        credential_id: saved.connection.credentialId,
+        ups_name: upsName,
+        backend,
+        snmp
       };
-
-      await invoke("connect_nut", { config: newConfig });
       setConfig(newConfig);
+      setShutdownConfig(settings.shutdown);
       setConnected(true);
 
       try {
@@ -180,18 +275,6 @@ export function SettingsModal() {
         console.warn("Fetch commands failed:", e);
       }
 
-      await invoke("start_background_polling", {
-        upsName: newConfig.ups_name,
-        intervalMs: 1000,
-        shutdownConfig: {
-          enabled: shutdownEnabled,
-          batteryThreshold: parseInt(batteryThreshold) || 30,
-          runtimeThreshold: parseInt(runtimeThreshold) || 120,
-          stopType,
-          delaySeconds: parseInt(delaySeconds) || 15,
-        }
-      });
-
       toast.success(`Connected to ${newConfig.ups_name} successfully`);
       setOpen(false);
     } catch (e) {
@@ -444,6 +527,72 @@ export function SettingsModal() {
               </div>
             ) : activeTab === 'connection' ? (
               <div className="grid gap-5">
+                <div className="grid grid-cols-4 items-center gap-4">
+                  <Label className="text-right text-[11px] font-bold text-muted-foreground uppercase">Protocol</Label>
+                  <div className="col-span-3 grid grid-cols-4 gap-2">
+                    {([['nut', 'NUT (upsd)', 3493], ['apcupsd', 'apcupsd NIS', 3551], ['snmp', 'SNMP', 161], ['simulator', 'Virtual UPS', null]] as const).map(([value, label, defaultPort]) => (
+                      <button
+                        key={value}
+                        onClick={() => {
+                          setBackend(value);
+                          // Swap the default port unless the user picked a custom one
+                          if (defaultPort && ['3493', '3551', '161'].includes(port)) setPort(String(defaultPort));
+                        }}
+                        className={`h-8 rounded-md border text-[10px] font-bold transition-all ${backend === value ? 'border-primary bg-primary/10 text-primary' : 'border-border hover:bg-muted'}`}
+                      >
+                        {label}
+                      </button>
+                    ))}
+                  </div>
+                </div>
+                {backend === 'simulator' && (
+                  <p className="text-[10px] text-muted-foreground text-right">Monitors a virtual UPS for demos and training. Shutdowns are never executed.</p>
+                )}
+                {backend === 'snmp' && (
+                  <div className="grid grid-cols-4 items-center gap-4">
+                    <Label className="text-right text-[11px] font-bold text-muted-foreground uppercase">SNMP</Label>
+                    <div className="col-span-3 grid grid-cols-3 gap-2">
+                      <select
+                        value={snmp.version}
+                        onChange={(e) => setSnmp({ ...snmp, version: e.target.value as SnmpSettings['version'] })}
+                        className="h-8 bg-muted/20 border border-border/50 rounded-md px-2 text-[11px] font-bold focus:outline-none focus:ring-1 focus:ring-primary"
+                      >
+                        <option value="v2c">v2c</option>
+                        <option value="v3">v3</option>
+                      </select>
+                      {snmp.version === 'v2c' ? (
+                        <Input
+                          value={snmp.community}
+                          onChange={(e) => setSnmp({ ...snmp, community: e.target.value })}
+                          placeholder="Community"
+                          className="col-span-2 h-8 text-[11px] bg-muted/20 border-border/50 focus:bg-background transition-all"
+                        />
+                      ) : (
+                        <>
+                          {/* v3 uses the Username and Password fields below as USM user and passphrase */}
+                          <select
+                            value={snmp.authProtocol}
+                            onChange={(e) => setSnmp({ ...snmp, authProtocol: e.target.value as SnmpSettings['authProtocol'] })}
+                            className="h-8 bg-muted/20 border border-border/50 rounded-md px-2 text-[11px] font-bold focus:outline-none focus:ring-1 focus:ring-primary"
+                          >
+                            <option value="none">No auth</option>
+                            <option value="md5">MD5</option>
+                            <option value="sha">SHA</option>
+                          </select>
+                          <select
+                            value={snmp.privProtocol}
+                            onChange={(e) => setSnmp({ ...snmp, privProtocol: e.target.value as SnmpSettings['privProtocol'] })}
+                            className="h-8 bg-muted/20 border border-border/50 rounded-md px-2 text-[11px] font-bold focus:outline-none focus:ring-1 focus:ring-primary"
+                          >
+                            <option value="none">No privacy</option>
+                            <option value="des">DES</option>
+                            <option value="aes">AES</option>
+                          </select>
+                        </>
+                      )}
+                    </div>
+                  </div>
+                )}
                 <div className="grid grid-cols-4 items-start gap-4">
                   <Label htmlFor="host" className="text-right text-[11px] font-bold text-muted-foreground uppercase pt-2">Host</Label>
                   <div className="col-span-3 space-y-2">
@@ -518,7 +667,7 @@ export function SettingsModal() {
                 </div>
                 <div className="grid grid-cols-4 items-center gap-4">
                   <Label htmlFor="password" className="text-right text-[11px] font-bold text-muted-foreground uppercase">Pass</Label>
-                  <Input id="password" type="password" value={password} onChange={(e) => setPassword(e.target.value)} placeholder="••••••••" className="col-span-3 h-8 text-[11px] bg-muted/20 border-border/50 focus:bg-background transition-all" />
+                  <Input id="password" type="password" value={password} onChange={(e) => setPassword(e.target.value)} placeholder={config?.credential_id ? "Stored securely" : "••••••••"} className="col-span-3 h-8 text-[11px] bg-muted/20 border-border/50 focus:bg-background transition-all" />
                 </div>
                 <div className="grid grid-cols-4 items-center gap-4">
                   <Label htmlFor="rated" className="text-right text-[11px] font-bold text-muted-foreground uppercase">Power</Label>
@@ -564,6 +713,7 @@ export function SettingsModal() {
                       Process Cleanup Now
                     </Button>
                   </div>
+                  <TariffSettings value={tariff} onChange={setTariff} />
                 </div>
               </div>
             ) : (
@@ -592,6 +742,15 @@ export function SettingsModal() {
                   <div className="col-span-3 flex items-center gap-2">
                     <Input value={runtimeThreshold} onChange={(e) => setRuntimeThreshold(e.target.value)} className="h-8 text-[11px] bg-muted/20 border-border/50" />
                     <span className="text-[10px] text-muted-foreground font-bold">SEC</span>
+                    <select
+                      value={runtimeSource}
+                      onChange={(e) => setRuntimeSource(e.target.value as RuntimeSource)}
+                      title="Predicted uses the lower bound of the runtime learned from past outages while on battery"
+                      className="h-8 bg-muted/20 border border-border/50 rounded-md px-2 text-[10px] font-bold focus:outline-none focus:ring-1 focus:ring-primary"
+                    >
+                      <option value="reported">Reported</option>
+                      <option value="predicted">Predicted</option>
+                    </select>
                   </div>
                 </div>
 
@@ -653,6 +812,28 @@ export function SettingsModal() {
                     </Button>
                   )}
                 </div>
+
+                <LoadSheddingSettings value={loadShedding} onChange={setLoadShedding} />
+                <SelfTestSettings value={selfTest} onChange={setSelfTest} />
+                <AlertSettings value={alerts} onChange={setAlerts} />
+                <NotificationSettings
+                  value={notifications}
+                  onChange={setNotifications}
+                  smtpPassword={smtpPassword}
+                  onSmtpPasswordChange={setSmtpPassword}
+                />
+                <WebhookSettings
+                  value={notifications.webhooks}
+                  onChange={(webhooks) => setNotifications({ ...notifications, webhooks })}
+                  secrets={webhookSecrets}
+                  onSecretsChange={setWebhookSecrets}
+                />
+                <TelegramSettings
+                  value={telegram}
+                  onChange={setTelegram}
+                  token={telegramToken}
+                  onTokenChange={setTelegramToken}
+                />
               </div>
             )}
           </div>
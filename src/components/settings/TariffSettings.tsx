@@ -0,0 +1,207 @@
+import { useEffect, useState } from 'react';
+import { Input } from '@/components/ui/input';
+import { Label } from '@/components/ui/label';
+import { Button } from '@/components/ui/button';
+import { Trash2 } from 'lucide-react';
+import { TariffConfig, TariffPeriod, TariffRate, TariffTier } from '../../types/ups';
+
+const fieldClass = 'h-7 text-[11px] bg-muted/20 border-border/50';
+
+// Decimal input that only reports its value once editing is done, so "0." can be typed
+function NumberField({
+  value,
+  onChange,
+  placeholder,
+  className,
+}: {
+  value: number | null;
+  onChange: (value: number | null) => void;
+  placeholder?: string;
+  className?: string;
+}) {
+  const [text, setText] = useState(value == null ? '' : String(value));
+  useEffect(() => setText(value == null ? '' : String(value)), [value]);
+  return (
+    <Input
+      value={text}
+      placeholder={placeholder}
+      onChange={(e) => setText(e.target.value)}
+      onBlur={() => {
+        const n = parseFloat(text);
+        onChange(Number.isFinite(n) ? n : null);
+      }}
+      className={`${fieldClass} ${className ?? ''}`}
+    />
+  );
+}
+
+const defaultRate = (type: TariffRate['type'], price: number): TariffRate => {
+  switch (type) {
+    case 'timeOfUse':
+      return { type, defaultPrice: price, periods: [{ startHour: 22, endHour: 6, pricePerKwh: price / 2 }] };
+    case 'tiered':
+      return { type, tiers: [{ upToKwh: 300, pricePerKwh: price }, { upToKwh: null, pricePerKwh: price * 1.5 }] };
+    default:
+      return { type: 'flat', pricePerKwh: price };
+  }
+};
+
+const basePrice = (rate: TariffRate) =>
+  rate.type === 'flat' ? rate.pricePerKwh : rate.type === 'timeOfUse' ? rate.defaultPrice : rate.tiers[0]?.pricePerKwh ?? 0;
+
+interface Props {
+  value: TariffConfig;
+  onChange: (value: TariffConfig) => void;
+}
+
+export function TariffSettings({ value, onChange }: Props) {
+  const update = (patch: Partial<TariffConfig>) => onChange({ ...value, ...patch });
+  const rate = value.rate;
+
+  const updatePeriod = (index: number, patch: Partial<TariffPeriod>) => {
+    if (rate.type !== 'timeOfUse') return;
+    update({ rate: { ...rate, periods: rate.periods.map((p, i) => (i === index ? { ...p, ...patch } : p)) } });
+  };
+  const updateTier = (index: number, patch: Partial<TariffTier>) => {
+    if (rate.type !== 'tiered') return;
+    update({ rate: { ...rate, tiers: rate.tiers.map((t, i) => (i === index ? { ...t, ...patch } : t)) } });
+  };
+
+  return (
+    <div className="p-4 bg-muted/10 border border-border rounded-lg space-y-3">
+      <h4 className="text-xs font-bold uppercase">Electricity Tariff</h4>
+      <div className="grid grid-cols-12 gap-2 items-center">
+        <Label className="col-span-2 text-[10px] font-bold uppercase text-muted-foreground">Currency</Label>
+        <Input
+          value={value.currency}
+          onChange={(e) => update({ currency: e.target.value.toUpperCase() })}
+          className={`col-span-2 ${fieldClass}`}
+        />
+        <Label className="col-span-2 text-[10px] font-bold uppercase text-muted-foreground">Rate</Label>
+        <select
+          value={rate.type}
+          onChange={(e) => update({ rate: defaultRate(e.target.value as TariffRate['type'], basePrice(rate)) })}
+          className="col-span-2 h-7 bg-muted/20 border border-border/50 rounded-md px-1 text-[10px] font-bold"
+        >
+          <option value="flat">Flat</option>
+          <option value="timeOfUse">Time of use</option>
+          <option value="tiered">Tiered</option>
+        </select>
+        <Label className="col-span-2 text-[10px] font-bold uppercase text-muted-foreground" title="kg CO2 per kWh">CO2 / kWh</Label>
+        <NumberField
+          value={value.carbonKgPerKwh}
+          onChange={(n) => update({ carbonKgPerKwh: n ?? 0 })}
+          className="col-span-2"
+        />
+      </div>
+
+      {rate.type === 'flat' && (
+        <div className="grid grid-cols-12 gap-2 items-center">
+          <Label className="col-span-4 text-[10px] font-bold uppercase text-muted-foreground">Price per kWh</Label>
+          <NumberField
+            value={rate.pricePerKwh}
+            onChange={(n) => update({ rate: { type: 'flat', pricePerKwh: n ?? 0 } })}
+            className="col-span-3"
+          />
+        </div>
+      )}
+
+      {rate.type === 'timeOfUse' && (
+        <div className="grid gap-2">
+          <div className="grid grid-cols-12 gap-2 items-center">
+            <Label className="col-span-4 text-[10px] font-bold uppercase text-muted-foreground">Other hours</Label>
+            <NumberField
+              value={rate.defaultPrice}
+              onChange={(n) => update({ rate: { ...rate, defaultPrice: n ?? 0 } })}
+              className="col-span-3"
+            />
+          </div>
+          {rate.periods.map((period, index) => (
+            <div key={index} className="grid grid-cols-12 gap-2 items-center">
+              <NumberField
+                value={period.startHour}
+                placeholder="From"
+                onChange={(n) => updatePeriod(index, { startHour: Math.round(n ?? 0) })}
+                className="col-span-2"
+              />
+              <NumberField
+                value={period.endHour}
+                placeholder="To"
+                onChange={(n) => updatePeriod(index, { endHour: Math.round(n ?? 0) })}
+                className="col-span-2"
+              />
+              <NumberField
+                value={period.pricePerKwh}
+                placeholder="Price"
+                onChange={(n) => updatePeriod(index, { pricePerKwh: n ?? 0 })}
+                className="col-span-3"
+              />
+              <div className="col-span-4 flex items-center gap-2">
+                <input
+                  type="checkbox"
+                  id={`tariff-weekdays-${index}`}
+                  checked={!!period.weekdaysOnly}
+                  onChange={(e) => updatePeriod(index, { weekdaysOnly: e.target.checked })}
+                  className="h-3.5 w-3.5 rounded border-border bg-muted checked:bg-primary"
+                />
+                <Label htmlFor={`tariff-weekdays-${index}`} className="text-[10px] font-bold uppercase text-muted-foreground cursor-pointer">Weekdays only</Label>
+              </div>
+              <button
+                onClick={() => update({ rate: { ...rate, periods: rate.periods.filter((_, i) => i !== index) } })}
+                className="col-span-1 flex justify-center text-muted-foreground hover:text-destructive"
+                title="Remove period"
+              >
+                <Trash2 className="h-3.5 w-3.5" />
+              </button>
+            </div>
+          ))}
+          <Button
+            variant="outline"
+            onClick={() =>
+              update({ rate: { ...rate, periods: [...rate.periods, { startHour: 7, endHour: 22, pricePerKwh: rate.defaultPrice }] } })
+            }
+            className="h-7 text-[10px] font-bold uppercase tracking-widest border-dashed"
+          >
+            Add Period
+          </Button>
+        </div>
+      )}
+
+      {rate.type === 'tiered' && (
+        <div className="grid gap-2">
+          <p className="text-[10px] text-muted-foreground">Monthly kWh each tier ends at; leave the last one empty.</p>
+          {rate.tiers.map((tier, index) => (
+            <div key={index} className="grid grid-cols-12 gap-2 items-center">
+              <NumberField
+                value={tier.upToKwh}
+                placeholder="No limit"
+                onChange={(n) => updateTier(index, { upToKwh: n })}
+                className="col-span-4"
+              />
+              <NumberField
+                value={tier.pricePerKwh}
+                placeholder="Price"
+                onChange={(n) => updateTier(index, { pricePerKwh: n ?? 0 })}
+                className="col-span-3"
+              />
+              <button
+                onClick={() => update({ rate: { ...rate, tiers: rate.tiers.filter((_, i) => i !== index) } })}
+                className="col-span-1 flex justify-center text-muted-foreground hover:text-destructive"
+                title="Remove tier"
+              >
+                <Trash2 className="h-3.5 w-3.5" />
+              </button>
+            </div>
+          ))}
+          <Button
+            variant="outline"
+            onClick={() => update({ rate: { ...rate, tiers: [...rate.tiers, { upToKwh: null, pricePerKwh: basePrice(rate) }] } })}
+            className="h-7 text-[10px] font-bold uppercase tracking-widest border-dashed"
+          >
+            Add Tier
+          </Button>
+        </div>
+      )}
+    </div>
+  );
+}
@@ -0,0 +1,91 @@
+import { useEffect, useState } from 'react';
+import { Input } from '@/components/ui/input';
+import { Label } from '@/components/ui/label';
+import { NOTIFICATION_KINDS, TelegramSettings as Telegram } from '../../types/ups';
+
+const fieldClass = 'h-7 text-[11px] bg-muted/20 border-border/50';
+const selectClass = 'h-7 bg-muted/20 border border-border/50 rounded-md px-1 text-[10px] font-bold';
+const labelClass = 'text-[10px] font-bold uppercase text-muted-foreground';
+
+const parseChats = (text: string) =>
+  text
+    .split(/[\s,]+/)
+    .map((id) => parseInt(id))
+    .filter((id) => !isNaN(id));
+
+interface Props {
+  value: Telegram;
+  onChange: (value: Telegram) => void;
+  // Only holds a newly typed bot token; the saved one stays in the OS keyring
+  token: string;
+  onTokenChange: (token: string) => void;
+}
+
+export function TelegramSettings({ value, onChange, token, onTokenChange }: Props) {
+  const update = (patch: Partial<Telegram>) => onChange({ ...value, ...patch });
+  // Kept as typed so a half-entered "-100" survives until the chat ids are parsed on blur
+  const [chats, setChats] = useState(value.allowedChats.join(', '));
+  useEffect(() => setChats(value.allowedChats.join(', ')), [value.allowedChats]);
+
+  return (
+    <div className="grid gap-3 pt-4 border-t border-border/20">
+      <div className="flex items-center gap-2">
+        <input
+          type="checkbox"
+          id="telegram-bot"
+          checked={value.enabled}
+          onChange={(e) => update({ enabled: e.target.checked })}
+          className="h-3.5 w-3.5 rounded border-border bg-muted checked:bg-primary"
+        />
+        <Label htmlFor="telegram-bot" className="text-[11px] font-bold uppercase tracking-wider cursor-pointer">
+          Telegram Bot
+        </Label>
+      </div>
+
+      {value.enabled && (
+        <div className="grid gap-2">
+          <div className="grid grid-cols-12 gap-2 items-center">
+            <Label className={`col-span-3 ${labelClass}`}>Bot token</Label>
+            <Input
+              type="password"
+              value={token}
+              placeholder={value.credentialId ? 'Stored securely' : 'From @BotFather'}
+              onChange={(e) => onTokenChange(e.target.value)}
+              className={`col-span-9 ${fieldClass}`}
+            />
+          </div>
+          <div className="grid grid-cols-12 gap-2 items-center">
+            <Label className={`col-span-3 ${labelClass}`}>Allowed chats</Label>
+            <Input
+              value={chats}
+              placeholder="Chat ids, comma separated"
+              title="Other chats are ignored; their ids show up in the log when they write to the bot"
+              onChange={(e) => setChats(e.target.value)}
+              onBlur={() => update({ allowedChats: parseChats(chats) })}
+              className={`col-span-9 ${fieldClass} font-mono`}
+            />
+          </div>
+          <div className="grid grid-cols-12 gap-2 items-center">
+            <Label className={`col-span-3 ${labelClass}`}>Notify on</Label>
+            <select
+              multiple
+              value={value.events}
+              onChange={(e) =>
+                update({ events: Array.from(e.target.selectedOptions, (o) => o.value as Telegram['events'][number]) })
+              }
+              className={`col-span-9 h-16 ${selectClass}`}
+              title="Events pushed to the allowed chats"
+            >
+              {NOTIFICATION_KINDS.map(({ kind, label }) => (
+                <option key={kind} value={kind}>{label}</option>
+              ))}
+            </select>
+          </div>
+          <p className="text-[10px] text-muted-foreground">
+            Answers /status, /history 24h, /abort and /cmd; instant commands run only after confirming.
+          </p>
+        </div>
+      )}
+    </div>
+  );
+}
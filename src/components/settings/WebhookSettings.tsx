@@ -0,0 +1,112 @@
+import { Input } from '@/components/ui/input';
+import { Label } from '@/components/ui/label';
+import { Button } from '@/components/ui/button';
+import { Trash2 } from 'lucide-react';
+import { NOTIFICATION_KINDS, WEBHOOK_FORMATS, Webhook, WebhookFormat } from '../../types/ups';
+
+const fieldClass = 'h-7 text-[11px] bg-muted/20 border-border/50';
+const selectClass = 'h-7 bg-muted/20 border border-border/50 rounded-md px-1 text-[10px] font-bold';
+const labelClass = 'text-[10px] font-bold uppercase text-muted-foreground';
+
+interface Props {
+  value: Webhook[];
+  onChange: (value: Webhook[]) => void;
+  // Newly typed secrets by webhook name; saved ones stay in the OS keyring
+  secrets: Record<string, string>;
+  onSecretsChange: (secrets: Record<string, string>) => void;
+}
+
+export function WebhookSettings({ value, onChange, secrets, onSecretsChange }: Props) {
+  const update = (index: number, patch: Partial<Webhook>) =>
+    onChange(value.map((w, i) => (i === index ? { ...w, ...patch } : w)));
+
+  return (
+    <div className="grid gap-3 pt-4 border-t border-border/20">
+      <Label className="text-[11px] font-bold uppercase tracking-wider">Webhooks</Label>
+
+      {value.map((webhook, index) => {
+        const secretLabel = WEBHOOK_FORMATS.find((f) => f.format === webhook.format)?.secret;
+        return (
+          <div key={index} className="grid gap-1.5">
+            <div className="grid grid-cols-12 gap-2 items-center">
+              <Input
+                value={webhook.name}
+                placeholder="Name"
+                onChange={(e) => update(index, { name: e.target.value })}
+                className={`col-span-3 ${fieldClass}`}
+              />
+              <select
+                value={webhook.format}
+                onChange={(e) => update(index, { format: e.target.value as WebhookFormat })}
+                className={`col-span-2 ${selectClass}`}
+              >
+                {WEBHOOK_FORMATS.map(({ format, label }) => (
+                  <option key={format} value={format}>{label}</option>
+                ))}
+              </select>
+              <Input
+                value={webhook.url}
+                placeholder={webhook.format === 'ntfy' ? 'https://ntfy.sh/my-ups' : 'https://'}
+                onChange={(e) => update(index, { url: e.target.value.trim() })}
+                className={`col-span-6 ${fieldClass} font-mono`}
+              />
+              <button
+                onClick={() => onChange(value.filter((_, i) => i !== index))}
+                className="col-span-1 flex justify-center text-muted-foreground hover:text-destructive"
+                title="Remove webhook"
+              >
+                <Trash2 className="h-3.5 w-3.5" />
+              </button>
+            </div>
+            <div className="grid grid-cols-12 gap-2 items-center">
+              <Label className={`col-span-3 ${labelClass}`}>{secretLabel ?? 'No secret'}</Label>
+              <Input
+                type="password"
+                value={secrets[webhook.name] ?? ''}
+                disabled={!secretLabel || !webhook.name}
+                placeholder={webhook.credentialId ? 'Stored securely' : 'Optional'}
+                onChange={(e) => onSecretsChange({ ...secrets, [webhook.name]: e.target.value })}
+                className={`col-span-4 ${fieldClass}`}
+              />
+              <select
+                multiple
+                value={webhook.events}
+                onChange={(e) =>
+                  update(index, {
+                    events: Array.from(e.target.selectedOptions, (o) => o.value as Webhook['events'][number]),
+                  })
+                }
+                className={`col-span-5 h-16 ${selectClass}`}
+                title="Events sent to this webhook"
+              >
+                {NOTIFICATION_KINDS.map(({ kind, label }) => (
+                  <option key={kind} value={kind}>{label}</option>
+                ))}
+              </select>
+            </div>
+          </div>
+        );
+      })}
+
+      <Button
+        variant="outline"
+        size="sm"
+        onClick={() =>
+          onChange([
+            ...value,
+            {
+              name: `Webhook ${value.length + 1}`,
+              url: '',
+              format: 'json',
+              credentialId: null,
+              events: NOTIFICATION_KINDS.map((k) => k.kind),
+            },
+          ])
+        }
+        className="h-7 text-[10px] font-bold uppercase tracking-widest border-dashed"
+      >
+        Add Webhook
+      </Button>
+    </div>
+  );
+}
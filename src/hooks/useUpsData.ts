@@ -1,19 +1,34 @@
 import { useEffect } from 'react';
 import { listen } from '@tauri-apps/api/event';
 import { useUpsStore } from '../store/upsStore';
-import { UpsData } from '../types/ups';
+import { AlertEvent, UpsData } from '../types/ups';
 
 export function useUpsData() {
   const setUpsData = useUpsStore((state) => state.setUpsData);
+  const addEvent = useUpsStore((state) => state.addEvent);
 
   useEffect(() => {
     const unlisten = listen<UpsData>('ups-update', (event) => {
       console.log('Received UPS update:', event.payload);
       setUpsData(event.payload);
     });
+    const unlistenShedding = listen<{ step: string; outcome: string }>('load-shedding', (event) => {
+      addEvent(`Load shedding: ${event.payload.step} (${event.payload.outcome})`, 'warning');
+    });
+    const unlistenSelfTest = listen<string>('self-test', (event) => {
+      addEvent(event.payload, 'info');
+    });
+    const unlistenAlert = listen<AlertEvent>('alert', (event) => {
+      const alert = event.payload;
+      const type = alert.state === 'resolved' || alert.severity === 'info' ? 'info' : alert.severity === 'critical' ? 'error' : 'warning';
+      addEvent(`Alert ${alert.rule} ${alert.state}: ${alert.detail}`, type);
+    });
 
     return () => {
       unlisten.then((f) => f());
+      unlistenShedding.then((f) => f());
+      unlistenSelfTest.then((f) => f());
+      unlistenAlert.then((f) => f());
     };
-  }, [setUpsData]);
+  }, [setUpsData, addEvent]);
 }
@@ -19,6 +19,7 @@ export const useUpsStore = create<UpsState>()(
         runtimeThreshold: 120,
         stopType: 'Shutdown',
         delaySeconds: 15,
+        runtimeSource: 'reported',
       },
       supportedCommands: [],
       theme: 'system',
@@ -91,6 +92,18 @@ export const useUpsStore = create<UpsState>()(
       setShutdownConfig: (shutdownConfig) => set({ shutdownConfig }),
       setSupportedCommands: (supportedCommands) => set({ supportedCommands }),
       setTheme: (theme) => set({ theme }),
+      addEvent: (message, type) =>
+        set((state) => ({
+          events: [
+            {
+              id: Math.random().toString(36).substring(2, 9),
+              time: new Date().toLocaleTimeString([], { hour: '2-digit', minute: '2-digit', second: '2-digit' }),
+              message,
+              type,
+            },
+            ...(state.events || []),
+          ].slice(0, 10),
+        })),
       clearEvents: () => set({ events: [] }),
     }),
     {
@@ -29,15 +29,182 @@ export interface UpsData {
   output_current?: number;
   battery_current?: number;
   ups_realpower?: number;
+  /** How power_watts was obtained */
+  power_method?: PowerMethod | null;
+  runtime_estimate?: RuntimeEstimate | null;
   extended_vars?: Record<string, string>;
+
+  // Typed views of extended_vars (see src-tauri/src/nut/models.rs)
+  input?: LineDetails;
+  output?: LineDetails;
+  transfer?: TransferDetails;
+  battery?: BatteryDetails;
+  device?: DeviceDetails;
+  ambient?: AmbientSensor[];
+  outlets?: Outlet[];
+  alarms?: string[];
+}
+
+export interface PhaseReading {
+  phase: number;
+  voltage?: number;
+  line_voltage?: number;
+  current?: number;
+  realpower?: number;
+  power?: number;
+  load?: number;
+}
+
+export interface LineDetails {
+  phases?: number;
+  voltage_nominal?: number;
+  frequency_nominal?: number;
+  current?: number;
+  current_nominal?: number;
+  realpower?: number;
+  power?: number;
+  power_nominal?: number;
+  per_phase: PhaseReading[];
+  voltage_imbalance?: number; // % deviation from the phase average
+  current_imbalance?: number;
+}
+
+export interface TransferDetails {
+  low?: number;
+  high?: number;
+  boost_low?: number;
+  boost_high?: number;
+  trim_low?: number;
+  trim_high?: number;
+  reason?: string;
+  sensitivity?: string;
+}
+
+export interface BatteryDetails {
+  charge_low?: number;
+  charge_warning?: number;
+  charge_restart?: number;
+  runtime_low?: number;
+  voltage_nominal?: number;
+  voltage_low?: number;
+  voltage_high?: number;
+  capacity?: number;
+  temperature?: number;
+  status?: string;
+  charger_status?: string;
+  date?: string;
+  mfr_date?: string;
+  packs?: number;
+  packs_bad?: number;
+}
+
+export interface DeviceDetails {
+  id?: string;
+  location?: string;
+  contact?: string;
+  mfr_date?: string;
+  temperature?: number;
+  efficiency?: number;
+  power?: number;
+  power_nominal?: number;
+  uptime?: number;
+  delay_shutdown?: number;
+  delay_start?: number;
+  timer_shutdown?: number;
+  timer_start?: number;
+  test_result?: string;
+  test_date?: string;
+}
+
+export interface AmbientSensor {
+  index: number; // 0 = unnumbered ambient.* sensor
+  name?: string;
+  temperature?: number;
+  humidity?: number;
+  temperature_high?: number;
+  temperature_low?: number;
+  humidity_high?: number;
+  humidity_low?: number;
+}
+
+export interface Outlet {
+  index: number; // 0 = main outlet
+  id?: string;
+  name?: string;
+  status?: string;
+  switchable?: boolean;
+  current?: number;
+  realpower?: number;
+  power?: number;
+  delay_shutdown?: number;
+  delay_start?: number;
+}
+
+export type OutletAction = 'on' | 'off' | 'cycle';
+
+/** A checked outlet switch returned by prepare_outlet_switch, confirmed by token. */
+export interface OutletSwitch {
+  token: string;
+  upsName: string;
+  index: number;
+  name: string;
+  action: OutletAction;
+  command: string;
+  status?: string | null;
+}
+
+/** Result of a tracked instant command. */
+export type CommandOutcome =
+  | { state: 'sent' }
+  | { state: 'succeeded' }
+  | { state: 'failed'; detail: string }
+  | { state: 'pending' };
+
+export type PowerMethod =
+  | 'measured'
+  | 'loadTimesRealpowerNominal'
+  | 'apparentTimesPowerFactor'
+  | 'voltageTimesCurrent';
+
+export const POWER_METHOD_LABELS: Record<PowerMethod, string> = {
+  measured: 'Measured',
+  loadTimesRealpowerNominal: 'Load × rated W',
+  apparentTimesPowerFactor: 'VA × power factor',
+  voltageTimesCurrent: 'V × A × power factor',
+};
+
+export type EnergyPeriod = 'hour' | 'day' | 'month';
+
+/** Energy used in one hour, day or month (get_energy). */
+export interface EnergyBucket {
+  start: number;
+  kwh: number;
+}
+
+export interface JournalEntry {
+  id: number;
+  timestamp: number;
+  category: string;
+  message: string;
+  detail?: string | null;
+}
+
+/** Entry from the backend's NUT variable dictionary (describe_variables). */
+export interface VariableInfo {
+  name: string;
+  kind: 'number' | 'text';
+  unit?: string | null;
+  description: string;
 }
 
 export interface NutConfig {
   host: string;
   port: number;
   username?: string;
-  password?: string;
+  credential_id?: string | null; // Password lives in the OS keyring, see src-tauri/src/credentials.rs
   ups_name: string;
+  backend?: UpsBackend;
+  snmp?: SnmpSettings;
 }
 
 export type ShutdownType = 'Shutdown' | 'Hibernate' | 'Sleep';
@@ -48,6 +215,377 @@ export interface ShutdownConfig {
   runtimeThreshold: number; // seconds
   stopType: ShutdownType;
   delaySeconds: number;
+  runtimeSource?: RuntimeSource;
+}
+
+// Which runtime the runtime threshold is compared with
+export type RuntimeSource = 'reported' | 'predicted';
+
+/** Runtime predicted from past discharges (seconds), with its 95% band. */
+export interface RuntimeEstimate {
+  runtime: number;
+  low: number;
+  high: number;
+  samples: number;
+}
+
+// Mirrors `Backend` in src-tauri/src/settings.rs
+export type UpsBackend = 'nut' | 'apcupsd' | 'snmp' | 'simulator';
+
+// Mirrors `SnmpSettings` in src-tauri/src/settings.rs. For v3 the connection's
+// username is the USM user and its password the passphrase.
+export interface SnmpSettings {
+  version: 'v2c' | 'v3';
+  community: string;
+  authProtocol: 'none' | 'md5' | 'sha';
+  privProtocol: 'none' | 'des' | 'aes';
+}
+
+export const DEFAULT_SNMP_SETTINGS: SnmpSettings = {
+  version: 'v2c',
+  community: 'public',
+  authProtocol: 'none',
+  privProtocol: 'none',
+};
+
+export interface ConnectionSettings {
+  backend?: UpsBackend;
+  host: string;
+  port: number;
+  username?: string | null;
+  credentialId?: string | null;
+  upsName: string;
+  snmp?: SnmpSettings;
+}
+
+// Mirrors `SimEvent` in src-tauri/src/nut/simulator.rs
+export type SimEvent =
+  | { type: 'outage' }
+  | { type: 'restore' }
+  | { type: 'inputVoltage'; volts: number }
+  | { type: 'setLoad'; percent: number }
+  | { type: 'setSpeed'; factor: number };
+
+export type SheddingTrigger =
+  | { type: 'onBatteryFor'; minutes: number }
+  | { type: 'chargeBelow'; percent: number };
+
+export type SheddingAction =
+  | { type: 'outletOff'; outlet: number }
+  | { type: 'command'; command: string; restoreCommand?: string | null };
+
+export interface SheddingRule {
+  name: string;
+  trigger: SheddingTrigger;
+  actions: SheddingAction[];
+}
+
+export interface LoadSheddingConfig {
+  enabled: boolean;
+  dryRun: boolean;
+  restore: boolean;
+  restoreDelaySec: number;
+  stepDelayMs: number;
+  rules: SheddingRule[];
+}
+
+export const DEFAULT_LOAD_SHEDDING: LoadSheddingConfig = {
+  enabled: false,
+  dryRun: false,
+  restore: true,
+  restoreDelaySec: 60,
+  stepDelayMs: 2000,
+  rules: [],
+};
+
+export type SelfTestSchedule = { type: 'weekly'; weekday: number } | { type: 'monthly'; day: number };
+
+export interface SelfTestConfig {
+  enabled: boolean;
+  kind: 'quick' | 'deep';
+  schedule: SelfTestSchedule;
+  hour: number;
+  quietHours: { startHour: number; endHour: number } | null;
+  minCharge: number;
+  timeoutMin: number;
+}
+
+export const DEFAULT_SELF_TEST: SelfTestConfig = {
+  enabled: false,
+  kind: 'quick',
+  schedule: { type: 'monthly', day: 1 },
+  hour: 10,
+  quietHours: null,
+  minCharge: 90,
+  timeoutMin: 30,
+};
+
+export type AlertSeverity = 'info' | 'warning' | 'critical';
+
+export type AlertCondition =
+  | { type: 'above'; metric: string; value: number }
+  | { type: 'below'; metric: string; value: number }
+  | { type: 'flag'; flag: string };
+
+export interface AlertRule {
+  name: string;
+  condition: AlertCondition;
+  severity: AlertSeverity;
+  forSec: number;
+  hysteresis: number;
+  cooldownSec: number;
+}
+
+export interface AlertsConfig {
+  rules: AlertRule[];
+}
+
+export const DEFAULT_ALERTS: AlertsConfig = { rules: [] };
+
+export type NotificationKind =
+  | 'powerLost'
+  | 'powerRestored'
+  | 'lowBattery'
+  | 'replaceBattery'
+  | 'connectionLost'
+  | 'connectionRestored'
+  | 'shutdownScheduled'
+  | 'shutdownCancelled'
+  | 'alert';
+
+export const NOTIFICATION_KINDS: { kind: NotificationKind; label: string }[] = [
+  { kind: 'powerLost', label: 'Power lost' },
+  { kind: 'powerRestored', label: 'Power restored' },
+  { kind: 'lowBattery', label: 'Low battery' },
+  { kind: 'replaceBattery', label: 'Replace battery' },
+  { kind: 'connectionLost', label: 'Connection lost' },
+  { kind: 'connectionRestored', label: 'Connection restored' },
+  { kind: 'shutdownScheduled', label: 'Shutdown scheduled' },
+  { kind: 'shutdownCancelled', label: 'Shutdown cancelled' },
+  { kind: 'alert', label: 'Alerts' },
+];
+
+export interface DesktopNotifications {
+  enabled: boolean;
+  events: NotificationKind[];
+  minIntervalSec: number;
+}
+
+export type SmtpSecurity = 'startTls' | 'tls' | 'none';
+
+// Placeholders {title}, {body}, {severity}, {event} and {time} are filled in by the backend
+export interface EmailNotifications {
+  enabled: boolean;
+  host: string;
+  port: number;
+  security: SmtpSecurity;
+  username: string | null;
+  credentialId: string | null;
+  from: string;
+  to: string[];
+  events: NotificationKind[];
+  subject: string;
+  body: string;
+  settleSec: number;
+}
+
+export type WebhookFormat = 'json' | 'slack' | 'teams' | 'discord' | 'ntfy' | 'gotify';
+
+export const WEBHOOK_FORMATS: { format: WebhookFormat; label: string; secret: string | null }[] = [
+  { format: 'json', label: 'JSON', secret: 'Signing key' },
+  { format: 'slack', label: 'Slack', secret: null },
+  { format: 'teams', label: 'Teams', secret: null },
+  { format: 'discord', label: 'Discord', secret: null },
+  { format: 'ntfy', label: 'ntfy', secret: 'Access token' },
+  { format: 'gotify', label: 'Gotify', secret: 'App token' },
+];
+
+export interface Webhook {
+  name: string;
+  url: string;
+  format: WebhookFormat;
+  credentialId: string | null;
+  events: NotificationKind[];
+}
+
+export interface NotificationsConfig {
+  command: string | null;
+  desktop: DesktopNotifications;
+  email: EmailNotifications;
+  webhooks: Webhook[];
+}
+
+export const DEFAULT_EMAIL_NOTIFICATIONS: EmailNotifications = {
+  enabled: false,
+  host: '',
+  port: 587,
+  security: 'startTls',
+  username: null,
+  credentialId: null,
+  from: '',
+  to: [],
+  events: NOTIFICATION_KINDS.map((k) => k.kind),
+  subject: '[UPS] {title}',
+  body: '{body}\n\nSeverity: {severity}\nTime: {time}',
+  settleSec: 300,
+};
+
+export const DEFAULT_NOTIFICATIONS: NotificationsConfig = {
+  command: null,
+  desktop: { enabled: true, events: NOTIFICATION_KINDS.map((k) => k.kind), minIntervalSec: 60 },
+  email: DEFAULT_EMAIL_NOTIFICATIONS,
+  webhooks: [],
+};
+
+export interface TelegramSettings {
+  enabled: boolean;
+  credentialId: string | null;
+  allowedChats: number[];
+  events: NotificationKind[];
+  apiUrl: string;
+}
+
+export const DEFAULT_TELEGRAM: TelegramSettings = {
+  enabled: false,
+  credentialId: null,
+  allowedChats: [],
+  events: NOTIFICATION_KINDS.map((k) => k.kind),
+  apiUrl: 'https://api.telegram.org',
+};
+
+/** Payload of the `alert` event. */
+export interface AlertEvent {
+  rule: string;
+  severity: AlertSeverity;
+  state: 'fired' | 'resolved';
+  detail: string;
+}
+
+export interface TariffPeriod {
+  startHour: number;
+  endHour: number;
+  pricePerKwh: number;
+  weekdaysOnly?: boolean;
+}
+
+export interface TariffTier {
+  upToKwh: number | null;
+  pricePerKwh: number;
+}
+
+export type TariffRate =
+  | { type: 'flat'; pricePerKwh: number }
+  | { type: 'timeOfUse'; defaultPrice: number; periods: TariffPeriod[] }
+  | { type: 'tiered'; tiers: TariffTier[] };
+
+export interface TariffConfig {
+  currency: string;
+  carbonKgPerKwh: number;
+  rate: TariffRate;
+}
+
+export const DEFAULT_TARIFF: TariffConfig = {
+  currency: 'USD',
+  carbonKgPerKwh: 0.4,
+  rate: { type: 'flat', pricePerKwh: 0.15 },
+};
+
+export type ReportPeriod = 'day' | 'week' | 'month';
+
+export interface CostRow {
+  start: number;
+  label: string;
+  kwh: number;
+  cost: number;
+  kg_co2: number;
+}
+
+/** Result of get_cost_report. */
+export interface CostReport {
+  period: ReportPeriod;
+  currency: string;
+  rows: CostRow[];
+  total: CostRow;
+}
+
+/** One outage analysed by get_battery_health. */
+export interface Discharge {
+  start: number;
+  end: number;
+  start_charge?: number | null;
+  end_charge?: number | null;
+  charge_slope?: number | null;
+  predicted_runtime?: number | null;
+  delivered_runtime?: number | null;
+  runtime_ratio?: number | null;
+  voltage_sag?: number | null;
+  avg_load?: number | null;
+}
+
+export interface BatteryHealth {
+  score: number;
+  capacity_percent?: number | null;
+  fade_per_month?: number | null;
+  replace_flag: boolean;
+  battery_date?: string | null;
+  age_years?: number | null;
+  replace_by?: number | null;
+  replace_reason?: string | null;
+  discharges: Discharge[];
+}
+
+/** Sags or swells counted by get_power_quality. */
+export interface VoltageEvents {
+  count: number;
+  total_secs: number;
+  longest_secs: number;
+  extreme?: number | null;
+  beyond_transfer: number;
+}
+
+export interface ModeActivity {
+  events: number;
+  secs: number;
+}
+
+export interface FrequencyStats {
+  nominal: number;
+  min: number;
+  max: number;
+  max_deviation: number;
+  secs_outside: number;
+}
+
+export interface PowerQualityReport {
+  nominal_voltage: number;
+  sag_below: number;
+  swell_above: number;
+  transfer_low?: number | null;
+  transfer_high?: number | null;
+  sags: VoltageEvents;
+  swells: VoltageEvents;
+  frequency?: FrequencyStats | null;
+  trim: ModeActivity;
+  boost: ModeActivity;
+  on_battery_secs: number;
+  observed_secs: number;
+  transfers: number;
+  transfers_per_day: { start: number; label: string; transfers: number }[];
+  histogram: { from: number; to: number; secs: number }[];
+}
+
+// Mirrors `AppSettings` in src-tauri/src/settings.rs (persisted by the backend)
+export interface AppSettings {
+  version: number;
+  connection: ConnectionSettings;
+  pollIntervalMs: number;
+  shutdown: ShutdownConfig;
+  loadShedding?: LoadSheddingConfig;
+  tariff?: TariffConfig;
+  selfTest?: SelfTestConfig;
+  alerts?: AlertsConfig;
+  notifications?: NotificationsConfig;
+  telegram?: TelegramSettings;
 }
 
 export interface EventLog {
@@ -94,5 +632,6 @@ export interface UpsState {
   setShutdownConfig: (config: ShutdownConfig) => void;
   setSupportedCommands: (commands: string[]) => void;
   setTheme: (theme: 'system' | 'light' | 'dark' | 'catppuccin' | 'dracula' | 'nord' | 'monokai' | 'github-dark') => void;
+  addEvent: (message: string, type: EventLog['type']) => void;
   clearEvents: () => void;
 }